@@ -0,0 +1,313 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    Expr, ExprLit, FnArg, GenericArgument, Ident, ItemFn, Lit, LitStr, Meta, Pat, PathArguments,
+    ReturnType, Type, parse_macro_input,
+};
+
+/// Turns a free function into a native tool. The function is kept as written and a unit struct
+/// named after it (`fn add_numbers` → `AddNumbersTool`) is generated that implements
+/// `AsyncToolCaller` and `ToolMetadataProvider`, so it registers like any other source:
+///
+/// ```ignore
+/// /// Adds two numbers.
+/// #[codemode_rs::tool(read_only)]
+/// async fn add(a: i64, b: i64) -> Result<i64, ToolCallError> {
+///     Ok(a + b)
+/// }
+///
+/// client.register_async_source(AddTool, "math").await?;
+/// ```
+///
+/// Doc comments become the description. Each parameter becomes an argument of the same name,
+/// with an input schema derived from its type: strings, integers, floats, `bool`, `Vec`, maps
+/// and `Option` (not required) map to their JSON Schema types and anything else accepts any
+/// value. The output schema comes from the return type, or from `T` in `Result<T, E>`; errors are
+/// surfaced through their `Display` impl.
+///
+/// Options: `name = "..."` (defaults to the function name), `read_only` and `destructive`.
+#[proc_macro_attribute]
+pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut options = ToolAttr::default();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            options.name = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("read_only") {
+            options.read_only = true;
+        } else if meta.path.is_ident("destructive") {
+            options.destructive = true;
+        } else {
+            return Err(meta.error("expected `name`, `read_only` or `destructive`"));
+        }
+        Ok(())
+    });
+    parse_macro_input!(attr with parser);
+    let function = parse_macro_input!(item as ItemFn);
+    expand(options, function)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct ToolAttr {
+    name: Option<LitStr>,
+    read_only: bool,
+    destructive: bool,
+}
+
+fn expand(options: ToolAttr, function: ItemFn) -> syn::Result<TokenStream2> {
+    let sig = &function.sig;
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &sig.generics,
+            "#[tool] functions cannot be generic",
+        ));
+    }
+
+    let fn_name = &sig.ident;
+    let struct_name = format_ident!("{}Tool", camel_case(&fn_name.to_string()));
+    let tool_name = options
+        .name
+        .unwrap_or_else(|| LitStr::new(&fn_name.to_string(), fn_name.span()));
+    let description = description(&function);
+    let read_only = options.read_only;
+    let destructive = options.destructive;
+    let vis = &function.vis;
+
+    let mut idents = Vec::new();
+    let mut extracts = Vec::new();
+    let mut properties = Vec::new();
+    let mut required = Vec::new();
+    for input in &sig.inputs {
+        let FnArg::Typed(arg) = input else {
+            return Err(syn::Error::new_spanned(
+                input,
+                "#[tool] functions cannot take `self`",
+            ));
+        };
+        let Pat::Ident(pat) = &*arg.pat else {
+            return Err(syn::Error::new_spanned(
+                &arg.pat,
+                "#[tool] parameters must be plain identifiers",
+            ));
+        };
+        if let Type::Reference(_) = &*arg.ty {
+            return Err(syn::Error::new_spanned(
+                &arg.ty,
+                "#[tool] parameters must be owned, e.g. `String` instead of `&str`",
+            ));
+        }
+        let ident = &pat.ident;
+        let ty = &arg.ty;
+        let key = ident.to_string();
+        let (schema, optional) = schema_for(ty);
+        if !optional {
+            required.push(key.clone());
+        }
+        properties.push(quote! { #key: #schema });
+        extracts.push(quote! {
+            let #ident: #ty = ::codemode_rs::__private::serde_json::from_value(
+                args.get_mut(#key)
+                    .map(::codemode_rs::__private::serde_json::Value::take)
+                    .unwrap_or_default(),
+            )
+            .map_err(|err| {
+                ::codemode_rs::ToolCallError::Message(format!("invalid argument '{}': {err}", #key))
+            })?;
+        });
+        idents.push(ident);
+    }
+    let args_pat = if idents.is_empty() {
+        quote!(_args)
+    } else {
+        quote!(mut args)
+    };
+
+    let call = match &sig.asyncness {
+        Some(_) => quote!(#fn_name(#(#idents),*).await),
+        None => quote!(#fn_name(#(#idents),*)),
+    };
+    let (output_schema, value) = match &sig.output {
+        ReturnType::Default => (quote!({ "type": "null" }), quote!(#call;)),
+        ReturnType::Type(_, ty) => match result_types(ty) {
+            Some((ok, err)) => {
+                let unwrap = if last_ident(err).is_some_and(|ident| ident == "ToolCallError") {
+                    quote!(#call?)
+                } else {
+                    quote! {
+                        #call.map_err(|err| ::codemode_rs::ToolCallError::Message(err.to_string()))?
+                    }
+                };
+                (schema_for(ok).0, quote!(let value = #unwrap;))
+            }
+            None => (schema_for(ty).0, quote!(let value = #call;)),
+        },
+    };
+    let serialize = match &sig.output {
+        ReturnType::Default => quote!(Ok(::codemode_rs::__private::serde_json::Value::Null)),
+        ReturnType::Type(..) => quote! {
+            ::codemode_rs::__private::serde_json::to_value(value).map_err(|err| {
+                ::codemode_rs::ToolCallError::Message(format!(
+                    "could not serialize '{}' output: {err}",
+                    #tool_name
+                ))
+            })
+        },
+    };
+
+    Ok(quote! {
+        #function
+
+        #[derive(Debug, Clone, Copy, Default)]
+        #vis struct #struct_name;
+
+        impl #struct_name {
+            #vis fn tool() -> ::codemode_rs::Tool {
+                ::codemode_rs::Tool {
+                    name: #tool_name.to_string(),
+                    description: #description.to_string(),
+                    tags: Vec::new(),
+                    inputs: ::codemode_rs::__private::serde_json::json!({
+                        "type": "object",
+                        "properties": { #(#properties),* },
+                        "required": [#(#required),*]
+                    }),
+                    outputs: ::codemode_rs::__private::serde_json::json!(#output_schema),
+                    is_async: true,
+                    is_streaming: false,
+                    read_only: #read_only,
+                    destructive: #destructive,
+                    deprecated: false,
+                    stability: ::codemode_rs::Stability::Stable,
+                }
+            }
+        }
+
+        #[::codemode_rs::__private::async_trait]
+        impl ::codemode_rs::AsyncToolCaller for #struct_name {
+            async fn call_tool_async(
+                &self,
+                _name: &str,
+                #args_pat: ::codemode_rs::__private::serde_json::Value,
+            ) -> Result<::codemode_rs::__private::serde_json::Value, ::codemode_rs::ToolCallError> {
+                #(#extracts)*
+                #value
+                #serialize
+            }
+        }
+
+        #[::codemode_rs::__private::async_trait]
+        impl ::codemode_rs::ToolMetadataProvider for #struct_name {
+            async fn list_tools(&self) -> Result<Vec<::codemode_rs::Tool>, ::codemode_rs::ToolCallError> {
+                Ok(vec![Self::tool()])
+            }
+        }
+    })
+}
+
+fn description(function: &ItemFn) -> LitStr {
+    let lines: Vec<String> = function
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(doc) => match &doc.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(line),
+                    ..
+                }) => Some(line.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    LitStr::new(lines.join("\n").trim(), Span::call_site())
+}
+
+/// The JSON Schema for a parameter or return type, and whether the argument may be omitted.
+fn schema_for(ty: &Type) -> (TokenStream2, bool) {
+    if let Type::Tuple(tuple) = ty
+        && tuple.elems.is_empty()
+    {
+        return (quote!({ "type": "null" }), false);
+    }
+    let Some(ident) = last_ident(ty) else {
+        return (quote!({}), false);
+    };
+    let schema = match ident.to_string().as_str() {
+        "String" | "str" | "char" => quote!({ "type": "string" }),
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+        | "usize" => quote!({ "type": "integer" }),
+        "f32" | "f64" => quote!({ "type": "number" }),
+        "bool" => quote!({ "type": "boolean" }),
+        "Option" => {
+            let inner = generic_args(ty).first().map(|inner| schema_for(inner).0);
+            return (inner.unwrap_or_else(|| quote!({})), true);
+        }
+        "Vec" | "VecDeque" | "HashSet" | "BTreeSet" => match generic_args(ty).first() {
+            Some(item) => {
+                let items = schema_for(item).0;
+                quote!({ "type": "array", "items": #items })
+            }
+            None => quote!({ "type": "array" }),
+        },
+        "HashMap" | "BTreeMap" => match generic_args(ty).get(1) {
+            Some(value) => {
+                let values = schema_for(value).0;
+                quote!({ "type": "object", "additionalProperties": #values })
+            }
+            None => quote!({ "type": "object" }),
+        },
+        _ => quote!({}),
+    };
+    (schema, false)
+}
+
+fn result_types(ty: &Type) -> Option<(&Type, &Type)> {
+    if last_ident(ty)? != "Result" {
+        return None;
+    }
+    match generic_args(ty).as_slice() {
+        [ok, err] => Some((ok, err)),
+        _ => None,
+    }
+}
+
+fn last_ident(ty: &Type) -> Option<&Ident> {
+    match ty {
+        Type::Path(path) => path.path.segments.last().map(|segment| &segment.ident),
+        _ => None,
+    }
+}
+
+fn generic_args(ty: &Type) -> Vec<&Type> {
+    let Type::Path(path) = ty else {
+        return Vec::new();
+    };
+    let Some(segment) = path.path.segments.last() else {
+        return Vec::new();
+    };
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return Vec::new();
+    };
+    args.args
+        .iter()
+        .filter_map(|arg| match arg {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        })
+        .collect()
+}
+
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
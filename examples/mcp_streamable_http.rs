@@ -19,7 +19,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = CodeModeClientConfigBuilder::default()
         .sandbox(SandboxConfig::new(tokio::runtime::Handle::current()))
         .build()?;
-    let mut client = CodeModeClient::new(config);
+    let client = CodeModeClient::new(config);
     client.register_async_source(mcp.clone(), "test").await?;
 
     let result = client
@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+
+use crate::client::ToolCallerEntry;
+use crate::dispatch::ToolDispatch;
+#[cfg(feature = "v8")]
+use crate::sandbox::ExecutionExtras;
+use crate::sandbox::{ExecutionResult, Sandbox, SandboxError};
+use crate::tool::{Tool, ToolCallError};
+use crate::ts_interface::ToolInterfaceGenerator;
+
+/// Runs scripts for a [`CodeModeClient`]. [`Sandbox`] is the V8 implementation, built with the
+/// `v8` feature; others can wrap it (for instrumentation) or drive an entirely different engine.
+///
+/// [`CodeModeClient`]: crate::client::CodeModeClient
+pub trait ScriptBackend: Send + Sync {
+    /// Runs `code` to completion on the calling thread, exposing `bindings` to it.
+    fn execute(
+        &self,
+        code: &str,
+        bindings: &ToolBindings<'_>,
+        cancel: &CancellationToken,
+    ) -> Result<ExecutionResult, SandboxError>;
+}
+
+/// The tools one execution may call, how scripts address them, and the `input` value if any.
+pub struct ToolBindings<'a> {
+    pub tools: &'a [&'a Tool],
+    pub interface_generator: &'a ToolInterfaceGenerator,
+    pub input: Option<&'a Value>,
+    #[cfg_attr(not(feature = "v8"), allow(dead_code))]
+    callers: &'a BTreeMap<String, ToolCallerEntry>,
+    dispatches: BTreeMap<String, ToolDispatch>,
+}
+
+impl<'a> ToolBindings<'a> {
+    pub(crate) fn new(
+        sandbox: &Sandbox,
+        tools: &'a [&'a Tool],
+        interface_generator: &'a ToolInterfaceGenerator,
+        callers: &'a BTreeMap<String, ToolCallerEntry>,
+        input: Option<&'a Value>,
+    ) -> Self {
+        Self {
+            tools,
+            interface_generator,
+            input,
+            callers,
+            dispatches: sandbox.host_dispatches(tools, callers),
+        }
+    }
+
+    /// Where scripts find `tool`, e.g. `github.searchIssues`.
+    pub fn access_path(&self, tool: &Tool) -> String {
+        self.interface_generator.tool_access_path(tool)
+    }
+
+    /// Calls `name`, one of `tools`, the way a script in the built-in sandbox would: policy,
+    /// validation, interceptors, limits, caching and fallbacks all apply. The future owns what
+    /// it needs, so it can be spawned onto any runtime. Streaming tools resolve to an array of
+    /// their chunks.
+    pub fn call_tool(
+        &self,
+        name: &str,
+        args: Value,
+    ) -> impl Future<Output = Result<Value, ToolCallError>> + Send + 'static {
+        let dispatch = self.dispatches.get(name).cloned();
+        let name = name.to_string();
+        async move {
+            let dispatch =
+                dispatch.ok_or_else(|| ToolCallError::Message(format!("unknown tool '{name}'")))?;
+            dispatch.call(args).await
+        }
+    }
+
+    /// Owned copies of the dispatches behind [`call_tool`](Self::call_tool), for engines whose
+    /// host functions must be `'static`.
+    #[cfg(any(feature = "boa", feature = "deno"))]
+    pub(crate) fn dispatches(&self) -> BTreeMap<String, ToolDispatch> {
+        self.dispatches.clone()
+    }
+}
+
+/// Reads back the outcome an engine's glue code records as JSON: `{ value }` with the result
+/// serialized, `{ undefined: true }`, or `{ error, stack }`. `None` if the script never settled.
+#[cfg(any(feature = "boa", feature = "deno"))]
+pub(crate) fn script_outcome(outcome: &str) -> Result<Option<ExecutionResult>, SandboxError> {
+    let outcome: Value = serde_json::from_str(outcome)
+        .map_err(|err| SandboxError::Serialization(err.to_string()))?;
+    if let Some(message) = outcome["error"].as_str() {
+        return Err(script_error(
+            message.to_string(),
+            outcome["stack"].as_str().map(str::to_string),
+        ));
+    }
+    if outcome["undefined"] == Value::Bool(true) {
+        return Ok(Some(ExecutionResult {
+            undefined: true,
+            ..Default::default()
+        }));
+    }
+    let Some(value) = outcome["value"].as_str() else {
+        return Ok(None);
+    };
+    Ok(Some(ExecutionResult {
+        result: serde_json::from_str(value)
+            .map_err(|err| SandboxError::Serialization(err.to_string()))?,
+        ..Default::default()
+    }))
+}
+
+#[cfg(any(feature = "boa", feature = "deno"))]
+pub(crate) fn script_error(message: String, stack: Option<String>) -> SandboxError {
+    SandboxError::Script {
+        message,
+        stack,
+        line: None,
+        column: None,
+    }
+}
+
+#[cfg(feature = "v8")]
+impl ScriptBackend for Sandbox {
+    fn execute(
+        &self,
+        code: &str,
+        bindings: &ToolBindings<'_>,
+        cancel: &CancellationToken,
+    ) -> Result<ExecutionResult, SandboxError> {
+        let extras = ExecutionExtras {
+            input: bindings.input.cloned(),
+            ..Default::default()
+        };
+        self.prepare_tools(
+            bindings.tools,
+            bindings.interface_generator,
+            bindings.callers,
+        )?
+        .execute_with(code, cancel, extras)
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+use serde_json::{Value, json};
+use thiserror::Error;
+use tracing::trace;
+
+const PREVIEW_BYTES: usize = 256;
+
+#[derive(Debug, Error)]
+pub enum BlobError {
+    #[error("blob not found: {0}")]
+    NotFound(String),
+    #[error("blob storage error: {0}")]
+    Storage(String),
+}
+
+/// Storage for tool results too large to hand to the script directly.
+pub trait BlobStore: Send + Sync {
+    fn put(&self, data: Vec<u8>) -> Result<String, BlobError>;
+    fn read(&self, id: &str, offset: usize, length: Option<usize>) -> Result<Vec<u8>, BlobError>;
+}
+
+#[derive(Default)]
+pub struct InMemoryBlobStore {
+    next_id: AtomicU64,
+    blobs: DashMap<String, Arc<Vec<u8>>>,
+}
+
+impl InMemoryBlobStore {
+    pub fn len(&self) -> usize {
+        self.blobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blobs.is_empty()
+    }
+
+    pub fn remove(&self, id: &str) -> bool {
+        self.blobs.remove(id).is_some()
+    }
+}
+
+impl BlobStore for InMemoryBlobStore {
+    fn put(&self, data: Vec<u8>) -> Result<String, BlobError> {
+        let id = format!("blob-{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        self.blobs.insert(id.clone(), Arc::new(data));
+        Ok(id)
+    }
+
+    fn read(&self, id: &str, offset: usize, length: Option<usize>) -> Result<Vec<u8>, BlobError> {
+        let blob = self
+            .blobs
+            .get(id)
+            .map(|entry| entry.value().clone())
+            .ok_or_else(|| BlobError::NotFound(id.to_string()))?;
+        let start = offset.min(blob.len());
+        let end = length
+            .map(|length| start.saturating_add(length).min(blob.len()))
+            .unwrap_or(blob.len());
+        Ok(blob[start..end].to_vec())
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct BlobOffload {
+    pub(crate) store: Arc<dyn BlobStore>,
+    pub(crate) threshold_bytes: usize,
+}
+
+impl BlobOffload {
+    /// Replaces values whose JSON encoding exceeds the threshold with `{ blobId, size, preview }`.
+    pub(crate) fn apply(&self, value: Value) -> Result<Value, BlobError> {
+        let encoded =
+            serde_json::to_vec(&value).map_err(|err| BlobError::Storage(err.to_string()))?;
+        if encoded.len() <= self.threshold_bytes {
+            return Ok(value);
+        }
+
+        let size = encoded.len();
+        let preview = preview(&encoded);
+        let id = self.store.put(encoded)?;
+        trace!(
+            blob = id.as_str(),
+            size, "tool result offloaded to blob store"
+        );
+        Ok(json!({ "blobId": id, "size": size, "preview": preview }))
+    }
+}
+
+fn preview(encoded: &[u8]) -> String {
+    let text = String::from_utf8_lossy(&encoded[..encoded.len().min(PREVIEW_BYTES)]);
+    text.trim_end_matches('\u{FFFD}').to_string()
+}
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+#[cfg(not(target_family = "wasm"))]
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use boa_engine::{
+    Context, JsError, JsNativeError, JsNativeErrorKind, JsResult, JsString, JsValue,
+    NativeFunction, Source, js_string,
+};
+use serde_json::{Value, json};
+use tokio_util::sync::CancellationToken;
+
+use crate::backend::{ScriptBackend, ToolBindings, script_error, script_outcome};
+use crate::dispatch::ToolDispatch;
+use crate::sandbox::{ExecutionResult, SandboxError};
+use crate::tool::ToolCallError;
+
+/// A [`ScriptBackend`] on the Boa engine, enabled with the `boa` feature. It needs no native
+/// V8 build, at the cost of speed and of the sandbox's extras: there are no timers, web
+/// globals, modules or traces, and tool calls run one at a time, so `Promise.all` over tool
+/// calls is sequential.
+///
+/// Tools are exposed at their access paths and `input` is a global, as in the V8 sandbox.
+/// The code runs as the body of an async function.
+///
+/// Boa cannot be interrupted mid-script, so runaway code is bounded by its loop and recursion
+/// limits instead, which end the execution with `BudgetExceeded`. The timeout and cancellation
+/// are checked around every tool call, whose wait they cut short.
+pub struct BoaBackend {
+    runtime: tokio::runtime::Handle,
+    timeout_ms: u64,
+    loop_iteration_limit: u64,
+    recursion_limit: usize,
+}
+
+impl BoaBackend {
+    /// Tool calls are spawned onto `runtime` and awaited from the thread running the script.
+    /// If that thread is the one driving a current-thread `runtime`, calls cannot finish and
+    /// the execution times out.
+    pub fn new(runtime: tokio::runtime::Handle) -> Self {
+        Self {
+            runtime,
+            timeout_ms: 30_000,
+            loop_iteration_limit: 10_000_000,
+            recursion_limit: 512,
+        }
+    }
+
+    /// Fails the execution with `Timeout` once it has run this long. Defaults to 30 seconds.
+    pub fn with_timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Iterations any single loop may run. Defaults to ten million.
+    pub fn with_loop_iteration_limit(mut self, limit: u64) -> Self {
+        self.loop_iteration_limit = limit;
+        self
+    }
+
+    /// How deeply calls may nest. Defaults to 512.
+    pub fn with_recursion_limit(mut self, limit: usize) -> Self {
+        self.recursion_limit = limit;
+        self
+    }
+
+    /// Why a run that ended early stopped: cancellation and the deadline take precedence over
+    /// whatever error the script saw as a result.
+    fn stopped(&self, cancel: &CancellationToken, deadline: Instant) -> Option<SandboxError> {
+        if cancel.is_cancelled() {
+            Some(SandboxError::Cancelled)
+        } else if Instant::now() >= deadline {
+            Some(SandboxError::Timeout(self.timeout_ms))
+        } else {
+            None
+        }
+    }
+
+    fn engine_error(&self, err: JsError) -> SandboxError {
+        match err.as_native().map(|native| &native.kind) {
+            Some(JsNativeErrorKind::RuntimeLimit) => {
+                SandboxError::BudgetExceeded(self.loop_iteration_limit)
+            }
+            _ => script_error(err.to_string(), None),
+        }
+    }
+}
+
+impl ScriptBackend for BoaBackend {
+    fn execute(
+        &self,
+        code: &str,
+        bindings: &ToolBindings<'_>,
+        cancel: &CancellationToken,
+    ) -> Result<ExecutionResult, SandboxError> {
+        if cancel.is_cancelled() {
+            return Err(SandboxError::Cancelled);
+        }
+        let deadline = Instant::now() + Duration::from_millis(self.timeout_ms);
+        let mut context = Context::default();
+        let limits = context.runtime_limits_mut();
+        limits.set_loop_iteration_limit(self.loop_iteration_limit);
+        limits.set_recursion_limit(self.recursion_limit);
+        let call = tool_call(ToolCallState {
+            dispatches: bindings.dispatches(),
+            runtime: self.runtime.clone(),
+            cancel: cancel.clone(),
+            deadline,
+        });
+        context
+            .register_global_builtin_callable(js_string!("__codemode_call"), 2, call)
+            .map_err(|err| SandboxError::Engine(format!("boa globals: {err}")))?;
+
+        let mut glue = String::from(
+            "const __codemode_define = (path, name) => {
+               const parts = path.split('.');
+               let target = globalThis;
+               for (const part of parts.slice(0, -1)) target = target[part] ??= {};
+               target[parts[parts.length - 1]] = async (args = {}) =>
+                 JSON.parse(__codemode_call(name, JSON.stringify(args)));
+             };\n",
+        );
+        for tool in bindings.tools {
+            glue.push_str(&format!(
+                "__codemode_define({}, {});\n",
+                json!(bindings.access_path(tool)),
+                json!(tool.name),
+            ));
+        }
+        if let Some(input) = bindings.input {
+            glue.push_str(&format!(
+                "Object.defineProperty(globalThis, 'input', {{ value: Object.freeze({input}) }});\n"
+            ));
+        }
+        context
+            .eval(Source::from_bytes(&glue))
+            .map_err(|err| SandboxError::Engine(format!("boa globals: {err}")))?;
+
+        // The outcome is read back as JSON, so no engine values cross into the host.
+        let wrapped = format!(
+            "(async () => {{\n{code}\n}})().then(
+               value => {{
+                 globalThis.__codemode_outcome = value === undefined
+                   ? {{ undefined: true }}
+                   : {{ value: JSON.stringify(value) ?? 'null' }};
+               }},
+               error => {{
+                 globalThis.__codemode_outcome = {{
+                   error: String(error?.message ?? error),
+                   stack: typeof error?.stack === 'string' ? error.stack : null,
+                 }};
+               }},
+             );"
+        );
+        let evaluated = context.eval(Source::from_bytes(&wrapped));
+        if evaluated.is_ok() {
+            context.run_jobs();
+        }
+        if let Some(stopped) = self.stopped(cancel, deadline) {
+            return Err(stopped);
+        }
+        evaluated.map_err(|err| self.engine_error(err))?;
+
+        let outcome = context
+            .eval(Source::from_bytes(
+                "JSON.stringify(globalThis.__codemode_outcome ?? null)",
+            ))
+            .and_then(|outcome| outcome.to_string(&mut context))
+            .map_err(|err| SandboxError::Engine(format!("boa outcome: {err}")))?
+            .to_std_string_escaped();
+        script_outcome(&outcome)?.ok_or_else(|| {
+            SandboxError::Engine(
+                "boa: the script never settled; it may be waiting on a timer".to_string(),
+            )
+        })
+    }
+}
+
+/// What `__codemode_call` needs; owned, as Boa host functions must be `'static`.
+struct ToolCallState {
+    dispatches: BTreeMap<String, ToolDispatch>,
+    runtime: tokio::runtime::Handle,
+    cancel: CancellationToken,
+    deadline: Instant,
+}
+
+type ToolCall = Pin<Box<dyn Future<Output = Result<Value, ToolCallError>> + Send>>;
+
+/// `__codemode_call(name, argsJson)`: runs the call to completion and returns its result as
+/// JSON, or throws the tool's error.
+fn tool_call(state: ToolCallState) -> NativeFunction {
+    let call = move |_: &JsValue, args: &[JsValue], context: &mut Context| -> JsResult<JsValue> {
+        let mut text = |index: usize| -> JsResult<String> {
+            Ok(args
+                .get(index)
+                .cloned()
+                .unwrap_or_default()
+                .to_string(context)?
+                .to_std_string_escaped())
+        };
+        let (name, args) = (text(0)?, text(1)?);
+        if state.cancel.is_cancelled() {
+            return Err(js_error("execution cancelled".to_string()));
+        }
+        let dispatch = state
+            .dispatches
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| js_error(format!("unknown tool '{name}'")))?;
+        let args: Value = serde_json::from_str(&args).map_err(|err| js_error(err.to_string()))?;
+        let result = run_call(&state, Box::pin(async move { dispatch.call(args).await }))?;
+        let result = result.map_err(|err| js_error(err.to_string()))?;
+        Ok(JsString::from(result.to_string().as_str()).into())
+    };
+    // SAFETY: the closure captures no garbage-collected values.
+    unsafe { NativeFunction::from_closure(call) }
+}
+
+/// Spawned and awaited over a channel rather than with `block_on`, which panics when the
+/// backend runs inside async code.
+#[cfg(not(target_family = "wasm"))]
+fn run_call(state: &ToolCallState, call: ToolCall) -> JsResult<Result<Value, ToolCallError>> {
+    let stop = state.cancel.child_token();
+    let (done, result) = mpsc::sync_channel(1);
+    let call_stop = stop.clone();
+    state.runtime.spawn(async move {
+        let _ = done.send(call_stop.run_until_cancelled(call).await);
+    });
+    let remaining = state.deadline.saturating_duration_since(Instant::now());
+    match result.recv_timeout(remaining) {
+        Ok(Some(result)) => Ok(result),
+        Ok(None) => Err(js_error("execution cancelled".to_string())),
+        Err(RecvTimeoutError::Timeout) => {
+            stop.cancel();
+            Err(js_error("execution timed out".to_string()))
+        }
+        Err(RecvTimeoutError::Disconnected) => Err(js_error("tool runtime shut down".to_string())),
+    }
+}
+
+/// wasm has no second thread to run the call on, so it is polled to completion here. The
+/// timeout cannot cut it short, and a tool that waits on Tokio timers or I/O never finishes.
+#[cfg(target_family = "wasm")]
+fn run_call(state: &ToolCallState, call: ToolCall) -> JsResult<Result<Value, ToolCallError>> {
+    let _runtime = state.runtime.enter();
+    futures::executor::block_on(state.cancel.run_until_cancelled(call))
+        .ok_or_else(|| js_error("execution cancelled".to_string()))
+}
+
+fn js_error(message: String) -> JsError {
+    JsNativeError::error().with_message(message).into()
+}
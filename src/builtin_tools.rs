@@ -0,0 +1,244 @@
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::tool::{SyncToolCaller, Tool, ToolCallError, ToolMetadataProvider};
+
+/// Pure host-side utilities, registered like any other source:
+/// `client.register_sync_source(BuiltinTools, "builtin").await?`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BuiltinTools;
+
+impl SyncToolCaller for BuiltinTools {
+    fn call_tool_sync(&self, name: &str, args: Value) -> Result<Value, ToolCallError> {
+        match name {
+            "now" => now(&args),
+            "convert_time" => convert_time(&args),
+            "uuid" => Ok(Value::String(uuid::Uuid::new_v4().to_string())),
+            "sha256" => {
+                let digest = Sha256::digest(string_arg(&args, "data")?.as_bytes());
+                Ok(Value::String(hex(&digest)))
+            }
+            "base64_encode" => Ok(Value::String(
+                BASE64.encode(string_arg(&args, "data")?.as_bytes()),
+            )),
+            "base64_decode" => {
+                let bytes = BASE64
+                    .decode(string_arg(&args, "data")?)
+                    .map_err(|err| ToolCallError::Message(format!("invalid base64: {err}")))?;
+                String::from_utf8(bytes)
+                    .map(Value::String)
+                    .map_err(|_| ToolCallError::Message("decoded data is not UTF-8".to_string()))
+            }
+            "json_diff" => {
+                let mut changes = Vec::new();
+                json_diff(
+                    "",
+                    args.get("a").unwrap_or(&Value::Null),
+                    args.get("b").unwrap_or(&Value::Null),
+                    &mut changes,
+                );
+                Ok(Value::Array(changes))
+            }
+            other => Err(ToolCallError::Message(format!(
+                "unknown builtin tool '{other}'"
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolMetadataProvider for BuiltinTools {
+    async fn list_tools(&self) -> Result<Vec<Tool>, ToolCallError> {
+        Ok(builtin_tool_list())
+    }
+}
+
+fn builtin_tool_list() -> Vec<Tool> {
+    let data_input = json!({
+        "type": "object",
+        "properties": { "data": { "type": "string" } },
+        "required": ["data"]
+    });
+    let string_output = json!({ "type": "string" });
+    let time_output = json!({
+        "type": "object",
+        "properties": {
+            "iso": { "type": "string", "description": "RFC 3339 timestamp" },
+            "unix_ms": { "type": "integer" },
+            "timezone": { "type": "string" }
+        }
+    });
+
+    vec![
+        builtin(
+            "now",
+            "Current time, optionally in an IANA timezone",
+            json!({
+                "type": "object",
+                "properties": {
+                    "timezone": { "type": "string", "description": "IANA timezone, e.g. Europe/Paris" }
+                }
+            }),
+            time_output.clone(),
+        ),
+        builtin(
+            "convert_time",
+            "Convert an RFC 3339 timestamp into another IANA timezone",
+            json!({
+                "type": "object",
+                "properties": {
+                    "time": { "type": "string", "description": "RFC 3339 timestamp" },
+                    "timezone": { "type": "string", "description": "Target IANA timezone" }
+                },
+                "required": ["time", "timezone"]
+            }),
+            time_output,
+        ),
+        builtin(
+            "uuid",
+            "Generate a random UUID v4",
+            json!({ "type": "object" }),
+            string_output.clone(),
+        ),
+        builtin(
+            "sha256",
+            "Hex-encoded SHA-256 digest of a UTF-8 string",
+            data_input.clone(),
+            string_output.clone(),
+        ),
+        builtin(
+            "base64_encode",
+            "Base64-encode a UTF-8 string",
+            data_input.clone(),
+            string_output.clone(),
+        ),
+        builtin(
+            "base64_decode",
+            "Decode base64 into a UTF-8 string",
+            data_input,
+            string_output,
+        ),
+        builtin(
+            "json_diff",
+            "List the differences between two JSON values as JSON pointer paths",
+            json!({
+                "type": "object",
+                "properties": { "a": {}, "b": {} },
+                "required": ["a", "b"]
+            }),
+            json!({
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string" },
+                        "op": { "type": "string", "enum": ["add", "remove", "replace"] },
+                        "from": {},
+                        "to": {}
+                    }
+                }
+            }),
+        ),
+    ]
+}
+
+fn builtin(name: &str, description: &str, inputs: Value, outputs: Value) -> Tool {
+    Tool::new(name, description)
+        .with_tags(["builtin"])
+        .with_inputs(inputs)
+        .with_outputs(outputs)
+}
+
+fn string_arg<'a>(args: &'a Value, key: &str) -> Result<&'a str, ToolCallError> {
+    args.get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| ToolCallError::Message(format!("missing string argument '{key}'")))
+}
+
+fn parse_timezone(name: &str) -> Result<Tz, ToolCallError> {
+    name.parse::<Tz>()
+        .map_err(|_| ToolCallError::Message(format!("unknown timezone '{name}'")))
+}
+
+fn time_value(time: DateTime<Utc>, timezone: Option<&str>) -> Result<Value, ToolCallError> {
+    let (iso, timezone) = match timezone {
+        Some(name) => (
+            time.with_timezone(&parse_timezone(name)?).to_rfc3339(),
+            name.to_string(),
+        ),
+        None => (time.to_rfc3339(), "UTC".to_string()),
+    };
+    Ok(json!({
+        "iso": iso,
+        "unix_ms": time.timestamp_millis(),
+        "timezone": timezone
+    }))
+}
+
+fn now(args: &Value) -> Result<Value, ToolCallError> {
+    time_value(Utc::now(), args.get("timezone").and_then(Value::as_str))
+}
+
+fn convert_time(args: &Value) -> Result<Value, ToolCallError> {
+    let time = DateTime::parse_from_rfc3339(string_arg(args, "time")?)
+        .map_err(|err| ToolCallError::Message(format!("invalid RFC 3339 time: {err}")))?;
+    time_value(
+        time.with_timezone(&Utc),
+        Some(string_arg(args, "timezone")?),
+    )
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn json_diff(path: &str, a: &Value, b: &Value, changes: &mut Vec<Value>) {
+    match (a, b) {
+        (Value::Object(left), Value::Object(right)) => {
+            for (key, left_value) in left {
+                let child = format!("{path}/{}", escape_pointer(key));
+                match right.get(key) {
+                    Some(right_value) => json_diff(&child, left_value, right_value, changes),
+                    None => {
+                        changes.push(json!({ "path": child, "op": "remove", "from": left_value }))
+                    }
+                }
+            }
+            for (key, right_value) in right {
+                if !left.contains_key(key) {
+                    let child = format!("{path}/{}", escape_pointer(key));
+                    changes.push(json!({ "path": child, "op": "add", "to": right_value }));
+                }
+            }
+        }
+        (Value::Array(left), Value::Array(right)) => {
+            for (idx, left_value) in left.iter().enumerate() {
+                let child = format!("{path}/{idx}");
+                match right.get(idx) {
+                    Some(right_value) => json_diff(&child, left_value, right_value, changes),
+                    None => {
+                        changes.push(json!({ "path": child, "op": "remove", "from": left_value }))
+                    }
+                }
+            }
+            for (idx, right_value) in right.iter().enumerate().skip(left.len()) {
+                changes.push(
+                    json!({ "path": format!("{path}/{idx}"), "op": "add", "to": right_value }),
+                );
+            }
+        }
+        _ if a != b => {
+            changes.push(json!({ "path": path, "op": "replace", "from": a, "to": b }));
+        }
+        _ => {}
+    }
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
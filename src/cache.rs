@@ -0,0 +1,112 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+/// Identifies a cached result: the tool's registered name and its canonical JSON arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub tool: String,
+    pub args: String,
+}
+
+impl CacheKey {
+    pub fn new(tool: &str, args: &Value) -> Self {
+        Self {
+            tool: tool.to_string(),
+            args: args.to_string(),
+        }
+    }
+}
+
+/// Stores tool results across executions. Only read-only tools (MCP `readOnlyHint`) and tools
+/// given a `ToolOptions::cache_ttl_ms` are cached.
+pub trait ToolResultCache: Send + Sync {
+    fn get(&self, key: &CacheKey) -> Option<Value>;
+    fn put(&self, key: CacheKey, value: Value, ttl: Duration);
+}
+
+/// Least-recently-used in-memory cache holding at most `capacity` results.
+pub struct InMemoryToolCache {
+    capacity: usize,
+    inner: Mutex<LruState>,
+}
+
+#[derive(Default)]
+struct LruState {
+    entries: HashMap<CacheKey, LruEntry>,
+    // Recency order: the smallest tick is the least recently used key.
+    order: BTreeMap<u64, CacheKey>,
+    tick: u64,
+}
+
+struct LruEntry {
+    value: Value,
+    expires_at: Instant,
+    tick: u64,
+}
+
+impl InMemoryToolCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            inner: Mutex::new(LruState::default()),
+        }
+    }
+}
+
+impl LruState {
+    fn touch(&mut self, key: &CacheKey) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.order.remove(&entry.tick);
+            entry.tick = tick;
+            self.order.insert(tick, key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.tick);
+        }
+    }
+}
+
+impl ToolResultCache for InMemoryToolCache {
+    fn get(&self, key: &CacheKey) -> Option<Value> {
+        let mut state = self.inner.lock().ok()?;
+        let expired = state.entries.get(key)?.expires_at <= Instant::now();
+        if expired {
+            state.remove(key);
+            return None;
+        }
+        state.touch(key);
+        state.entries.get(key).map(|entry| entry.value.clone())
+    }
+
+    fn put(&self, key: CacheKey, value: Value, ttl: Duration) {
+        let Ok(mut state) = self.inner.lock() else {
+            return;
+        };
+        state.remove(&key);
+        while state.entries.len() >= self.capacity {
+            let Some((_, oldest)) = state.order.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+        state.tick += 1;
+        let tick = state.tick;
+        state.order.insert(tick, key.clone());
+        state.entries.insert(
+            key,
+            LruEntry {
+                value,
+                expires_at: Instant::now() + ttl,
+                tick,
+            },
+        );
+    }
+}
@@ -1,13 +1,45 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use derive_builder::Builder;
-use serde_json::Value;
+use futures::Stream;
+use futures::future::Either;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::{CancellationToken, DropGuard};
 use tracing::{debug, trace};
 
-use crate::sandbox::{ExecutionResult, Sandbox, SandboxConfig, SandboxError};
-use crate::tool::{AsyncToolCaller, SyncToolCaller, Tool, ToolCallError, ToolMetadataProvider};
-use crate::ts_interface::ToolInterfaceGenerator;
+use crate::backend::{ScriptBackend, ToolBindings};
+use crate::cache::ToolResultCache;
+use crate::dispatch::ResultCache;
+use crate::markdown;
+use crate::memory::MemoryStore;
+use crate::policy::{ApprovalGate, ToolApprover, ToolPolicy};
+use crate::prompt::PromptTemplate;
+use crate::redact::{Redactor, format_value};
+use crate::sandbox::{
+    ChainEvent, ExecutionExtras, ExecutionResult, Sandbox, SandboxConfig, SandboxError,
+};
+#[cfg(feature = "v8")]
+use crate::sandbox::{PreparedSandbox, ScriptDiagnostics};
+use crate::select::ToolSelector;
+use crate::snapshot::{InterfaceDrift, InterfaceSnapshot};
+use crate::tool::{
+    ArgAdapterFn, AsyncToolCaller, ResultTransformer, StreamingToolCaller, SyncToolCaller, Tool,
+    ToolCallError, ToolFilter, ToolInterceptor, ToolMetadataProvider,
+};
+use crate::ts_interface::{
+    self, BudgetedInterfaces, InterfaceOptions, ToolInterfaceGenerator, VerifyError,
+};
+use crate::validate::LazySchema;
+use crate::wire::{RawToolAdapter, RawToolCaller};
+use crate::zod;
 
 #[derive(Clone, Builder)]
 #[builder(pattern = "owned")]
@@ -17,62 +49,123 @@ pub struct CodeModeClientConfig {
     pub callers: HashMap<String, ToolCallerEntry>,
     #[builder(setter(custom))]
     pub sandbox: SandboxConfig,
+    #[builder(default)]
+    pub interface_options: InterfaceOptions,
+    /// Caps simultaneous `call_tool_chain` executions; callers queue in FIFO order.
+    #[builder(default)]
+    pub max_concurrent_executions: Option<usize>,
+    /// How long a queued execution waits for a slot before failing with `QueueTimeout`.
+    #[builder(default)]
+    pub execution_queue_timeout_ms: Option<u64>,
+    /// Read-only `env` global in every execution (user id, locale, feature flags). Replaces
+    /// `SandboxConfig::env` when set.
+    #[builder(setter(custom))]
+    #[builder(default)]
+    pub context: Option<Value>,
 }
 
 impl CodeModeClientConfigBuilder {
+    /// Tools registered up front, from any map or list of pairs; the client orders them by name.
+    pub fn callers(mut self, callers: impl IntoIterator<Item = (String, ToolCallerEntry)>) -> Self {
+        self.callers = Some(callers.into_iter().collect());
+        self
+    }
+
+    pub fn context(mut self, context: Value) -> Self {
+        self.context = Some(Some(context));
+        self
+    }
+
     pub fn sandbox(mut self, sandbox: SandboxConfig) -> Self {
         self.sandbox = Some(sandbox);
         self
     }
 }
 
+/// Every method takes `&self`, so one client behind an `Arc` can register tools while serving
+/// executions. Each execution runs against the registry as it was when the execution started.
 pub struct CodeModeClient {
-    callers: HashMap<String, ToolCallerEntry>,
-    sandbox: Sandbox,
-    interface_generator: ToolInterfaceGenerator,
+    // Ordered by tool name so interface output and injection order are stable across runs.
+    callers: RwLock<BTreeMap<String, ToolCallerEntry>>,
+    sandbox: Arc<Sandbox>,
+    // Replaces the sandbox for `call_tool_chain*` when set.
+    backend: RwLock<Option<Arc<dyn ScriptBackend>>>,
+    interface_generator: Arc<ToolInterfaceGenerator>,
+    execution_limiter: Option<Arc<Semaphore>>,
+    execution_queue_timeout: Option<Duration>,
+    // Narrows the tools for `*_for_query` calls to the selector's top K.
+    tool_selector: RwLock<Option<(Arc<dyn ToolSelector>, usize)>>,
 }
 
 impl CodeModeClient {
     pub fn new(config: CodeModeClientConfig) -> Self {
         trace!("codemode client initialized");
+        let mut sandbox = config.sandbox;
+        if let Some(context) = config.context {
+            sandbox.env = Some(context);
+        }
         Self {
-            callers: config.callers,
-            sandbox: Sandbox::new(config.sandbox),
-            interface_generator: ToolInterfaceGenerator::default(),
+            callers: RwLock::new(config.callers.into_iter().collect()),
+            sandbox: Arc::new(Sandbox::new(sandbox)),
+            backend: RwLock::new(None),
+            interface_generator: Arc::new(ToolInterfaceGenerator::new(config.interface_options)),
+            execution_limiter: config
+                .max_concurrent_executions
+                .map(|permits| Arc::new(Semaphore::new(permits.max(1)))),
+            execution_queue_timeout: config.execution_queue_timeout_ms.map(Duration::from_millis),
+            tool_selector: RwLock::new(None),
         }
     }
 
-    pub fn get_tool(&self, name: &str) -> Option<&Tool> {
+    pub fn get_tool(&self, name: &str) -> Option<Tool> {
         trace!(tool = name, "codemode get_tool");
-        self.callers.get(name).map(|entry| &entry.tool)
+        self.callers
+            .read()
+            .ok()
+            .and_then(|callers| callers.get(name).map(|entry| entry.tool.clone()))
     }
 
-    pub fn get_tools(&self) -> Vec<&Tool> {
-        let tools: Vec<&Tool> = self.callers.values().map(|entry| &entry.tool).collect();
+    pub fn get_tools(&self) -> Vec<Tool> {
+        let tools = self
+            .callers
+            .read()
+            .map(|callers| registered_tools(&callers))
+            .unwrap_or_default();
         trace!(count = tools.len(), "codemode get_tools");
         tools
     }
 
+    fn tool_count(&self) -> usize {
+        self.callers
+            .read()
+            .map(|callers| callers.len())
+            .unwrap_or_default()
+    }
+
+    // A copy of the registry for one execution; tools registered while it runs apply to the
+    // next one.
+    fn registry(&self) -> BTreeMap<String, ToolCallerEntry> {
+        self.callers
+            .read()
+            .map(|callers| callers.clone())
+            .unwrap_or_default()
+    }
+
     pub fn register_async_tool(
-        &mut self,
-        mut tool: Tool,
+        &self,
+        tool: Tool,
         raw_name: String,
         caller: Arc<dyn AsyncToolCaller>,
     ) {
-        tool.is_async = true;
-        let name = tool.name.clone();
-        let entry = ToolCallerEntry {
+        self.register_entry(ToolCallerEntry::new(
             tool,
             raw_name,
-            caller: CallerKind::Async(caller),
-        };
-        if self.callers.insert(name.clone(), entry).is_some() {
-            trace!(tool = name.as_str(), "tool caller overwritten");
-        }
+            CallerKind::Async(caller),
+        ));
     }
 
     pub async fn register_async_source<S>(
-        &mut self,
+        &self,
         source: S,
         prefix: &str,
     ) -> Result<(), ToolCallError>
@@ -89,26 +182,29 @@ impl CodeModeClient {
         Ok(())
     }
 
+    /// Registers a caller that receives arguments pre-encoded in its [`WireFormat`]. It is exposed
+    /// to scripts as an async tool.
+    ///
+    /// [`WireFormat`]: crate::wire::WireFormat
+    pub fn register_raw_tool(&self, tool: Tool, raw_name: String, caller: Arc<dyn RawToolCaller>) {
+        self.register_async_tool(tool, raw_name, Arc::new(RawToolAdapter::new(caller)));
+    }
+
     pub fn register_sync_tool(
-        &mut self,
-        mut tool: Tool,
+        &self,
+        tool: Tool,
         raw_name: String,
         caller: Arc<dyn SyncToolCaller>,
     ) {
-        tool.is_async = false;
-        let name = tool.name.clone();
-        let entry = ToolCallerEntry {
+        self.register_entry(ToolCallerEntry::new(
             tool,
             raw_name,
-            caller: CallerKind::Sync(caller),
-        };
-        if self.callers.insert(name.clone(), entry).is_some() {
-            trace!(tool = name.as_str(), "tool caller overwritten");
-        }
+            CallerKind::Sync(caller),
+        ));
     }
 
     pub async fn register_sync_source<S>(
-        &mut self,
+        &self,
         source: S,
         prefix: &str,
     ) -> Result<(), ToolCallError>
@@ -125,6 +221,274 @@ impl CodeModeClient {
         Ok(())
     }
 
+    /// Registers a tool whose calls return an async iterable in the sandbox.
+    pub fn register_streaming_tool(
+        &self,
+        tool: Tool,
+        raw_name: String,
+        caller: Arc<dyn StreamingToolCaller>,
+    ) {
+        self.register_entry(ToolCallerEntry::new(
+            tool,
+            raw_name,
+            CallerKind::Streaming(caller),
+        ));
+    }
+
+    pub async fn register_streaming_source<S>(
+        &self,
+        source: S,
+        prefix: &str,
+    ) -> Result<(), ToolCallError>
+    where
+        S: StreamingToolCaller + ToolMetadataProvider + Clone + 'static,
+    {
+        let tools = source.list_tools().await?;
+        let caller = Arc::new(source);
+        for mut tool in tools {
+            let raw_name = tool.name.clone();
+            tool.name = apply_prefix(prefix, &tool.name);
+            self.register_streaming_tool(tool, raw_name, caller.clone());
+        }
+        Ok(())
+    }
+
+    /// Removes `name` and releases its interface and access path, returning its metadata. Like
+    /// registration, this leaves a [`warm_snapshot`](Self::warm_snapshot) untouched.
+    pub fn unregister_tool(&self, name: &str) -> Option<Tool> {
+        let entry = self.callers.write().ok()?.remove(name)?;
+        self.interface_generator.release(name);
+        trace!(tool = name, "tool unregistered");
+        Some(entry.tool)
+    }
+
+    /// Removes every tool whose name starts with `prefix` (such as `"github."` for one MCP
+    /// server), returning the removed names.
+    pub fn unregister_prefix(&self, prefix: &str) -> Vec<String> {
+        let Ok(mut callers) = self.callers.write() else {
+            return Vec::new();
+        };
+        let names = callers
+            .keys()
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect::<Vec<String>>();
+        for name in &names {
+            callers.remove(name);
+            self.interface_generator.release(name);
+        }
+        trace!(prefix = prefix, count = names.len(), "tools unregistered");
+        names
+    }
+
+    pub fn clear_tools(&self) {
+        let removed = self
+            .callers
+            .write()
+            .map(|mut callers| std::mem::take(&mut *callers))
+            .unwrap_or_default();
+        for name in removed.into_keys() {
+            self.interface_generator.release(&name);
+        }
+        trace!("tools cleared");
+    }
+
+    // Options (and the in-use concurrency limiter) survive re-registration so refreshed
+    // sources keep their configuration.
+    fn register_entry(&self, mut entry: ToolCallerEntry) {
+        self.interface_generator.claim_access_path(&entry.tool);
+        let name = entry.tool.name.clone();
+        let Ok(mut callers) = self.callers.write() else {
+            return;
+        };
+        if let Some(existing) = callers.get(&name) {
+            entry.options = existing.options.clone();
+            entry.limiter = existing.limiter.clone();
+        }
+        if callers.insert(name.clone(), entry).is_some() {
+            self.interface_generator.invalidate(&name);
+            trace!(tool = name.as_str(), "tool caller overwritten");
+        }
+    }
+
+    pub fn configure_tool(
+        &self,
+        name: &str,
+        configure: impl FnOnce(&mut ToolOptions),
+    ) -> Result<(), ToolCallError> {
+        let mut callers = self
+            .callers
+            .write()
+            .map_err(|_| ToolCallError::Message("tool registry lock poisoned".to_string()))?;
+        let entry = callers
+            .get_mut(name)
+            .ok_or_else(|| ToolCallError::Message(format!("unknown tool '{name}'")))?;
+        let previous_max_concurrent = entry.options.max_concurrent;
+        configure(&mut entry.options);
+        if entry.options.max_concurrent != previous_max_concurrent {
+            entry.limiter = entry
+                .options
+                .max_concurrent
+                .map(|permits| Arc::new(Semaphore::new(permits.max(1))));
+        }
+        entry.revision = next_revision();
+        trace!(tool = name, "tool options updated");
+        Ok(())
+    }
+
+    pub fn set_result_transformer(
+        &self,
+        name: &str,
+        transformer: ResultTransformer,
+    ) -> Result<(), ToolCallError> {
+        self.configure_tool(name, |options| options.transformer = Some(transformer))
+    }
+
+    pub fn set_tool_max_concurrent(
+        &self,
+        name: &str,
+        max_concurrent: usize,
+    ) -> Result<(), ToolCallError> {
+        self.configure_tool(name, |options| {
+            options.max_concurrent = Some(max_concurrent)
+        })
+    }
+
+    /// Caches results of read-only tools (and tools with `ToolOptions::cache_ttl_ms`) in `cache`
+    /// across executions; `default_ttl` applies unless the tool sets its own.
+    pub fn set_result_cache(&self, cache: Arc<dyn ToolResultCache>, default_ttl: Duration) {
+        self.sandbox.set_result_cache(Some(ResultCache {
+            cache,
+            ttl: default_ttl,
+        }));
+    }
+
+    /// Scrubs tool arguments, results and emitted values with `redactor` before they are logged.
+    pub fn set_redactor(&self, redactor: Arc<dyn Redactor>) {
+        self.sandbox.set_redactor(Some(redactor));
+    }
+
+    /// Installs a plain Rust callback at `name` in every execution. It is not a tool: it has no
+    /// schema and does not appear in the generated interfaces.
+    pub fn register_global_fn(
+        &self,
+        name: impl Into<String>,
+        function: impl Fn(Vec<Value>) -> Result<Value, String> + Send + Sync + 'static,
+    ) {
+        self.sandbox.register_global_fn(name, Arc::new(function));
+    }
+
+    /// Runs `call_tool_chain`, its `_with_*` variants and `call_tool_chain_stream` on `backend`
+    /// instead of the built-in V8 sandbox. Planning and prepared sandboxes keep using V8.
+    pub fn set_backend(&self, backend: Arc<dyn ScriptBackend>) {
+        if let Ok(mut slot) = self.backend.write() {
+            *slot = Some(backend);
+        }
+    }
+
+    /// Ranks tools for [`select_tools`](Self::select_tools) and the `*_for_query` methods, which
+    /// then see only the `top_k` best.
+    pub fn set_tool_selector(&self, selector: Arc<dyn ToolSelector>, top_k: usize) {
+        if let Ok(mut slot) = self.tool_selector.write() {
+            *slot = Some((selector, top_k));
+        }
+    }
+
+    /// The registered tools relevant to `query`, best first; every tool when no selector is set.
+    pub fn select_tools(&self, query: &str) -> Vec<Tool> {
+        self.select_from(query, self.get_tools())
+    }
+
+    fn select_from(&self, query: &str, tools: Vec<Tool>) -> Vec<Tool> {
+        let selector = self
+            .tool_selector
+            .read()
+            .ok()
+            .and_then(|selector| selector.clone());
+        let Some((selector, top_k)) = selector else {
+            return tools;
+        };
+        let selected = selector
+            .select(query, &tools.iter().collect::<Vec<&Tool>>(), top_k)
+            .into_iter()
+            .filter_map(|name| tools.iter().find(|tool| tool.name == name).cloned())
+            .take(top_k)
+            .collect::<Vec<Tool>>();
+        debug!(
+            query = query,
+            selected = selected.len(),
+            "codemode select_tools"
+        );
+        selected
+    }
+
+    /// Interfaces of the tools [`select_tools`](Self::select_tools) picks for `query`.
+    pub fn get_tools_typescript_interfaces_for_query(&self, query: &str) -> String {
+        let mut tools = self.select_tools(query);
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        self.render_interfaces(tools.iter().collect())
+    }
+
+    /// Persists `codemode.memory` in `store` across executions.
+    pub fn set_memory_store(&self, store: Arc<dyn MemoryStore>) {
+        self.sandbox.set_memory_store(Some(store));
+    }
+
+    /// Rewrites every failed tool call's message with `handler(tool, message)` before the
+    /// script sees it.
+    pub fn set_tool_error_handler(
+        &self,
+        handler: impl Fn(&str, String) -> String + Send + Sync + 'static,
+    ) {
+        self.sandbox.set_tool_error_handler(Some(Arc::new(handler)));
+    }
+
+    /// Checks every tool call a script makes against `policy`; see [`RulePolicy`] for rules by
+    /// name, tag, prefix or MCP annotation. It applies to custom backends and `call_tool` too.
+    ///
+    /// [`RulePolicy`]: crate::policy::RulePolicy
+    pub fn set_tool_policy(&self, policy: Arc<dyn ToolPolicy>) {
+        self.sandbox.set_tool_policy(Some(policy));
+    }
+
+    /// Pauses each call the tool policy marks `RequireApproval` until `approver` answers. A call
+    /// with no answer within `timeout` is rejected; other calls in the script keep running.
+    pub fn set_tool_approver(&self, approver: Arc<dyn ToolApprover>, timeout: Duration) {
+        self.sandbox
+            .set_approval(Some(ApprovalGate { approver, timeout }));
+    }
+
+    /// Runs `interceptor` around every tool call, after any previously added ones.
+    pub fn add_interceptor(&self, interceptor: Arc<dyn ToolInterceptor>) {
+        self.sandbox.add_interceptor(interceptor);
+    }
+
+    pub fn set_tool_timeout(&self, name: &str, timeout_ms: u64) -> Result<(), ToolCallError> {
+        self.configure_tool(name, |options| options.timeout_ms = Some(timeout_ms))
+    }
+
+    /// Retries a failed call to `primary` against `fallback`, with arguments reshaped by
+    /// `arg_adapter`. Each substitution shows up in [`ExecutionResult::trace`].
+    pub fn register_fallback(
+        &self,
+        primary: &str,
+        fallback: &str,
+        arg_adapter: impl Fn(Value) -> Value + Send + Sync + 'static,
+    ) -> Result<(), ToolCallError> {
+        if !self
+            .callers
+            .read()
+            .is_ok_and(|callers| callers.contains_key(fallback))
+        {
+            return Err(ToolCallError::Message(format!("unknown tool '{fallback}'")));
+        }
+        let fallback = ToolFallback {
+            tool: fallback.to_string(),
+            arg_adapter: Arc::new(arg_adapter),
+        };
+        self.configure_tool(primary, |options| options.fallback = Some(fallback))
+    }
+
     pub fn tool_to_typescript_interface(&self, tool: &Tool) -> String {
         trace!(
             tool = tool.name.as_str(),
@@ -133,58 +497,644 @@ impl CodeModeClient {
         self.interface_generator.tool_to_typescript_interface(tool)
     }
 
-    pub fn get_all_tools_typescript_interfaces(&self) -> String {
+    /// A complete system prompt built from `template` with the current interfaces.
+    pub fn system_prompt(&self, template: &PromptTemplate) -> String {
+        template.render(
+            &self.get_all_tools_typescript_interfaces(),
+            self.tool_count(),
+        )
+    }
+
+    /// Writes the interfaces of every registered tool to one `.d.ts` file.
+    pub fn write_declaration_file(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let tools = self.get_tools();
+        self.interface_generator
+            .write_declaration_file(&tools.iter().collect::<Vec<&Tool>>(), path)
+    }
+
+    /// Zod validators for every registered tool's input, as a module importing `"zod"`; see
+    /// [`zod::zod_module`].
+    pub fn zod_module(&self) -> String {
+        let tools = self.get_tools();
+        zod::zod_module(
+            &tools.iter().collect::<Vec<&Tool>>(),
+            &self.interface_generator,
+        )
+    }
+
+    /// Markdown docs for every registered tool, for dashboards and prompt appendices.
+    pub fn tools_to_markdown(&self) -> String {
         let tools = self.get_tools();
+        markdown::tools_to_markdown(
+            &tools.iter().collect::<Vec<&Tool>>(),
+            &self.interface_generator,
+        )
+    }
+
+    /// Forces the next interface generation for `name` to rebuild from its metadata.
+    pub fn invalidate_interface(&self, name: &str) {
+        self.interface_generator.invalidate(name);
+    }
+
+    pub fn clear_interface_cache(&self) {
+        self.interface_generator.clear();
+    }
+
+    pub fn get_all_tools_typescript_interfaces(&self) -> String {
         trace!(
-            count = tools.len(),
+            count = self.tool_count(),
             "codemode get_all_tools_typescript_interfaces"
         );
-        let interfaces = tools
+        self.get_tools_typescript_interfaces_filtered(&ToolFilter::default())
+    }
+
+    /// Interfaces for the registered tools `filter` passes, e.g. only travel tools for one
+    /// prompt and finance tools for another.
+    pub fn get_tools_typescript_interfaces_filtered(&self, filter: &ToolFilter) -> String {
+        let tools = self.get_tools();
+        let tools = tools
             .iter()
-            .map(|tool| self.interface_generator.tool_to_typescript_interface(tool))
-            .collect::<Vec<String>>();
-        format!(
-            "// Auto-generated TypeScript interfaces for UTCP tools\n{}",
-            interfaces.join("\n\n")
-        )
+            .filter(|tool| filter.matches(tool))
+            .collect::<Vec<&Tool>>();
+        trace!(count = tools.len(), "codemode filtered interfaces");
+        self.render_interfaces(tools)
     }
 
-    pub async fn call_tool_chain(&self, code: &str) -> Result<ExecutionResult, SandboxError> {
+    fn render_interfaces(&self, tools: Vec<&Tool>) -> String {
+        if self.interface_generator.options().merge_namespaces {
+            return self.interface_generator.catalog_interfaces(&tools);
+        }
+        let interfaces = tools
+            .into_iter()
+            .map(|tool| {
+                let interface = self.interface_generator.tool_to_typescript_interface(tool);
+                (tool.name.clone(), interface)
+            })
+            .collect();
+        InterfaceSnapshot::new(interfaces).render()
+    }
+
+    /// Runs [`ts_interface::verify`] over the prompt interfaces and the declaration bundle, so
+    /// CI can assert a tool catalog still produces valid declarations.
+    pub fn verify_interfaces(&self) -> Result<(), Vec<VerifyError>> {
         let tools = self.get_tools();
+        let bundle = self
+            .interface_generator
+            .declaration_bundle(&tools.iter().collect::<Vec<&Tool>>());
+        let mut errors = ts_interface::verify(&self.get_all_tools_typescript_interfaces())
+            .err()
+            .unwrap_or_default();
+        errors.extend(ts_interface::verify(&bundle).err().unwrap_or_default());
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Freezes the current interfaces so prompts can keep using them while the registry changes.
+    pub fn snapshot_interfaces(&self) -> InterfaceSnapshot {
+        let interfaces = self
+            .get_tools()
+            .into_iter()
+            .map(|tool| {
+                let interface = self.interface_generator.tool_to_typescript_interface(&tool);
+                (tool.name, interface)
+            })
+            .collect();
+        InterfaceSnapshot::new(interfaces)
+    }
+
+    /// Like [`snapshot_interfaces`](Self::snapshot_interfaces), shrunk to fit roughly
+    /// `max_tokens` of prompt.
+    pub fn budgeted_interfaces(&self, max_tokens: usize) -> BudgetedInterfaces {
+        let tools = self.get_tools();
+        self.interface_generator
+            .budgeted_interfaces(&tools.iter().collect::<Vec<&Tool>>(), max_tokens)
+    }
+
+    /// Tools added, removed or changed in the live registry since `snapshot` was taken.
+    pub fn interface_drift(&self, snapshot: &InterfaceSnapshot) -> InterfaceDrift {
+        snapshot.drift(&self.snapshot_interfaces())
+    }
+
+    #[cfg(feature = "v8")]
+    /// Compiles `code` without running it, so bad code can be regenerated before any tool is
+    /// called.
+    pub fn validate_code(&self, code: &str) -> Result<(), ScriptDiagnostics> {
+        self.sandbox.validate(code)
+    }
+
+    pub async fn call_tool_chain(&self, code: &str) -> Result<ExecutionResult, SandboxError> {
+        self.call_tool_chain_with_cancel(code, CancellationToken::new())
+            .await
+    }
+
+    /// Runs `code` and deserializes its returned value into `T`; see
+    /// [`ExecutionResult::deserialize`].
+    pub async fn call_tool_chain_as<T: DeserializeOwned>(
+        &self,
+        code: &str,
+    ) -> Result<T, SandboxError> {
+        self.call_tool_chain(code).await?.deserialize()
+    }
+
+    /// Calls one registered tool from the host, without running a script. The call goes through
+    /// the same interceptors, policy, caches, fallback, timeout and telemetry as a call made by
+    /// a chain; a JS result transformer only applies inside scripts.
+    pub async fn call_tool(&self, name: &str, args: Value) -> Result<Value, ToolCallError> {
+        let callers = self.registry();
+        let tool = callers
+            .get(name)
+            .map(|entry| entry.tool.clone())
+            .ok_or_else(|| ToolCallError::Message(format!("unknown tool '{name}'")))?;
+        let args = match args {
+            Value::Null if tool.takes_no_input() => Value::Object(Map::new()),
+            Value::Object(_) => args,
+            _ => {
+                return Err(ToolCallError::Message(format!(
+                    "'{name}' expects an object of arguments"
+                )));
+            }
+        };
+        debug!(
+            tool = name,
+            args = %format_value(&args, self.sandbox.redactor().as_deref()),
+            "codemode call_tool"
+        );
+        let dispatch = self.sandbox.host_dispatch(&tool, &callers);
+        #[cfg(feature = "otel")]
+        {
+            crate::otel::traced_call(Some(opentelemetry::Context::current()), dispatch, args).await
+        }
+        #[cfg(not(feature = "otel"))]
+        {
+            dispatch.call(args).await
+        }
+    }
+
+    /// Runs `code` until it finishes or `cancel` fires. Cancelling terminates the script and
+    /// drops in-flight tool futures; a call still waiting for an execution slot gives up.
+    pub async fn call_tool_chain_with_cancel(
+        &self,
+        code: &str,
+        cancel: CancellationToken,
+    ) -> Result<ExecutionResult, SandboxError> {
+        let callers = self.registry();
+        self.run_chain(code, registered_tools(&callers), callers, None, cancel)
+            .await
+    }
+
+    /// Runs `code` with `input` exposed as a frozen `input` global, so host data never has to be
+    /// spliced into the source.
+    pub async fn call_tool_chain_with_input(
+        &self,
+        code: &str,
+        input: Value,
+    ) -> Result<ExecutionResult, SandboxError> {
+        let callers = self.registry();
+        self.run_chain(
+            code,
+            registered_tools(&callers),
+            callers,
+            Some(input),
+            CancellationToken::new(),
+        )
+        .await
+    }
+
+    /// Runs `code` with only the tools [`select_tools`](Self::select_tools) picks for `query`
+    /// injected; pair it with [`get_tools_typescript_interfaces_for_query`] for the prompt.
+    ///
+    /// [`get_tools_typescript_interfaces_for_query`]: Self::get_tools_typescript_interfaces_for_query
+    pub async fn call_tool_chain_for_query(
+        &self,
+        query: &str,
+        code: &str,
+    ) -> Result<ExecutionResult, SandboxError> {
+        let callers = self.registry();
+        let mut tools = self.select_from(query, registered_tools(&callers));
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        self.run_chain(code, tools, callers, None, CancellationToken::new())
+            .await
+    }
+
+    /// Runs `code` with only the tools named in `tools` injected, for least-privilege
+    /// executions. Fails before running when a name is not registered or `code` calls a
+    /// registered tool outside the list.
+    pub async fn call_tool_chain_with_tools(
+        &self,
+        code: &str,
+        tools: &[&str],
+    ) -> Result<ExecutionResult, SandboxError> {
+        let mut callers = self.registry();
+        if let Some(unknown) = tools.iter().find(|name| !callers.contains_key(**name)) {
+            return Err(SandboxError::Tool(format!("unknown tool '{unknown}'")));
+        }
+        let excluded = callers
+            .values()
+            .filter(|entry| !tools.contains(&entry.tool.name.as_str()))
+            .find(|entry| {
+                calls_tool(
+                    code,
+                    &self.interface_generator.tool_access_path(&entry.tool),
+                )
+            });
+        if let Some(entry) = excluded {
+            return Err(SandboxError::ToolNotAllowed(entry.tool.name.clone()));
+        }
+        callers.retain(|name, _| tools.contains(&name.as_str()));
+        self.run_chain(
+            code,
+            registered_tools(&callers),
+            callers,
+            None,
+            CancellationToken::new(),
+        )
+        .await
+    }
+
+    async fn run_chain(
+        &self,
+        code: &str,
+        tools: Vec<Tool>,
+        callers: BTreeMap<String, ToolCallerEntry>,
+        input: Option<Value>,
+        cancel: CancellationToken,
+    ) -> Result<ExecutionResult, SandboxError> {
         debug!(
             code = code,
             tool_count = tools.len(),
             "codemode call_tool_chain"
         );
-        let sandbox = &self.sandbox;
-        let interface_generator = &self.interface_generator;
+        let extras = ExecutionExtras {
+            input,
+            ..Default::default()
+        };
+        self.chain_runner()
+            .run(code.to_string(), tools, callers, extras, cancel)
+            .await
+    }
+
+    fn chain_runner(&self) -> ChainRunner {
+        ChainRunner {
+            sandbox: self.sandbox.clone(),
+            backend: self.backend.read().ok().and_then(|backend| backend.clone()),
+            interface_generator: self.interface_generator.clone(),
+            execution_limiter: self.execution_limiter.clone(),
+            execution_queue_timeout: self.execution_queue_timeout,
+        }
+    }
+
+    #[cfg(feature = "v8")]
+    /// Runs `code` without calling any tool and returns the calls it would make in
+    /// [`ExecutionResult::plan`]. Tools answer with values simulated from their output
+    /// schemas, so branches that depend on real results may differ from an actual run.
+    pub async fn plan_tool_chain(&self, code: &str) -> Result<ExecutionResult, SandboxError> {
+        let callers = self.registry();
+        let tools = registered_tools(&callers);
+        debug!(
+            code = code,
+            tool_count = tools.len(),
+            "codemode plan_tool_chain"
+        );
+        let sandbox = self.sandbox.clone();
         let code = code.to_string();
-        let result = tokio::task::block_in_place(|| {
-            sandbox.execute(&code, &tools, interface_generator, &self.callers)
-        })?;
+        let interface_generator = self.interface_generator.clone();
+        run_on_sandbox_thread(move || {
+            let tools = tools.iter().collect::<Vec<&Tool>>();
+            sandbox
+                .prepare_tools(&tools, &interface_generator, &callers)?
+                .plan(&code)
+        })
+        .await
+    }
+
+    /// Runs `code` as the stream is polled, yielding each `codemode.emit(value)` as it happens
+    /// and then the final result. Runs on the same backend, pool and threads as
+    /// [`call_tool_chain`](Self::call_tool_chain); a custom backend yields only the result.
+    /// Dropping the stream cancels the execution.
+    pub fn call_tool_chain_stream(
+        &self,
+        code: &str,
+    ) -> impl Stream<Item = ChainEvent> + Send + 'static {
+        let (events, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let callers = self.registry();
+        let tools = registered_tools(&callers);
         debug!(
-            result = %format_value(&result.result),
+            code = code,
+            tool_count = tools.len(),
+            "codemode call_tool_chain_stream"
+        );
+        let cancel = CancellationToken::new();
+        let extras = ExecutionExtras {
+            emitter: Some(events.clone()),
+            ..Default::default()
+        };
+        let chain =
+            self.chain_runner()
+                .run(code.to_string(), tools, callers, extras, cancel.clone());
+        let chain: ChainFuture = Box::pin(async move {
+            let result = chain.await;
+            let _ = events.send(ChainEvent::Finished(result));
+        });
+        let state = ChainStream {
+            chain: Some(chain),
+            receiver,
+            _cancel_on_drop: cancel.drop_guard(),
+        };
+        futures::stream::unfold(Some(state), |state| async move {
+            let mut state = state?;
+            let event = state.next_event().await?;
+            let finished = matches!(event, ChainEvent::Finished(_));
+            Some((event, (!finished).then_some(state)))
+        })
+    }
+
+    #[cfg(feature = "v8")]
+    /// Injects the current tool set once for many executions; see [`PreparedSandbox`].
+    pub fn prepare_sandbox(&self) -> Result<PreparedSandbox, SandboxError> {
+        let callers = self
+            .callers
+            .read()
+            .map_err(|_| SandboxError::V8("tool registry lock poisoned".to_string()))?;
+        let tools = callers
+            .values()
+            .map(|entry| &entry.tool)
+            .collect::<Vec<&Tool>>();
+        trace!(tool_count = tools.len(), "codemode prepare_sandbox");
+        self.sandbox
+            .prepare_tools(&tools, &self.interface_generator, &callers)
+    }
+
+    #[cfg(feature = "v8")]
+    /// Boots future isolates from a startup snapshot that already contains the current tool
+    /// namespaces. Call again after the tool set changes.
+    pub fn warm_snapshot(&self) -> Result<(), SandboxError> {
+        let tools = self.get_tools();
+        let snapshot = self.sandbox.build_snapshot(
+            &tools.iter().collect::<Vec<&Tool>>(),
+            &self.interface_generator,
+        )?;
+        trace!(bytes = snapshot.size_bytes(), "codemode warm_snapshot");
+        self.sandbox.set_snapshot(Some(snapshot));
+        Ok(())
+    }
+}
+
+/// What a chain runs against, captured when it starts so it can outlive the borrow of the
+/// client.
+struct ChainRunner {
+    sandbox: Arc<Sandbox>,
+    backend: Option<Arc<dyn ScriptBackend>>,
+    interface_generator: Arc<ToolInterfaceGenerator>,
+    execution_limiter: Option<Arc<Semaphore>>,
+    execution_queue_timeout: Option<Duration>,
+}
+
+impl ChainRunner {
+    async fn run(
+        self,
+        code: String,
+        tools: Vec<Tool>,
+        callers: BTreeMap<String, ToolCallerEntry>,
+        extras: ExecutionExtras,
+        cancel: CancellationToken,
+    ) -> Result<ExecutionResult, SandboxError> {
+        let _permit = cancel
+            .run_until_cancelled(acquire_execution_permit(
+                self.execution_limiter.clone(),
+                self.execution_queue_timeout,
+            ))
+            .await
+            .ok_or(SandboxError::Cancelled)??;
+        #[cfg(feature = "v8")]
+        let pool = match self.backend {
+            Some(_) => None,
+            None => self.sandbox.pool()?,
+        };
+        #[cfg(feature = "otel")]
+        let otel_context = crate::otel::start_chain(tools.len());
+        let extras = ExecutionExtras {
+            #[cfg(feature = "otel")]
+            otel_context: Some(otel_context.clone()),
+            ..extras
+        };
+        let outcome = match self.backend {
+            Some(backend) => {
+                let sandbox = self.sandbox.clone();
+                let interface_generator = self.interface_generator;
+                run_on_sandbox_thread(move || {
+                    let tools = tools.iter().collect::<Vec<&Tool>>();
+                    let bindings = ToolBindings::new(
+                        &sandbox,
+                        &tools,
+                        &interface_generator,
+                        &callers,
+                        extras.input.as_ref(),
+                    );
+                    backend.execute(&code, &bindings, &cancel)
+                })
+                .await
+            }
+            #[cfg(feature = "v8")]
+            None => match pool {
+                Some(pool) => {
+                    pool.execute(
+                        &code,
+                        tools,
+                        self.interface_generator,
+                        callers,
+                        cancel,
+                        extras,
+                    )
+                    .await
+                }
+                None => {
+                    let sandbox = self.sandbox.clone();
+                    let interface_generator = self.interface_generator;
+                    run_on_sandbox_thread(move || {
+                        let tools = tools.iter().collect::<Vec<&Tool>>();
+                        sandbox.execute_with(
+                            &code,
+                            &tools,
+                            &interface_generator,
+                            &callers,
+                            &cancel,
+                            extras,
+                        )
+                    })
+                    .await
+                }
+            },
+            #[cfg(not(feature = "v8"))]
+            None => Err(SandboxError::Engine(
+                "no script backend: enable the `v8` feature or call set_backend".to_string(),
+            )),
+        };
+        #[cfg(feature = "otel")]
+        crate::otel::end_chain(&otel_context, &outcome);
+        let result = outcome?;
+        debug!(
+            result = %format_value(&result.result, self.sandbox.redactor().as_deref()),
             "codemode call_tool_chain result"
         );
         Ok(result)
     }
 }
 
+type ChainFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A chain driven by polling its event stream. `Finished` is sent through the same channel as
+/// the emitted values, so it always arrives after them.
+struct ChainStream {
+    chain: Option<ChainFuture>,
+    receiver: tokio::sync::mpsc::UnboundedReceiver<ChainEvent>,
+    _cancel_on_drop: DropGuard,
+}
+
+impl ChainStream {
+    async fn next_event(&mut self) -> Option<ChainEvent> {
+        loop {
+            let Some(chain) = self.chain.as_mut() else {
+                return self.receiver.recv().await;
+            };
+            match futures::future::select(chain.as_mut(), Box::pin(self.receiver.recv())).await {
+                Either::Left(_) => {}
+                Either::Right((event, _)) => return event,
+            }
+            self.chain = None;
+        }
+    }
+}
+
+/// Runs script work off the async workers and awaits the result, so the calling runtime (of
+/// either flavor) stays free to drive the tool futures the script waits on. Tokio's blocking
+/// pool keeps its threads between executions; outside Tokio each execution gets a thread of its
+/// own.
+#[cfg(not(target_family = "wasm"))]
+async fn run_on_sandbox_thread<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, SandboxError> + Send + 'static,
+) -> Result<T, SandboxError> {
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        return handle
+            .spawn_blocking(work)
+            .await
+            .map_err(|_| SandboxError::V8("sandbox thread panicked".to_string()))?;
+    }
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    std::thread::Builder::new()
+        .name("codemode-sandbox".to_string())
+        .spawn(move || {
+            let _ = sender.send(work());
+        })
+        .map_err(|err| SandboxError::V8(format!("sandbox thread: {err}")))?;
+    receiver
+        .await
+        .map_err(|_| SandboxError::V8("sandbox thread panicked".to_string()))?
+}
+
+/// wasm has no threads to move the work to, so it runs in place.
+#[cfg(target_family = "wasm")]
+async fn run_on_sandbox_thread<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, SandboxError> + Send + 'static,
+) -> Result<T, SandboxError> {
+    work()
+}
+
+async fn acquire_execution_permit(
+    limiter: Option<Arc<Semaphore>>,
+    queue_timeout: Option<Duration>,
+) -> Result<Option<OwnedSemaphorePermit>, SandboxError> {
+    let Some(limiter) = limiter else {
+        return Ok(None);
+    };
+    let permit = match queue_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, limiter.acquire_owned())
+            .await
+            .map_err(|_| SandboxError::QueueTimeout(timeout.as_millis() as u64))?,
+        None => limiter.acquire_owned().await,
+    };
+    permit
+        .map(Some)
+        .map_err(|_| SandboxError::V8("execution limiter closed".to_string()))
+}
+
 #[derive(Clone)]
 pub struct ToolCallerEntry {
     pub tool: Tool,
     pub raw_name: String,
     pub caller: CallerKind,
+    pub options: ToolOptions,
+    pub(crate) limiter: Option<Arc<Semaphore>>,
+    pub(crate) input_schema: Arc<LazySchema>,
+    pub(crate) output_schema: Arc<LazySchema>,
+    /// Changes whenever the entry does, so pool workers can tell a prepared context is stale.
+    pub(crate) revision: u64,
+}
+
+impl ToolCallerEntry {
+    /// An entry with default options; the tool's `is_async`/`is_streaming` follow `caller`.
+    pub(crate) fn new(mut tool: Tool, raw_name: String, caller: CallerKind) -> Self {
+        tool.is_async = matches!(caller, CallerKind::Async(_));
+        tool.is_streaming = matches!(caller, CallerKind::Streaming(_));
+        Self {
+            input_schema: Arc::new(LazySchema::new(tool.inputs.clone())),
+            output_schema: Arc::new(LazySchema::new(tool.outputs.clone())),
+            tool,
+            raw_name,
+            caller,
+            options: ToolOptions::default(),
+            limiter: None,
+            revision: next_revision(),
+        }
+    }
+}
+
+fn next_revision() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Clone, Default)]
+pub struct ToolOptions {
+    pub transformer: Option<ResultTransformer>,
+    /// Maximum simultaneous in-flight calls to this tool, across all executions.
+    pub max_concurrent: Option<usize>,
+    /// Overrides `SandboxConfig::tool_timeout_ms` for this tool.
+    pub timeout_ms: Option<u64>,
+    /// Caches this tool's results in the client's result cache for this long, even if the tool
+    /// is not marked read-only.
+    pub cache_ttl_ms: Option<u64>,
+    pub fallback: Option<ToolFallback>,
+}
+
+#[derive(Clone)]
+pub struct ToolFallback {
+    pub tool: String,
+    pub arg_adapter: Arc<ArgAdapterFn>,
 }
 
 #[derive(Clone)]
 pub enum CallerKind {
     Async(Arc<dyn AsyncToolCaller>),
     Sync(Arc<dyn SyncToolCaller>),
+    Streaming(Arc<dyn StreamingToolCaller>),
+}
+
+fn registered_tools(callers: &BTreeMap<String, ToolCallerEntry>) -> Vec<Tool> {
+    callers.values().map(|entry| entry.tool.clone()).collect()
 }
 
-fn format_value(value: &Value) -> String {
-    serde_json::to_string(value).unwrap_or_else(|_| "<unserializable>".to_string())
+// Whether `code` calls the tool at `path`. The path must not continue another identifier on
+// either side, so `github.get_issue` matches neither `github.get_issue_2(` nor `my_github...`.
+fn calls_tool(code: &str, path: &str) -> bool {
+    code.match_indices(path).any(|(start, _)| {
+        let before = code[..start].chars().next_back();
+        let after = code[start + path.len()..].trim_start();
+        !before.is_some_and(|ch| ch.is_alphanumeric() || matches!(ch, '_' | '$' | '.'))
+            && after.starts_with('(')
+    })
 }
 
 fn apply_prefix(prefix: &str, name: &str) -> String {
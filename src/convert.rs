@@ -0,0 +1,256 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{Map, Number, Value, json};
+
+// Deeper values are almost certainly cyclic; JSON.stringify would throw on them too.
+const MAX_DEPTH: usize = 256;
+
+// Largest magnitude a JS number holds without losing integer precision (2^53).
+const MAX_SAFE_INTEGER: u64 = 9_007_199_254_740_992;
+
+/// Converts a JS value to JSON the way `JSON.stringify` would, except that:
+/// - typed arrays, `DataView`s and `ArrayBuffer`s become `{ "type": "bytes", "base64": ... }`,
+/// - `BigInt`s become integers (or decimal strings past 64 bits),
+/// - `Date`s become RFC3339 strings (`null` when invalid),
+/// - `Map`s become objects keyed by the stringified key and `Set`s become arrays.
+pub(crate) fn v8_to_json(
+    scope: &mut v8::PinScope<'_, '_>,
+    value: v8::Local<v8::Value>,
+) -> Result<Value, String> {
+    to_json_at(scope, value, 0)
+}
+
+/// Converts JSON to a JS value; `{ "type": "bytes", "base64": ... }` objects become `Uint8Array`s
+/// and integers outside the safe range become `BigInt`s.
+pub(crate) fn json_to_v8<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    value: &Value,
+) -> Option<v8::Local<'a, v8::Value>> {
+    let value = match value {
+        Value::Null => v8::null(scope).into(),
+        Value::Bool(flag) => v8::Boolean::new(scope, *flag).into(),
+        Value::Number(number) => number_to_v8(scope, number)?,
+        Value::String(text) => v8::String::new(scope, text)?.into(),
+        Value::Array(items) => {
+            let elements = items
+                .iter()
+                .map(|item| json_to_v8(scope, item))
+                .collect::<Option<Vec<_>>>()?;
+            v8::Array::new_with_elements(scope, &elements).into()
+        }
+        Value::Object(map) => match decode_bytes(map) {
+            Some(bytes) => bytes_to_v8(scope, bytes)?,
+            None => {
+                let object = v8::Object::new(scope);
+                for (key, item) in map {
+                    let key = v8::String::new(scope, key)?;
+                    let item = json_to_v8(scope, item)?;
+                    object.set(scope, key.into(), item)?;
+                }
+                object.into()
+            }
+        },
+    };
+    Some(value)
+}
+
+fn bytes_envelope(bytes: &[u8]) -> Value {
+    json!({ "type": "bytes", "base64": STANDARD.encode(bytes) })
+}
+
+fn decode_bytes(map: &Map<String, Value>) -> Option<Vec<u8>> {
+    if map.len() != 2 || map.get("type")?.as_str()? != "bytes" {
+        return None;
+    }
+    STANDARD.decode(map.get("base64")?.as_str()?).ok()
+}
+
+pub(crate) fn bytes_to_v8<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    bytes: Vec<u8>,
+) -> Option<v8::Local<'a, v8::Value>> {
+    let length = bytes.len();
+    let buffer = array_buffer(scope, bytes);
+    Some(v8::Uint8Array::new(scope, buffer, 0, length)?.into())
+}
+
+pub(crate) fn array_buffer<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    bytes: Vec<u8>,
+) -> v8::Local<'a, v8::ArrayBuffer> {
+    let store = v8::ArrayBuffer::new_backing_store_from_vec(bytes).make_shared();
+    v8::ArrayBuffer::with_backing_store(scope, &store)
+}
+
+/// Copies the bytes out of a typed array, `DataView` or `ArrayBuffer`.
+pub(crate) fn binary_bytes(value: v8::Local<v8::Value>) -> Option<Vec<u8>> {
+    if let Ok(view) = v8::Local::<v8::ArrayBufferView>::try_from(value) {
+        let mut bytes = vec![0; view.byte_length()];
+        view.copy_contents(&mut bytes);
+        return Some(bytes);
+    }
+    let buffer = v8::Local::<v8::ArrayBuffer>::try_from(value).ok()?;
+    let store = buffer.get_backing_store();
+    Some(store.iter().map(|byte| byte.get()).collect())
+}
+
+fn to_json_at(
+    scope: &mut v8::PinScope<'_, '_>,
+    value: v8::Local<v8::Value>,
+    depth: usize,
+) -> Result<Value, String> {
+    if depth > MAX_DEPTH {
+        return Err("value is cyclic or nested too deeply".to_string());
+    }
+    if value.is_null_or_undefined() || value.is_function() || value.is_symbol() {
+        return Ok(Value::Null);
+    }
+    if value.is_boolean() {
+        return Ok(Value::Bool(value.boolean_value(scope)));
+    }
+    if value.is_number() {
+        return Ok(number_to_json(
+            value.number_value(scope).unwrap_or(f64::NAN),
+        ));
+    }
+    if value.is_string() {
+        return Ok(Value::String(value.to_rust_string_lossy(scope)));
+    }
+    if let Ok(bigint) = v8::Local::<v8::BigInt>::try_from(value) {
+        return Ok(bigint_to_json(scope, bigint));
+    }
+    if value.is_date() {
+        let object = value
+            .to_object(scope)
+            .ok_or_else(|| "date is not an object".to_string())?;
+        // toISOString throws on invalid dates; JSON.stringify writes null for those too.
+        return Ok(call_method(scope, object, "toISOString")
+            .filter(|iso| iso.is_string())
+            .map(|iso| Value::String(iso.to_rust_string_lossy(scope)))
+            .unwrap_or(Value::Null));
+    }
+    if let Some(bytes) = binary_bytes(value) {
+        return Ok(bytes_envelope(&bytes));
+    }
+    if let Ok(array) = v8::Local::<v8::Array>::try_from(value) {
+        let mut items = Vec::with_capacity(array.length() as usize);
+        for index in 0..array.length() {
+            let item = array
+                .get_index(scope, index)
+                .unwrap_or_else(|| v8::undefined(scope).into());
+            items.push(to_json_at(scope, item, depth + 1)?);
+        }
+        return Ok(Value::Array(items));
+    }
+    if let Ok(map) = v8::Local::<v8::Map>::try_from(value) {
+        let entries = map.as_array(scope);
+        let mut object = Map::new();
+        for index in (0..entries.length()).step_by(2) {
+            let (Some(key), Some(item)) = (
+                entries.get_index(scope, index),
+                entries.get_index(scope, index + 1),
+            ) else {
+                continue;
+            };
+            let key = key.to_rust_string_lossy(scope);
+            object.insert(key, to_json_at(scope, item, depth + 1)?);
+        }
+        return Ok(Value::Object(object));
+    }
+    if let Ok(set) = v8::Local::<v8::Set>::try_from(value) {
+        let entries = set.as_array(scope);
+        let mut items = Vec::with_capacity(entries.length() as usize);
+        for index in 0..entries.length() {
+            if let Some(item) = entries.get_index(scope, index) {
+                items.push(to_json_at(scope, item, depth + 1)?);
+            }
+        }
+        return Ok(Value::Array(items));
+    }
+
+    let object = value
+        .to_object(scope)
+        .ok_or_else(|| "value is not an object".to_string())?;
+    if let Some(replacement) = call_to_json(scope, object) {
+        return to_json_at(scope, replacement, depth + 1);
+    }
+    let keys = object
+        .get_own_property_names(scope, Default::default())
+        .ok_or_else(|| "object keys".to_string())?;
+    let mut map = Map::new();
+    for index in 0..keys.length() {
+        let Some(key) = keys.get_index(scope, index) else {
+            continue;
+        };
+        let Some(item) = object.get(scope, key) else {
+            continue;
+        };
+        // JSON.stringify drops these from objects rather than writing null.
+        if item.is_undefined() || item.is_function() || item.is_symbol() {
+            continue;
+        }
+        let key = key.to_rust_string_lossy(scope);
+        map.insert(key, to_json_at(scope, item, depth + 1)?);
+    }
+    Ok(Value::Object(map))
+}
+
+// Honors `toJSON` like JSON.stringify does.
+fn call_to_json<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    object: v8::Local<'a, v8::Object>,
+) -> Option<v8::Local<'a, v8::Value>> {
+    call_method(scope, object, "toJSON")
+}
+
+fn call_method<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    object: v8::Local<'a, v8::Object>,
+    name: &str,
+) -> Option<v8::Local<'a, v8::Value>> {
+    let key = v8::String::new(scope, name)?;
+    let method = object.get(scope, key.into())?;
+    let method = v8::Local::<v8::Function>::try_from(method).ok()?;
+    method.call(scope, object.into(), &[])
+}
+
+fn bigint_to_json(scope: &mut v8::PinScope<'_, '_>, bigint: v8::Local<v8::BigInt>) -> Value {
+    if let (value, true) = bigint.i64_value() {
+        return Value::Number(Number::from(value));
+    }
+    if let (value, true) = bigint.u64_value() {
+        return Value::Number(Number::from(value));
+    }
+    // Wider than 64 bits: keep every digit rather than rounding through f64.
+    Value::String(bigint.to_rust_string_lossy(scope))
+}
+
+fn number_to_v8<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    number: &Number,
+) -> Option<v8::Local<'a, v8::Value>> {
+    if let Some(value) = number.as_i64()
+        && value.unsigned_abs() > MAX_SAFE_INTEGER
+    {
+        return Some(v8::BigInt::new_from_i64(scope, value).into());
+    }
+    if let Some(value) = number.as_u64()
+        && value > MAX_SAFE_INTEGER
+    {
+        return Some(v8::BigInt::new_from_u64(scope, value).into());
+    }
+    Some(v8::Number::new(scope, number.as_f64()?).into())
+}
+
+fn number_to_json(number: f64) -> Value {
+    if !number.is_finite() {
+        return Value::Null;
+    }
+    // Integral doubles stay integers so `3` does not come back as `3.0`.
+    if number.fract() == 0.0 && number.abs() < MAX_SAFE_INTEGER as f64 {
+        return Value::Number(Number::from(number as i64));
+    }
+    Number::from_f64(number)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
@@ -0,0 +1,342 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use deno_core::error::ModuleLoaderError;
+use deno_core::{
+    Extension, JsRuntime, ModuleLoadResponse, ModuleLoader, ModuleSource, ModuleSourceCode,
+    ModuleSpecifier, ModuleType, OpState, PollEventLoopOptions, RequestedModuleType,
+    ResolutionKind, RuntimeOptions, op2, v8,
+};
+use deno_error::JsErrorBox;
+use serde_json::{Value, json};
+use tokio_util::sync::CancellationToken;
+
+use crate::backend::{ScriptBackend, ToolBindings, script_error, script_outcome};
+use crate::dispatch::ToolDispatch;
+use crate::sandbox::{ExecutionResult, SandboxError, split_imports};
+
+/// How often the calling thread checks for cancellation and the deadline while a script runs.
+const WATCH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A [`ScriptBackend`] on `deno_core`, enabled with the `deno` feature. Scripts get deno_core's
+/// event loop, so `setTimeout` and `setInterval` work, tool calls run concurrently, and leading
+/// `import` statements load modules registered with [`with_module`](Self::with_module).
+///
+/// Tools are exposed at their access paths and `input` is a global, as in the V8 sandbox.
+/// The code runs as the body of an async function.
+///
+/// Each execution gets a fresh runtime on a thread of its own. A script that runs past its
+/// timeout or is cancelled is terminated, even inside a loop that never yields.
+pub struct DenoBackend {
+    runtime: tokio::runtime::Handle,
+    timeout_ms: u64,
+    modules: BTreeMap<String, String>,
+}
+
+impl DenoBackend {
+    /// Tool calls are spawned onto `runtime`, so tools keep running on the host's runtime
+    /// rather than the one driving the script.
+    pub fn new(runtime: tokio::runtime::Handle) -> Self {
+        Self {
+            runtime,
+            timeout_ms: 30_000,
+            modules: BTreeMap::new(),
+        }
+    }
+
+    /// Terminates the execution with `Timeout` once it has run this long. Defaults to 30
+    /// seconds.
+    pub fn with_timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Makes `source` (an ES module) importable as `name`, like
+    /// [`SandboxConfig::register_module`](crate::sandbox::SandboxConfig::register_module).
+    pub fn with_module(mut self, name: impl Into<String>, source: impl Into<String>) -> Self {
+        self.modules.insert(name.into(), source.into());
+        self
+    }
+}
+
+impl ScriptBackend for DenoBackend {
+    fn execute(
+        &self,
+        code: &str,
+        bindings: &ToolBindings<'_>,
+        cancel: &CancellationToken,
+    ) -> Result<ExecutionResult, SandboxError> {
+        if cancel.is_cancelled() {
+            return Err(SandboxError::Cancelled);
+        }
+        let deadline = Instant::now() + Duration::from_millis(self.timeout_ms);
+        let stop = cancel.child_token();
+        let job = Job {
+            glue: glue(bindings),
+            main: main_module(code),
+            modules: self.modules.clone(),
+            tools: ToolState {
+                dispatches: bindings.dispatches(),
+                runtime: self.runtime.clone(),
+                stop: stop.clone(),
+            },
+        };
+
+        let (isolate_sender, isolate) = mpsc::sync_channel(1);
+        let (done, result) = mpsc::sync_channel(1);
+        std::thread::Builder::new()
+            .name("codemode-deno".to_string())
+            .spawn(move || {
+                let _ = done.send(job.run(isolate_sender));
+            })
+            .map_err(|err| SandboxError::Engine(format!("deno thread: {err}")))?;
+        let Ok(isolate) = isolate.recv() else {
+            // The runtime never started; the thread has reported why.
+            return result
+                .recv()
+                .unwrap_or_else(|_| Err(SandboxError::Engine("deno thread panicked".to_string())));
+        };
+
+        loop {
+            match result.recv_timeout(WATCH_INTERVAL) {
+                Ok(result) => return result,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(SandboxError::Engine("deno thread panicked".to_string()));
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            let stopped = if cancel.is_cancelled() {
+                SandboxError::Cancelled
+            } else if Instant::now() >= deadline {
+                SandboxError::Timeout(self.timeout_ms)
+            } else {
+                continue;
+            };
+            stop.cancel();
+            isolate.terminate_execution();
+            return Err(stopped);
+        }
+    }
+}
+
+/// One execution, moved onto the thread that owns its `JsRuntime`.
+struct Job {
+    glue: String,
+    main: String,
+    modules: BTreeMap<String, String>,
+    tools: ToolState,
+}
+
+impl Job {
+    fn run(
+        self,
+        isolate: mpsc::SyncSender<v8::IsolateHandle>,
+    ) -> Result<ExecutionResult, SandboxError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|err| SandboxError::Engine(format!("deno runtime: {err}")))?;
+        let _entered = runtime.enter();
+        let stop = self.tools.stop.clone();
+        let mut js = JsRuntime::new(RuntimeOptions {
+            module_loader: Some(Rc::new(RegisteredModules(self.modules))),
+            extensions: vec![Extension {
+                name: "codemode",
+                ops: std::borrow::Cow::Borrowed(&[
+                    op_codemode_call::DECL,
+                    op_codemode_sleep::DECL,
+                    op_codemode_finish::DECL,
+                ]),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        let _ = isolate.send(js.v8_isolate().thread_safe_handle());
+        js.op_state().borrow_mut().put(self.tools);
+        js.execute_script("codemode:glue", self.glue)
+            .map_err(|err| SandboxError::Engine(format!("deno globals: {err}")))?;
+
+        let main = ModuleSpecifier::parse("codemode:main")
+            .map_err(|err| SandboxError::Engine(format!("deno main module: {err}")))?;
+        let evaluated = runtime.block_on(stop.run_until_cancelled(async {
+            let id = js.load_main_es_module_from_code(&main, self.main).await?;
+            let evaluation = js.mod_evaluate(id);
+            js.run_event_loop(PollEventLoopOptions::default()).await?;
+            evaluation.await
+        }));
+        // A stopped run is reported by the calling thread, which terminated it.
+        let Some(evaluated) = evaluated else {
+            return Err(SandboxError::Cancelled);
+        };
+        evaluated.map_err(|err| script_error(err.to_string(), None))?;
+
+        let outcome = js.op_state().borrow_mut().try_take::<Outcome>();
+        let outcome = outcome.map(|Outcome(outcome)| outcome);
+        script_outcome(outcome.as_deref().unwrap_or("null"))?.ok_or_else(|| {
+            SandboxError::Engine("deno: the script finished without settling".to_string())
+        })
+    }
+}
+
+/// What the ops need, kept in the runtime's `OpState`.
+struct ToolState {
+    dispatches: BTreeMap<String, ToolDispatch>,
+    runtime: tokio::runtime::Handle,
+    stop: CancellationToken,
+}
+
+/// The outcome JSON recorded by `op_codemode_finish`.
+struct Outcome(String);
+
+/// Runs a tool call on the host's runtime and resolves with its result as JSON, or rejects
+/// with the tool's error.
+#[op2(async)]
+#[string]
+async fn op_codemode_call(
+    state: Rc<RefCell<OpState>>,
+    #[string] name: String,
+    #[string] args: String,
+) -> Result<String, JsErrorBox> {
+    let (dispatch, runtime, stop) = {
+        let state = state.borrow();
+        let tools = state.borrow::<ToolState>();
+        let dispatch = tools
+            .dispatches
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| JsErrorBox::generic(format!("unknown tool '{name}'")))?;
+        (dispatch, tools.runtime.clone(), tools.stop.clone())
+    };
+    let args: Value =
+        serde_json::from_str(&args).map_err(|err| JsErrorBox::generic(err.to_string()))?;
+    let call = runtime.spawn(async move { stop.run_until_cancelled(dispatch.call(args)).await });
+    match call.await {
+        Ok(Some(Ok(result))) => Ok(result.to_string()),
+        Ok(Some(Err(err))) => Err(JsErrorBox::generic(err.to_string())),
+        Ok(None) => Err(JsErrorBox::generic("execution cancelled")),
+        Err(_) => Err(JsErrorBox::generic("tool call panicked")),
+    }
+}
+
+/// Resolves after `ms` milliseconds; the timers in the glue code are built on it.
+#[op2(async)]
+async fn op_codemode_sleep(ms: u32) {
+    tokio::time::sleep(Duration::from_millis(ms.into())).await;
+}
+
+#[op2]
+fn op_codemode_finish(state: &mut OpState, #[string] outcome: String) {
+    state.put(Outcome(outcome));
+}
+
+/// Resolves modules registered on the backend by name and refuses everything else, so scripts
+/// cannot reach the filesystem or network through `import`.
+struct RegisteredModules(BTreeMap<String, String>);
+
+impl ModuleLoader for RegisteredModules {
+    fn resolve(
+        &self,
+        specifier: &str,
+        _referrer: &str,
+        _kind: ResolutionKind,
+    ) -> Result<ModuleSpecifier, ModuleLoaderError> {
+        if !self.0.contains_key(specifier) {
+            return Err(JsErrorBox::generic(format!("unknown module '{specifier}'")));
+        }
+        ModuleSpecifier::parse(&format!("codemode:module/{specifier}"))
+            .map_err(|err| JsErrorBox::generic(format!("module '{specifier}': {err}")))
+    }
+
+    fn load(
+        &self,
+        specifier: &ModuleSpecifier,
+        _referrer: Option<&ModuleSpecifier>,
+        _is_dyn_import: bool,
+        _requested_module_type: RequestedModuleType,
+    ) -> ModuleLoadResponse {
+        let source = specifier
+            .as_str()
+            .strip_prefix("codemode:module/")
+            .and_then(|name| self.0.get(name));
+        ModuleLoadResponse::Sync(match source {
+            Some(source) => Ok(ModuleSource::new(
+                ModuleType::JavaScript,
+                ModuleSourceCode::String(source.clone().into()),
+                specifier,
+                None,
+            )),
+            None => Err(JsErrorBox::generic(format!("unknown module '{specifier}'"))),
+        })
+    }
+}
+
+/// Defines the tool functions, timers and `input` before the script runs.
+fn glue(bindings: &ToolBindings<'_>) -> String {
+    let mut glue = String::from(
+        "(() => {
+           const ops = Deno.core.ops;
+           const define = (path, name) => {
+             const parts = path.split('.');
+             let target = globalThis;
+             for (const part of parts.slice(0, -1)) target = target[part] ??= {};
+             target[parts[parts.length - 1]] = async (args = {}) =>
+               JSON.parse(await ops.op_codemode_call(name, JSON.stringify(args)));
+           };
+           const timers = new Map();
+           let nextTimer = 0;
+           const schedule = (id, callback, ms, args, repeat) => {
+             ops.op_codemode_sleep(Math.max(0, ms | 0)).then(() => {
+               if (!timers.has(id)) return;
+               if (repeat) schedule(id, callback, ms, args, repeat);
+               else timers.delete(id);
+               callback(...args);
+             });
+           };
+           const start = (repeat) => (callback, ms = 0, ...args) => {
+             const id = ++nextTimer;
+             timers.set(id, true);
+             schedule(id, callback, ms, args, repeat);
+             return id;
+           };
+           globalThis.setTimeout = start(false);
+           globalThis.setInterval = start(true);
+           globalThis.clearTimeout = globalThis.clearInterval = (id) => { timers.delete(id); };
+           globalThis.__codemode_finish = (outcome) =>
+             ops.op_codemode_finish(JSON.stringify(outcome));
+         ",
+    );
+    for tool in bindings.tools {
+        glue.push_str(&format!(
+            "define({}, {});\n",
+            json!(bindings.access_path(tool)),
+            json!(tool.name),
+        ));
+    }
+    if let Some(input) = bindings.input {
+        glue.push_str(&format!(
+            "Object.defineProperty(globalThis, 'input', {{ value: Object.freeze({input}) }});\n"
+        ));
+    }
+    glue.push_str("})();\n");
+    glue
+}
+
+/// The main module: leading imports, then `code` as the body of an async function whose
+/// outcome is recorded as JSON, so no engine values cross into the host.
+fn main_module(code: &str) -> String {
+    let (imports, body) = split_imports(code).unwrap_or(("", code));
+    format!(
+        "{imports}await (async () => {{\n{body}\n}})().then(
+           value => __codemode_finish(value === undefined
+             ? {{ undefined: true }}
+             : {{ value: JSON.stringify(value) ?? 'null' }}),
+           error => __codemode_finish({{
+             error: String(error?.message ?? error),
+             stack: typeof error?.stack === 'string' ? error.stack : null,
+           }}),
+         );"
+    )
+}
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::{StreamExt, TryStreamExt};
+use serde_json::{Value, json};
+use tokio::sync::Semaphore;
+use tracing::{trace, warn};
+
+use crate::blob::BlobOffload;
+use crate::cache::{CacheKey, ToolResultCache};
+use crate::client::CallerKind;
+use crate::policy::BoundPolicy;
+use crate::sandbox::{CacheStats, DeprecatedToolPolicy, TraceEvent};
+use crate::tool::{
+    ArgAdapterFn, ResultTransformFn, ToolCallError, ToolErrorFn, ToolInterceptor, ValueStream,
+};
+use crate::validate::SchemaCheck;
+
+pub(crate) type TraceLog = Arc<Mutex<Vec<TraceEvent>>>;
+
+/// Everything needed to run one tool call off the V8 thread.
+#[derive(Clone)]
+pub(crate) struct ToolDispatch {
+    pub(crate) tool_name: String,
+    pub(crate) raw_name: String,
+    pub(crate) caller: Option<CallerKind>,
+    pub(crate) transformer: Option<Arc<ResultTransformFn>>,
+    pub(crate) blob_offload: Option<BlobOffload>,
+    pub(crate) limiter: Option<Arc<Semaphore>>,
+    /// Shared by every tool in the sandbox to throttle fan-out such as `Promise.all`.
+    pub(crate) call_limiter: Option<Arc<Semaphore>>,
+    /// Applies to each attempt separately, so a timed-out primary still reaches its fallback.
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) fallback: Option<FallbackTarget>,
+    pub(crate) trace: TraceLog,
+    pub(crate) interceptors: Arc<[Arc<dyn ToolInterceptor>]>,
+    pub(crate) cache: Option<Arc<CallCache>>,
+    pub(crate) result_cache: Option<ResultCache>,
+    pub(crate) error_handler: Option<Arc<ToolErrorFn>>,
+    /// Failures resolve to an `{ ok: false, error }` value instead of an error.
+    pub(crate) continue_on_error: bool,
+    pub(crate) input_check: Option<SchemaCheck>,
+    /// Checks the primary's result only; a mismatch counts as a failure and reaches the fallback.
+    pub(crate) output_check: Option<SchemaCheck>,
+    /// `Allow` unless the tool is deprecated.
+    pub(crate) deprecation: DeprecatedToolPolicy,
+    pub(crate) policy: Option<BoundPolicy>,
+}
+
+/// The client's cross-execution cache, with the TTL that applies to this tool.
+#[derive(Clone)]
+pub(crate) struct ResultCache {
+    pub(crate) cache: Arc<dyn ToolResultCache>,
+    pub(crate) ttl: Duration,
+}
+
+/// Successful results of one execution keyed by tool and arguments.
+#[derive(Default)]
+pub(crate) struct CallCache {
+    entries: Mutex<HashMap<CacheKey, Value>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CallCache {
+    fn get(&self, key: &CacheKey) -> Option<Value> {
+        let cached = self.entries.lock().ok()?.get(key).cloned();
+        let counter = if cached.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    fn insert(&self, key: CacheKey, value: Value) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(key, value);
+        }
+    }
+
+    /// Empties the cache for the next execution and returns this one's counts.
+    #[cfg_attr(not(feature = "v8"), allow(dead_code))]
+    pub(crate) fn reset(&self) -> CacheStats {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+        CacheStats {
+            hits: self.hits.swap(0, Ordering::Relaxed),
+            misses: self.misses.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct FallbackTarget {
+    pub(crate) tool_name: String,
+    pub(crate) raw_name: String,
+    pub(crate) caller: CallerKind,
+    pub(crate) arg_adapter: Arc<ArgAdapterFn>,
+    /// Judged against the fallback tool itself, so a denied tool is not reachable as a fallback.
+    pub(crate) policy: Option<BoundPolicy>,
+    /// The fallback's own schemas, checked against the adapted arguments and its result.
+    pub(crate) input_check: Option<SchemaCheck>,
+    pub(crate) output_check: Option<SchemaCheck>,
+}
+
+impl ToolDispatch {
+    pub(crate) async fn call(&self, args: Value) -> Result<Value, ToolCallError> {
+        let checked = match self
+            .check_deprecation()
+            .and_then(|()| self.check_inputs(&args))
+        {
+            Ok(()) => self.check_policy(&args).await,
+            Err(err) => Err(err),
+        };
+        let result = match checked {
+            Ok(()) => self.call_cached(args).await,
+            Err(err) => Err(err),
+        };
+        match result.map_err(|err| self.translate_error(err)) {
+            Err(ToolCallError::Message(message)) if self.continue_on_error => Ok(json!({
+                "ok": false,
+                "error": { "tool": self.tool_name, "message": message }
+            })),
+            result => result,
+        }
+    }
+
+    fn check_deprecation(&self) -> Result<(), ToolCallError> {
+        match self.deprecation {
+            DeprecatedToolPolicy::Allow => Ok(()),
+            DeprecatedToolPolicy::Warn => {
+                warn!(tool = self.tool_name.as_str(), "deprecated tool called");
+                Ok(())
+            }
+            DeprecatedToolPolicy::Reject => Err(ToolCallError::Message(format!(
+                "'{}' is deprecated",
+                self.tool_name
+            ))),
+        }
+    }
+
+    fn check_inputs(&self, args: &Value) -> Result<(), ToolCallError> {
+        check_inputs(self.input_check.as_ref(), &self.tool_name, args)
+    }
+
+    async fn check_policy(&self, args: &Value) -> Result<(), ToolCallError> {
+        match &self.policy {
+            Some(policy) => policy.check(args).await,
+            None => Ok(()),
+        }
+    }
+
+    fn translate_error(&self, err: ToolCallError) -> ToolCallError {
+        match (err, &self.error_handler) {
+            (ToolCallError::Message(message), Some(handler)) => {
+                ToolCallError::Message(handler(&self.tool_name, message))
+            }
+            (err, None) => err,
+        }
+    }
+
+    async fn call_cached(&self, args: Value) -> Result<Value, ToolCallError> {
+        // Keyed on the intercepted arguments, so interceptors that add a caller's identity or
+        // credentials keep their callers' entries apart.
+        let args = self.intercept_args(args);
+        if self.cache.is_none() && self.result_cache.is_none() {
+            return self.call_intercepted(args).await;
+        }
+        let key = CacheKey::new(&self.tool_name, &args);
+        if let Some(value) = self.cached(&key) {
+            trace!(tool = self.tool_name.as_str(), "tool result cache hit");
+            return Ok(value);
+        }
+        let value = self.call_intercepted(args).await?;
+        if let Some(result_cache) = &self.result_cache {
+            result_cache
+                .cache
+                .put(key.clone(), value.clone(), result_cache.ttl);
+        }
+        if let Some(cache) = &self.cache {
+            cache.insert(key, value.clone());
+        }
+        Ok(value)
+    }
+
+    fn cached(&self, key: &CacheKey) -> Option<Value> {
+        if let Some(value) = self.cache.as_ref().and_then(|cache| cache.get(key)) {
+            return Some(value);
+        }
+        self.result_cache.as_ref()?.cache.get(key)
+    }
+
+    async fn call_intercepted(&self, args: Value) -> Result<Value, ToolCallError> {
+        let _call_permit = match &self.call_limiter {
+            Some(limiter) => limiter.acquire().await.ok(),
+            None => None,
+        };
+        let _permit = match &self.limiter {
+            Some(limiter) => limiter.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        let result = self.invoke(args).await;
+        self.finish(result)
+    }
+
+    fn intercept_args(&self, args: Value) -> Value {
+        self.interceptors.iter().fold(args, |args, interceptor| {
+            interceptor.before(&self.tool_name, args)
+        })
+    }
+
+    /// Opens a streaming tool; every chunk goes through the same post-processing as a result.
+    #[cfg_attr(not(feature = "v8"), allow(dead_code))]
+    pub(crate) async fn open_stream(&self, args: Value) -> Result<ValueStream, ToolCallError> {
+        self.check_deprecation()?;
+        self.check_inputs(&args)?;
+        self.check_policy(&args).await?;
+        let Some(CallerKind::Streaming(caller)) = &self.caller else {
+            return Err(ToolCallError::Message(format!(
+                "'{}' is not a streaming tool",
+                self.tool_name
+            )));
+        };
+        // The permit is held until the stream is dropped.
+        let permit = match &self.limiter {
+            Some(limiter) => limiter.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        let args = self.intercept_args(args);
+        let stream = caller.call_tool_stream(&self.raw_name, args).await?;
+        let dispatch = self.clone();
+        Ok(Box::pin(stream.map(move |chunk| {
+            let _permit = &permit;
+            dispatch.finish(chunk)
+        })))
+    }
+
+    async fn invoke(&self, args: Value) -> Result<Value, ToolCallError> {
+        let Some(caller) = &self.caller else {
+            return Err(ToolCallError::Message(format!(
+                "no caller registered for '{}'",
+                self.tool_name
+            )));
+        };
+        let fallback_args = self.fallback.as_ref().map(|_| args.clone());
+        let result = self
+            .attempt(&self.tool_name, caller, &self.raw_name, args)
+            .await
+            .and_then(|value| check_outputs(self.output_check.as_ref(), &self.tool_name, value));
+        match (result, &self.fallback, fallback_args) {
+            (Err(err), Some(fallback), Some(args)) => {
+                trace!(
+                    tool = self.tool_name.as_str(),
+                    fallback = fallback.tool_name.as_str(),
+                    error = %err,
+                    "tool failed over to fallback"
+                );
+                self.record(TraceEvent::Fallback {
+                    tool: self.tool_name.clone(),
+                    fallback: fallback.tool_name.clone(),
+                    error: err.to_string(),
+                });
+                let args = (fallback.arg_adapter)(args);
+                check_inputs(fallback.input_check.as_ref(), &fallback.tool_name, &args)?;
+                if let Some(policy) = &fallback.policy {
+                    policy.check(&args).await?;
+                }
+                self.attempt(
+                    &fallback.tool_name,
+                    &fallback.caller,
+                    &fallback.raw_name,
+                    args,
+                )
+                .await
+                .and_then(|value| {
+                    check_outputs(fallback.output_check.as_ref(), &fallback.tool_name, value)
+                })
+            }
+            (result, _, _) => result,
+        }
+    }
+
+    async fn attempt(
+        &self,
+        tool_name: &str,
+        caller: &CallerKind,
+        raw_name: &str,
+        args: Value,
+    ) -> Result<Value, ToolCallError> {
+        let Some(timeout) = self.timeout else {
+            return call_caller(caller, raw_name, args).await;
+        };
+        // Sync callers run inside the poll and cannot be interrupted; only async work is bounded.
+        tokio::time::timeout(timeout, call_caller(caller, raw_name, args))
+            .await
+            .unwrap_or_else(|_| {
+                Err(ToolCallError::Message(format!(
+                    "'{tool_name}' timed out after {}ms",
+                    timeout.as_millis()
+                )))
+            })
+    }
+
+    fn finish(&self, result: Result<Value, ToolCallError>) -> Result<Value, ToolCallError> {
+        let result = self
+            .interceptors
+            .iter()
+            .fold(result, |result, interceptor| {
+                interceptor.after(&self.tool_name, result)
+            });
+        let value = result?;
+        let value = match &self.transformer {
+            Some(transform) => transform(value),
+            None => value,
+        };
+        match &self.blob_offload {
+            Some(offload) => offload
+                .apply(value)
+                .map_err(|err| ToolCallError::Message(err.to_string())),
+            None => Ok(value),
+        }
+    }
+
+    pub(crate) fn record(&self, event: TraceEvent) {
+        if let Ok(mut trace) = self.trace.lock() {
+            trace.push(event);
+        }
+    }
+}
+
+fn check_inputs(
+    check: Option<&SchemaCheck>,
+    tool_name: &str,
+    args: &Value,
+) -> Result<(), ToolCallError> {
+    match check {
+        Some(check) => check.check(
+            &format!("'{tool_name}' called with invalid arguments"),
+            args,
+        ),
+        None => Ok(()),
+    }
+}
+
+fn check_outputs(
+    check: Option<&SchemaCheck>,
+    tool_name: &str,
+    value: Value,
+) -> Result<Value, ToolCallError> {
+    if let Some(check) = check {
+        check.check(
+            &format!("'{tool_name}' returned a result that does not match its output schema"),
+            &value,
+        )?;
+    }
+    Ok(value)
+}
+
+pub(crate) async fn call_caller(
+    caller: &CallerKind,
+    raw_name: &str,
+    args: Value,
+) -> Result<Value, ToolCallError> {
+    match caller {
+        CallerKind::Async(caller) => caller.call_tool_async(raw_name, args).await,
+        CallerKind::Sync(caller) => caller.call_tool_sync(raw_name, args),
+        // Used as a plain call (e.g. as a fallback target), a stream collapses into an array.
+        CallerKind::Streaming(caller) => caller
+            .call_tool_stream(raw_name, args)
+            .await?
+            .try_collect::<Vec<Value>>()
+            .await
+            .map(Value::Array),
+    }
+}
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+use crate::sandbox::ExecutionResult;
+use crate::schema::JsonSchema;
+
+pub const ENVELOPE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionEnvelope {
+    pub version: u32,
+    /// Absent when the chain returned `undefined` (see `SandboxConfig::distinguish_undefined`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default)]
+    pub logs: Vec<String>,
+    #[serde(default)]
+    pub trace: Vec<Value>,
+    #[serde(default)]
+    pub metrics: Map<String, Value>,
+    #[serde(default)]
+    pub artifacts: Vec<Value>,
+    #[serde(default)]
+    pub emitted: Vec<Value>,
+}
+
+impl ExecutionEnvelope {
+    pub fn new(result: Option<Value>) -> Self {
+        Self {
+            version: ENVELOPE_VERSION,
+            result,
+            logs: Vec::new(),
+            trace: Vec::new(),
+            metrics: Map::new(),
+            artifacts: Vec::new(),
+            emitted: Vec::new(),
+        }
+    }
+
+    pub fn is_supported_version(&self) -> bool {
+        self.version <= ENVELOPE_VERSION
+    }
+
+    pub fn schema() -> JsonSchema {
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "$id": "https://github.com/dt665m/codemode-rs/schemas/execution-envelope.json",
+            "title": "ExecutionEnvelope",
+            "type": "object",
+            "properties": {
+                "version": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "Envelope format version"
+                },
+                "result": {
+                    "description": "Value returned by the chain; absent when it returned undefined"
+                },
+                "logs": { "type": "array", "items": { "type": "string" } },
+                "trace": { "type": "array", "items": { "type": "object" } },
+                "metrics": { "type": "object" },
+                "artifacts": { "type": "array" },
+                "emitted": { "type": "array" }
+            },
+            "required": ["version"]
+        })
+    }
+}
+
+impl From<ExecutionResult> for ExecutionEnvelope {
+    fn from(result: ExecutionResult) -> Self {
+        let mut envelope = Self::new((!result.undefined).then_some(result.result));
+        if let Some(interfaces) = result.interfaces {
+            envelope.trace.push(json!({
+                "type": "interfaces",
+                "hash": interfaces.hash,
+                "tools": interfaces.tools
+            }));
+        }
+        envelope.emitted = result.emitted;
+        envelope.logs = result.logs;
+        envelope.trace.extend(
+            result
+                .trace
+                .iter()
+                .filter_map(|event| serde_json::to_value(event).ok()),
+        );
+        envelope
+    }
+}
@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "fetch")]
+use serde_json::{Map, Value, json};
+
+/// Limits for the sandbox `fetch()` global, enabled with `SandboxCapabilities::fetch` and the
+/// `fetch` feature.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FetchConfig {
+    /// `host` or `host:port` entries; a bare host allows any port. Empty denies every request.
+    pub allowed_hosts: Vec<String>,
+    /// Responses with larger bodies reject instead of resolving.
+    pub max_response_bytes: usize,
+    pub timeout_ms: u64,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: Vec::new(),
+            max_response_bytes: 1024 * 1024,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+impl FetchConfig {
+    pub fn allows(&self, host: &str, port: u16) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.allowed_hosts.iter().any(|entry| {
+            let entry = entry.to_ascii_lowercase();
+            match entry.rsplit_once(':') {
+                Some((allowed, allowed_port)) if allowed_port.parse::<u16>().is_ok() => {
+                    allowed == host && allowed_port.parse::<u16>() == Ok(port)
+                }
+                _ => entry == host,
+            }
+        })
+    }
+}
+
+#[cfg(feature = "fetch")]
+pub(crate) struct FetchRequest {
+    pub(crate) url: String,
+    pub(crate) method: String,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Option<Vec<u8>>,
+}
+
+/// Client used for sandbox requests. Redirects are not followed, so a response can't bounce the
+/// script to a host outside the allowlist.
+#[cfg(feature = "fetch")]
+pub(crate) fn client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|err| format!("fetch client: {err}"))
+}
+
+/// Performs `request` within `config`'s limits, answering with the raw response the JS
+/// `Response` wrapper is built from.
+#[cfg(feature = "fetch")]
+pub(crate) async fn perform(
+    client: &reqwest::Client,
+    config: &FetchConfig,
+    request: FetchRequest,
+) -> Result<Value, String> {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+
+    let url = reqwest::Url::parse(&request.url).map_err(|err| format!("invalid url: {err}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported url scheme '{}'", url.scheme()));
+    }
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or_default();
+    if !config.allows(host, port) {
+        return Err(format!("fetch to {host}:{port} is not allowed"));
+    }
+    let method = reqwest::Method::from_bytes(request.method.to_ascii_uppercase().as_bytes())
+        .map_err(|_| format!("invalid method '{}'", request.method))?;
+    let mut builder = client
+        .request(method, url)
+        .timeout(std::time::Duration::from_millis(config.timeout_ms));
+    for (name, value) in request.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(body) = request.body {
+        builder = builder.body(body);
+    }
+    let mut response = builder.send().await.map_err(|err| err.to_string())?;
+
+    let status = response.status();
+    let url = response.url().to_string();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            (name.as_str().to_string(), Value::String(value))
+        })
+        .collect::<Map<String, Value>>();
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|err| err.to_string())? {
+        if body.len() + chunk.len() > config.max_response_bytes {
+            return Err(format!(
+                "response body exceeds {} bytes",
+                config.max_response_bytes
+            ));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(json!({
+        "status": status.as_u16(),
+        "statusText": status.canonical_reason().unwrap_or_default(),
+        "url": url,
+        "headers": headers,
+        "body": { "type": "bytes", "base64": STANDARD.encode(body) },
+    }))
+}
@@ -0,0 +1,297 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use sha2::{Digest, Sha256};
+
+use crate::convert::{array_buffer, binary_bytes, json_to_v8, v8_to_json};
+use crate::sandbox::{SandboxError, ensure_namespace, run_script, throw_error};
+
+// Same per-call quota as the Web Crypto spec.
+const MAX_RANDOM_BYTES: usize = 65_536;
+
+/// Installs `crypto.randomUUID`, `crypto.getRandomValues` and `crypto.subtle.digest`.
+pub(crate) fn inject_crypto<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+) -> Result<(), SandboxError> {
+    let crypto = ensure_namespace(scope, global, "crypto")?;
+    set_function(scope, crypto, "randomUUID", random_uuid_callback)?;
+    set_function(scope, crypto, "getRandomValues", get_random_values_callback)?;
+    let subtle = ensure_namespace(scope, crypto, "subtle")?;
+    set_function(scope, subtle, "digest", digest_callback)?;
+    Ok(())
+}
+
+/// Installs `structuredClone` (a copy through the JSON bridge) and `queueMicrotask`.
+pub(crate) fn inject_platform<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+) -> Result<(), SandboxError> {
+    set_function(scope, global, "structuredClone", structured_clone_callback)?;
+    set_function(scope, global, "queueMicrotask", queue_microtask_callback)?;
+    Ok(())
+}
+
+// UTF-8 only, like most runtimes' defaults; the natives do the actual transcoding.
+const ENCODING_CLASSES: &str = r#"(function(encode, decode) {
+  class TextEncoder {
+    get encoding() { return "utf-8"; }
+    encode(input = "") { return encode(String(input)); }
+  }
+  class TextDecoder {
+    constructor(label = "utf-8") {
+      if (!/^utf-?8$/i.test(String(label))) {
+        throw new RangeError(`unsupported encoding '${label}'`);
+      }
+    }
+    get encoding() { return "utf-8"; }
+    decode(input) { return input === undefined ? "" : decode(input); }
+  }
+  return { TextEncoder, TextDecoder };
+})"#;
+
+/// Installs `TextEncoder`, `TextDecoder` (UTF-8), `atob` and `btoa`.
+pub(crate) fn inject_encoding<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+) -> Result<(), SandboxError> {
+    set_function(scope, global, "atob", atob_callback)?;
+    set_function(scope, global, "btoa", btoa_callback)?;
+    let encode = v8::Function::builder(utf8_encode_callback)
+        .build(scope)
+        .ok_or_else(|| SandboxError::V8("utf-8 encode function".to_string()))?;
+    let decode = v8::Function::builder(utf8_decode_callback)
+        .build(scope)
+        .ok_or_else(|| SandboxError::V8("utf-8 decode function".to_string()))?;
+    let factory = v8::Local::<v8::Function>::try_from(run_script(scope, ENCODING_CLASSES)?)
+        .map_err(|_| SandboxError::V8("encoding classes".to_string()))?;
+    let receiver = v8::undefined(scope).into();
+    let classes = factory
+        .call(scope, receiver, &[encode.into(), decode.into()])
+        .and_then(|classes| classes.to_object(scope))
+        .ok_or_else(|| SandboxError::V8("encoding classes".to_string()))?;
+    for name in ["TextEncoder", "TextDecoder"] {
+        let key =
+            v8::String::new(scope, name).ok_or_else(|| SandboxError::V8(format!("{name} key")))?;
+        let class = classes
+            .get(scope, key.into())
+            .ok_or_else(|| SandboxError::V8(format!("{name} class")))?;
+        global.set(scope, key.into(), class);
+    }
+    Ok(())
+}
+
+fn set_function<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    target: v8::Local<'a, v8::Object>,
+    name: &str,
+    callback: impl v8::MapFnTo<v8::FunctionCallback>,
+) -> Result<(), SandboxError> {
+    let function = v8::Function::builder(callback)
+        .build(scope)
+        .ok_or_else(|| SandboxError::V8(format!("{name} function")))?;
+    let key =
+        v8::String::new(scope, name).ok_or_else(|| SandboxError::V8(format!("{name} key")))?;
+    target.set(scope, key.into(), function.into());
+    Ok(())
+}
+
+fn structured_clone_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let value = args.get(0);
+    if value.is_undefined() {
+        rv.set(value);
+        return;
+    }
+    let cloned = v8_to_json(scope, value)
+        .map_err(|err| format!("structuredClone: {err}"))
+        .and_then(|json| {
+            json_to_v8(scope, &json).ok_or_else(|| "structuredClone: value".to_string())
+        });
+    match cloned {
+        Ok(cloned) => rv.set(cloned),
+        Err(message) => throw_error(scope, &message),
+    }
+}
+
+// Runs at the next microtask checkpoint of the completion loop.
+fn queue_microtask_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    match v8::Local::<v8::Function>::try_from(args.get(0)) {
+        Ok(callback) => scope.enqueue_microtask(callback),
+        Err(_) => throw_error(scope, "queueMicrotask expects a function"),
+    }
+}
+
+fn utf8_encode_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let bytes = args.get(0).to_rust_string_lossy(scope).into_bytes();
+    let length = bytes.len();
+    let buffer = array_buffer(scope, bytes);
+    if let Some(array) = v8::Uint8Array::new(scope, buffer, 0, length) {
+        rv.set(array.into());
+    }
+}
+
+pub(crate) fn utf8_decode_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let Some(bytes) = binary_bytes(args.get(0)) else {
+        throw_error(
+            scope,
+            "TextDecoder.decode expects an ArrayBuffer or typed array",
+        );
+        return;
+    };
+    match v8::String::new(scope, &String::from_utf8_lossy(&bytes)) {
+        Some(text) => rv.set(text.into()),
+        None => throw_error(scope, "decoded text is too long"),
+    }
+}
+
+// `btoa` and `atob` work on "binary strings": one char per byte.
+fn btoa_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let text = args.get(0).to_rust_string_lossy(scope);
+    let Some(bytes) = text
+        .chars()
+        .map(|ch| u8::try_from(u32::from(ch)).ok())
+        .collect::<Option<Vec<u8>>>()
+    else {
+        throw_error(scope, "btoa: string contains characters outside of Latin1");
+        return;
+    };
+    if let Some(encoded) = v8::String::new(scope, &STANDARD.encode(bytes)) {
+        rv.set(encoded.into());
+    }
+}
+
+fn atob_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let text = args.get(0).to_rust_string_lossy(scope);
+    let compact = text
+        .chars()
+        .filter(|ch| !ch.is_ascii_whitespace())
+        .collect::<String>();
+    // atob accepts input with or without padding.
+    let unpadded = compact.trim_end_matches('=');
+    let padded = format!("{unpadded}{}", "=".repeat((4 - unpadded.len() % 4) % 4));
+    let Ok(bytes) = STANDARD.decode(padded) else {
+        throw_error(scope, "atob: invalid base64");
+        return;
+    };
+    let decoded = bytes.into_iter().map(char::from).collect::<String>();
+    if let Some(decoded) = v8::String::new(scope, &decoded) {
+        rv.set(decoded.into());
+    }
+}
+
+fn random_uuid_callback(
+    scope: &mut v8::PinScope,
+    _args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let uuid = uuid::Uuid::new_v4().to_string();
+    match v8::String::new(scope, &uuid) {
+        Some(uuid) => rv.set(uuid.into()),
+        None => throw_error(scope, "failed to allocate uuid"),
+    }
+}
+
+fn get_random_values_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let target = args.get(0);
+    let Ok(view) = v8::Local::<v8::ArrayBufferView>::try_from(target) else {
+        throw_error(scope, "getRandomValues expects an integer typed array");
+        return;
+    };
+    if target.is_float32_array() || target.is_float64_array() || target.is_data_view() {
+        throw_error(scope, "getRandomValues expects an integer typed array");
+        return;
+    }
+    let length = view.byte_length();
+    if length > MAX_RANDOM_BYTES {
+        throw_error(
+            scope,
+            &format!("getRandomValues is limited to {MAX_RANDOM_BYTES} bytes"),
+        );
+        return;
+    }
+    let mut bytes = vec![0; length];
+    if let Err(err) = getrandom::fill(&mut bytes) {
+        throw_error(scope, &format!("random source failed: {err}"));
+        return;
+    }
+    let Some(buffer) = view.buffer(scope) else {
+        throw_error(scope, "getRandomValues target has no buffer");
+        return;
+    };
+    let store = buffer.get_backing_store();
+    let offset = view.byte_offset();
+    for (cell, byte) in store[offset..offset + length].iter().zip(bytes) {
+        cell.set(byte);
+    }
+    rv.set(target);
+}
+
+fn digest_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let Some(resolver) = v8::PromiseResolver::new(scope) else {
+        return;
+    };
+    rv.set(resolver.get_promise(scope).into());
+
+    let algorithm = algorithm_name(scope, args.get(0));
+    let digest = match (algorithm.as_str(), binary_bytes(args.get(1))) {
+        ("SHA-256", Some(data)) => Ok(Sha256::digest(data).to_vec()),
+        ("SHA-256", None) => Err("digest expects an ArrayBuffer or typed array".to_string()),
+        _ => Err(format!("unsupported digest algorithm '{algorithm}'")),
+    };
+    match digest {
+        Ok(digest) => {
+            let buffer = array_buffer(scope, digest);
+            resolver.resolve(scope, buffer.into());
+        }
+        Err(message) => {
+            let Some(message) = v8::String::new(scope, &message) else {
+                return;
+            };
+            let exception = v8::Exception::error(scope, message);
+            resolver.reject(scope, exception);
+        }
+    }
+}
+
+// Accepts both `"SHA-256"` and `{ name: "SHA-256" }`, case-insensitively.
+fn algorithm_name(scope: &mut v8::PinScope<'_, '_>, value: v8::Local<v8::Value>) -> String {
+    let name = match value.to_object(scope) {
+        Some(object) if !value.is_string() => v8::String::new(scope, "name")
+            .and_then(|key| object.get(scope, key.into()))
+            .map(|name| name.to_rust_string_lossy(scope))
+            .unwrap_or_default(),
+        _ => value.to_rust_string_lossy(scope),
+    };
+    name.to_ascii_uppercase()
+}
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+/// Chrome DevTools access to executions, enabled with `SandboxConfig::inspector` and the
+/// `inspector` feature. Each prepared context is listed at `http://{addr}/json/list`, so
+/// `chrome://inspect` can attach to it, pause on breakpoints in `codemode.js` and inspect
+/// pending tool-call promises.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InspectorConfig {
+    /// Address the DevTools endpoint listens on. Keep it on loopback: an attached debugger can
+    /// run any code in the sandbox.
+    pub addr: String,
+    /// Hold each execution before its first statement until DevTools attaches, so breakpoints
+    /// can be set before the code runs.
+    pub wait_for_debugger: bool,
+}
+
+impl Default for InspectorConfig {
+    fn default() -> Self {
+        Self {
+            addr: "127.0.0.1:9229".to_string(),
+            wait_for_debugger: false,
+        }
+    }
+}
+
+#[cfg(feature = "inspector")]
+mod devtools;
+
+#[cfg(feature = "inspector")]
+pub(crate) use devtools::{SandboxInspector, poll};
@@ -0,0 +1,533 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::rc::{Rc, Weak};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, mpsc};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+use v8::inspector::{
+    Channel, ChannelImpl, StringBuffer, StringView, V8Inspector, V8InspectorClient,
+    V8InspectorClientImpl, V8InspectorClientTrustLevel, V8InspectorSession,
+};
+
+use crate::inspector::InspectorConfig;
+use crate::profiler::CpuProfiler;
+use crate::sandbox::SandboxError;
+
+const CONTEXT_GROUP_ID: i32 = 1;
+
+// How often a connection thread flushes replies while waiting for DevTools to send something.
+const SOCKET_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// How often an execution waiting for a debugger checks for cancellation and its deadline.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// DevTools endpoints by listen address, shared by every sandbox configured with that address.
+static SERVERS: Mutex<BTreeMap<String, Arc<InspectorServer>>> = Mutex::new(BTreeMap::new());
+
+/// The inspector of one prepared context. Lives on the context's thread; DevTools messages
+/// reach it from the connection thread over `InspectorState::events`.
+pub(crate) struct SandboxInspector {
+    id: String,
+    server: Arc<InspectorServer>,
+    state: Rc<InspectorState>,
+    wait_for_debugger: bool,
+}
+
+impl SandboxInspector {
+    pub(crate) fn new(
+        isolate: &mut v8::Isolate,
+        config: &InspectorConfig,
+    ) -> Result<Self, SandboxError> {
+        let server = server(&config.addr)?;
+        let (sender, events) = mpsc::channel();
+        let state = Rc::new(InspectorState {
+            events,
+            inspector: RefCell::new(None),
+            session: RefCell::new(None),
+            paused: Cell::new(false),
+            released: Cell::new(false),
+            dispatching: Cell::new(0),
+            closed: Cell::new(false),
+        });
+        let client = V8InspectorClient::new(Box::new(Client(Rc::downgrade(&state))));
+        *state.inspector.borrow_mut() = Some(V8Inspector::create(isolate, client));
+        isolate.set_slot(Rc::downgrade(&state));
+        let id = uuid::Uuid::new_v4().to_string();
+        server.targets().insert(
+            id.clone(),
+            Target {
+                events: sender,
+                isolate: isolate.thread_safe_handle(),
+                attached: false,
+            },
+        );
+        let inspector = Self {
+            id,
+            server,
+            state,
+            wait_for_debugger: config.wait_for_debugger,
+        };
+        info!(
+            url = inspector.url().as_str(),
+            "inspector target ready; attach from chrome://inspect"
+        );
+        Ok(inspector)
+    }
+
+    pub(crate) fn url(&self) -> String {
+        format!("ws://{}/ws/{}", self.server.addr, self.id)
+    }
+
+    pub(crate) fn context_created(&self, context: v8::Local<v8::Context>) {
+        if let Some(inspector) = self.state.inspector.borrow().as_ref() {
+            inspector.context_created(
+                context,
+                CONTEXT_GROUP_ID,
+                StringView::from(&b"codemode"[..]),
+                StringView::from(&br#"{"isDefault":true}"#[..]),
+            );
+        }
+    }
+
+    /// V8's CPU profiler on this inspector; an isolate cannot have a second one.
+    pub(crate) fn profiler(&self, interval_us: u64) -> Result<CpuProfiler, SandboxError> {
+        match self.state.inspector.borrow().as_ref() {
+            Some(inspector) => CpuProfiler::attach(inspector, interval_us),
+            None => Err(SandboxError::V8("inspector already dropped".to_string())),
+        }
+    }
+
+    /// Applies the DevTools messages queued since the last execution (breakpoints set while
+    /// idle) and, with `wait_for_debugger`, blocks until a debugger attaches and resumes, the
+    /// execution is cancelled, or `timeout_ms` passes.
+    pub(crate) fn before_execution(
+        &self,
+        cancel: &CancellationToken,
+        timeout_ms: u64,
+    ) -> Result<(), SandboxError> {
+        self.state.poll();
+        if !self.wait_for_debugger || self.state.released.get() {
+            return Ok(());
+        }
+        info!(
+            url = self.url().as_str(),
+            "execution waiting for a debugger"
+        );
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+        while !self.state.released.get() {
+            if cancel.is_cancelled() {
+                return Err(SandboxError::Cancelled);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(SandboxError::Timeout(timeout_ms));
+            }
+            match self
+                .state
+                .events
+                .recv_timeout(WAIT_POLL_INTERVAL.min(deadline - now))
+            {
+                Ok(event) => self.state.handle(event),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SandboxInspector {
+    fn drop(&mut self) {
+        self.server.targets().remove(&self.id);
+        // Session before inspector, both while the isolate is still alive.
+        self.state.session.borrow_mut().take();
+        self.state.inspector.borrow_mut().take();
+    }
+}
+
+/// Applies queued DevTools messages for `isolate`'s context, if it has an inspector. Called from
+/// the completion loop so a script waiting on tools stays debuggable.
+pub(crate) fn poll(isolate: &v8::Isolate) {
+    if let Some(state) = isolate
+        .get_slot::<Weak<InspectorState>>()
+        .and_then(Weak::upgrade)
+    {
+        state.poll();
+    }
+}
+
+extern "C" fn inspector_interrupt(isolate: v8::UnsafeRawIsolatePtr, _data: *mut c_void) {
+    // SAFETY: V8 passes the isolate the interrupt runs on.
+    poll(unsafe { v8::Isolate::ref_from_raw_isolate_ptr(&isolate) });
+}
+
+enum InspectorEvent {
+    Connected(mpsc::Sender<String>),
+    Message(String),
+    Disconnected,
+}
+
+struct InspectorState {
+    events: mpsc::Receiver<InspectorEvent>,
+    inspector: RefCell<Option<V8Inspector>>,
+    session: RefCell<Option<V8InspectorSession>>,
+    paused: Cell<bool>,
+    // Set by `Runtime.runIfWaitingForDebugger`; cleared when the debugger leaves.
+    released: Cell<bool>,
+    // Depth of nested `dispatch_protocol_message` calls; a pause can start inside one.
+    dispatching: Cell<u32>,
+    // The debugger left mid-dispatch; the session is dropped once the dispatch unwinds.
+    closed: Cell<bool>,
+}
+
+impl InspectorState {
+    fn poll(&self) {
+        while let Ok(event) = self.events.try_recv() {
+            self.handle(event);
+        }
+    }
+
+    fn handle(&self, event: InspectorEvent) {
+        match event {
+            InspectorEvent::Connected(replies) => {
+                let Ok(mut slot) = self.session.try_borrow_mut() else {
+                    return;
+                };
+                let inspector = self.inspector.borrow();
+                let Some(inspector) = inspector.as_ref() else {
+                    return;
+                };
+                *slot = Some(inspector.connect(
+                    CONTEXT_GROUP_ID,
+                    Channel::new(Box::new(Replies(replies))),
+                    StringView::empty(),
+                    V8InspectorClientTrustLevel::FullyTrusted,
+                ));
+                self.closed.set(false);
+            }
+            InspectorEvent::Message(message) => {
+                let depth = self.dispatching.get();
+                self.dispatching.set(depth + 1);
+                if let Some(session) = self.session.borrow().as_ref() {
+                    session.dispatch_protocol_message(StringView::from(message.as_bytes()));
+                }
+                self.dispatching.set(depth);
+                if depth == 0 && self.closed.take() {
+                    self.session.borrow_mut().take();
+                }
+            }
+            InspectorEvent::Disconnected => {
+                self.paused.set(false);
+                self.released.set(false);
+                if self.dispatching.get() == 0 {
+                    self.session.borrow_mut().take();
+                } else {
+                    self.closed.set(true);
+                }
+            }
+        }
+    }
+}
+
+struct Client(Weak<InspectorState>);
+
+impl V8InspectorClientImpl for Client {
+    // Runs while a breakpoint holds the isolate: only DevTools messages are processed until
+    // the debugger resumes or leaves. Timeouts keep counting meanwhile.
+    fn run_message_loop_on_pause(&self, _context_group_id: i32) {
+        let Some(state) = self.0.upgrade() else {
+            return;
+        };
+        state.paused.set(true);
+        while state.paused.get() {
+            match state.events.recv() {
+                Ok(event) => state.handle(event),
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn quit_message_loop_on_pause(&self) {
+        if let Some(state) = self.0.upgrade() {
+            state.paused.set(false);
+        }
+    }
+
+    fn run_if_waiting_for_debugger(&self, _context_group_id: i32) {
+        if let Some(state) = self.0.upgrade() {
+            state.released.set(true);
+        }
+    }
+}
+
+/// Forwards protocol replies and notifications to the connection thread.
+struct Replies(mpsc::Sender<String>);
+
+impl Replies {
+    fn forward(&self, message: v8::UniquePtr<StringBuffer>) {
+        if let Some(message) = message.as_ref() {
+            let _ = self.0.send(message.string().to_string());
+        }
+    }
+}
+
+impl ChannelImpl for Replies {
+    fn send_response(&self, _call_id: i32, message: v8::UniquePtr<StringBuffer>) {
+        self.forward(message);
+    }
+
+    fn send_notification(&self, message: v8::UniquePtr<StringBuffer>) {
+        self.forward(message);
+    }
+
+    fn flush_protocol_notifications(&self) {}
+}
+
+fn server(addr: &str) -> Result<Arc<InspectorServer>, SandboxError> {
+    let mut servers = SERVERS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(server) = servers.get(addr) {
+        return Ok(server.clone());
+    }
+    let listener = TcpListener::bind(addr)
+        .map_err(|err| SandboxError::V8(format!("inspector listen on {addr}: {err}")))?;
+    let server = Arc::new(InspectorServer {
+        addr: listener
+            .local_addr()
+            .map_err(|err| SandboxError::V8(format!("inspector address: {err}")))?,
+        targets: Mutex::new(BTreeMap::new()),
+    });
+    std::thread::Builder::new()
+        .name("codemode-inspector".to_string())
+        .spawn({
+            let server = server.clone();
+            move || server.accept(listener)
+        })
+        .map_err(|err| SandboxError::V8(format!("inspector thread: {err}")))?;
+    info!(addr = %server.addr, "inspector listening");
+    servers.insert(addr.to_string(), server.clone());
+    Ok(server)
+}
+
+/// Serves the DevTools discovery endpoints (`/json/list`, `/json/version`) and one WebSocket
+/// per attached target, each on its own thread.
+struct InspectorServer {
+    addr: SocketAddr,
+    targets: Mutex<BTreeMap<String, Target>>,
+}
+
+struct Target {
+    events: mpsc::Sender<InspectorEvent>,
+    isolate: v8::IsolateHandle,
+    attached: bool,
+}
+
+impl InspectorServer {
+    fn targets(&self) -> MutexGuard<'_, BTreeMap<String, Target>> {
+        self.targets.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn accept(self: Arc<Self>, listener: TcpListener) {
+        for stream in listener.incoming().flatten() {
+            let server = self.clone();
+            let _ = std::thread::Builder::new()
+                .name("codemode-inspector-connection".to_string())
+                .spawn(move || {
+                    if let Err(err) = server.serve(stream) {
+                        debug!(error = %err, "inspector connection closed");
+                    }
+                });
+        }
+    }
+
+    fn serve(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut head = [0; 4096];
+        let length = stream.peek(&mut head)?;
+        let head = String::from_utf8_lossy(&head[..length]).to_ascii_lowercase();
+        if head.contains("upgrade: websocket") {
+            self.debug(stream)
+        } else {
+            self.describe(stream)
+        }
+    }
+
+    fn describe(&self, mut stream: TcpStream) -> io::Result<()> {
+        let mut head = [0; 4096];
+        let length = stream.read(&mut head)?;
+        let request = String::from_utf8_lossy(&head[..length]);
+        if !self.trusted(header(&request, "host"), header(&request, "origin")) {
+            return respond(stream, "403 Forbidden", "text/plain", b"forbidden");
+        }
+        let path = request.split_whitespace().nth(1).unwrap_or("/");
+        let body = match path.trim_end_matches('/') {
+            "/json" | "/json/list" => Value::Array(self.list()),
+            "/json/version" => json!({
+                "Browser": format!("codemode-rs/{}", env!("CARGO_PKG_VERSION")),
+                "Protocol-Version": "1.3",
+                "V8-Version": v8::V8::get_version(),
+            }),
+            _ => return respond(stream, "404 Not Found", "text/plain", b"not found"),
+        };
+        respond(
+            stream,
+            "200 OK",
+            "application/json; charset=UTF-8",
+            body.to_string().as_bytes(),
+        )
+    }
+
+    fn list(&self) -> Vec<Value> {
+        self.targets()
+            .keys()
+            .map(|id| {
+                let socket = format!("{}/ws/{id}", self.addr);
+                json!({
+                    "id": id,
+                    "type": "node",
+                    "title": "codemode sandbox",
+                    "description": "codemode sandbox",
+                    "url": "codemode.js",
+                    "devtoolsFrontendUrl": format!(
+                        "devtools://devtools/bundled/js_app.html?experiments=true&v8only=true&ws={socket}"
+                    ),
+                    "webSocketDebuggerUrl": format!("ws://{socket}"),
+                })
+            })
+            .collect()
+    }
+
+    fn debug(&self, stream: TcpStream) -> io::Result<()> {
+        let mut path = String::new();
+        let mut socket = tungstenite::accept_hdr(
+            stream,
+            |request: &tungstenite::handshake::server::Request,
+             response: tungstenite::handshake::server::Response| {
+                let header = |name| {
+                    request
+                        .headers()
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                };
+                if !self.trusted(header("host"), header("origin")) {
+                    let mut rejection = tungstenite::handshake::server::ErrorResponse::new(Some(
+                        "forbidden".to_string(),
+                    ));
+                    *rejection.status_mut() = tungstenite::http::StatusCode::FORBIDDEN;
+                    return Err(rejection);
+                }
+                path = request.uri().path().to_string();
+                Ok(response)
+            },
+        )
+        .map_err(|err| io::Error::other(err.to_string()))?;
+        let Some((id, events, isolate)) = path.strip_prefix("/ws/").and_then(|id| {
+            self.attach(id)
+                .map(|(events, isolate)| (id, events, isolate))
+        }) else {
+            let _ = socket.close(None);
+            return Ok(());
+        };
+        let (replies, outgoing) = mpsc::channel();
+        let notify = |event| {
+            let sent = events.send(event).is_ok();
+            isolate.request_interrupt(inspector_interrupt, std::ptr::null_mut());
+            sent
+        };
+        notify(InspectorEvent::Connected(replies));
+        socket
+            .get_ref()
+            .set_read_timeout(Some(SOCKET_POLL_INTERVAL))?;
+        let outcome = loop {
+            match flush(&mut socket, &outgoing) {
+                Ok(true) => {}
+                Ok(false) => {
+                    // The context was dropped; its session with it.
+                    let _ = socket.close(None);
+                    break Ok(());
+                }
+                Err(err) => break Err(err),
+            }
+            match socket.read() {
+                Ok(tungstenite::Message::Text(text)) => {
+                    if !notify(InspectorEvent::Message(text.as_str().to_string())) {
+                        break Ok(());
+                    }
+                }
+                Ok(tungstenite::Message::Close(_)) => break Ok(()),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(err))
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) => {}
+                Err(err) => break Err(io::Error::other(err.to_string())),
+            }
+        };
+        if let Some(target) = self.targets().get_mut(id) {
+            target.attached = false;
+        }
+        notify(InspectorEvent::Disconnected);
+        outcome
+    }
+
+    /// Guards against DNS rebinding and cross-site requests the way Node and Chrome do: the
+    /// `Host` must name a loopback address on this server's port, and browser pages may not
+    /// connect; only DevTools' own `devtools://` origin is accepted.
+    fn trusted(&self, host: Option<&str>, origin: Option<&str>) -> bool {
+        let port = self.addr.port();
+        let loopback = host.is_some_and(|host| match host.parse::<SocketAddr>() {
+            Ok(addr) => addr.ip().is_loopback() && addr.port() == port,
+            Err(_) => host.eq_ignore_ascii_case(&format!("localhost:{port}")),
+        });
+        loopback && origin.is_none_or(|origin| origin.starts_with("devtools://"))
+    }
+
+    /// Claims `id` for a new connection; a target takes one debugger at a time.
+    fn attach(&self, id: &str) -> Option<(mpsc::Sender<InspectorEvent>, v8::IsolateHandle)> {
+        let mut targets = self.targets();
+        let target = targets.get_mut(id).filter(|target| !target.attached)?;
+        target.attached = true;
+        Some((target.events.clone(), target.isolate.clone()))
+    }
+}
+
+/// The value of header `name` in a raw HTTP request head.
+fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then_some(value.trim())
+    })
+}
+
+/// Sends every queued reply; `false` once the context side has gone away.
+fn flush(
+    socket: &mut tungstenite::WebSocket<TcpStream>,
+    outgoing: &mpsc::Receiver<String>,
+) -> io::Result<bool> {
+    loop {
+        match outgoing.try_recv() {
+            Ok(reply) => socket
+                .send(tungstenite::Message::text(reply))
+                .map_err(|err| io::Error::other(err.to_string()))?,
+            Err(mpsc::TryRecvError::Empty) => return Ok(true),
+            Err(mpsc::TryRecvError::Disconnected) => return Ok(false),
+        }
+    }
+}
+
+fn respond(mut stream: TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)
+}
@@ -1,21 +1,125 @@
+// `SandboxConfig::schema` is a single `json!` literal deeper than the default limit allows.
+#![recursion_limit = "256"]
+
+#[cfg(all(feature = "deno", feature = "v8"))]
+compile_error!(
+    "the `deno` feature links deno_core's own V8, which cannot share a binary with the `v8` \
+     feature; build with `default-features = false`"
+);
+
+pub mod backend;
+pub mod blob;
+pub mod cache;
 pub mod client;
+mod dispatch;
+pub mod envelope;
+pub mod fetch;
+pub mod inspector;
+pub mod markdown;
+pub mod memory;
+pub mod policy;
+pub mod prompt;
+pub mod redact;
 pub mod sandbox;
 mod schema;
+pub mod select;
+pub mod simulation;
+pub mod snapshot;
 mod tool;
 pub mod ts_interface;
+#[cfg(feature = "typescript")]
+mod typescript;
+mod validate;
+pub mod wire;
+pub mod zod;
 
+#[cfg(feature = "boa")]
+pub mod boa;
+#[cfg(feature = "builtin-tools")]
+pub mod builtin_tools;
+#[cfg(feature = "v8")]
+mod convert;
+#[cfg(feature = "deno")]
+pub mod deno;
+#[cfg(feature = "v8")]
+mod globals;
 #[cfg(feature = "mcp")]
 pub mod mcp;
+#[cfg(feature = "v8")]
+mod monitor;
+#[cfg(feature = "otel")]
+mod otel;
+#[cfg(feature = "v8")]
+mod pool;
+#[cfg(feature = "v8")]
+mod profiler;
+
+pub use crate::tool::{
+    AsyncToolCaller, GlobalFn, ResultTransformer, Stability, StreamingToolCaller, SyncToolCaller,
+    Tool, ToolCallError, ToolErrorFn, ToolFilter, ToolInterceptor, ToolMetadataProvider,
+    ValueStream,
+};
+pub use crate::ts_interface::ToolInterfaceGenerator;
+#[cfg(feature = "macros")]
+pub use codemode_macros::tool;
+
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    pub use async_trait::async_trait;
+    pub use serde_json;
+}
 
 pub mod prelude {
-    pub use crate::client::{CodeModeClient, CodeModeClientConfig, CodeModeClientConfigBuilder};
-    pub use crate::sandbox::{ExecutionResult, SandboxConfig, SandboxConfigBuilder};
+    pub use crate::backend::{ScriptBackend, ToolBindings};
+    pub use crate::blob::{BlobError, BlobStore, InMemoryBlobStore};
+    pub use crate::cache::{CacheKey, InMemoryToolCache, ToolResultCache};
+    pub use crate::client::{
+        CodeModeClient, CodeModeClientConfig, CodeModeClientConfigBuilder, ToolFallback,
+        ToolOptions,
+    };
+    pub use crate::envelope::{ENVELOPE_VERSION, ExecutionEnvelope};
+    pub use crate::fetch::FetchConfig;
+    pub use crate::inspector::InspectorConfig;
+    pub use crate::memory::{InMemoryStore, MemoryError, MemoryStore};
+    pub use crate::policy::{
+        Approval, PolicyDecision, PolicyTarget, RulePolicy, ToolApprover, ToolPolicy,
+    };
+    pub use crate::prompt::PromptTemplate;
+    pub use crate::redact::{Redactor, SecretRedactor};
+    pub use crate::sandbox::{
+        CacheStats, ChainEvent, DeprecatedToolPolicy, ExecutionResult, InterfaceReport,
+        PlannedCall, Sandbox, SandboxCapabilities, SandboxConfig, SandboxConfigBuilder,
+        SchemaValidation, ScriptDiagnostic, ScriptDiagnostics, StackSamples, ToolRuntimeConfig,
+        TraceEvent, WrapMode,
+    };
     pub use crate::schema::JsonSchema;
+    pub use crate::select::{KeywordSelector, ToolSelector};
+    pub use crate::simulation::{MockToolCaller, Simulator};
+    pub use crate::snapshot::{InterfaceDrift, InterfaceSnapshot, SNAPSHOT_VERSION};
     pub use crate::tool::{
-        AsyncToolCaller, SyncToolCaller, Tool, ToolCallError, ToolMetadataProvider,
+        AsyncToolCaller, GlobalFn, ResultTransformer, Stability, StreamingToolCaller,
+        SyncToolCaller, Tool, ToolCallError, ToolErrorFn, ToolFilter, ToolInterceptor,
+        ToolMetadataProvider, ValueStream,
     };
-    pub use crate::ts_interface::ToolInterfaceGenerator;
+    pub use crate::ts_interface::{
+        BudgetedInterfaces, InterfaceOptions, InterfaceOptionsBuilder, NamespaceStrategy,
+        ToolInterfaceGenerator, TruncatedInterface, Truncation, VerifyError,
+    };
+    pub use crate::wire::{RawPayload, RawToolCaller, WireFormat};
+
+    pub use tokio_util::sync::CancellationToken;
 
+    #[cfg(feature = "boa")]
+    pub use crate::boa::BoaBackend;
+    #[cfg(feature = "builtin-tools")]
+    pub use crate::builtin_tools::BuiltinTools;
+    #[cfg(feature = "deno")]
+    pub use crate::deno::DenoBackend;
     #[cfg(feature = "mcp")]
-    pub use crate::mcp::{McpToolClient, rmcp};
+    pub use crate::mcp::{McpToolClient, NotificationBuffer, NotificationFilter, rmcp};
+    #[cfg(feature = "v8")]
+    pub use crate::sandbox::{PreparedSandbox, StartupSnapshot};
+    #[cfg(feature = "schemars")]
+    pub use schemars;
 }
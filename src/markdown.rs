@@ -0,0 +1,94 @@
+use serde_json::Value;
+
+use crate::simulation::Simulator;
+use crate::tool::{Stability, Tool};
+use crate::ts_interface::{ToolInterfaceGenerator, describe_property, resolve_refs};
+
+/// Human-readable docs for `tools`: description, tags, a parameter table and an example call
+/// per tool, rendered from the same schemas as the TypeScript interfaces.
+pub fn tools_to_markdown(tools: &[&Tool], generator: &ToolInterfaceGenerator) -> String {
+    tools
+        .iter()
+        .map(|tool| tool_to_markdown(tool, generator))
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+fn tool_to_markdown(tool: &Tool, generator: &ToolInterfaceGenerator) -> String {
+    let mut sections = vec![format!("## `{}`", tool.name)];
+    if !tool.description.is_empty() {
+        sections.push(tool.description.clone());
+    }
+    if tool.deprecated {
+        sections.push("**Deprecated.**".to_string());
+    }
+    match tool.stability {
+        Stability::Stable => {}
+        Stability::Beta => sections.push("**Stability:** beta".to_string()),
+        Stability::Experimental => sections.push("**Stability:** experimental".to_string()),
+    }
+    if !tool.tags.is_empty() {
+        let tags = tool
+            .tags
+            .iter()
+            .map(|tag| format!("`{tag}`"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        sections.push(format!("**Tags:** {tags}"));
+    }
+
+    let access_path = generator.tool_access_path(tool);
+    let inputs = resolve_refs(&tool.inputs, &access_path.replace('.', "_"));
+    sections.push(parameter_table(&inputs));
+
+    let args = if tool.takes_no_input() {
+        String::new()
+    } else {
+        let args = Simulator::default().generate(&inputs);
+        serde_json::to_string_pretty(&args).unwrap_or_else(|_| "{}".to_string())
+    };
+    let call = if tool.is_streaming {
+        format!("for await (const chunk of {access_path}({args})) {{\n  // ...\n}}")
+    } else if tool.is_async {
+        format!("const result = await {access_path}({args});")
+    } else {
+        format!("const result = {access_path}({args});")
+    };
+    sections.push(format!("**Example:**\n\n```js\n{call}\n```"));
+    sections.join("\n\n")
+}
+
+fn parameter_table(inputs: &Value) -> String {
+    let Some(properties) = inputs
+        .get("properties")
+        .and_then(Value::as_object)
+        .filter(|properties| !properties.is_empty())
+    else {
+        return "_No parameters._".to_string();
+    };
+    let required = inputs
+        .get("required")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+
+    let mut rows = vec![
+        "| Name | Type | Required | Description |".to_string(),
+        "| --- | --- | --- | --- |".to_string(),
+    ];
+    for (name, schema) in properties {
+        let (ts_type, doc) = describe_property(schema);
+        let is_required = required.iter().any(|req| req.as_str() == Some(name));
+        rows.push(format!(
+            "| `{name}` | `{}` | {} | {} |",
+            escape_cell(&ts_type),
+            if is_required { "yes" } else { "no" },
+            escape_cell(&doc.unwrap_or_default()),
+        ));
+    }
+    format!("**Parameters:**\n\n{}", rows.join("\n"))
+}
+
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
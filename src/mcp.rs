@@ -1,12 +1,19 @@
-use crate::tool::{AsyncToolCaller, Tool, ToolCallError, ToolMetadataProvider};
+use crate::tool::{AsyncToolCaller, Stability, Tool, ToolCallError, ToolMetadataProvider};
 
 use async_trait::async_trait;
 use dashmap::DashMap;
-use std::sync::Arc;
-use rmcp::model::{CallToolRequestParams, Content, RawContent, Tool as McpTool};
-use rmcp::service::{Peer, RoleClient, RunningService};
-use serde_json::{Map, Value};
+use rmcp::ClientHandler;
+use rmcp::model::{
+    CallToolRequestParams, Content, CustomNotification, LoggingMessageNotificationParam,
+    ProgressNotificationParam, RawContent, ResourceUpdatedNotificationParam, Tool as McpTool,
+};
+use rmcp::service::{NotificationContext, Peer, RoleClient, RunningService};
+use serde_json::{Map, Value, json};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use thiserror::Error;
+use tokio::sync::Notify;
 use tracing::trace;
 
 pub use rmcp;
@@ -21,21 +28,40 @@ pub enum McpClientError {
     EmptyContent,
 }
 
+pub const NEXT_NOTIFICATION_TOOL: &str = "nextNotification";
+
+const DEFAULT_NOTIFICATION_CAPACITY: usize = 256;
+const DEFAULT_NOTIFICATION_TIMEOUT_MS: u64 = 30000;
+
 #[derive(Clone)]
-pub struct McpToolClient {
-    service: Arc<RunningService<RoleClient, ()>>,
+pub struct McpToolClient<H: ClientHandler = ()> {
+    service: Arc<RunningService<RoleClient, H>>,
     tools: Arc<DashMap<String, Tool>>,
+    notifications: Option<NotificationBuffer>,
 }
 
-impl McpToolClient {
-    pub fn new(service: RunningService<RoleClient, ()>) -> Self {
+impl<H: ClientHandler> McpToolClient<H> {
+    pub fn new(service: RunningService<RoleClient, H>) -> Self {
         trace!("mcp client created");
         Self {
             service: Arc::new(service),
             tools: Arc::new(DashMap::new()),
+            notifications: None,
         }
     }
 
+    /// Exposes `buffer` to chains as an awaitable `nextNotification` tool. The buffer must be
+    /// the handler (or be fed by the handler) the service was started with. Listing tools fails
+    /// if the server has a tool of that name, rather than shadowing it.
+    pub fn with_notifications(mut self, buffer: NotificationBuffer) -> Self {
+        self.notifications = Some(buffer);
+        self
+    }
+
+    pub fn notifications(&self) -> Option<&NotificationBuffer> {
+        self.notifications.as_ref()
+    }
+
     pub fn peer(&self) -> &Peer<RoleClient> {
         self.service.peer()
     }
@@ -98,8 +124,22 @@ impl McpToolClient {
 }
 
 #[async_trait]
-impl AsyncToolCaller for McpToolClient {
+impl<H: ClientHandler> AsyncToolCaller for McpToolClient<H> {
     async fn call_tool_async(&self, name: &str, args: Value) -> Result<Value, ToolCallError> {
+        if name == NEXT_NOTIFICATION_TOOL
+            && let Some(notifications) = &self.notifications
+        {
+            let timeout_ms = args
+                .get("timeoutMs")
+                .and_then(Value::as_u64)
+                .unwrap_or(DEFAULT_NOTIFICATION_TIMEOUT_MS);
+            let filter = NotificationFilter::from_value(args.get("filter"));
+            let notification = notifications
+                .next(Duration::from_millis(timeout_ms), &filter)
+                .await;
+            return Ok(notification.unwrap_or(Value::Null));
+        }
+
         self.call_tool(name, args)
             .await
             .map_err(|err| ToolCallError::Message(err.to_string()))
@@ -107,15 +147,236 @@ impl AsyncToolCaller for McpToolClient {
 }
 
 #[async_trait]
-impl ToolMetadataProvider for McpToolClient {
+impl<H: ClientHandler> ToolMetadataProvider for McpToolClient<H> {
     async fn list_tools(&self) -> Result<Vec<Tool>, ToolCallError> {
-        self.refresh_tools()
+        let mut tools = self
+            .refresh_tools()
             .await
-            .map_err(|err| ToolCallError::Message(err.to_string()))
+            .map_err(|err| ToolCallError::Message(err.to_string()))?;
+        if self.notifications.is_some() {
+            if tools.iter().any(|tool| tool.name == NEXT_NOTIFICATION_TOOL) {
+                return Err(ToolCallError::Message(format!(
+                    "server tool '{NEXT_NOTIFICATION_TOOL}' collides with the notification tool; \
+                     drop `with_notifications` to use it"
+                )));
+            }
+            tools.push(next_notification_tool());
+        }
+        Ok(tools)
+    }
+}
+
+/// Client handler that buffers server-pushed notifications so chains can await them.
+#[derive(Clone)]
+pub struct NotificationBuffer {
+    inner: Arc<NotificationBufferInner>,
+}
+
+struct NotificationBufferInner {
+    queue: Mutex<VecDeque<Value>>,
+    notify: Notify,
+    capacity: usize,
+}
+
+impl Default for NotificationBuffer {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_NOTIFICATION_CAPACITY)
+    }
+}
+
+impl NotificationBuffer {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(NotificationBufferInner {
+                queue: Mutex::new(VecDeque::new()),
+                notify: Notify::new(),
+                capacity: capacity.max(1),
+            }),
+        }
+    }
+
+    pub fn push(&self, method: &str, params: Value) {
+        trace!(method = method, "mcp notification buffered");
+        if let Ok(mut queue) = self.inner.queue.lock() {
+            if queue.len() >= self.inner.capacity {
+                queue.pop_front();
+            }
+            queue.push_back(json!({ "method": method, "params": params }));
+        }
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner
+            .queue
+            .lock()
+            .map(|queue| queue.len())
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub async fn next(&self, timeout: Duration, filter: &NotificationFilter) -> Option<Value> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let notified = self.inner.notify.notified();
+            if let Some(notification) = self.take_matching(filter) {
+                return Some(notification);
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return None;
+            }
+        }
+    }
+
+    fn take_matching(&self, filter: &NotificationFilter) -> Option<Value> {
+        let mut queue = self.inner.queue.lock().ok()?;
+        let position = queue.iter().position(|notification| {
+            notification
+                .get("method")
+                .and_then(Value::as_str)
+                .is_some_and(|method| filter.matches(method))
+        })?;
+        queue.remove(position)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NotificationFilter {
+    methods: Vec<String>,
+}
+
+impl NotificationFilter {
+    pub fn methods(methods: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            methods: methods.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    fn from_value(value: Option<&Value>) -> Self {
+        match value {
+            Some(Value::String(method)) => Self::methods([method.clone()]),
+            Some(Value::Array(methods)) => {
+                Self::methods(methods.iter().filter_map(Value::as_str).map(str::to_string))
+            }
+            _ => Self::default(),
+        }
+    }
+
+    fn matches(&self, method: &str) -> bool {
+        self.methods.is_empty() || self.methods.iter().any(|candidate| candidate == method)
+    }
+}
+
+impl ClientHandler for NotificationBuffer {
+    async fn on_progress(
+        &self,
+        params: ProgressNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        self.push(
+            "notifications/progress",
+            serde_json::to_value(params).unwrap_or(Value::Null),
+        );
+    }
+
+    async fn on_logging_message(
+        &self,
+        params: LoggingMessageNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        self.push(
+            "notifications/message",
+            serde_json::to_value(params).unwrap_or(Value::Null),
+        );
+    }
+
+    async fn on_resource_updated(
+        &self,
+        params: ResourceUpdatedNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        self.push(
+            "notifications/resources/updated",
+            serde_json::to_value(params).unwrap_or(Value::Null),
+        );
+    }
+
+    async fn on_resource_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        self.push("notifications/resources/list_changed", Value::Null);
+    }
+
+    async fn on_tool_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        self.push("notifications/tools/list_changed", Value::Null);
+    }
+
+    async fn on_prompt_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        self.push("notifications/prompts/list_changed", Value::Null);
+    }
+
+    async fn on_custom_notification(
+        &self,
+        notification: CustomNotification,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        self.push(
+            &notification.method,
+            notification.params.unwrap_or(Value::Null),
+        );
+    }
+}
+
+fn next_notification_tool() -> Tool {
+    Tool {
+        name: NEXT_NOTIFICATION_TOOL.to_string(),
+        description: "Wait for the next server notification. Resolves to null if none arrives \
+before the timeout."
+            .to_string(),
+        tags: vec!["notifications".to_string()],
+        inputs: json!({
+            "type": "object",
+            "properties": {
+                "timeoutMs": {
+                    "type": "integer",
+                    "description": "Maximum time to wait in milliseconds"
+                },
+                "filter": {
+                    "type": ["string", "array"],
+                    "items": { "type": "string" },
+                    "description": "Notification method (or list of methods) to wait for"
+                }
+            }
+        }),
+        // `null` when the wait times out; list-changed notifications carry no params.
+        outputs: json!({
+            "type": ["object", "null"],
+            "properties": {
+                "method": { "type": "string" },
+                "params": { "type": ["object", "null"] }
+            }
+        }),
+        is_async: true,
+        is_streaming: false,
+        read_only: false,
+        destructive: false,
+        deprecated: false,
+        stability: Stability::Stable,
     }
 }
 
 fn convert_tool(tool: McpTool) -> Tool {
+    let read_only = tool
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.read_only_hint)
+        .unwrap_or(false);
+    let destructive = tool
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.destructive_hint)
+        .unwrap_or(false);
     Tool {
         name: tool.name.to_string(),
         description: tool
@@ -129,6 +390,11 @@ fn convert_tool(tool: McpTool) -> Tool {
             .map(|schema| Value::Object(schema.as_ref().clone()))
             .unwrap_or_else(|| Value::Object(Map::new())),
         is_async: true,
+        is_streaming: false,
+        read_only,
+        destructive,
+        deprecated: false,
+        stability: Stability::Stable,
     }
 }
 
@@ -0,0 +1,52 @@
+use dashmap::DashMap;
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MemoryError {
+    #[error("memory storage error: {0}")]
+    Storage(String),
+}
+
+/// Key-value state behind `codemode.memory`. A store registered on the client outlives single
+/// executions, so multi-step agents can pick up where a previous script left off.
+pub trait MemoryStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<Value>, MemoryError>;
+    fn set(&self, key: &str, value: Value) -> Result<(), MemoryError>;
+    /// Returns whether the key was present.
+    fn delete(&self, key: &str) -> Result<bool, MemoryError>;
+}
+
+#[derive(Default)]
+pub struct InMemoryStore {
+    entries: DashMap<String, Value>,
+}
+
+impl InMemoryStore {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.entries.clear();
+    }
+}
+
+impl MemoryStore for InMemoryStore {
+    fn get(&self, key: &str) -> Result<Option<Value>, MemoryError> {
+        Ok(self.entries.get(key).map(|entry| entry.value().clone()))
+    }
+
+    fn set(&self, key: &str, value: Value) -> Result<(), MemoryError> {
+        self.entries.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, MemoryError> {
+        Ok(self.entries.remove(key).is_some())
+    }
+}
@@ -0,0 +1,272 @@
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::sandbox::SandboxError;
+
+/// One thread for every running execution in the process: it terminates executions that are
+/// cancelled or past their deadline and issues the interrupts behind `interrupt_budget`, so an execution costs no threads of
+/// its own.
+static MONITOR: Monitor = Monitor {
+    watches: Mutex::new(Vec::new()),
+    wake: Condvar::new(),
+    next_id: AtomicU64::new(0),
+};
+
+static MONITOR_THREAD: OnceLock<Result<(), String>> = OnceLock::new();
+
+// How often cancellation tokens are checked; `CancellationToken` has no blocking wait to hook.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// What the monitor enforces on one execution.
+pub(crate) struct WatchLimits {
+    pub(crate) timeout: Duration,
+    pub(crate) cancel: CancellationToken,
+    /// Interrupt budget in ticks, and the length of a tick.
+    pub(crate) budget: Option<(u64, Duration)>,
+}
+
+/// What the monitor saw of an execution, read once it has been unwatched.
+pub(crate) struct WatchReport {
+    pub(crate) cancelled: bool,
+    pub(crate) timed_out: bool,
+    /// Ticks counted when the interrupt budget ran out.
+    pub(crate) budget_exceeded: Option<u64>,
+}
+
+/// Registration of one execution with the monitor; dropping it unwatches the execution.
+pub(crate) struct ExecutionWatch {
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+    timed_out: Arc<AtomicBool>,
+    budget: Option<Arc<BudgetState>>,
+}
+
+impl ExecutionWatch {
+    pub(crate) fn start(
+        isolate: v8::IsolateHandle,
+        limits: WatchLimits,
+    ) -> Result<Self, SandboxError> {
+        let monitor = monitor()?;
+        let now = Instant::now();
+        let id = monitor.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let budget = limits.budget.map(|(budget, tick)| {
+            let state = Arc::new(BudgetState {
+                budget,
+                ticks: AtomicU64::new(0),
+                in_flight: AtomicBool::new(false),
+                exceeded: AtomicBool::new(false),
+                stopped: AtomicBool::new(false),
+            });
+            Ticker::new(state, tick, now, budget_interrupt)
+        });
+        let watch = Self {
+            id,
+            cancelled: cancelled.clone(),
+            timed_out: timed_out.clone(),
+            budget: budget.as_ref().map(|ticker| ticker.state.clone()),
+        };
+        monitor.watches().push(Watch {
+            id,
+            isolate,
+            deadline: now + limits.timeout,
+            cancel: limits.cancel,
+            cancelled,
+            timed_out,
+            terminated: false,
+            budget,
+        });
+        monitor.wake.notify_one();
+        Ok(watch)
+    }
+
+    /// Unwatches the execution and reports what happened to it. Once this returns the monitor
+    /// can no longer terminate the isolate, so nothing leaks into its next execution.
+    pub(crate) fn stop(self) -> WatchReport {
+        self.unwatch();
+        WatchReport {
+            cancelled: self.cancelled.load(Ordering::Acquire),
+            timed_out: self.timed_out.load(Ordering::Acquire),
+            budget_exceeded: self
+                .budget
+                .as_ref()
+                .filter(|budget| budget.exceeded.load(Ordering::Acquire))
+                .map(|budget| budget.ticks.load(Ordering::Acquire)),
+        }
+    }
+
+    fn unwatch(&self) {
+        MONITOR.watches().retain(|watch| watch.id != self.id);
+        // Interrupts still queued on the isolate see this flag and do nothing.
+        if let Some(budget) = &self.budget {
+            budget.stopped.store(true, Ordering::Release);
+        }
+    }
+}
+
+impl Drop for ExecutionWatch {
+    fn drop(&mut self) {
+        self.unwatch();
+    }
+}
+
+fn monitor() -> Result<&'static Monitor, SandboxError> {
+    MONITOR_THREAD
+        .get_or_init(|| {
+            std::thread::Builder::new()
+                .name("codemode-monitor".to_string())
+                .spawn(|| MONITOR.run())
+                .map(drop)
+                .map_err(|err| err.to_string())
+        })
+        .clone()
+        .map_err(|err| SandboxError::V8(format!("monitor thread: {err}")))?;
+    Ok(&MONITOR)
+}
+
+struct Monitor {
+    watches: Mutex<Vec<Watch>>,
+    wake: Condvar,
+    next_id: AtomicU64,
+}
+
+impl Monitor {
+    fn watches(&self) -> MutexGuard<'_, Vec<Watch>> {
+        self.watches.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn run(&self) {
+        let mut watches = self.watches();
+        loop {
+            let now = Instant::now();
+            let wake_at = watches.iter_mut().filter_map(|watch| watch.poll(now)).min();
+            watches = match wake_at {
+                Some(wake_at) => {
+                    self.wake
+                        .wait_timeout(watches, wake_at.saturating_duration_since(now))
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+                None => self
+                    .wake
+                    .wait(watches)
+                    .unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+    }
+}
+
+struct Watch {
+    id: u64,
+    isolate: v8::IsolateHandle,
+    deadline: Instant,
+    cancel: CancellationToken,
+    cancelled: Arc<AtomicBool>,
+    timed_out: Arc<AtomicBool>,
+    terminated: bool,
+    budget: Option<Ticker<BudgetState>>,
+}
+
+impl Watch {
+    /// Acts on everything due by `now` and returns when the watch next needs attention. Runs
+    /// under the watch list lock, so it never races with `ExecutionWatch::stop`.
+    fn poll(&mut self, now: Instant) -> Option<Instant> {
+        if self.terminated {
+            return None;
+        }
+        let fired = if self.cancel.is_cancelled() {
+            &self.cancelled
+        } else if now >= self.deadline {
+            &self.timed_out
+        } else {
+            let mut wake_at = self.deadline.min(now + CANCEL_POLL_INTERVAL);
+            if let Some(budget) = &mut self.budget {
+                wake_at = wake_at.min(budget.poll(&self.isolate, now));
+            }
+            return Some(wake_at);
+        };
+        fired.store(true, Ordering::Release);
+        self.isolate.terminate_execution();
+        self.terminated = true;
+        None
+    }
+}
+
+/// State shared with an interrupt callback; `in_flight` keeps one interrupt queued at a time.
+trait InterruptState: Send + Sync + 'static {
+    fn in_flight(&self) -> &AtomicBool;
+}
+
+/// A periodic interrupt, such as a budget tick.
+struct Ticker<S> {
+    state: Arc<S>,
+    interval: Duration,
+    next: Instant,
+    callback: extern "C" fn(v8::UnsafeRawIsolatePtr, *mut c_void),
+}
+
+impl<S: InterruptState> Ticker<S> {
+    fn new(
+        state: Arc<S>,
+        interval: Duration,
+        now: Instant,
+        callback: extern "C" fn(v8::UnsafeRawIsolatePtr, *mut c_void),
+    ) -> Self {
+        Self {
+            state,
+            interval,
+            next: now + interval,
+            callback,
+        }
+    }
+
+    fn poll(&mut self, isolate: &v8::IsolateHandle, now: Instant) -> Instant {
+        if now < self.next {
+            return self.next;
+        }
+        self.next = now + self.interval;
+        // Only one interrupt is queued at a time so idle waits on tools count as one tick.
+        if !self.state.in_flight().swap(true, Ordering::AcqRel) {
+            let data = Arc::into_raw(self.state.clone()) as *mut c_void;
+            if !isolate.request_interrupt(self.callback, data) {
+                // SAFETY: The interrupt was not queued, so ownership of `data` never left us.
+                drop(unsafe { Arc::from_raw(data as *const S) });
+            }
+        }
+        self.next
+    }
+}
+
+struct BudgetState {
+    budget: u64,
+    ticks: AtomicU64,
+    in_flight: AtomicBool,
+    exceeded: AtomicBool,
+    stopped: AtomicBool,
+}
+
+impl InterruptState for BudgetState {
+    fn in_flight(&self) -> &AtomicBool {
+        &self.in_flight
+    }
+}
+
+extern "C" fn budget_interrupt(isolate: v8::UnsafeRawIsolatePtr, data: *mut c_void) {
+    // SAFETY: `data` came from Arc::into_raw in `Ticker::poll`; each interrupt owns one count.
+    let state = unsafe { Arc::from_raw(data as *const BudgetState) };
+    // SAFETY: V8 passes the isolate the interrupt runs on.
+    let isolate = unsafe { v8::Isolate::ref_from_raw_isolate_ptr(&isolate) };
+    state.in_flight.store(false, Ordering::Release);
+    if state.stopped.load(Ordering::Acquire) {
+        return;
+    }
+    let ticks = state.ticks.fetch_add(1, Ordering::AcqRel) + 1;
+    if ticks > state.budget && !state.exceeded.swap(true, Ordering::AcqRel) {
+        isolate.terminate_execution();
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::Instant;
+
+use opentelemetry::context::FutureExt;
+use opentelemetry::trace::{Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue, global};
+use serde_json::Value;
+
+use crate::dispatch::ToolDispatch;
+use crate::sandbox::{ExecutionResult, SandboxError};
+use crate::tool::ToolCallError;
+
+const TRACER: &str = "codemode-rs";
+
+/// Starts the span covering one `call_tool_chain`, as a child of the caller's active context.
+pub(crate) fn start_chain(tool_count: usize) -> Context {
+    let tracer = global::tracer(TRACER);
+    let span = tracer
+        .span_builder("call_tool_chain")
+        .with_attributes([KeyValue::new("codemode.tool_count", tool_count as i64)])
+        .start(&tracer);
+    Context::current_with_span(span)
+}
+
+pub(crate) fn end_chain(context: &Context, outcome: &Result<ExecutionResult, SandboxError>) {
+    let span = context.span();
+    if let Err(err) = outcome {
+        span.set_attribute(KeyValue::new("codemode.error", err.to_string()));
+        span.set_status(Status::error(err.to_string()));
+    }
+    span.end();
+}
+
+/// Runs one tool call inside a child span of `parent`; the span is the active context while
+/// the caller's future runs, so callers can propagate it further.
+pub(crate) async fn traced_call(
+    parent: Option<Context>,
+    dispatch: ToolDispatch,
+    args: Value,
+) -> Result<Value, ToolCallError> {
+    let Some(parent) = parent else {
+        return dispatch.call(args).await;
+    };
+    let tracer = global::tracer(TRACER);
+    let span = tracer
+        .span_builder("tool_call")
+        .with_attributes([KeyValue::new("codemode.tool", dispatch.tool_name.clone())])
+        .start_with_context(&tracer, &parent);
+    let context = parent.with_span(span);
+    let started = Instant::now();
+    let result = dispatch.call(args).with_context(context.clone()).await;
+
+    let span = context.span();
+    span.set_attribute(KeyValue::new(
+        "codemode.duration_ms",
+        started.elapsed().as_millis() as i64,
+    ));
+    if let Err(err) = &result {
+        span.set_attribute(KeyValue::new("codemode.error", err.to_string()));
+        span.set_status(Status::error(err.to_string()));
+    }
+    span.end();
+    result
+}
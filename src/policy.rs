@@ -0,0 +1,153 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::debug;
+
+use crate::tool::{Tool, ToolCallError};
+
+/// What a [`ToolPolicy`] decided for one call.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyDecision {
+    #[default]
+    Allow,
+    /// The call rejects in the script with this reason, so the model can change its plan.
+    Deny(String),
+    /// The call needs a host's confirmation; without one it is denied.
+    RequireApproval,
+}
+
+/// Consulted before every tool call a script makes, with the arguments the script passed.
+pub trait ToolPolicy: Send + Sync {
+    fn evaluate(&self, tool: &Tool, args: &Value) -> PolicyDecision;
+}
+
+/// A host's answer to an approval request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Approval {
+    Approved,
+    /// The call rejects in the script with this reason.
+    Rejected(String),
+}
+
+/// Asked about each call a [`ToolPolicy`] marks [`PolicyDecision::RequireApproval`]. The call
+/// waits until this resolves, e.g. after a human confirms in a UI.
+#[async_trait]
+pub trait ToolApprover: Send + Sync {
+    async fn approve(&self, tool: &Tool, args: &Value) -> Approval;
+}
+
+/// The tools a [`RulePolicy`] rule applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyTarget {
+    Name(String),
+    Tag(String),
+    Prefix(String),
+    /// Tools marked [`Tool::destructive`] (MCP `destructiveHint`).
+    Destructive,
+    /// Tools marked [`Tool::read_only`] (MCP `readOnlyHint`).
+    ReadOnly,
+}
+
+impl PolicyTarget {
+    pub fn matches(&self, tool: &Tool) -> bool {
+        match self {
+            Self::Name(name) => tool.name == *name,
+            Self::Tag(tag) => tool.tags.contains(tag),
+            Self::Prefix(prefix) => tool.name.starts_with(prefix.as_str()),
+            Self::Destructive => tool.destructive,
+            Self::ReadOnly => tool.read_only,
+        }
+    }
+}
+
+/// Static rules checked in order: the first whose target matches decides, and tools no rule
+/// matches get `fallback`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RulePolicy {
+    pub rules: Vec<(PolicyTarget, PolicyDecision)>,
+    pub fallback: PolicyDecision,
+}
+
+impl RulePolicy {
+    pub fn new(fallback: PolicyDecision) -> Self {
+        Self {
+            rules: Vec::new(),
+            fallback,
+        }
+    }
+
+    pub fn rule(mut self, target: PolicyTarget, decision: PolicyDecision) -> Self {
+        self.rules.push((target, decision));
+        self
+    }
+}
+
+impl ToolPolicy for RulePolicy {
+    fn evaluate(&self, tool: &Tool, _args: &Value) -> PolicyDecision {
+        self.rules
+            .iter()
+            .find(|(target, _)| target.matches(tool))
+            .map_or_else(|| self.fallback.clone(), |(_, decision)| decision.clone())
+    }
+}
+
+/// The client's approver, with how long a call waits for an answer before it is rejected.
+#[derive(Clone)]
+pub(crate) struct ApprovalGate {
+    pub(crate) approver: Arc<dyn ToolApprover>,
+    pub(crate) timeout: Duration,
+}
+
+impl ApprovalGate {
+    async fn request(&self, tool: &Tool, args: &Value) -> Approval {
+        debug!(tool = tool.name.as_str(), "tool call awaiting approval");
+        tokio::time::timeout(self.timeout, self.approver.approve(tool, args))
+            .await
+            .unwrap_or_else(|_| {
+                Approval::Rejected(format!(
+                    "approval timed out after {}ms",
+                    self.timeout.as_millis()
+                ))
+            })
+    }
+}
+
+/// A policy together with the tool it judges, carried by each injected tool.
+#[derive(Clone)]
+pub(crate) struct BoundPolicy {
+    pub(crate) policy: Arc<dyn ToolPolicy>,
+    pub(crate) approval: Option<ApprovalGate>,
+    pub(crate) tool: Tool,
+}
+
+impl BoundPolicy {
+    pub(crate) async fn check(&self, args: &Value) -> Result<(), ToolCallError> {
+        let reason = match self.policy.evaluate(&self.tool, args) {
+            PolicyDecision::Allow => return Ok(()),
+            PolicyDecision::Deny(reason) => reason,
+            PolicyDecision::RequireApproval => match &self.approval {
+                Some(gate) => match gate.request(&self.tool, args).await {
+                    Approval::Approved => return Ok(()),
+                    Approval::Rejected(reason) => reason,
+                },
+                None => "requires approval".to_string(),
+            },
+        };
+        debug!(
+            tool = self.tool.name.as_str(),
+            reason = reason.as_str(),
+            "tool call denied by policy"
+        );
+        Err(ToolCallError::Message(format!(
+            "'{}' denied by policy: {reason}",
+            self.tool.name
+        )))
+    }
+}
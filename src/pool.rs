@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, PoisonError, mpsc};
+
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+use crate::client::ToolCallerEntry;
+use crate::sandbox::{ExecutionExtras, ExecutionResult, PreparedSandbox, Sandbox, SandboxError};
+use crate::tool::Tool;
+use crate::ts_interface::ToolInterfaceGenerator;
+
+type JobResult = Result<ExecutionResult, SandboxError>;
+
+/// Fixed set of worker threads fed from a shared queue. Each keeps one isolate and, with
+/// `harden`, its prepared context too while jobs arrive with the same tools and sandbox
+/// settings; otherwise every job gets a fresh context on the worker's isolate.
+pub(crate) struct IsolatePool {
+    jobs: mpsc::Sender<PoolJob>,
+}
+
+struct PoolJob {
+    code: String,
+    tools: Vec<Tool>,
+    interface_generator: Arc<ToolInterfaceGenerator>,
+    callers: BTreeMap<String, ToolCallerEntry>,
+    cancel: CancellationToken,
+    extras: ExecutionExtras,
+    respond: oneshot::Sender<JobResult>,
+}
+
+impl IsolatePool {
+    /// `worker` builds the per-thread sandbox, again whenever a worker restarts after a panic;
+    /// workers stop once the pool is dropped.
+    pub(crate) fn start(
+        size: usize,
+        worker: impl Fn() -> Sandbox + Send + Sync + 'static,
+    ) -> Result<Self, SandboxError> {
+        let (jobs, queue) = mpsc::channel::<PoolJob>();
+        let queue = Arc::new(Mutex::new(queue));
+        let worker = Arc::new(worker);
+        for index in 0..size.max(1) {
+            let queue = queue.clone();
+            let worker = worker.clone();
+            std::thread::Builder::new()
+                .name(format!("codemode-isolate-{index}"))
+                .spawn(move || {
+                    // A panic loses the job in hand and the worker's isolate; the worker starts
+                    // over with fresh ones rather than leaving the pool a thread short.
+                    while std::panic::catch_unwind(AssertUnwindSafe(|| {
+                        run_worker(worker(), &queue)
+                    }))
+                    .is_err()
+                    {
+                        warn!(
+                            worker = index,
+                            "isolate pool worker panicked; restarting it"
+                        );
+                    }
+                })
+                .map_err(|err| SandboxError::V8(format!("isolate pool worker: {err}")))?;
+        }
+        Ok(Self { jobs })
+    }
+
+    pub(crate) async fn execute(
+        &self,
+        code: &str,
+        tools: Vec<Tool>,
+        interface_generator: Arc<ToolInterfaceGenerator>,
+        callers: BTreeMap<String, ToolCallerEntry>,
+        cancel: CancellationToken,
+        extras: ExecutionExtras,
+    ) -> JobResult {
+        let (respond, response) = oneshot::channel();
+        let job = PoolJob {
+            code: code.to_string(),
+            tools,
+            interface_generator,
+            callers,
+            cancel,
+            extras,
+            respond,
+        };
+        self.jobs
+            .send(job)
+            .map_err(|_| SandboxError::V8("isolate pool stopped".to_string()))?;
+        response
+            .await
+            .map_err(|_| SandboxError::V8("isolate pool worker exited".to_string()))?
+    }
+}
+
+/// What a prepared context was built from; a job with an equal key can run in it.
+#[derive(PartialEq, Eq)]
+struct ContextKey {
+    revision: u64,
+    interface_generator: usize,
+    tools: Vec<String>,
+    callers: Vec<u64>,
+}
+
+impl ContextKey {
+    fn new(revision: u64, job: &PoolJob) -> Self {
+        Self {
+            revision,
+            interface_generator: Arc::as_ptr(&job.interface_generator) as usize,
+            tools: job.tools.iter().map(|tool| tool.name.clone()).collect(),
+            callers: job.callers.values().map(|entry| entry.revision).collect(),
+        }
+    }
+}
+
+fn run_worker(sandbox: Sandbox, queue: &Mutex<mpsc::Receiver<PoolJob>>) {
+    let mut warm: Option<(ContextKey, PreparedSandbox)> = None;
+    let mut isolate = None;
+    loop {
+        let job = {
+            let queue = queue.lock().unwrap_or_else(PoisonError::into_inner);
+            match queue.recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            }
+        };
+        if job.cancel.is_cancelled() {
+            let _ = job.respond.send(Err(SandboxError::Cancelled));
+            continue;
+        }
+        let key = sandbox
+            .context_revision()
+            .map(|revision| ContextKey::new(revision, &job));
+        let prepared = match warm.take() {
+            Some((warm_key, prepared)) if key.as_ref() == Some(&warm_key) => Ok(prepared),
+            stale => {
+                let reused = stale
+                    .map(|(_, prepared)| prepared.into_isolate())
+                    .or_else(|| isolate.take())
+                    .unwrap_or_else(|| sandbox.create_isolate());
+                let tools = job.tools.iter().collect::<Vec<&Tool>>();
+                sandbox.prepare_in(reused, &tools, &job.interface_generator, &job.callers)
+            }
+        };
+        let result = prepared.and_then(|mut prepared| {
+            let result = prepared.execute_with(&job.code, &job.cancel, job.extras);
+            // Only a context that finished cleanly is kept; a failed run may have been cut off
+            // halfway through changing it.
+            match key {
+                Some(key) if result.is_ok() && prepared.forget_globals().is_ok() => {
+                    warm = Some((key, prepared));
+                }
+                _ => isolate = Some(prepared.into_isolate()),
+            }
+            result
+        });
+        debug!(ok = result.is_ok(), "isolate pool job done");
+        let _ = job.respond.send(result);
+    }
+}
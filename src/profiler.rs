@@ -0,0 +1,172 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use serde_json::{Value, json};
+use v8::inspector::{
+    Channel, ChannelImpl, StringBuffer, StringView, V8Inspector, V8InspectorClient,
+    V8InspectorClientImpl, V8InspectorClientTrustLevel, V8InspectorSession,
+};
+
+use crate::sandbox::SandboxError;
+
+const CONTEXT_GROUP_ID: i32 = 1;
+
+// Nodes V8 adds for time spent outside JS; host time is reported as `idle_ms` instead.
+const HOST_NODES: [&str; 2] = ["(program)", "(idle)"];
+
+/// V8's CPU profiler for one prepared context, driven through the inspector's `Profiler`
+/// domain on an in-process session.
+pub(crate) struct CpuProfiler {
+    // Session before inspector: a session must not outlive the inspector it was opened on.
+    session: V8InspectorSession,
+    _inspector: Option<V8Inspector>,
+    replies: Rc<RefCell<BTreeMap<i32, String>>>,
+    next_id: Cell<i32>,
+}
+
+impl CpuProfiler {
+    /// Profiles `isolate` through an inspector of its own. An isolate has one inspector, so
+    /// when DevTools is configured use [`CpuProfiler::attach`] to its inspector instead.
+    pub(crate) fn new(isolate: &mut v8::Isolate, interval_us: u64) -> Result<Self, SandboxError> {
+        let inspector = V8Inspector::create(isolate, V8InspectorClient::new(Box::new(Client)));
+        let mut profiler = Self::attach(&inspector, interval_us)?;
+        profiler._inspector = Some(inspector);
+        Ok(profiler)
+    }
+
+    pub(crate) fn attach(inspector: &V8Inspector, interval_us: u64) -> Result<Self, SandboxError> {
+        let replies = Rc::new(RefCell::new(BTreeMap::new()));
+        let session = inspector.connect(
+            CONTEXT_GROUP_ID,
+            Channel::new(Box::new(Replies(replies.clone()))),
+            StringView::empty(),
+            V8InspectorClientTrustLevel::FullyTrusted,
+        );
+        let profiler = Self {
+            session,
+            _inspector: None,
+            replies,
+            next_id: Cell::new(0),
+        };
+        profiler.call("Profiler.enable", json!({}))?;
+        profiler.call(
+            "Profiler.setSamplingInterval",
+            json!({ "interval": interval_us.max(1) }),
+        )?;
+        Ok(profiler)
+    }
+
+    pub(crate) fn start(&self) -> Result<(), SandboxError> {
+        self.call("Profiler.start", json!({})).map(drop)
+    }
+
+    /// Ends the profile begun by [`CpuProfiler::start`] and folds it into stacks (`outer;inner`,
+    /// root first) with their sample counts.
+    pub(crate) fn stop(&self) -> Result<BTreeMap<String, u64>, SandboxError> {
+        let result = self.call("Profiler.stop", json!({}))?;
+        Ok(fold(&result["profile"]))
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, SandboxError> {
+        let id = self.next_id.get() + 1;
+        self.next_id.set(id);
+        let message = json!({ "id": id, "method": method, "params": params }).to_string();
+        // Profiler replies are sent before the dispatch returns.
+        self.session
+            .dispatch_protocol_message(StringView::from(message.as_bytes()));
+        let reply = self
+            .replies
+            .borrow_mut()
+            .remove(&id)
+            .ok_or_else(|| SandboxError::V8(format!("profiler: no reply to {method}")))?;
+        let mut reply: Value = serde_json::from_str(&reply)
+            .map_err(|err| SandboxError::Serialization(err.to_string()))?;
+        if let Some(error) = reply.get("error") {
+            return Err(SandboxError::V8(format!(
+                "profiler {method}: {}",
+                error["message"].as_str().unwrap_or("failed")
+            )));
+        }
+        Ok(reply["result"].take())
+    }
+}
+
+/// Folds a `Profiler.Profile` into stacks keyed by `function:line` frames.
+fn fold(profile: &Value) -> BTreeMap<String, u64> {
+    let mut frames = BTreeMap::new();
+    let mut parents = BTreeMap::new();
+    for node in profile["nodes"].as_array().into_iter().flatten() {
+        let Some(id) = node["id"].as_u64() else {
+            continue;
+        };
+        for child in node["children"].as_array().into_iter().flatten() {
+            if let Some(child) = child.as_u64() {
+                parents.insert(child, id);
+            }
+        }
+        frames.insert(id, frame(&node["callFrame"]));
+    }
+    let mut hits = BTreeMap::<u64, u64>::new();
+    for sample in profile["samples"].as_array().into_iter().flatten() {
+        if let Some(id) = sample.as_u64() {
+            *hits.entry(id).or_default() += 1;
+        }
+    }
+
+    let mut stacks = BTreeMap::new();
+    for (id, count) in hits {
+        let Some(frame) = frames.get(&id) else {
+            continue;
+        };
+        if HOST_NODES.contains(&frame.as_str()) {
+            continue;
+        }
+        // The parentless node is V8's `(root)`, which is not a frame.
+        let mut stack = Vec::new();
+        let mut node = id;
+        while let Some(&parent) = parents.get(&node) {
+            stack.extend(frames.get(&node).cloned());
+            node = parent;
+        }
+        if stack.is_empty() {
+            continue;
+        }
+        stack.reverse();
+        *stacks.entry(stack.join(";")).or_default() += count;
+    }
+    stacks
+}
+
+fn frame(call_frame: &Value) -> String {
+    let name = call_frame["functionName"]
+        .as_str()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("(anonymous)");
+    match call_frame["lineNumber"].as_i64() {
+        // Protocol line numbers are zero-based; V8's own nodes have none.
+        Some(line) if line >= 0 => format!("{name}:{}", line + 1),
+        _ => name.to_string(),
+    }
+}
+
+struct Client;
+
+impl V8InspectorClientImpl for Client {}
+
+/// Keeps protocol replies by call id; notifications are not needed.
+struct Replies(Rc<RefCell<BTreeMap<i32, String>>>);
+
+impl ChannelImpl for Replies {
+    fn send_response(&self, call_id: i32, message: v8::UniquePtr<StringBuffer>) {
+        if let Some(message) = message.as_ref() {
+            self.0
+                .borrow_mut()
+                .insert(call_id, message.string().to_string());
+        }
+    }
+
+    fn send_notification(&self, _message: v8::UniquePtr<StringBuffer>) {}
+
+    fn flush_protocol_notifications(&self) {}
+}
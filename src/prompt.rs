@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The template used when [`PromptTemplate::template`] is left at its default.
+pub const DEFAULT_TEMPLATE: &str = "\
+{{intro}}
+
+## Rules
+
+{{rules}}
+
+## Tools
+
+Available tools ({{tool_count}}), callable as functions in the sandbox:
+
+```typescript
+{{interfaces}}
+```
+
+## Constraints
+
+{{constraints}}
+
+## Examples
+
+{{examples}}";
+
+/// Assembles a code-mode system prompt from a template with `{{placeholder}}`s:
+/// `intro`, `rules`, `interfaces`, `tool_count`, `constraints`, `examples`, plus any key in
+/// `variables`. Sections left empty are dropped along with their `##` heading.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptTemplate {
+    pub template: String,
+    pub intro: String,
+    pub rules: Vec<String>,
+    pub constraints: Vec<String>,
+    /// Example scripts, rendered as fenced JavaScript blocks.
+    pub examples: Vec<String>,
+    pub variables: BTreeMap<String, String>,
+}
+
+impl Default for PromptTemplate {
+    fn default() -> Self {
+        Self {
+            template: DEFAULT_TEMPLATE.to_string(),
+            intro: "You solve tasks by writing JavaScript that calls tools. Reply with one script \
+                    per turn; it runs in a sandbox and its return value is sent back to you."
+                .to_string(),
+            rules: vec![
+                "Call tools as the functions declared below; no other tools exist.".to_string(),
+                "`await` tools that return a Promise and use `for await` for AsyncIterables."
+                    .to_string(),
+                "Combine several tool calls in one script instead of one call per turn."
+                    .to_string(),
+                "`return` the final result; only returned values are visible.".to_string(),
+            ],
+            constraints: Vec::new(),
+            examples: Vec::new(),
+            variables: BTreeMap::new(),
+        }
+    }
+}
+
+impl PromptTemplate {
+    pub fn render(&self, interfaces: &str, tool_count: usize) -> String {
+        let mut values = vec![
+            ("intro", self.intro.clone()),
+            ("rules", bullets(&self.rules)),
+            ("interfaces", interfaces.trim().to_string()),
+            ("tool_count", tool_count.to_string()),
+            ("constraints", bullets(&self.constraints)),
+            ("examples", examples(&self.examples)),
+        ];
+        values.extend(
+            self.variables
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.clone())),
+        );
+
+        let mut prompt = drop_empty_sections(&self.template, &values);
+        for (key, value) in &values {
+            prompt = prompt.replace(&format!("{{{{{key}}}}}"), value);
+        }
+        prompt.trim().to_string()
+    }
+}
+
+fn bullets(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| format!("- {item}"))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn examples(scripts: &[String]) -> String {
+    scripts
+        .iter()
+        .map(|script| format!("```javascript\n{}\n```", script.trim()))
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+// Removes `## Heading` sections whose only content is a placeholder that renders empty.
+fn drop_empty_sections(template: &str, values: &[(&str, String)]) -> String {
+    let empty = values
+        .iter()
+        .filter(|(_, value)| value.is_empty())
+        .map(|(key, _)| format!("{{{{{key}}}}}"))
+        .collect::<Vec<String>>();
+    template
+        .split("\n## ")
+        .enumerate()
+        .filter(|(index, section)| {
+            let body = section.split_once('\n').map_or("", |(_, body)| body).trim();
+            *index == 0 || !empty.iter().any(|placeholder| body == placeholder)
+        })
+        .map(|(_, section)| section)
+        .collect::<Vec<&str>>()
+        .join("\n## ")
+}
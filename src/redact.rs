@@ -0,0 +1,78 @@
+use serde_json::{Map, Value};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Scrubs secrets from tool arguments, results and script output before they are logged.
+pub trait Redactor: Send + Sync {
+    fn redact(&self, value: &Value) -> Value;
+}
+
+/// Replaces the values of configured object keys (matched case-insensitively, at any depth)
+/// and every occurrence of configured secret strings with `[REDACTED]`.
+#[derive(Debug, Clone, Default)]
+pub struct SecretRedactor {
+    keys: Vec<String>,
+    secrets: Vec<String>,
+}
+
+impl SecretRedactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.keys.push(key.into().to_lowercase());
+        self
+    }
+
+    pub fn secret(mut self, secret: impl Into<String>) -> Self {
+        let secret = secret.into();
+        if !secret.is_empty() {
+            self.secrets.push(secret);
+        }
+        self
+    }
+
+    fn redact_text(&self, text: &str) -> String {
+        self.secrets.iter().fold(text.to_string(), |text, secret| {
+            text.replace(secret, REDACTED)
+        })
+    }
+}
+
+impl Redactor for SecretRedactor {
+    fn redact(&self, value: &Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.redact_text(text)),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|item| self.redact(item)).collect())
+            }
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(key, item)| {
+                        let item = if self.keys.contains(&key.to_lowercase()) {
+                            Value::String(REDACTED.to_string())
+                        } else {
+                            self.redact(item)
+                        };
+                        (key.clone(), item)
+                    })
+                    .collect::<Map<String, Value>>(),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+/// Renders `value` for logging, redacted first when a redactor is configured.
+pub(crate) fn format_value(value: &Value, redactor: Option<&dyn Redactor>) -> String {
+    let redacted;
+    let value = match redactor {
+        Some(redactor) => {
+            redacted = redactor.redact(value);
+            &redacted
+        }
+        None => value,
+    };
+    serde_json::to_string(value).unwrap_or_else(|_| "<unserializable>".to_string())
+}
@@ -1,494 +1,1239 @@
-use std::cell::{Cell, RefCell};
-use std::collections::HashMap;
-use std::ffi::c_void;
-use std::sync::Once;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+#[cfg(feature = "v8")]
+use std::sync::Mutex;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::mpsc;
-use std::time::{Duration, Instant};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use derive_builder::Builder;
-use serde_json::Value;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
 use thiserror::Error;
-use tracing::{debug, trace};
-use v8;
+use tokio::sync::Semaphore;
+use tracing::trace;
 
-use crate::tool::Tool;
-use crate::ts_interface::ToolInterfaceGenerator;
+use crate::blob::{BlobOffload, BlobStore};
+use crate::client::{CallerKind, ToolCallerEntry};
+use crate::dispatch::{CallCache, FallbackTarget, ResultCache, ToolDispatch, TraceLog};
+use crate::fetch::FetchConfig;
+use crate::inspector::InspectorConfig;
+use crate::memory::MemoryStore;
+use crate::policy::{ApprovalGate, BoundPolicy, ToolPolicy};
+#[cfg(feature = "v8")]
+use crate::pool::IsolatePool;
+use crate::redact::Redactor;
+use crate::schema::JsonSchema;
+use crate::tool::{
+    AsyncToolCaller, GlobalFn, ResultTransformer, StreamingToolCaller, SyncToolCaller, Tool,
+    ToolErrorFn, ToolInterceptor,
+};
+use crate::ts_interface::{InterfaceOptions, ToolInterfaceGenerator};
+use crate::validate::SchemaCheck;
+
+#[cfg(feature = "v8")]
+mod engine;
+
+#[cfg(feature = "v8")]
+use engine::ToolRuntime;
+#[cfg(feature = "v8")]
+pub use engine::{PreparedSandbox, StartupSnapshot};
+#[cfg(feature = "v8")]
+pub(crate) use engine::{ensure_namespace, run_script, throw_error};
 
 #[derive(Debug, Error)]
 pub enum SandboxError {
     #[error("v8 error: {0}")]
     V8(String),
+    /// A failure inside a script engine other than V8, such as a [`ScriptBackend`]'s.
+    ///
+    /// [`ScriptBackend`]: crate::backend::ScriptBackend
+    #[error("script engine error: {0}")]
+    Engine(String),
     #[error("tool call error: {0}")]
     Tool(String),
     #[error("serialization error: {0}")]
     Serialization(String),
+    #[error("timed out after {0}ms waiting for an execution slot")]
+    QueueTimeout(u64),
+    #[error("instruction budget exceeded after {0} ticks")]
+    BudgetExceeded(u64),
+    #[error("execution timed out after {0}ms")]
+    Timeout(u64),
+    #[error("execution cancelled")]
+    Cancelled,
+    /// The code calls a registered tool left out of the execution's tool subset.
+    #[error("tool '{0}' is not available to this execution")]
+    ToolNotAllowed(String),
+    /// The returned value could not be deserialized into the type the host asked for.
+    #[error("result does not match `{expected}`: {message}")]
+    ResultShape { expected: String, message: String },
+    /// The final value or a single tool result serialized past `SandboxConfig::max_result_bytes`.
+    #[error("result of {size} bytes exceeds the {limit} byte limit")]
+    ResultTooLarge { size: usize, limit: usize },
+    /// TypeScript could not be stripped; the message ends with the 1-based line and column in
+    /// the submitted code.
+    #[error("typescript error: {0}")]
+    Transpile(String),
+    /// An uncaught exception or rejection from user code. `line` and `column` are 1-based and
+    /// point into the submitted code.
+    #[error("script error: {message}")]
+    Script {
+        message: String,
+        stack: Option<String>,
+        line: Option<usize>,
+        column: Option<usize>,
+    },
+}
+
+/// Problems found by [`Sandbox::validate`] without running the code.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[error("{}", .diagnostics.iter().map(ToString::to_string).collect::<Vec<String>>().join("; "))]
+pub struct ScriptDiagnostics {
+    pub diagnostics: Vec<ScriptDiagnostic>,
+}
+
+/// One compile error. `line` and `column` are 1-based and point into the submitted code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptDiagnostic {
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
 }
 
-#[derive(Debug, Clone, Builder)]
+impl std::fmt::Display for ScriptDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "{line}:{column}: {}", self.message),
+            (Some(line), None) => write!(f, "{line}: {}", self.message),
+            _ => f.write_str(&self.message),
+        }
+    }
+}
+
+impl From<SandboxError> for ScriptDiagnostics {
+    fn from(err: SandboxError) -> Self {
+        let diagnostic = match err {
+            SandboxError::Script {
+                message,
+                line,
+                column,
+                ..
+            } => ScriptDiagnostic {
+                message,
+                line,
+                column,
+            },
+            SandboxError::Transpile(message) => ScriptDiagnostic {
+                message,
+                line: None,
+                column: None,
+            },
+            other => ScriptDiagnostic {
+                message: other.to_string(),
+                line: None,
+                column: None,
+            },
+        };
+        Self {
+            diagnostics: vec![diagnostic],
+        }
+    }
+}
+
+#[derive(Clone, Builder, Serialize, Deserialize)]
 #[builder(pattern = "owned")]
 pub struct SandboxConfig {
+    /// Wall-clock limit per execution; a watchdog terminates even synchronous loops.
     #[builder(default = "30000")]
+    #[serde(default = "default_timeout_ms")]
     pub timeout_ms: u64,
     #[builder(default = "128")]
+    #[serde(default = "default_max_heap_mb")]
     pub max_heap_mb: usize,
-    #[builder(setter(custom))]
-    pub runtime_handle: tokio::runtime::Handle,
+    /// Initial heap size per isolate; 0 lets V8 pick.
+    #[builder(default)]
+    #[serde(default)]
+    pub initial_heap_mb: usize,
+    /// Extra V8 flags such as `--max-lazy`. V8 flags are process-wide: only the configuration
+    /// of the first sandbox to start V8 applies, and the same goes for `stack_size_kb`,
+    /// `jitless` and `allow_code_generation_from_strings`.
+    #[builder(default)]
+    #[serde(default)]
+    pub v8_flags: Vec<String>,
+    /// JS stack size (`--stack-size`); deep recursion throws a `RangeError` past it.
+    #[builder(default)]
+    #[serde(default)]
+    pub stack_size_kb: Option<usize>,
+    /// Interpret bytecode only (`--jitless`): slower, but no executable memory is generated.
+    #[builder(default)]
+    #[serde(default)]
+    pub jitless: bool,
+    /// When false, `eval` and `new Function` throw (`--disallow-code-generation-from-strings`).
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub allow_code_generation_from_strings: bool,
+    /// When the heap nears `max_heap_mb`, write a V8 heap snapshot into this directory (once per
+    /// isolate) before the limit is hit.
+    #[builder(default)]
+    #[serde(default)]
+    pub heap_snapshot_dir: Option<PathBuf>,
+    /// Serve each prepared context to Chrome DevTools. Needs the `inspector` feature.
+    #[builder(default)]
+    #[serde(default)]
+    pub inspector: Option<InspectorConfig>,
+    #[builder(default)]
+    #[serde(default)]
+    pub wrap_mode: WrapMode,
+    #[builder(default)]
+    #[serde(default)]
+    pub strict: bool,
+    /// Lock the realm down before scripts run: freeze the built-in prototypes, disable `eval`
+    /// and the `Function` constructors, and delete built-in globals outside a safe list.
+    #[builder(default)]
+    #[serde(default)]
+    pub harden: bool,
+    /// Optional globals to install; all off by default.
+    #[builder(default)]
+    #[serde(default)]
+    pub capabilities: SandboxCapabilities,
+    #[builder(default)]
+    #[serde(default)]
+    pub fetch: FetchConfig,
+    /// Accept TypeScript source by stripping types before compiling. Needs the `typescript`
+    /// feature.
+    #[builder(default)]
+    #[serde(default)]
+    pub typescript: bool,
+    /// Flag `undefined` results via `ExecutionResult::undefined` instead of folding them into `null`.
+    #[builder(default)]
+    #[serde(default)]
+    pub distinguish_undefined: bool,
+    /// Attach the interface hash and injected tool list to each `ExecutionResult`.
+    #[builder(default)]
+    #[serde(default)]
+    pub report_interfaces: bool,
+    /// Deterministic guard for runaway loops: terminate once JS has been interrupted this many
+    /// times. Each tick is one `request_interrupt` serviced while code runs.
+    #[builder(default)]
+    #[serde(default)]
+    pub interrupt_budget: Option<u64>,
+    #[builder(default = "1000")]
+    #[serde(default = "default_interrupt_tick_us")]
+    pub interrupt_tick_us: u64,
+    /// Run V8's CPU profiler during each execution, sampling every this many microseconds, and
+    /// return the sampled stacks in `ExecutionResult::stack_samples`.
+    #[builder(default)]
+    #[serde(default)]
+    pub stack_sample_interval_us: Option<u64>,
+    /// Maximum `setTimeout`/`setInterval` registrations per execution; further calls throw.
+    #[builder(default = "1000")]
+    #[serde(default = "default_max_timers")]
+    pub max_timers: u64,
+    /// Maximum tool invocations per execution; further calls reject with a budget error.
+    #[builder(default)]
+    #[serde(default)]
+    pub max_tool_calls: Option<u64>,
+    /// Fail the execution with `SandboxError::ResultTooLarge` when its result, or any single
+    /// tool result, serializes to more JSON bytes than this.
+    #[builder(default)]
+    #[serde(default)]
+    pub max_result_bytes: Option<usize>,
+    /// Resolve failed tool calls to `{ ok: false, error: { tool, message } }` instead of
+    /// rejecting, so one failure does not abort a `Promise.all`.
+    #[builder(default)]
+    #[serde(default)]
+    pub continue_on_error: bool,
+    /// What happens when a script calls a tool marked `deprecated`.
+    #[builder(default)]
+    #[serde(default)]
+    pub deprecated_tools: DeprecatedToolPolicy,
+    /// Checks the arguments of each tool call against the tool's `inputs` schema before it is
+    /// dispatched. Defaults to `Warn`; `Reject` keeps malformed calls from reaching the tool.
+    #[builder(default = "SchemaValidation::Warn")]
+    #[serde(default = "default_input_validation")]
+    pub input_validation: SchemaValidation,
+    /// Checks each tool result against the tool's `outputs` schema, to catch servers whose
+    /// declared output schema does not match what they return. Streamed chunks are not checked.
+    #[builder(default)]
+    #[serde(default)]
+    pub output_validation: SchemaValidation,
+    /// Maximum tool calls in flight at once within a sandbox; extra calls queue. Streams are
+    /// not counted.
+    #[builder(default)]
+    #[serde(default)]
+    pub max_concurrent_tool_calls: Option<usize>,
+    /// Default limit for a single tool call; the call's promise rejects when it passes.
+    /// `ToolOptions::timeout_ms` overrides it per tool.
+    #[builder(default)]
+    #[serde(default)]
+    pub tool_timeout_ms: Option<u64>,
+    /// Script run once per context after tools are injected, before any user code; its
+    /// top-level declarations (helpers, policies) are visible to every execution.
+    #[builder(default)]
+    #[serde(default)]
+    pub preamble: Option<String>,
+    /// Body of a JS `function(result) { ... }` applied to each execution's result; it may
+    /// return a promise.
+    #[builder(default)]
+    #[serde(default)]
+    pub postamble: Option<String>,
+    /// Exposed to every execution as the deeply frozen `env` global, e.g. user id, locale or
+    /// feature flags.
+    #[builder(default)]
+    #[serde(default)]
+    pub env: Option<Value>,
+    /// Reuse results of identical `(tool, args)` calls within one execution. Hit and miss counts
+    /// are reported in `ExecutionResult::cache`.
+    #[builder(default)]
+    #[serde(default)]
+    pub cache_tool_results: bool,
+    /// Virtual modules scripts can `import` by name; see [`SandboxConfig::register_module`].
+    #[builder(default)]
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+    /// Tool results whose JSON encoding exceeds this size are moved into `blob_store`.
+    #[builder(default)]
+    #[serde(default)]
+    pub blob_threshold_bytes: Option<usize>,
+    #[builder(setter(custom), default)]
+    #[serde(skip)]
+    pub blob_store: Option<Arc<dyn BlobStore>>,
+    /// Run executions on this many worker threads, each reusing one isolate, instead of
+    /// creating an isolate per call on the calling thread.
+    #[builder(default)]
+    #[serde(default)]
+    pub pool_size: Option<usize>,
+    /// Run tool calls on a dedicated runtime instead of `runtime_handle`, so slow or
+    /// CPU-heavy tools cannot starve the host.
+    #[builder(default)]
+    #[serde(default)]
+    pub tool_runtime: Option<ToolRuntimeConfig>,
+    /// Runtime that tool calls run on. Not serialized; when unset, executions use the ambient
+    /// Tokio runtime and fail outside one.
+    #[builder(setter(custom), default)]
+    #[serde(skip)]
+    pub runtime_handle: Option<tokio::runtime::Handle>,
 }
 
 impl SandboxConfigBuilder {
     pub fn runtime_handle(mut self, handle: tokio::runtime::Handle) -> Self {
-        self.runtime_handle = Some(handle);
+        self.runtime_handle = Some(Some(handle));
         self
     }
+
+    pub fn blob_store(mut self, store: Arc<dyn BlobStore>) -> Self {
+        self.blob_store = Some(Some(store));
+        self
+    }
+}
+
+impl std::fmt::Debug for SandboxConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SandboxConfig")
+            .field("timeout_ms", &self.timeout_ms)
+            .field("max_heap_mb", &self.max_heap_mb)
+            .field("initial_heap_mb", &self.initial_heap_mb)
+            .field("v8_flags", &self.v8_flags)
+            .field("stack_size_kb", &self.stack_size_kb)
+            .field("jitless", &self.jitless)
+            .field(
+                "allow_code_generation_from_strings",
+                &self.allow_code_generation_from_strings,
+            )
+            .field("heap_snapshot_dir", &self.heap_snapshot_dir)
+            .field("inspector", &self.inspector)
+            .field("wrap_mode", &self.wrap_mode)
+            .field("strict", &self.strict)
+            .field("harden", &self.harden)
+            .field("capabilities", &self.capabilities)
+            .field("fetch", &self.fetch)
+            .field("typescript", &self.typescript)
+            .field("distinguish_undefined", &self.distinguish_undefined)
+            .field("report_interfaces", &self.report_interfaces)
+            .field("interrupt_budget", &self.interrupt_budget)
+            .field("interrupt_tick_us", &self.interrupt_tick_us)
+            .field("stack_sample_interval_us", &self.stack_sample_interval_us)
+            .field("max_timers", &self.max_timers)
+            .field("max_tool_calls", &self.max_tool_calls)
+            .field("max_result_bytes", &self.max_result_bytes)
+            .field("continue_on_error", &self.continue_on_error)
+            .field("deprecated_tools", &self.deprecated_tools)
+            .field("input_validation", &self.input_validation)
+            .field("output_validation", &self.output_validation)
+            .field("max_concurrent_tool_calls", &self.max_concurrent_tool_calls)
+            .field("tool_timeout_ms", &self.tool_timeout_ms)
+            .field("preamble", &self.preamble)
+            .field("postamble", &self.postamble)
+            .field("env", &self.env)
+            .field("cache_tool_results", &self.cache_tool_results)
+            .field("modules", &self.modules.keys().collect::<Vec<&String>>())
+            .field("blob_threshold_bytes", &self.blob_threshold_bytes)
+            .field("pool_size", &self.pool_size)
+            .field("tool_runtime", &self.tool_runtime)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for SandboxConfig {
+    /// The same settings a deserialized empty object or a default builder produces, with no
+    /// runtime handle.
+    fn default() -> Self {
+        Self {
+            timeout_ms: default_timeout_ms(),
+            max_heap_mb: default_max_heap_mb(),
+            initial_heap_mb: 0,
+            v8_flags: Vec::new(),
+            stack_size_kb: None,
+            jitless: false,
+            allow_code_generation_from_strings: true,
+            heap_snapshot_dir: None,
+            inspector: None,
+            wrap_mode: WrapMode::default(),
+            strict: false,
+            harden: false,
+            capabilities: SandboxCapabilities::default(),
+            fetch: FetchConfig::default(),
+            typescript: false,
+            distinguish_undefined: false,
+            report_interfaces: false,
+            interrupt_budget: None,
+            interrupt_tick_us: default_interrupt_tick_us(),
+            stack_sample_interval_us: None,
+            max_timers: default_max_timers(),
+            max_tool_calls: None,
+            max_result_bytes: None,
+            continue_on_error: false,
+            deprecated_tools: DeprecatedToolPolicy::default(),
+            input_validation: default_input_validation(),
+            output_validation: SchemaValidation::default(),
+            max_concurrent_tool_calls: None,
+            tool_timeout_ms: None,
+            preamble: None,
+            postamble: None,
+            env: None,
+            cache_tool_results: false,
+            modules: BTreeMap::new(),
+            blob_threshold_bytes: None,
+            blob_store: None,
+            pool_size: None,
+            tool_runtime: None,
+            runtime_handle: None,
+        }
+    }
 }
 
 impl SandboxConfig {
     pub fn new(runtime_handle: tokio::runtime::Handle) -> Self {
         Self {
-            timeout_ms: 30000,
-            max_heap_mb: 128,
-            runtime_handle,
+            runtime_handle: Some(runtime_handle),
+            ..Self::default()
+        }
+    }
+
+    /// `runtime_handle`, or the runtime the caller is running in.
+    #[cfg_attr(not(feature = "v8"), allow(dead_code))]
+    pub(crate) fn runtime(&self) -> Result<tokio::runtime::Handle, SandboxError> {
+        match &self.runtime_handle {
+            Some(handle) => Ok(handle.clone()),
+            None => tokio::runtime::Handle::try_current().map_err(|_| {
+                SandboxError::V8(
+                    "no Tokio runtime: set SandboxConfig::runtime_handle or execute inside one"
+                        .to_string(),
+                )
+            }),
+        }
+    }
+
+    /// Makes `source` (an ES module) importable as `name`, e.g.
+    /// `import { groupBy } from "utils"`. Async-function scripts may start with such imports.
+    pub fn register_module(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        self.modules.insert(name.into(), source.into());
+    }
+
+    pub fn schema() -> JsonSchema {
+        json!({
+            "title": "SandboxConfig",
+            "type": "object",
+            "properties": {
+                "timeout_ms": { "type": "integer", "minimum": 0, "default": default_timeout_ms() },
+                "max_heap_mb": { "type": "integer", "minimum": 1, "default": default_max_heap_mb() },
+                "initial_heap_mb": { "type": "integer", "minimum": 0, "default": 0 },
+                "v8_flags": { "type": "array", "items": { "type": "string" } },
+                "stack_size_kb": { "type": ["integer", "null"], "minimum": 1 },
+                "jitless": { "type": "boolean", "default": false },
+                "allow_code_generation_from_strings": { "type": "boolean", "default": true },
+                "heap_snapshot_dir": { "type": ["string", "null"] },
+                "inspector": {
+                    "type": ["object", "null"],
+                    "properties": {
+                        "addr": { "type": "string", "default": "127.0.0.1:9229" },
+                        "wait_for_debugger": { "type": "boolean", "default": false }
+                    }
+                },
+                "wrap_mode": {
+                    "type": "string",
+                    "enum": ["async_function", "expression", "module"],
+                    "default": "async_function"
+                },
+                "strict": { "type": "boolean", "default": false },
+                "harden": { "type": "boolean", "default": false },
+                "capabilities": {
+                    "type": "object",
+                    "properties": {
+                        "timers": { "type": "boolean", "default": false },
+                        "crypto": { "type": "boolean", "default": false },
+                        "encoding": { "type": "boolean", "default": false },
+                        "platform": { "type": "boolean", "default": false },
+                        "console": { "type": "boolean", "default": false },
+                        "fetch": { "type": "boolean", "default": false }
+                    }
+                },
+                "fetch": {
+                    "type": "object",
+                    "properties": {
+                        "allowed_hosts": { "type": "array", "items": { "type": "string" } },
+                        "max_response_bytes": { "type": "integer", "minimum": 0, "default": 1048576 },
+                        "timeout_ms": { "type": "integer", "minimum": 0, "default": 10000 }
+                    }
+                },
+                "typescript": { "type": "boolean", "default": false },
+                "distinguish_undefined": { "type": "boolean", "default": false },
+                "report_interfaces": { "type": "boolean", "default": false },
+                "interrupt_budget": { "type": ["integer", "null"], "minimum": 1 },
+                "interrupt_tick_us": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "default": default_interrupt_tick_us()
+                },
+                "stack_sample_interval_us": { "type": ["integer", "null"], "minimum": 1 },
+                "max_timers": { "type": "integer", "minimum": 0, "default": default_max_timers() },
+                "max_tool_calls": { "type": ["integer", "null"], "minimum": 0 },
+                "max_result_bytes": { "type": ["integer", "null"], "minimum": 1 },
+                "continue_on_error": { "type": "boolean", "default": false },
+                "deprecated_tools": {
+                    "type": "string",
+                    "enum": ["allow", "warn", "reject"],
+                    "default": "warn"
+                },
+                "input_validation": {
+                    "type": "string",
+                    "enum": ["off", "warn", "reject"],
+                    "default": "warn"
+                },
+                "output_validation": {
+                    "type": "string",
+                    "enum": ["off", "warn", "reject"],
+                    "default": "off"
+                },
+                "max_concurrent_tool_calls": { "type": ["integer", "null"], "minimum": 1 },
+                "tool_timeout_ms": { "type": ["integer", "null"], "minimum": 1 },
+                "preamble": { "type": ["string", "null"] },
+                "postamble": { "type": ["string", "null"] },
+                "env": {},
+                "cache_tool_results": { "type": "boolean", "default": false },
+                "modules": { "type": "object", "additionalProperties": { "type": "string" } },
+                "blob_threshold_bytes": { "type": ["integer", "null"], "minimum": 0 },
+                "pool_size": { "type": ["integer", "null"], "minimum": 1 },
+                "tool_runtime": {
+                    "type": ["object", "null"],
+                    "properties": {
+                        "worker_threads": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "default": default_tool_worker_threads()
+                        },
+                        "thread_name": {
+                            "type": "string",
+                            "default": default_tool_thread_name()
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Shape of the dedicated runtime used for tool dispatch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolRuntimeConfig {
+    #[serde(default = "default_tool_worker_threads")]
+    pub worker_threads: usize,
+    #[serde(default = "default_tool_thread_name")]
+    pub thread_name: String,
+}
+
+impl Default for ToolRuntimeConfig {
+    fn default() -> Self {
+        Self {
+            worker_threads: default_tool_worker_threads(),
+            thread_name: default_tool_thread_name(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// Which optional globals a sandbox installs. Tool namespaces and `codemode.emit` are always
+/// present.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxCapabilities {
+    /// `setTimeout`, `setInterval`, `clearTimeout` and `clearInterval`.
+    pub timers: bool,
+    /// `crypto.randomUUID`, `crypto.getRandomValues` and `crypto.subtle.digest`.
+    pub crypto: bool,
+    /// `TextEncoder`, `TextDecoder`, `atob` and `btoa`.
+    pub encoding: bool,
+    /// `structuredClone` and `queueMicrotask`.
+    pub platform: bool,
+    /// `console.log` and friends, captured into `ExecutionResult::logs`.
+    pub console: bool,
+    /// `fetch()`, limited by `SandboxConfig::fetch`. Needs the `fetch` feature.
+    pub fetch: bool,
+}
+
+impl SandboxCapabilities {
+    /// Everything except `fetch`, which reaches the network and is enabled on its own.
+    pub fn all() -> Self {
+        Self {
+            timers: true,
+            crypto: true,
+            encoding: true,
+            platform: true,
+            console: true,
+            fetch: false,
+        }
+    }
+}
+
+/// How user code is turned into a runnable script.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+    /// Body of an async function; `return` produces the result and `await` is allowed.
+    #[default]
+    AsyncFunction,
+    /// Plain script; the completion value of the last expression is the result.
+    Expression,
+    /// ES module run without wrapping; the default export is the result.
+    Module,
+}
+
+/// Handling of calls to tools marked [`Tool::deprecated`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeprecatedToolPolicy {
+    Allow,
+    /// Log a warning and make the call.
+    #[default]
+    Warn,
+    /// Fail the call without reaching the tool.
+    Reject,
+}
+
+/// Handling of tool values that do not match the tool's declared schema.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaValidation {
+    #[default]
+    Off,
+    /// Log the mismatch and carry on.
+    Warn,
+    /// Fail the call, listing each mismatched field.
+    Reject,
+}
+
+fn default_input_validation() -> SchemaValidation {
+    SchemaValidation::Warn
+}
+
+fn default_timeout_ms() -> u64 {
+    30000
+}
+
+fn default_max_heap_mb() -> usize {
+    128
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_interrupt_tick_us() -> u64 {
+    1000
+}
+
+fn default_max_timers() -> u64 {
+    1000
+}
+
+fn default_tool_worker_threads() -> usize {
+    2
+}
+
+fn default_tool_thread_name() -> String {
+    "codemode-tools".to_string()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ExecutionResult {
     pub result: Value,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub undefined: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interfaces: Option<InterfaceReport>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trace: Vec<TraceEvent>,
+    /// Values passed to `codemode.emit(...)`, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub emitted: Vec<Value>,
+    /// Console output as `[level] message` lines, redacted like the trace logs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub logs: Vec<String>,
+    /// Variables captured with `codemode.debugState({ ... })`; later calls overwrite earlier
+    /// keys. Meant for inspecting intermediate values when the result is wrong.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub debug_state: Map<String, Value>,
+    /// Tool calls recorded instead of dispatched by a plan-only run, in call order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plan: Vec<PlannedCall>,
+    /// Per-execution result cache counts, when `SandboxConfig::cache_tool_results` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheStats>,
+    /// CPU profile of the execution, when `SandboxConfig::stack_sample_interval_us` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stack_samples: Option<StackSamples>,
 }
 
-pub struct Sandbox {
-    config: SandboxConfig,
+/// JS stacks sampled by V8's CPU profiler while an execution ran. `stacks` maps folded stacks (`outer;inner`, root
+/// first) to sample counts; `idle_ms` is time spent waiting on tools and timers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StackSamples {
+    pub interval_us: u64,
+    pub stacks: BTreeMap<String, u64>,
+    pub total_ms: u64,
+    pub idle_ms: u64,
 }
 
-impl Sandbox {
-    pub fn new(config: SandboxConfig) -> Self {
-        Self { config }
+impl StackSamples {
+    /// The samples in the folded format read by `flamegraph.pl` and `inferno-flamegraph`.
+    pub fn folded(&self) -> String {
+        self.stacks
+            .iter()
+            .map(|(stack, count)| format!("{stack} {count}\n"))
+            .collect()
     }
+}
 
-    pub fn execute(
-        &self,
-        code: &str,
-        tools: &[&Tool],
-        interface_generator: &ToolInterfaceGenerator,
-        callers: &HashMap<String, crate::client::ToolCallerEntry>,
-    ) -> Result<ExecutionResult, SandboxError> {
-        init_v8();
-        let mut isolate = v8::Isolate::new(
-            v8::CreateParams::default()
-                .heap_limits(0, self.config.max_heap_mb * 1024 * 1024),
-        );
-        let scope = std::pin::pin!(v8::HandleScope::new(&mut isolate));
-        let scope = &mut scope.init();
-        let context = v8::Context::new(scope, Default::default());
-        let scope = &mut v8::ContextScope::new(scope, context);
-        let global = context.global(scope);
-
-        let (tx, rx) = mpsc::channel::<Completion>();
-        let mut state = SandboxState::new(tx);
-        let shared_ptr = state.shared_ptr();
-
-        let interfaces = tools
-            .iter()
-            .map(|tool| interface_generator.tool_to_typescript_interface(tool))
-            .collect::<Vec<String>>()
-            .join("\n\n");
-        debug!(interfaces = %interfaces, "sandbox tool interfaces");
-
-        inject_tools(
-            scope,
-            global,
-            tools,
-            interface_generator,
-            callers,
-            self.config.runtime_handle.clone(),
-            shared_ptr,
-            &mut state,
-        )?;
-
-        let wrapped = format!("(async function() {{ {} }})()", code);
-        let result = run_script(scope, &wrapped)?;
-        let result = resolve_value(scope, result, rx, shared_ptr, self.config.timeout_ms)?;
-        let result = v8_value_to_json(scope, result)?;
-
-        trace!(result = %format_value(&result), "sandbox execute done");
-        Ok(ExecutionResult { result })
-    }
-}
-
-#[allow(clippy::too_many_arguments)]
-fn inject_tools<'a>(
-    scope: &mut v8::PinScope<'a, '_>,
-    global: v8::Local<'a, v8::Object>,
-    tools: &[&Tool],
-    interface_generator: &ToolInterfaceGenerator,
-    callers: &HashMap<String, crate::client::ToolCallerEntry>,
-    runtime_handle: tokio::runtime::Handle,
-    shared_state: *const AsyncSharedState,
-    state: &mut SandboxState,
-) -> Result<(), SandboxError> {
-    for tool in tools {
-        let access_path = interface_generator.tool_access_path(tool);
-        let parts = access_path.split('.').collect::<Vec<&str>>();
-        if parts.is_empty() {
-            continue;
-        }
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
 
-        let mut target = global;
-        for part in &parts[..parts.len() - 1] {
-            target = ensure_namespace(scope, target, part)?;
-        }
+/// A tool call a plan-only run would have made.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedCall {
+    pub tool: String,
+    pub args: Value,
+}
 
-        let caller_entry = callers.get(&tool.name);
-        let (async_caller, sync_caller) = match caller_entry {
-            Some(crate::client::ToolCallerEntry {
-                caller: crate::client::CallerKind::Async(caller),
-                ..
-            }) => (Some(caller.clone()), None),
-            Some(crate::client::ToolCallerEntry {
-                caller: crate::client::CallerKind::Sync(caller),
-                ..
-            }) => (None, Some(caller.clone())),
-            None => (None, None),
-        };
-        let raw_name = caller_entry
-            .map(|entry| entry.raw_name.clone())
-            .unwrap_or_else(|| tool.name.clone());
-        let tool_state = Box::new(ToolCallbackState {
-            tool_name: tool.name.clone(),
-            raw_name,
-            async_caller,
-            sync_caller,
-            runtime: runtime_handle.clone(),
-            shared: shared_state,
-            is_async: tool.is_async,
-        });
-        let tool_external = v8::External::new(scope, &*tool_state as *const _ as *mut c_void);
-        let tool_fn = v8::Function::builder(tool_callback)
-            .data(tool_external.into())
-            .build(scope)
-            .ok_or_else(|| SandboxError::V8("tool function".to_string()))?;
-        let fn_key = v8::String::new(scope, parts[parts.len() - 1])
-            .ok_or_else(|| SandboxError::V8("tool name string".to_string()))?;
-        target.set(scope, fn_key.into(), tool_fn.into());
-        state.tool_states.push(tool_state);
-    }
+/// Items of [`CodeModeClient::call_tool_chain_stream`](crate::client::CodeModeClient::call_tool_chain_stream).
+// `Finished` comes once per stream, so boxing it to shrink `Emitted` would not pay.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum ChainEvent {
+    /// A value passed to `codemode.emit(...)` while the chain runs.
+    Emitted(Value),
+    /// The chain's outcome; always the last item.
+    Finished(Result<ExecutionResult, SandboxError>),
+}
+
+pub(crate) type ChainEmitter = tokio::sync::mpsc::UnboundedSender<ChainEvent>;
 
-    Ok(())
+/// Per-execution values that are not part of the prepared context.
+#[derive(Default)]
+pub(crate) struct ExecutionExtras {
+    pub(crate) input: Option<Value>,
+    #[cfg_attr(not(feature = "v8"), allow(dead_code))]
+    pub(crate) emitter: Option<ChainEmitter>,
+    /// Parent of the per-tool-call spans.
+    #[cfg(feature = "otel")]
+    pub(crate) otel_context: Option<opentelemetry::Context>,
 }
 
-struct ToolCallbackState {
-    tool_name: String,
-    raw_name: String,
-    async_caller: Option<std::sync::Arc<dyn crate::tool::AsyncToolCaller>>,
-    sync_caller: Option<std::sync::Arc<dyn crate::tool::SyncToolCaller>>,
-    runtime: tokio::runtime::Handle,
-    shared: *const AsyncSharedState,
-    is_async: bool,
+/// Notable dispatch decisions made while an execution ran.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TraceEvent {
+    Fallback {
+        tool: String,
+        fallback: String,
+        error: String,
+    },
 }
 
-#[allow(clippy::vec_box)]
-struct SandboxState {
-    // Box is required here for stable heap addresses - V8 callbacks hold pointers to these
-    tool_states: Vec<Box<ToolCallbackState>>,
-    shared: Box<AsyncSharedState>,
+/// The interface text and tool set an execution was given.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterfaceReport {
+    pub hash: String,
+    pub tools: Vec<String>,
 }
 
-impl SandboxState {
-    fn new(sender: mpsc::Sender<Completion>) -> Self {
-        Self {
-            tool_states: Vec::new(),
-            shared: Box::new(AsyncSharedState::new(sender)),
-        }
+const RESULT_PREVIEW_BYTES: usize = 200;
+
+impl ExecutionResult {
+    pub fn into_envelope(self) -> crate::envelope::ExecutionEnvelope {
+        self.into()
+    }
+
+    /// `result` as a `T`. A mismatch names the expected type and quotes the start of what the
+    /// script actually returned.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, SandboxError> {
+        T::deserialize(&self.result).map_err(|err| {
+            let returned = if self.undefined {
+                "undefined".to_string()
+            } else {
+                let mut text = self.result.to_string();
+                if text.len() > RESULT_PREVIEW_BYTES {
+                    let mut end = RESULT_PREVIEW_BYTES;
+                    while !text.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    text.truncate(end);
+                    text.push_str("...");
+                }
+                text
+            };
+            SandboxError::ResultShape {
+                expected: std::any::type_name::<T>().to_string(),
+                message: format!("{err} (script returned {returned})"),
+            }
+        })
     }
 
-    fn shared_ptr(&self) -> *const AsyncSharedState {
-        // SAFETY: This pointer is valid as long as SandboxState is alive.
-        // The pointer is only used during sandbox execution and never stored beyond that.
-        &*self.shared as *const AsyncSharedState
+    pub fn schema() -> JsonSchema {
+        json!({
+            "title": "ExecutionResult",
+            "type": "object",
+            "properties": {
+                "result": { "description": "Value returned by the chain" },
+                "undefined": {
+                    "type": "boolean",
+                    "description": "The chain returned undefined; `result` is null"
+                },
+                "interfaces": {
+                    "type": "object",
+                    "properties": {
+                        "hash": { "type": "string" },
+                        "tools": { "type": "array", "items": { "type": "string" } }
+                    }
+                },
+                "emitted": { "type": "array" },
+                "logs": { "type": "array", "items": { "type": "string" } },
+                "cache": {
+                    "type": "object",
+                    "properties": {
+                        "hits": { "type": "integer", "minimum": 0 },
+                        "misses": { "type": "integer", "minimum": 0 }
+                    }
+                },
+                "stack_samples": {
+                    "type": "object",
+                    "properties": {
+                        "interval_us": { "type": "integer", "minimum": 1 },
+                        "stacks": {
+                            "type": "object",
+                            "additionalProperties": { "type": "integer", "minimum": 1 }
+                        },
+                        "total_ms": { "type": "integer", "minimum": 0 },
+                        "idle_ms": { "type": "integer", "minimum": 0 }
+                    }
+                },
+                "plan": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "tool": { "type": "string" },
+                            "args": {}
+                        },
+                        "required": ["tool", "args"]
+                    }
+                },
+                "trace": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": { "type": { "type": "string" } },
+                        "required": ["type"]
+                    }
+                }
+            },
+            "required": ["result"]
+        })
     }
 }
 
-struct AsyncSharedState {
-    next_id: AtomicU64,
-    pending: Cell<usize>,
-    resolvers: RefCell<HashMap<u64, v8::Global<v8::PromiseResolver>>>,
-    sender: mpsc::Sender<Completion>,
+pub struct Sandbox {
+    config: SandboxConfig,
+    #[cfg(feature = "v8")]
+    tool_runtime: Arc<Mutex<Option<Arc<ToolRuntime>>>>,
+    #[cfg(feature = "v8")]
+    startup_snapshot: Arc<RwLock<Option<StartupSnapshot>>>,
+    interceptors: Arc<RwLock<Vec<Arc<dyn ToolInterceptor>>>>,
+    result_cache: Arc<RwLock<Option<ResultCache>>>,
+    redactor: Arc<RwLock<Option<Arc<dyn Redactor>>>>,
+    error_handler: Arc<RwLock<Option<Arc<ToolErrorFn>>>>,
+    policy: Arc<RwLock<Option<Arc<dyn ToolPolicy>>>>,
+    approval: Arc<RwLock<Option<ApprovalGate>>>,
+    memory: Arc<RwLock<Option<Arc<dyn MemoryStore>>>>,
+    global_fns: Arc<RwLock<BTreeMap<String, Arc<GlobalFn>>>>,
+    // Tools registered on the sandbox itself, for use without a `CodeModeClient`.
+    registry: Arc<RwLock<BTreeMap<String, ToolCallerEntry>>>,
+    interface_generator: Arc<ToolInterfaceGenerator>,
+    #[cfg(feature = "v8")]
+    pool: Arc<Mutex<Option<Arc<IsolatePool>>>>,
+    // Bumped by every setter so pool workers know when a prepared context has gone stale.
+    revision: Arc<AtomicU64>,
 }
 
-impl AsyncSharedState {
-    fn new(sender: mpsc::Sender<Completion>) -> Self {
+impl Sandbox {
+    pub fn new(config: SandboxConfig) -> Self {
         Self {
-            next_id: AtomicU64::new(1),
-            pending: Cell::new(0),
-            resolvers: RefCell::new(HashMap::new()),
-            sender,
+            config,
+            #[cfg(feature = "v8")]
+            tool_runtime: Arc::new(Mutex::new(None)),
+            #[cfg(feature = "v8")]
+            startup_snapshot: Arc::new(RwLock::new(None)),
+            interceptors: Arc::new(RwLock::new(Vec::new())),
+            result_cache: Arc::new(RwLock::new(None)),
+            redactor: Arc::new(RwLock::new(None)),
+            error_handler: Arc::new(RwLock::new(None)),
+            policy: Arc::new(RwLock::new(None)),
+            approval: Arc::new(RwLock::new(None)),
+            memory: Arc::new(RwLock::new(None)),
+            global_fns: Arc::new(RwLock::new(BTreeMap::new())),
+            registry: Arc::new(RwLock::new(BTreeMap::new())),
+            interface_generator: Arc::new(ToolInterfaceGenerator::new(InterfaceOptions::default())),
+            #[cfg(feature = "v8")]
+            pool: Arc::new(Mutex::new(None)),
+            revision: Arc::new(AtomicU64::new(0)),
         }
     }
 
-    fn next_id(&self) -> u64 {
-        self.next_id.fetch_add(1, Ordering::Relaxed)
+    /// Applies `interceptor` to tool calls in sandboxes prepared from now on.
+    pub fn add_interceptor(&self, interceptor: Arc<dyn ToolInterceptor>) {
+        if let Ok(mut interceptors) = self.interceptors.write() {
+            interceptors.push(interceptor);
+        }
+        self.touch();
     }
-}
-
-struct Completion {
-    id: u64,
-    result: Result<Value, String>,
-}
 
-fn resolve_value<'a>(
-    scope: &mut v8::PinScope<'a, '_>,
-    value: v8::Local<'a, v8::Value>,
-    rx: mpsc::Receiver<Completion>,
-    shared: *const AsyncSharedState,
-    timeout_ms: u64,
-) -> Result<v8::Local<'a, v8::Value>, SandboxError> {
-    if !value.is_promise() {
-        return Ok(value);
+    pub(crate) fn set_result_cache(&self, cache: Option<ResultCache>) {
+        if let Ok(mut slot) = self.result_cache.write() {
+            *slot = cache;
+        }
+        self.touch();
     }
 
-    let promise = v8::Local::<v8::Promise>::try_from(value)
-        .map_err(|_| SandboxError::V8("promise cast".to_string()))?;
-    let start = Instant::now();
+    /// Redacts tool arguments, results and emitted values in logs of sandboxes prepared from
+    /// now on.
+    pub fn set_redactor(&self, redactor: Option<Arc<dyn Redactor>>) {
+        if let Ok(mut slot) = self.redactor.write() {
+            *slot = redactor;
+        }
+        self.touch();
+    }
 
-    loop {
-        drain_completions(scope, &rx, shared)?;
-        scope.perform_microtask_checkpoint();
+    pub(crate) fn redactor(&self) -> Option<Arc<dyn Redactor>> {
+        self.redactor
+            .read()
+            .ok()
+            .and_then(|redactor| redactor.clone())
+    }
 
-        if promise.state() != v8::PromiseState::Pending {
-            if promise.state() == v8::PromiseState::Rejected {
-                let message = promise
-                    .result(scope)
-                    .to_string(scope)
-                    .map(|val| val.to_rust_string_lossy(scope))
-                    .unwrap_or_else(|| "promise rejected".to_string());
-                return Err(SandboxError::Tool(message));
-            }
-            return Ok(promise.result(scope));
+    /// Rewrites tool error messages in sandboxes prepared from now on. Scripts can layer their
+    /// own handler on top with `codemode.onToolError`.
+    pub fn set_tool_error_handler(&self, handler: Option<Arc<ToolErrorFn>>) {
+        if let Ok(mut slot) = self.error_handler.write() {
+            *slot = handler;
         }
+        self.touch();
+    }
 
-        if start.elapsed() > Duration::from_millis(timeout_ms) {
-            return Err(SandboxError::V8("execution timeout".to_string()));
+    /// Consults `policy` before every tool call in sandboxes prepared from now on. Denied calls
+    /// reject in the script with the policy's reason.
+    pub fn set_tool_policy(&self, policy: Option<Arc<dyn ToolPolicy>>) {
+        if let Ok(mut slot) = self.policy.write() {
+            *slot = policy;
         }
+        self.touch();
+    }
 
-        match rx.recv_timeout(Duration::from_millis(5)) {
-            Ok(completion) => {
-                apply_completion(scope, shared, completion)?;
-            }
-            Err(mpsc::RecvTimeoutError::Timeout) => {}
-            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+    pub(crate) fn set_approval(&self, approval: Option<ApprovalGate>) {
+        if let Ok(mut slot) = self.approval.write() {
+            *slot = approval;
         }
+        self.touch();
     }
 
-    Err(SandboxError::V8("execution incomplete".to_string()))
-}
-
-fn drain_completions(
-    scope: &mut v8::PinScope<'_, '_>,
-    rx: &mpsc::Receiver<Completion>,
-    shared: *const AsyncSharedState,
-) -> Result<(), SandboxError> {
-    loop {
-        match rx.try_recv() {
-            Ok(completion) => apply_completion(scope, shared, completion)?,
-            Err(mpsc::TryRecvError::Empty) => return Ok(()),
-            Err(mpsc::TryRecvError::Disconnected) => return Ok(()),
+    /// Backs `codemode.memory` with `store` in sandboxes prepared from now on. Without a store,
+    /// each execution starts with empty memory.
+    pub fn set_memory_store(&self, store: Option<Arc<dyn MemoryStore>>) {
+        if let Ok(mut slot) = self.memory.write() {
+            *slot = store;
         }
+        self.touch();
     }
-}
-
-fn apply_completion(
-    scope: &mut v8::PinScope<'_, '_>,
-    shared: *const AsyncSharedState,
-    completion: Completion,
-) -> Result<(), SandboxError> {
-    // SAFETY: The shared pointer is valid as long as SandboxState is alive.
-    // This function is only called during sandbox execution while the state exists.
-    let shared = unsafe { &*shared };
-    let Some(resolver) = shared.resolvers.borrow_mut().remove(&completion.id) else {
-        return Ok(());
-    };
-    shared.pending.set(shared.pending.get().saturating_sub(1));
-    let resolver = v8::Local::new(scope, &resolver);
 
-    match completion.result {
-        Ok(value) => {
-            if let Some(value) = json_to_v8(scope, &value) {
-                resolver.resolve(scope, value);
-            } else {
-                let message = v8::String::new(scope, "failed to serialize tool result")
-                    .ok_or_else(|| SandboxError::V8("error string".to_string()))?;
-                let exception = v8::Exception::error(scope, message);
-                resolver.reject(scope, exception);
-            }
-        }
-        Err(message) => {
-            let message = v8::String::new(scope, &message)
-                .ok_or_else(|| SandboxError::V8("error string".to_string()))?;
-            let exception = v8::Exception::error(scope, message);
-            resolver.reject(scope, exception);
+    /// Installs `function` at `name` (a dotted path such as `host.metrics.count`) in sandboxes
+    /// prepared from now on. Unlike tools it has no schema and is not advertised in the
+    /// generated interfaces, which suits logging hooks, metrics and privileged helpers.
+    pub fn register_global_fn(&self, name: impl Into<String>, function: Arc<GlobalFn>) {
+        if let Ok(mut global_fns) = self.global_fns.write() {
+            global_fns.insert(name.into(), function);
         }
+        self.touch();
     }
 
-    Ok(())
-}
+    fn touch(&self) {
+        self.revision.fetch_add(1, Ordering::Release);
+    }
 
-fn init_v8() {
-    static INIT: Once = Once::new();
-    INIT.call_once(|| {
-        let platform = v8::new_default_platform(0, false).make_shared();
-        v8::V8::initialize_platform(platform);
-        v8::V8::initialize();
-    });
-}
+    /// Exposes `tool` to scripts run by [`Sandbox::execute`] as an async function; `raw_name`
+    /// is the name passed to `caller`.
+    pub fn register_async_tool(
+        &self,
+        tool: Tool,
+        raw_name: String,
+        caller: Arc<dyn AsyncToolCaller>,
+    ) {
+        self.register_tool(tool, raw_name, CallerKind::Async(caller));
+    }
 
-fn run_script<'a>(
-    scope: &mut v8::PinScope<'a, '_>,
-    source: &str,
-) -> Result<v8::Local<'a, v8::Value>, SandboxError> {
-    let code = v8::String::new(scope, source)
-        .ok_or_else(|| SandboxError::V8("script source".to_string()))?;
-    let script = v8::Script::compile(scope, code, None)
-        .ok_or_else(|| SandboxError::V8("script compile".to_string()))?;
-    script
-        .run(scope)
-        .ok_or_else(|| SandboxError::V8("script run".to_string()))
-}
+    pub fn register_sync_tool(
+        &self,
+        tool: Tool,
+        raw_name: String,
+        caller: Arc<dyn SyncToolCaller>,
+    ) {
+        self.register_tool(tool, raw_name, CallerKind::Sync(caller));
+    }
 
-fn tool_callback(
-    scope: &mut v8::PinScope,
-    args: v8::FunctionCallbackArguments,
-    mut rv: v8::ReturnValue,
-) {
-    let external = match v8::Local::<v8::External>::try_from(args.data()) {
-        Ok(external) => external,
-        Err(_) => return,
-    };
-    let state_ptr = external.value() as *const ToolCallbackState;
-    if state_ptr.is_null() {
-        return;
-    }
-    // SAFETY: The state pointer points to a Box<ToolCallbackState> stored in SandboxState.tool_states.
-    // It remains valid for the entire duration of sandbox execution.
-    let state = unsafe { &*state_ptr };
-    let args_value = args.get(0);
-    let args_json = v8::json::stringify(scope, args_value)
-        .map(|val| val.to_rust_string_lossy(scope))
-        .unwrap_or_else(|| "{}".to_string());
-    let parsed_args: Value = serde_json::from_str(&args_json).unwrap_or(Value::Null);
-    trace!(tool = state.tool_name.as_str(), args = %format_value(&parsed_args), "sandbox call_tool");
-
-    if state.is_async {
-        // SAFETY: state.shared points to AsyncSharedState which is valid as long as SandboxState is alive.
-        let shared = unsafe { &*state.shared };
-        let resolver = match v8::PromiseResolver::new(scope) {
-            Some(resolver) => resolver,
-            None => {
-                throw_error(scope, "failed to create promise resolver");
-                return;
-            }
-        };
-        let promise = resolver.get_promise(scope);
-        let id = shared.next_id();
-        shared
-            .resolvers
-            .borrow_mut()
-            .insert(id, v8::Global::new(scope, resolver));
-        shared.pending.set(shared.pending.get() + 1);
-
-        let sender = shared.sender.clone();
-        let tool_name = state.raw_name.clone();
-        let caller = match state.async_caller.clone() {
-            Some(caller) => caller,
-            None => {
-                throw_error(scope, "async caller missing");
-                return;
-            }
-        };
-        state.runtime.spawn(async move {
-            let result = caller.call_tool_async(&tool_name, parsed_args).await;
-            let completion = Completion {
-                id,
-                result: result.map_err(|err| err.to_string()),
-            };
-            let _ = sender.send(completion);
-        });
+    pub fn register_streaming_tool(
+        &self,
+        tool: Tool,
+        raw_name: String,
+        caller: Arc<dyn StreamingToolCaller>,
+    ) {
+        self.register_tool(tool, raw_name, CallerKind::Streaming(caller));
+    }
 
-        rv.set(promise.into());
-    } else {
-        let sync = match &state.sync_caller {
-            Some(sync) => sync,
-            None => {
-                throw_error(scope, "sync caller missing");
-                return;
-            }
-        };
-        let result = sync.call_tool_sync(&state.raw_name, parsed_args);
-        match result {
-            Ok(value) => {
-                if let Some(value) = json_to_v8(scope, &value) {
-                    rv.set(value);
-                } else {
-                    throw_error(scope, "failed to serialize tool result");
-                }
-            }
-            Err(err) => {
-                throw_error(scope, &err.to_string());
-            }
+    fn register_tool(&self, tool: Tool, raw_name: String, caller: CallerKind) {
+        self.interface_generator.claim_access_path(&tool);
+        let entry = ToolCallerEntry::new(tool, raw_name, caller);
+        let name = entry.tool.name.clone();
+        if let Ok(mut registry) = self.registry.write()
+            && registry.insert(name.clone(), entry).is_some()
+        {
+            self.interface_generator.invalidate(&name);
+            trace!(tool = name.as_str(), "sandbox tool overwritten");
         }
     }
-}
 
-fn ensure_namespace<'a>(
-    scope: &mut v8::PinScope<'a, '_>,
-    parent: v8::Local<'a, v8::Object>,
-    name: &str,
-) -> Result<v8::Local<'a, v8::Object>, SandboxError> {
-    let key = v8::String::new(scope, name)
-        .ok_or_else(|| SandboxError::V8(format!("namespace key '{name}'")))?;
-    if let Some(existing) = parent.get(scope, key.into())
-        && existing.is_object()
-    {
-        return existing
-            .to_object(scope)
-            .ok_or_else(|| SandboxError::V8("namespace object".to_string()));
+    /// Tools registered on this sandbox, ordered by name.
+    pub fn tools(&self) -> Vec<Tool> {
+        self.registry
+            .read()
+            .map(|registry| registry.values().map(|entry| entry.tool.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// TypeScript declarations for the registered tools, as scripts see them.
+    pub fn tool_interfaces(&self) -> String {
+        let tools = self.tools();
+        self.interface_generator
+            .catalog_interfaces(&tools.iter().collect::<Vec<&Tool>>())
+    }
+
+    /// The dispatch a script's call to `tool` would use, for calls made from the host. The
+    /// tool's JS result transformer, if any, is not applied.
+    pub(crate) fn host_dispatch(
+        &self,
+        tool: &Tool,
+        callers: &BTreeMap<String, ToolCallerEntry>,
+    ) -> ToolDispatch {
+        tool_dispatch(tool, callers, &self.config, &self.dispatch_context())
+    }
+
+    /// Dispatches for `tools` sharing one execution's limits, caches and trace, for backends
+    /// that run scripts outside this sandbox.
+    pub(crate) fn host_dispatches(
+        &self,
+        tools: &[&Tool],
+        callers: &BTreeMap<String, ToolCallerEntry>,
+    ) -> BTreeMap<String, ToolDispatch> {
+        let context = self.dispatch_context();
+        tools
+            .iter()
+            .map(|tool| {
+                let dispatch = tool_dispatch(tool, callers, &self.config, &context);
+                (tool.name.clone(), dispatch)
+            })
+            .collect()
     }
-    let obj = v8::Object::new(scope);
-    parent.set(scope, key.into(), obj.into());
-    Ok(obj)
-}
 
-fn v8_value_to_json(
-    scope: &mut v8::PinScope<'_, '_>,
-    value: v8::Local<v8::Value>,
-) -> Result<Value, SandboxError> {
-    if value.is_undefined() || value.is_null() {
-        return Ok(Value::Null);
+    /// Per-execution dispatch settings seeded with the sandbox-level ones.
+    fn dispatch_context(&self) -> DispatchContext {
+        DispatchContext {
+            blob_offload: None,
+            call_limiter: self
+                .config
+                .max_concurrent_tool_calls
+                .map(|permits| Arc::new(Semaphore::new(permits.max(1)))),
+            trace: TraceLog::default(),
+            interceptors: self
+                .interceptors
+                .read()
+                .map(|interceptors| interceptors.iter().cloned().collect())
+                .unwrap_or_default(),
+            call_cache: self
+                .config
+                .cache_tool_results
+                .then(|| Arc::new(CallCache::default())),
+            result_cache: self
+                .result_cache
+                .read()
+                .ok()
+                .and_then(|cache| cache.clone()),
+            error_handler: self
+                .error_handler
+                .read()
+                .ok()
+                .and_then(|handler| handler.clone()),
+            policy: self.policy.read().ok().and_then(|policy| policy.clone()),
+            approval: self
+                .approval
+                .read()
+                .ok()
+                .and_then(|approval| approval.clone()),
+        }
     }
+}
 
-    let json = v8::json::stringify(scope, value)
-        .map(|val| val.to_rust_string_lossy(scope))
-        .ok_or_else(|| SandboxError::Serialization("result stringify".to_string()))?;
-    serde_json::from_str(&json).map_err(|err| SandboxError::Serialization(err.to_string()))
+/// The per-execution settings every tool dispatch shares: limits, caches, the trace and the
+/// sandbox's interceptors, error handler and policy.
+struct DispatchContext {
+    blob_offload: Option<Box<BlobOffload>>,
+    call_limiter: Option<Arc<Semaphore>>,
+    trace: TraceLog,
+    interceptors: Arc<[Arc<dyn ToolInterceptor>]>,
+    call_cache: Option<Arc<CallCache>>,
+    result_cache: Option<ResultCache>,
+    error_handler: Option<Arc<ToolErrorFn>>,
+    policy: Option<Arc<dyn ToolPolicy>>,
+    approval: Option<ApprovalGate>,
 }
 
-fn format_value(value: &Value) -> String {
-    serde_json::to_string(value).unwrap_or_else(|_| "<unserializable>".to_string())
+/// The dispatch for one injected tool; the tool's JS result transformer is compiled separately.
+fn tool_dispatch(
+    tool: &Tool,
+    callers: &BTreeMap<String, ToolCallerEntry>,
+    config: &SandboxConfig,
+    context: &DispatchContext,
+) -> ToolDispatch {
+    let caller_entry = callers.get(&tool.name);
+    let raw_name = caller_entry
+        .map(|entry| entry.raw_name.clone())
+        .unwrap_or_else(|| tool.name.clone());
+    let transformer = match caller_entry.and_then(|entry| entry.options.transformer.as_ref()) {
+        Some(ResultTransformer::Rust(transform)) => Some(transform.clone()),
+        _ => None,
+    };
+    let fallback = caller_entry
+        .and_then(|entry| entry.options.fallback.as_ref())
+        .and_then(|fallback| {
+            let target = callers.get(&fallback.tool)?;
+            Some(FallbackTarget {
+                tool_name: fallback.tool.clone(),
+                raw_name: target.raw_name.clone(),
+                caller: target.caller.clone(),
+                arg_adapter: fallback.arg_adapter.clone(),
+                policy: bind_policy(context, &target.tool),
+                input_check: SchemaCheck::new(&target.input_schema, config.input_validation),
+                output_check: SchemaCheck::new(&target.output_schema, config.output_validation),
+            })
+        });
+    ToolDispatch {
+        tool_name: tool.name.clone(),
+        raw_name,
+        caller: caller_entry.map(|entry| entry.caller.clone()),
+        transformer,
+        blob_offload: context.blob_offload.as_deref().cloned(),
+        limiter: caller_entry.and_then(|entry| entry.limiter.clone()),
+        call_limiter: context.call_limiter.clone(),
+        timeout: caller_entry
+            .and_then(|entry| entry.options.timeout_ms)
+            .or(config.tool_timeout_ms)
+            .map(Duration::from_millis),
+        fallback,
+        trace: context.trace.clone(),
+        interceptors: context.interceptors.clone(),
+        cache: context.call_cache.clone(),
+        result_cache: context.result_cache.as_ref().and_then(|cache| {
+            let ttl = caller_entry
+                .and_then(|entry| entry.options.cache_ttl_ms)
+                .map(Duration::from_millis)
+                .or(tool.read_only.then_some(cache.ttl))?;
+            Some(ResultCache {
+                cache: cache.cache.clone(),
+                ttl,
+            })
+        }),
+        error_handler: context.error_handler.clone(),
+        continue_on_error: config.continue_on_error,
+        input_check: caller_entry
+            .and_then(|entry| SchemaCheck::new(&entry.input_schema, config.input_validation)),
+        output_check: caller_entry
+            .and_then(|entry| SchemaCheck::new(&entry.output_schema, config.output_validation)),
+        deprecation: if tool.deprecated {
+            config.deprecated_tools
+        } else {
+            DeprecatedToolPolicy::Allow
+        },
+        policy: bind_policy(context, tool),
+    }
 }
 
-fn json_to_v8<'a>(
-    scope: &mut v8::PinScope<'a, '_>,
-    value: &Value,
-) -> Option<v8::Local<'a, v8::Value>> {
-    let json = serde_json::to_string(value).ok()?;
-    let json = v8::String::new(scope, &json)?;
-    v8::json::parse(scope, json)
+fn bind_policy(context: &DispatchContext, tool: &Tool) -> Option<BoundPolicy> {
+    context.policy.clone().map(|policy| BoundPolicy {
+        policy,
+        approval: context.approval.clone(),
+        tool: tool.clone(),
+    })
 }
 
-fn throw_error(scope: &mut v8::PinScope<'_, '_>, message: &str) {
-    if let Some(message) = v8::String::new(scope, message) {
-        let exception = v8::Exception::error(scope, message);
-        scope.throw_exception(exception);
+/// Splits leading `import` statements (and any blank or comment lines between them) from the
+/// rest of the code, so async-function scripts can import registered modules.
+#[cfg(any(feature = "v8", feature = "deno"))]
+pub(crate) fn split_imports(code: &str) -> Option<(&str, &str)> {
+    let mut end = 0;
+    let mut found = false;
+    let mut open_import = false;
+    for line in code.split_inclusive('\n') {
+        let trimmed = line.trim();
+        let completes = trimmed.contains('"') || trimmed.contains('\'');
+        if open_import {
+            open_import = !completes;
+        } else if trimmed.starts_with("import ") || trimmed.starts_with("import{") {
+            found = true;
+            // A multi-line import ends at the line holding its specifier.
+            open_import = !completes;
+        } else if !(trimmed.is_empty() || trimmed.starts_with("//")) {
+            break;
+        }
+        end += line.len();
     }
+    found.then(|| code.split_at(end))
 }
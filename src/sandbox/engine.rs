@@ -0,0 +1,2953 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use serde_json::{Map, Value, json};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, trace};
+use v8;
+
+use crate::blob::BlobOffload;
+use crate::client::ToolCallerEntry;
+use crate::convert::{bytes_to_v8, json_to_v8, v8_to_json};
+use crate::dispatch::ToolDispatch;
+use crate::globals::{inject_crypto, inject_encoding, inject_platform};
+#[cfg(feature = "inspector")]
+use crate::inspector::SandboxInspector;
+use crate::memory::{InMemoryStore, MemoryStore};
+use crate::monitor::{ExecutionWatch, WatchLimits};
+use crate::pool::IsolatePool;
+use crate::profiler::CpuProfiler;
+use crate::redact::{Redactor, format_value};
+use crate::simulation::Simulator;
+use crate::tool::{GlobalFn, ResultTransformer, Tool, ToolCallError};
+use crate::ts_interface::{ToolInterfaceGenerator, content_hash};
+#[cfg(feature = "typescript")]
+use crate::typescript::strip_types;
+
+use super::{
+    ChainEmitter, ChainEvent, DispatchContext, ExecutionExtras, ExecutionResult, InterfaceReport,
+    PlannedCall, Sandbox, SandboxConfig, SandboxError, ScriptDiagnostics, StackSamples,
+    ToolRuntimeConfig, TraceEvent, WrapMode, default_max_timers, split_imports, tool_dispatch,
+};
+
+impl Sandbox {
+    /// A sandbox for one pool worker: it shares this sandbox's settings but has no pool.
+    fn pool_worker(&self) -> Self {
+        Self {
+            config: SandboxConfig {
+                pool_size: None,
+                ..self.config.clone()
+            },
+            tool_runtime: self.tool_runtime.clone(),
+            startup_snapshot: self.startup_snapshot.clone(),
+            interceptors: self.interceptors.clone(),
+            result_cache: self.result_cache.clone(),
+            redactor: self.redactor.clone(),
+            error_handler: self.error_handler.clone(),
+            policy: self.policy.clone(),
+            approval: self.approval.clone(),
+            memory: self.memory.clone(),
+            global_fns: self.global_fns.clone(),
+            registry: self.registry.clone(),
+            interface_generator: self.interface_generator.clone(),
+            pool: Arc::new(Mutex::new(None)),
+            revision: self.revision.clone(),
+        }
+    }
+
+    /// Builds a startup snapshot whose default context already holds the namespace objects for
+    /// `tools`. Tool functions are native callbacks and are still bound per context.
+    pub fn build_snapshot(
+        &self,
+        tools: &[&Tool],
+        interface_generator: &ToolInterfaceGenerator,
+    ) -> Result<StartupSnapshot, SandboxError> {
+        init_v8(&self.config);
+        let namespaces = tools
+            .iter()
+            .filter_map(|tool| {
+                let access_path = interface_generator.tool_access_path(tool);
+                let (namespace, _) = access_path.rsplit_once('.')?;
+                Some(
+                    namespace
+                        .split('.')
+                        .map(str::to_string)
+                        .collect::<Vec<String>>(),
+                )
+            })
+            .collect::<BTreeSet<Vec<String>>>();
+        let paths = serde_json::to_string(&namespaces)
+            .map_err(|err| SandboxError::Serialization(err.to_string()))?;
+        let source = format!(
+            "for (const path of {paths}) {{ let target = globalThis; for (const key of path) target = target[key] ??= {{}}; }}"
+        );
+
+        let mut creator = v8::Isolate::snapshot_creator(None, None);
+        {
+            let scope = std::pin::pin!(v8::HandleScope::new(&mut creator));
+            let scope = &mut scope.init();
+            let context = v8::Context::new(scope, Default::default());
+            let scope = &mut v8::ContextScope::new(scope, context);
+            run_script(scope, &source)?;
+            scope.set_default_context(context);
+        }
+        let blob = creator
+            .create_blob(v8::FunctionCodeHandling::Keep)
+            .ok_or_else(|| SandboxError::V8("snapshot blob".to_string()))?;
+        debug!(
+            namespaces = namespaces.len(),
+            bytes = blob.len(),
+            "sandbox startup snapshot built"
+        );
+        Ok(StartupSnapshot {
+            blob: Arc::from(&blob[..]),
+        })
+    }
+
+    /// Boots subsequent isolates (including pool workers' next isolate) from `snapshot`.
+    pub fn set_snapshot(&self, snapshot: Option<StartupSnapshot>) {
+        if let Ok(mut slot) = self.startup_snapshot.write() {
+            *slot = snapshot;
+        }
+    }
+
+    /// Identifies the settings a prepared context was built with, or `None` when contexts must
+    /// not be reused: without `harden` a script can change built-ins the next caller would
+    /// see, and top-level declarations of `WrapMode::Expression` scripts outlive the execution
+    /// and would clash with the next one's.
+    pub(crate) fn context_revision(&self) -> Option<u64> {
+        (self.config.harden && self.config.wrap_mode != WrapMode::Expression)
+            .then(|| self.revision.load(Ordering::Acquire))
+    }
+
+    // Like the tool runtime, the pool starts on first use so construction stays infallible.
+    pub(crate) fn pool(&self) -> Result<Option<Arc<IsolatePool>>, SandboxError> {
+        let Some(size) = self.config.pool_size else {
+            return Ok(None);
+        };
+        let mut slot = self
+            .pool
+            .lock()
+            .map_err(|_| SandboxError::V8("isolate pool lock poisoned".to_string()))?;
+        if let Some(pool) = slot.as_ref() {
+            return Ok(Some(pool.clone()));
+        }
+        let mut template = self.pool_worker();
+        // Workers run on their own threads, outside any runtime the caller is in.
+        if template.config.tool_runtime.is_none() {
+            template.config.runtime_handle = Some(self.config.runtime()?);
+        }
+        let pool = Arc::new(IsolatePool::start(size, move || template.pool_worker())?);
+        *slot = Some(pool.clone());
+        Ok(Some(pool))
+    }
+
+    // The dedicated runtime is built on first use so construction stays infallible.
+    fn tool_runtime(&self) -> Result<Option<Arc<ToolRuntime>>, SandboxError> {
+        let Some(runtime_config) = &self.config.tool_runtime else {
+            return Ok(None);
+        };
+        let mut slot = self
+            .tool_runtime
+            .lock()
+            .map_err(|_| SandboxError::V8("tool runtime lock poisoned".to_string()))?;
+        if let Some(runtime) = slot.as_ref() {
+            return Ok(Some(runtime.clone()));
+        }
+        let runtime = Arc::new(ToolRuntime::build(runtime_config)?);
+        *slot = Some(runtime.clone());
+        Ok(Some(runtime))
+    }
+
+    /// Runs `code` against the tools registered on this sandbox.
+    pub fn execute(&self, code: &str) -> Result<ExecutionResult, SandboxError> {
+        self.prepare()?.execute(code)
+    }
+
+    pub fn execute_with_cancel(
+        &self,
+        code: &str,
+        cancel: &CancellationToken,
+    ) -> Result<ExecutionResult, SandboxError> {
+        self.prepare()?.execute_with_cancel(code, cancel)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn execute_with(
+        &self,
+        code: &str,
+        tools: &[&Tool],
+        interface_generator: &ToolInterfaceGenerator,
+        callers: &BTreeMap<String, ToolCallerEntry>,
+        cancel: &CancellationToken,
+        extras: ExecutionExtras,
+    ) -> Result<ExecutionResult, SandboxError> {
+        self.prepare_tools(tools, interface_generator, callers)?
+            .execute_with(code, cancel, extras)
+    }
+
+    /// Creates an isolate with the registered tools already injected so repeated executions
+    /// only load user code.
+    pub fn prepare(&self) -> Result<PreparedSandbox, SandboxError> {
+        let registry = self
+            .registry
+            .read()
+            .map_err(|_| SandboxError::V8("tool registry lock poisoned".to_string()))?;
+        let tools = registry
+            .values()
+            .map(|entry| &entry.tool)
+            .collect::<Vec<&Tool>>();
+        self.prepare_tools(&tools, &self.interface_generator, &registry)
+    }
+
+    /// Like [`Sandbox::prepare`], with a tool set supplied by the caller (the client's).
+    pub(crate) fn prepare_tools(
+        &self,
+        tools: &[&Tool],
+        interface_generator: &ToolInterfaceGenerator,
+        callers: &BTreeMap<String, ToolCallerEntry>,
+    ) -> Result<PreparedSandbox, SandboxError> {
+        self.prepare_in(self.create_isolate(), tools, interface_generator, callers)
+    }
+
+    /// Runs `code` without dispatching any tool: each call is recorded in
+    /// [`ExecutionResult::plan`] and answered with a value simulated from the tool's output
+    /// schema.
+    pub fn plan(&self, code: &str) -> Result<ExecutionResult, SandboxError> {
+        self.prepare()?.plan(code)
+    }
+
+    /// Compiles `code` the way `execute` would, without injecting tools or running it.
+    pub fn validate(&self, code: &str) -> Result<(), ScriptDiagnostics> {
+        let script = prepare_source(&self.config, code)?;
+        let mut isolate = self.create_isolate();
+        let scope = std::pin::pin!(v8::HandleScope::new(&mut isolate));
+        let scope = &mut scope.init();
+        let context = v8::Context::new(scope, Default::default());
+        let scope = &mut v8::ContextScope::new(scope, context);
+        match script.mode {
+            WrapMode::Module => {
+                compile_module(scope, &script.source, script.column_offset).map(|_| ())
+            }
+            WrapMode::AsyncFunction | WrapMode::Expression => {
+                compile_script(scope, &script.source, script.column_offset).map(|_| ())
+            }
+        }?;
+        Ok(())
+    }
+
+    pub(crate) fn create_isolate(&self) -> v8::OwnedIsolate {
+        init_v8(&self.config);
+        let params = v8::CreateParams::default().heap_limits(
+            self.config.initial_heap_mb * 1024 * 1024,
+            self.config.max_heap_mb * 1024 * 1024,
+        );
+        let snapshot = self
+            .startup_snapshot
+            .read()
+            .ok()
+            .and_then(|slot| slot.as_ref().map(|snapshot| snapshot.blob.to_vec()));
+        let params = match snapshot {
+            Some(blob) => params.snapshot_blob(blob.into()),
+            None => params,
+        };
+        let mut isolate = v8::Isolate::new(params);
+        if let Some(dir) = &self.config.heap_snapshot_dir {
+            let trigger = Box::new(HeapSnapshotTrigger {
+                isolate: &mut *isolate as *mut v8::Isolate,
+                dir: dir.clone(),
+                taken: AtomicBool::new(false),
+            });
+            let data = &*trigger as *const HeapSnapshotTrigger as *mut c_void;
+            // The slot owns the trigger for as long as the isolate lives.
+            isolate.set_slot(trigger);
+            isolate.add_near_heap_limit_callback(near_heap_limit, data);
+        }
+        isolate
+    }
+
+    /// Per-execution state seeded with the sandbox-level settings.
+    fn new_state(&self, sender: mpsc::Sender<Completion>) -> SandboxState {
+        let state = SandboxState::new(sender, self.dispatch_context());
+        state.shared.max_tool_calls.set(self.config.max_tool_calls);
+        state.shared.max_timers.set(self.config.max_timers);
+        state
+            .shared
+            .max_result_bytes
+            .set(self.config.max_result_bytes);
+        *state.shared.redactor.borrow_mut() = self.redactor();
+        *state.shared.memory.borrow_mut() = self.memory.read().ok().and_then(|store| store.clone());
+        state
+    }
+
+    /// Like [`Sandbox::prepare`], but gives `tools` a fresh context inside an existing isolate.
+    pub(crate) fn prepare_in(
+        &self,
+        mut isolate: v8::OwnedIsolate,
+        tools: &[&Tool],
+        interface_generator: &ToolInterfaceGenerator,
+        callers: &BTreeMap<String, ToolCallerEntry>,
+    ) -> Result<PreparedSandbox, SandboxError> {
+        let tool_runtime = self.tool_runtime()?;
+        let runtime_handle = match &tool_runtime {
+            Some(runtime) => runtime.handle(),
+            None => self.config.runtime()?,
+        };
+
+        let (tx, rx) = mpsc::channel::<Completion>();
+        let mut state = self.new_state(tx);
+        let shared_ptr = state.shared_ptr();
+        let interface_report;
+        let postamble;
+        let baseline_globals;
+        #[cfg(feature = "inspector")]
+        let inspector = self
+            .config
+            .inspector
+            .as_ref()
+            .map(|config| SandboxInspector::new(&mut isolate, config))
+            .transpose()?;
+        #[cfg(not(feature = "inspector"))]
+        if self.config.inspector.is_some() {
+            return Err(SandboxError::V8(
+                "inspector needs the `inspector` feature".to_string(),
+            ));
+        }
+        let profiler = match self.config.stack_sample_interval_us {
+            Some(interval) => {
+                #[cfg(feature = "inspector")]
+                let profiler = match &inspector {
+                    Some(inspector) => inspector.profiler(interval),
+                    None => CpuProfiler::new(&mut isolate, interval),
+                };
+                #[cfg(not(feature = "inspector"))]
+                let profiler = CpuProfiler::new(&mut isolate, interval);
+                Some(profiler?)
+            }
+            None => None,
+        };
+
+        let context = {
+            let scope = std::pin::pin!(v8::HandleScope::new(&mut isolate));
+            let scope = &mut scope.init();
+            let context = v8::Context::new(scope, Default::default());
+            let scope = &mut v8::ContextScope::new(scope, context);
+            #[cfg(feature = "inspector")]
+            if let Some(inspector) = &inspector {
+                inspector.context_created(context);
+            }
+            let global = context.global(scope);
+            let builtins = if self.config.harden {
+                let mut names = global_names(scope, global)?;
+                // V8 ships its own `console`, which the capability replaces and must survive.
+                if self.config.capabilities.console {
+                    names.retain(|name| name != "console");
+                }
+                names
+            } else {
+                Vec::new()
+            };
+            context.set_slot(Rc::new(ModuleRegistry {
+                sources: self.config.modules.clone(),
+                compiled: RefCell::new(HashMap::new()),
+            }));
+
+            if let (Some(store), Some(threshold_bytes)) =
+                (&self.config.blob_store, self.config.blob_threshold_bytes)
+            {
+                state.dispatch.blob_offload = Some(Box::new(BlobOffload {
+                    store: store.clone(),
+                    threshold_bytes,
+                }));
+                inject_blob_helpers(scope, global, &state)?;
+            }
+            inject_emit(scope, global, &state)?;
+            inject_memory(scope, global, &state)?;
+            if let Ok(global_fns) = self.global_fns.read() {
+                inject_global_fns(scope, global, &global_fns, &mut state)?;
+            }
+            let capabilities = self.config.capabilities;
+            if capabilities.crypto {
+                inject_crypto(scope, global)?;
+            }
+            if capabilities.platform {
+                inject_platform(scope, global)?;
+            }
+            if capabilities.encoding {
+                inject_encoding(scope, global)?;
+            }
+            if capabilities.timers {
+                inject_timers(scope, global, &state)?;
+            }
+            if capabilities.console {
+                inject_console(scope, global, &state)?;
+            }
+            if let Some(env) = &self.config.env {
+                define_frozen(scope, global, "env", env)?;
+            }
+            if capabilities.fetch {
+                #[cfg(feature = "fetch")]
+                inject_fetch(
+                    scope,
+                    global,
+                    &mut state,
+                    runtime_handle.clone(),
+                    &self.config.fetch,
+                )?;
+                #[cfg(not(feature = "fetch"))]
+                return Err(SandboxError::V8(
+                    "fetch capability needs the `fetch` feature".to_string(),
+                ));
+            }
+
+            let tool_interfaces = tools
+                .iter()
+                .map(|tool| interface_generator.tool_to_typescript_interface(tool))
+                .collect::<Vec<String>>();
+            let interfaces = tool_interfaces.join("\n\n");
+            let report = InterfaceReport {
+                hash: content_hash(&interfaces),
+                tools: tools.iter().map(|tool| tool.name.clone()).collect(),
+            };
+            debug!(
+                interfaces = %interfaces,
+                interface_hash = report.hash.as_str(),
+                tools = ?report.tools,
+                "sandbox tool interfaces"
+            );
+            interface_report = self.config.report_interfaces.then_some(report);
+
+            if tools.iter().any(|tool| tool.is_streaming) {
+                inject_stream_factory(scope, &state)?;
+            }
+            inject_discovery(scope, global, tools, &tool_interfaces, interface_generator)?;
+
+            inject_tools(
+                scope,
+                global,
+                tools,
+                interface_generator,
+                callers,
+                &self.config,
+                runtime_handle,
+                shared_ptr,
+                &mut state,
+            )?;
+
+            if let Some(preamble) = &self.config.preamble {
+                run_script(scope, preamble)?;
+            }
+            if self.config.harden {
+                harden_realm(scope, &builtins, tools, interface_generator)?;
+            }
+            postamble = self
+                .config
+                .postamble
+                .as_deref()
+                .map(|body| compile_result_function(scope, body))
+                .transpose()?;
+            baseline_globals = global_names(scope, global)?.into_iter().collect();
+
+            v8::Global::new(scope, context)
+        };
+
+        Ok(PreparedSandbox {
+            profiler,
+            #[cfg(feature = "inspector")]
+            inspector,
+            context,
+            state,
+            rx,
+            config: self.config.clone(),
+            interface_report,
+            postamble,
+            baseline_globals,
+            _tool_runtime: tool_runtime,
+            isolate,
+        })
+    }
+}
+
+/// Runtime owned by a [`Sandbox`] for tool dispatch; see [`SandboxConfig::tool_runtime`].
+pub(super) struct ToolRuntime {
+    runtime: Option<tokio::runtime::Runtime>,
+}
+
+impl ToolRuntime {
+    fn build(config: &ToolRuntimeConfig) -> Result<Self, SandboxError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(config.worker_threads.max(1))
+            .thread_name(config.thread_name.clone())
+            .enable_all()
+            .build()
+            .map_err(|err| SandboxError::Tool(format!("tool runtime: {err}")))?;
+        Ok(Self {
+            runtime: Some(runtime),
+        })
+    }
+
+    fn handle(&self) -> tokio::runtime::Handle {
+        self.runtime
+            .as_ref()
+            .map(|runtime| runtime.handle().clone())
+            .expect("tool runtime is only taken on drop")
+    }
+}
+
+impl Drop for ToolRuntime {
+    // Dropping a runtime blocks, which panics when the last owner lives inside the host runtime.
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// A V8 startup snapshot built by [`Sandbox::build_snapshot`]. Only valid for the V8 build that
+/// produced it, so keep it in memory rather than persisting it.
+#[derive(Clone)]
+pub struct StartupSnapshot {
+    blob: Arc<[u8]>,
+}
+
+impl StartupSnapshot {
+    pub fn size_bytes(&self) -> usize {
+        self.blob.len()
+    }
+}
+
+/// An isolate and context with tools injected, reusable across executions on one thread.
+///
+/// Globals assigned by one execution remain visible to the next.
+pub struct PreparedSandbox {
+    // Field order matters: V8 handles and callback state must drop before the isolate, and the
+    // profiler's session before the inspector it may be attached to.
+    profiler: Option<CpuProfiler>,
+    #[cfg(feature = "inspector")]
+    inspector: Option<SandboxInspector>,
+    context: v8::Global<v8::Context>,
+    state: SandboxState,
+    rx: mpsc::Receiver<Completion>,
+    config: SandboxConfig,
+    interface_report: Option<InterfaceReport>,
+    postamble: Option<v8::Global<v8::Function>>,
+    // Globals present once preparation finished; see `forget_globals`.
+    baseline_globals: BTreeSet<String>,
+    _tool_runtime: Option<Arc<ToolRuntime>>,
+    isolate: v8::OwnedIsolate,
+}
+
+impl PreparedSandbox {
+    /// Releases the context and callback state, keeping the isolate for another context.
+    pub(crate) fn into_isolate(self) -> v8::OwnedIsolate {
+        let PreparedSandbox {
+            profiler,
+            #[cfg(feature = "inspector")]
+            inspector,
+            context,
+            state,
+            isolate,
+            ..
+        } = self;
+        drop(profiler);
+        #[cfg(feature = "inspector")]
+        drop(inspector);
+        drop(context);
+        drop(state);
+        isolate
+    }
+
+    /// Deletes the globals executions added since preparation, so a context handed to another
+    /// caller starts clean. Changes to objects that already existed are not undone; `harden`
+    /// freezes the built-ins a script could otherwise tamper with.
+    pub(crate) fn forget_globals(&mut self) -> Result<(), SandboxError> {
+        let scope = std::pin::pin!(v8::HandleScope::new(&mut self.isolate));
+        let scope = &mut scope.init();
+        let context = v8::Local::new(scope, &self.context);
+        let scope = &mut v8::ContextScope::new(scope, context);
+        let global = context.global(scope);
+        for name in global_names(scope, global)? {
+            if self.baseline_globals.contains(&name) {
+                continue;
+            }
+            let key = v8::String::new(scope, &name)
+                .ok_or_else(|| SandboxError::V8("global name".to_string()))?;
+            global.delete(scope, key.into());
+        }
+        Ok(())
+    }
+
+    pub fn execute(&mut self, code: &str) -> Result<ExecutionResult, SandboxError> {
+        self.execute_with_cancel(code, &CancellationToken::new())
+    }
+
+    /// WebSocket URL Chrome DevTools can attach to when `SandboxConfig::inspector` is set.
+    #[cfg(feature = "inspector")]
+    pub fn inspector_url(&self) -> Option<String> {
+        self.inspector.as_ref().map(SandboxInspector::url)
+    }
+
+    /// Writes a V8 heap snapshot of this sandbox's isolate to `path`; open it in the Memory tab
+    /// of Chrome DevTools.
+    pub fn take_heap_snapshot(&mut self, path: &Path) -> Result<(), SandboxError> {
+        write_heap_snapshot(&mut self.isolate, path)
+            .map_err(|err| SandboxError::V8(format!("heap snapshot {}: {err}", path.display())))
+    }
+
+    /// Like [`PreparedSandbox::execute`]; firing `cancel` terminates the script and drops any
+    /// in-flight tool futures.
+    pub fn execute_with_cancel(
+        &mut self,
+        code: &str,
+        cancel: &CancellationToken,
+    ) -> Result<ExecutionResult, SandboxError> {
+        self.execute_with(code, cancel, ExecutionExtras::default())
+    }
+
+    /// Like [`PreparedSandbox::execute`], with `input` exposed to the script as a frozen
+    /// `input` global.
+    pub fn execute_with_input(
+        &mut self,
+        code: &str,
+        input: Value,
+    ) -> Result<ExecutionResult, SandboxError> {
+        let extras = ExecutionExtras {
+            input: Some(input),
+            ..Default::default()
+        };
+        self.execute_with(code, &CancellationToken::new(), extras)
+    }
+
+    /// Plan-only counterpart of [`PreparedSandbox::execute`]; see [`Sandbox::plan`].
+    pub fn plan(&mut self, code: &str) -> Result<ExecutionResult, SandboxError> {
+        self.state.shared.planning.set(true);
+        let outcome = self.execute_inner(code, &CancellationToken::new());
+        self.state.shared.planning.set(false);
+        outcome
+    }
+
+    pub(crate) fn execute_with(
+        &mut self,
+        code: &str,
+        cancel: &CancellationToken,
+        extras: ExecutionExtras,
+    ) -> Result<ExecutionResult, SandboxError> {
+        *self.state.shared.emitter.borrow_mut() = extras.emitter;
+        *self.state.shared.input.borrow_mut() = extras.input;
+        #[cfg(feature = "otel")]
+        {
+            *self.state.shared.otel_context.borrow_mut() = extras.otel_context;
+        }
+        let outcome = self.execute_inner(code, cancel);
+        self.state.shared.emitter.borrow_mut().take();
+        self.state.shared.input.borrow_mut().take();
+        #[cfg(feature = "otel")]
+        self.state.shared.otel_context.borrow_mut().take();
+        outcome
+    }
+
+    fn execute_inner(
+        &mut self,
+        code: &str,
+        cancel: &CancellationToken,
+    ) -> Result<ExecutionResult, SandboxError> {
+        self.state.reset_pending();
+        self.state.take_trace();
+        while self.rx.try_recv().is_ok() {}
+        let cancel = cancel.child_token();
+        *self.state.shared.cancel.borrow_mut() = cancel.clone();
+
+        // Before the watch starts: waiting for a debugger gets its own `timeout_ms`, so the
+        // script keeps its full time once the debugger resumes it.
+        #[cfg(feature = "inspector")]
+        if let Some(inspector) = &self.inspector {
+            inspector.before_execution(&cancel, self.config.timeout_ms)?;
+        }
+        let started = Instant::now();
+        let watch = ExecutionWatch::start(
+            self.isolate.thread_safe_handle(),
+            WatchLimits {
+                timeout: Duration::from_millis(self.config.timeout_ms),
+                cancel,
+                budget: self.config.interrupt_budget.map(|ticks| {
+                    let tick = Duration::from_micros(self.config.interrupt_tick_us.max(1));
+                    (ticks, tick)
+                }),
+            },
+        )?;
+        if let Some(profiler) = &self.profiler {
+            profiler.start()?;
+        }
+        let outcome = self.run(code);
+        let report = watch.stop();
+        let stacks = self.profiler.as_ref().map(CpuProfiler::stop).transpose()?;
+        let outcome = match stacks {
+            Some(stacks) => outcome.map(|mut result| {
+                result.stack_samples = Some(StackSamples {
+                    interval_us: self.config.stack_sample_interval_us.unwrap_or_default(),
+                    stacks,
+                    total_ms: started.elapsed().as_millis() as u64,
+                    idle_ms: self.state.shared.idle.get().as_millis() as u64,
+                });
+                result
+            }),
+            None => outcome,
+        };
+        if report.cancelled {
+            self.isolate.cancel_terminate_execution();
+            return Err(SandboxError::Cancelled);
+        }
+        if let Some(ticks) = report.budget_exceeded {
+            self.isolate.cancel_terminate_execution();
+            return Err(SandboxError::BudgetExceeded(ticks));
+        }
+        if report.timed_out {
+            self.isolate.cancel_terminate_execution();
+            return Err(SandboxError::Timeout(self.config.timeout_ms));
+        }
+        if let Some(size) = self.state.shared.oversized.take() {
+            self.isolate.cancel_terminate_execution();
+            return Err(SandboxError::ResultTooLarge {
+                size,
+                limit: self.config.max_result_bytes.unwrap_or_default(),
+            });
+        }
+        outcome
+    }
+
+    fn run(&mut self, code: &str) -> Result<ExecutionResult, SandboxError> {
+        let shared_ptr = self.state.shared_ptr();
+        let scope = std::pin::pin!(v8::HandleScope::new(&mut self.isolate));
+        let scope = &mut scope.init();
+        let context = v8::Local::new(scope, &self.context);
+        let scope = &mut v8::ContextScope::new(scope, context);
+        let rx = &self.rx;
+
+        let timeout_ms = self.config.timeout_ms;
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+        let script = prepare_source(&self.config, code)?;
+        let input = self.state.shared.input.borrow().clone();
+        let global = context.global(scope);
+        install_input(scope, global, input.as_ref())?;
+        let result = evaluate(
+            scope,
+            &script.source,
+            script.mode,
+            script.column_offset,
+            rx,
+            shared_ptr,
+            deadline,
+            timeout_ms,
+        )?;
+        let result = match &self.postamble {
+            Some(postamble) => {
+                let postamble = v8::Local::new(scope, postamble);
+                let transformed = {
+                    let tc = std::pin::pin!(v8::TryCatch::new(scope));
+                    let tc = &mut tc.init();
+                    let receiver = v8::undefined(tc).into();
+                    postamble
+                        .call(tc, receiver, &[result])
+                        .ok_or_else(|| caught_error(tc, "postamble"))?
+                };
+                resolve_value(scope, transformed, rx, shared_ptr, deadline, timeout_ms)?
+            }
+            None => result,
+        };
+        let undefined = self.config.distinguish_undefined && result.is_undefined();
+        let result = v8_value_to_json(scope, result)?;
+        if let Some(limit) = self.config.max_result_bytes {
+            let size = serialized_len(&result);
+            if size > limit {
+                return Err(SandboxError::ResultTooLarge { size, limit });
+            }
+        }
+
+        trace!(
+            result = %self.state.shared.format_value(&result),
+            undefined,
+            "sandbox execute done"
+        );
+        Ok(ExecutionResult {
+            result,
+            undefined,
+            interfaces: self.interface_report.clone(),
+            trace: self.state.take_trace(),
+            emitted: self.state.shared.emitted.take(),
+            logs: self.state.shared.logs.take(),
+            debug_state: self.state.shared.debug_state.take(),
+            plan: self.state.shared.plan.take(),
+            cache: self
+                .state
+                .dispatch
+                .call_cache
+                .as_ref()
+                .map(|cache| cache.reset()),
+            stack_samples: None,
+        })
+    }
+}
+
+struct HeapSnapshotTrigger {
+    isolate: *mut v8::Isolate,
+    dir: PathBuf,
+    taken: AtomicBool,
+}
+
+extern "C" fn near_heap_limit(
+    data: *mut c_void,
+    current_heap_limit: usize,
+    _initial_heap_limit: usize,
+) -> usize {
+    // SAFETY: `data` points at the trigger owned by the isolate's slot, which lives as long as
+    // the isolate that invokes this callback.
+    let trigger = unsafe { &*(data as *const HeapSnapshotTrigger) };
+    if trigger.taken.swap(true, Ordering::AcqRel) {
+        return current_heap_limit;
+    }
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default();
+    let path = trigger.dir.join(format!("codemode-{millis}.heapsnapshot"));
+    // SAFETY: V8 runs this callback on the isolate's own thread while it is entered.
+    let isolate = unsafe { &mut *trigger.isolate };
+    match write_heap_snapshot(isolate, &path) {
+        Ok(()) => debug!(path = %path.display(), "heap snapshot written near heap limit"),
+        Err(err) => debug!(path = %path.display(), error = %err, "heap snapshot failed"),
+    }
+    current_heap_limit
+}
+
+fn write_heap_snapshot(isolate: &mut v8::Isolate, path: &Path) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut written = Ok(());
+    isolate.take_heap_snapshot(|chunk| match file.write_all(chunk) {
+        Ok(()) => true,
+        Err(err) => {
+            written = Err(err);
+            false
+        }
+    });
+    written?;
+    file.flush()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn inject_tools<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+    tools: &[&Tool],
+    interface_generator: &ToolInterfaceGenerator,
+    callers: &BTreeMap<String, ToolCallerEntry>,
+    config: &SandboxConfig,
+    runtime_handle: tokio::runtime::Handle,
+    shared_state: *const AsyncSharedState,
+    state: &mut SandboxState,
+) -> Result<(), SandboxError> {
+    for tool in tools {
+        let access_path = interface_generator.tool_access_path(tool);
+        let parts = access_path.split('.').collect::<Vec<&str>>();
+        if parts.is_empty() {
+            continue;
+        }
+
+        let mut target = global;
+        for part in &parts[..parts.len() - 1] {
+            target = ensure_namespace(scope, target, part)?;
+        }
+
+        let js_transformer = match callers
+            .get(&tool.name)
+            .and_then(|entry| entry.options.transformer.as_ref())
+        {
+            Some(ResultTransformer::Js(body)) => {
+                Some(compile_transformer(scope, &tool.name, body)?)
+            }
+            _ => None,
+        };
+        let tool_state = Box::new(ToolCallbackState {
+            dispatch: tool_dispatch(tool, callers, config, &state.dispatch),
+            runtime: runtime_handle.clone(),
+            shared: shared_state,
+            is_async: tool.is_async,
+            is_streaming: tool.is_streaming,
+            takes_no_input: tool.takes_no_input(),
+            js_transformer,
+            placeholder: Simulator::default().generate(&tool.outputs),
+        });
+        let tool_external = v8::External::new(scope, &*tool_state as *const _ as *mut c_void);
+        let tool_fn = v8::Function::builder(tool_callback)
+            .data(tool_external.into())
+            .build(scope)
+            .ok_or_else(|| SandboxError::V8("tool function".to_string()))?;
+        let fn_key = v8::String::new(scope, parts[parts.len() - 1])
+            .ok_or_else(|| SandboxError::V8("tool name string".to_string()))?;
+        target.set(scope, fn_key.into(), tool_fn.into());
+        state.tool_states.push(tool_state);
+    }
+
+    Ok(())
+}
+
+struct ToolCallbackState {
+    dispatch: ToolDispatch,
+    runtime: tokio::runtime::Handle,
+    shared: *const AsyncSharedState,
+    is_async: bool,
+    is_streaming: bool,
+    /// Zero-argument tools also accept `tool(null)`.
+    takes_no_input: bool,
+    js_transformer: Option<v8::Global<v8::Function>>,
+    /// Plan-only runs answer with this instead of calling the tool.
+    placeholder: Value,
+}
+
+fn compile_transformer(
+    scope: &mut v8::PinScope<'_, '_>,
+    tool_name: &str,
+    body: &str,
+) -> Result<v8::Global<v8::Function>, SandboxError> {
+    compile_result_function(scope, body)
+        .map_err(|_| SandboxError::V8(format!("result transformer for '{tool_name}'")))
+}
+
+fn compile_result_function(
+    scope: &mut v8::PinScope<'_, '_>,
+    body: &str,
+) -> Result<v8::Global<v8::Function>, SandboxError> {
+    let source = format!("(function(result) {{ {body} }})");
+    let value = run_script(scope, &source)?;
+    let function = v8::Local::<v8::Function>::try_from(value)
+        .map_err(|_| SandboxError::V8("result function".to_string()))?;
+    Ok(v8::Global::new(scope, function))
+}
+
+fn apply_js_transformer<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    state: &ToolCallbackState,
+    value: v8::Local<'a, v8::Value>,
+) -> Option<v8::Local<'a, v8::Value>> {
+    let Some(transformer) = &state.js_transformer else {
+        return Some(value);
+    };
+    let transformer = v8::Local::new(scope, transformer);
+    let receiver = v8::undefined(scope).into();
+    transformer.call(scope, receiver, &[value])
+}
+
+#[allow(clippy::vec_box)]
+struct SandboxState {
+    // Box is required here for stable heap addresses - V8 callbacks hold pointers to these
+    tool_states: Vec<Box<ToolCallbackState>>,
+    shared: Box<AsyncSharedState>,
+    dispatch: DispatchContext,
+    global_fns: Vec<Box<Arc<GlobalFn>>>,
+    #[cfg(feature = "fetch")]
+    fetch: Option<Box<FetchState>>,
+}
+
+impl SandboxState {
+    fn new(sender: mpsc::Sender<Completion>, dispatch: DispatchContext) -> Self {
+        Self {
+            tool_states: Vec::new(),
+            shared: Box::new(AsyncSharedState::new(sender)),
+            dispatch,
+            global_fns: Vec::new(),
+            #[cfg(feature = "fetch")]
+            fetch: None,
+        }
+    }
+
+    fn take_trace(&self) -> Vec<TraceEvent> {
+        self.dispatch
+            .trace
+            .lock()
+            .map(|mut trace| std::mem::take(&mut *trace))
+            .unwrap_or_default()
+    }
+
+    // Drops promises left unresolved by a previous execution on the same context.
+    fn reset_pending(&self) {
+        self.shared.resolvers.borrow_mut().clear();
+        self.shared.streams.borrow_mut().clear();
+        self.shared.pending.set(0);
+        self.shared.tool_calls.set(0);
+        self.shared.emitted.take();
+        self.shared.logs.take();
+        self.shared.debug_state.take();
+        self.shared.idle.set(Duration::ZERO);
+        self.shared.oversized.set(None);
+        self.shared.plan.take();
+        self.shared.error_handler.take();
+        self.shared.scratch.clear();
+        self.shared.timers.borrow_mut().clear();
+        self.shared.timers_created.set(0);
+        if let Some(cache) = &self.dispatch.call_cache {
+            cache.reset();
+        }
+    }
+
+    fn shared_ptr(&self) -> *const AsyncSharedState {
+        // SAFETY: This pointer is valid as long as SandboxState is alive.
+        // The pointer is only used during sandbox execution and never stored beyond that.
+        &*self.shared as *const AsyncSharedState
+    }
+}
+
+struct AsyncSharedState {
+    next_id: AtomicU64,
+    pending: Cell<usize>,
+    resolvers: RefCell<HashMap<u64, v8::Global<v8::PromiseResolver>>>,
+    streams: RefCell<HashMap<u64, StreamEntry>>,
+    stream_factory: RefCell<Option<v8::Global<v8::Function>>>,
+    // Replaced per execution; tool futures stop when it fires.
+    cancel: RefCell<CancellationToken>,
+    tool_calls: Cell<u64>,
+    max_tool_calls: Cell<Option<u64>>,
+    emitted: RefCell<Vec<Value>>,
+    logs: RefCell<Vec<String>>,
+    debug_state: RefCell<Map<String, Value>>,
+    // Time the completion loop spent blocked waiting for tools or timers.
+    idle: Cell<Duration>,
+    max_result_bytes: Cell<Option<usize>>,
+    // Size of the tool result that broke `max_result_bytes`; the execution then fails.
+    oversized: Cell<Option<usize>>,
+    emitter: RefCell<Option<ChainEmitter>>,
+    input: RefCell<Option<Value>>,
+    redactor: RefCell<Option<Arc<dyn Redactor>>>,
+    // Set by `codemode.onToolError`; sees every failed tool call before the script does.
+    error_handler: RefCell<Option<v8::Global<v8::Function>>>,
+    memory: RefCell<Option<Arc<dyn MemoryStore>>>,
+    // Backs `codemode.memory` for one execution when the host registered no store.
+    scratch: Arc<InMemoryStore>,
+    #[cfg(feature = "otel")]
+    otel_context: RefCell<Option<opentelemetry::Context>>,
+    timers: RefCell<BTreeMap<u64, TimerEntry>>,
+    timers_created: Cell<u64>,
+    max_timers: Cell<u64>,
+    planning: Cell<bool>,
+    plan: RefCell<Vec<PlannedCall>>,
+    sender: mpsc::Sender<Completion>,
+}
+
+struct TimerEntry {
+    due: Instant,
+    callback: v8::Global<v8::Function>,
+    args: Vec<v8::Global<v8::Value>>,
+    interval: Option<Duration>,
+}
+
+impl AsyncSharedState {
+    fn new(sender: mpsc::Sender<Completion>) -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            pending: Cell::new(0),
+            resolvers: RefCell::new(HashMap::new()),
+            streams: RefCell::new(HashMap::new()),
+            stream_factory: RefCell::new(None),
+            cancel: RefCell::new(CancellationToken::new()),
+            tool_calls: Cell::new(0),
+            max_tool_calls: Cell::new(None),
+            emitted: RefCell::new(Vec::new()),
+            logs: RefCell::new(Vec::new()),
+            debug_state: RefCell::new(Map::new()),
+            idle: Cell::new(Duration::ZERO),
+            max_result_bytes: Cell::new(None),
+            oversized: Cell::new(None),
+            emitter: RefCell::new(None),
+            input: RefCell::new(None),
+            redactor: RefCell::new(None),
+            error_handler: RefCell::new(None),
+            memory: RefCell::new(None),
+            scratch: Arc::new(InMemoryStore::default()),
+            #[cfg(feature = "otel")]
+            otel_context: RefCell::new(None),
+            timers: RefCell::new(BTreeMap::new()),
+            timers_created: Cell::new(0),
+            max_timers: Cell::new(default_max_timers()),
+            planning: Cell::new(false),
+            plan: RefCell::new(Vec::new()),
+            sender,
+        }
+    }
+
+    /// Checks a tool result against `max_result_bytes`; an oversized one terminates the
+    /// execution, which then fails with `SandboxError::ResultTooLarge`.
+    fn accept_result_size(&self, scope: &mut v8::PinScope<'_, '_>, value: &Value) -> bool {
+        let Some(limit) = self.max_result_bytes.get() else {
+            return true;
+        };
+        let size = serialized_len(value);
+        if size <= limit {
+            return true;
+        }
+        self.oversized.set(Some(size));
+        scope.terminate_execution();
+        false
+    }
+
+    fn memory_store(&self) -> Arc<dyn MemoryStore> {
+        match self.memory.borrow().as_ref() {
+            Some(store) => store.clone(),
+            None => self.scratch.clone(),
+        }
+    }
+
+    fn format_value(&self, value: &Value) -> String {
+        format_value(value, self.redactor.borrow().as_deref())
+    }
+
+    fn count_tool_call(&self) -> Result<(), String> {
+        let calls = self.tool_calls.get() + 1;
+        self.tool_calls.set(calls);
+        match self.max_tool_calls.get() {
+            Some(max) if calls > max => Err(format!("tool call budget of {max} exceeded")),
+            _ => Ok(()),
+        }
+    }
+
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+struct Completion {
+    id: u64,
+    // Set for tool calls, whose failures go through `codemode.onToolError`.
+    tool: Option<String>,
+    result: Result<Value, String>,
+}
+
+fn resolve_value<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    value: v8::Local<'a, v8::Value>,
+    rx: &mpsc::Receiver<Completion>,
+    shared: *const AsyncSharedState,
+    deadline: Instant,
+    timeout_ms: u64,
+) -> Result<v8::Local<'a, v8::Value>, SandboxError> {
+    if !value.is_promise() {
+        return Ok(value);
+    }
+
+    let promise = v8::Local::<v8::Promise>::try_from(value)
+        .map_err(|_| SandboxError::V8("promise cast".to_string()))?;
+
+    loop {
+        drain_completions(scope, rx, shared)?;
+        run_due_timers(scope, shared)?;
+        scope.perform_microtask_checkpoint();
+        #[cfg(feature = "inspector")]
+        crate::inspector::poll(scope);
+
+        if scope.is_execution_terminating() {
+            return Err(SandboxError::V8("execution terminated".to_string()));
+        }
+
+        // SAFETY: The shared pointer is valid as long as SandboxState is alive.
+        if unsafe { &*shared }.cancel.borrow().is_cancelled() {
+            return Err(SandboxError::Cancelled);
+        }
+
+        if promise.state() != v8::PromiseState::Pending {
+            if promise.state() == v8::PromiseState::Rejected {
+                let reason = promise.result(scope);
+                return Err(script_error(scope, reason));
+            }
+            return Ok(promise.result(scope));
+        }
+
+        if Instant::now() > deadline {
+            return Err(SandboxError::Timeout(timeout_ms));
+        }
+
+        let wait = next_timer_wait(shared).unwrap_or(POLL_INTERVAL);
+        let waiting_since = Instant::now();
+        let received = rx.recv_timeout(wait.min(POLL_INTERVAL));
+        // SAFETY: The shared pointer is valid as long as SandboxState is alive.
+        let idle = &unsafe { &*shared }.idle;
+        idle.set(idle.get() + waiting_since.elapsed());
+        match received {
+            Ok(completion) => {
+                apply_completion(scope, shared, completion)?;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    Err(SandboxError::V8("execution incomplete".to_string()))
+}
+
+/// JSON length of `value`, counted without building the string.
+fn serialized_len(value: &Value) -> usize {
+    struct Counter(usize);
+
+    impl std::io::Write for Counter {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0 += bytes.len();
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    let _ = serde_json::to_writer(&mut counter, value);
+    counter.0
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+fn next_timer_wait(shared: *const AsyncSharedState) -> Option<Duration> {
+    // SAFETY: The shared pointer is valid as long as SandboxState is alive.
+    let shared = unsafe { &*shared };
+    let timers = shared.timers.borrow();
+    let due = timers.values().map(|timer| timer.due).min()?;
+    Some(due.saturating_duration_since(Instant::now()))
+}
+
+/// Fires every timer that is due, earliest first. A throwing callback fails the execution.
+fn run_due_timers(
+    scope: &mut v8::PinScope<'_, '_>,
+    shared: *const AsyncSharedState,
+) -> Result<(), SandboxError> {
+    // SAFETY: The shared pointer is valid as long as SandboxState is alive.
+    let shared = unsafe { &*shared };
+    let now = Instant::now();
+    let mut due = shared
+        .timers
+        .borrow()
+        .iter()
+        .filter(|(_, timer)| timer.due <= now)
+        .map(|(id, timer)| (timer.due, *id))
+        .collect::<Vec<(Instant, u64)>>();
+    due.sort();
+
+    for (_, id) in due {
+        // An earlier callback may have cleared this one.
+        let Some(timer) = shared.timers.borrow_mut().remove(&id) else {
+            continue;
+        };
+        let callback = v8::Local::new(scope, &timer.callback);
+        let args = timer
+            .args
+            .iter()
+            .map(|arg| v8::Local::new(scope, arg))
+            .collect::<Vec<v8::Local<v8::Value>>>();
+        if let Some(interval) = timer.interval {
+            // Re-armed before the call so the callback can clear its own interval.
+            shared.timers.borrow_mut().insert(
+                id,
+                TimerEntry {
+                    due: now + interval,
+                    ..timer
+                },
+            );
+        }
+
+        let tc = std::pin::pin!(v8::TryCatch::new(scope));
+        let tc = &mut tc.init();
+        let receiver = v8::undefined(tc).into();
+        if callback.call(tc, receiver, &args).is_none() {
+            return Err(caught_error(tc, "timer callback"));
+        }
+    }
+    Ok(())
+}
+
+fn drain_completions(
+    scope: &mut v8::PinScope<'_, '_>,
+    rx: &mpsc::Receiver<Completion>,
+    shared: *const AsyncSharedState,
+) -> Result<(), SandboxError> {
+    loop {
+        match rx.try_recv() {
+            Ok(completion) => apply_completion(scope, shared, completion)?,
+            Err(mpsc::TryRecvError::Empty) => return Ok(()),
+            Err(mpsc::TryRecvError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+fn apply_completion(
+    scope: &mut v8::PinScope<'_, '_>,
+    shared: *const AsyncSharedState,
+    completion: Completion,
+) -> Result<(), SandboxError> {
+    // SAFETY: The shared pointer is valid as long as SandboxState is alive.
+    // This function is only called during sandbox execution while the state exists.
+    let shared = unsafe { &*shared };
+    let Some(resolver) = shared.resolvers.borrow_mut().remove(&completion.id) else {
+        return Ok(());
+    };
+    shared.pending.set(shared.pending.get().saturating_sub(1));
+    let resolver = v8::Local::new(scope, &resolver);
+
+    match completion.result {
+        Ok(value) if !shared.accept_result_size(scope, &value) => {}
+        Ok(value) => {
+            if let Some(value) = json_to_v8(scope, &value) {
+                resolver.resolve(scope, value);
+            } else {
+                let message = v8::String::new(scope, "failed to serialize tool result")
+                    .ok_or_else(|| SandboxError::V8("error string".to_string()))?;
+                let exception = v8::Exception::error(scope, message);
+                resolver.reject(scope, exception);
+            }
+        }
+        Err(message) => {
+            let exception = match &completion.tool {
+                Some(tool) => tool_error(scope, shared, tool, &message),
+                None => v8::String::new(scope, &message)
+                    .map(|message| v8::Exception::error(scope, message)),
+            }
+            .ok_or_else(|| SandboxError::V8("error string".to_string()))?;
+            resolver.reject(scope, exception);
+        }
+    }
+
+    Ok(())
+}
+
+// Builds the error for a failed tool call, tagged with the tool's name. A handler registered
+// with `codemode.onToolError` may replace it: a string becomes the new message, any other
+// value (or a thrown one) becomes the error itself, and `undefined` keeps the original.
+fn tool_error<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    shared: &AsyncSharedState,
+    tool: &str,
+    message: &str,
+) -> Option<v8::Local<'a, v8::Value>> {
+    let error = new_tool_error(scope, tool, message)?;
+    let Some(handler) = shared.error_handler.borrow().clone() else {
+        return Some(error);
+    };
+    let handler = v8::Local::new(scope, handler);
+    let receiver = v8::undefined(scope).into();
+    let tc = std::pin::pin!(v8::TryCatch::new(scope));
+    let tc = &mut tc.init();
+    let replacement = match handler.call(tc, receiver, &[error]) {
+        Some(replacement) => replacement,
+        None => return tc.exception().or(Some(error)),
+    };
+    if replacement.is_undefined() {
+        Some(error)
+    } else if replacement.is_string() {
+        let message = replacement.to_rust_string_lossy(tc);
+        new_tool_error(tc, tool, &message)
+    } else {
+        Some(replacement)
+    }
+}
+
+fn new_tool_error<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    tool: &str,
+    message: &str,
+) -> Option<v8::Local<'a, v8::Value>> {
+    let message = v8::String::new(scope, message)?;
+    let error = v8::Exception::error(scope, message);
+    let object = error.to_object(scope)?;
+    let key = v8::String::new(scope, "tool")?;
+    let name = v8::String::new(scope, tool)?;
+    object.set(scope, key.into(), name.into());
+    Some(error)
+}
+
+fn init_v8(config: &SandboxConfig) {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let mut flags = config.v8_flags.clone();
+        if let Some(stack_size_kb) = config.stack_size_kb {
+            flags.push(format!("--stack-size={stack_size_kb}"));
+        }
+        if config.jitless {
+            flags.push("--jitless".to_string());
+        }
+        if !config.allow_code_generation_from_strings {
+            flags.push("--disallow-code-generation-from-strings".to_string());
+        }
+        if !flags.is_empty() {
+            v8::V8::set_flags_from_string(&flags.join(" "));
+        }
+        let platform = v8::new_default_platform(0, false).make_shared();
+        v8::V8::initialize_platform(platform);
+        v8::V8::initialize();
+    });
+}
+
+pub(crate) fn run_script<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    source: &str,
+) -> Result<v8::Local<'a, v8::Value>, SandboxError> {
+    run_script_at(scope, source, 0)
+}
+
+fn run_script_at<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    source: &str,
+    column_offset: i32,
+) -> Result<v8::Local<'a, v8::Value>, SandboxError> {
+    let script = compile_script(scope, source, column_offset)?;
+    let tc = std::pin::pin!(v8::TryCatch::new(scope));
+    let tc = &mut tc.init();
+    match script.run(tc) {
+        Some(value) => Ok(value),
+        None => Err(caught_error(tc, "script run")),
+    }
+}
+
+fn compile_script<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    source: &str,
+    column_offset: i32,
+) -> Result<v8::Local<'a, v8::Script>, SandboxError> {
+    let code = v8::String::new(scope, source)
+        .ok_or_else(|| SandboxError::V8("script source".to_string()))?;
+    let origin = script_origin(scope, "codemode.js", column_offset, false)?;
+    let tc = std::pin::pin!(v8::TryCatch::new(scope));
+    let tc = &mut tc.init();
+    v8::Script::compile(tc, code, Some(&origin)).ok_or_else(|| caught_error(tc, "script compile"))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn evaluate<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    source: &str,
+    mode: WrapMode,
+    column_offset: i32,
+    rx: &mpsc::Receiver<Completion>,
+    shared: *const AsyncSharedState,
+    deadline: Instant,
+    timeout_ms: u64,
+) -> Result<v8::Local<'a, v8::Value>, SandboxError> {
+    match mode {
+        WrapMode::Module => {
+            let module = compile_module(scope, source, column_offset)?;
+            let evaluation = evaluate_module(scope, module)?;
+            resolve_value(scope, evaluation, rx, shared, deadline, timeout_ms)?;
+            let default = module_default_export(scope, module)?;
+            resolve_value(scope, default, rx, shared, deadline, timeout_ms)
+        }
+        WrapMode::AsyncFunction | WrapMode::Expression => {
+            let result = run_script_at(scope, source, column_offset)?;
+            resolve_value(scope, result, rx, shared, deadline, timeout_ms)
+        }
+    }
+}
+
+fn caught_error(
+    tc: &mut v8::PinnedRef<'_, v8::TryCatch<'_, '_, v8::HandleScope<'_>>>,
+    what: &str,
+) -> SandboxError {
+    if tc.has_terminated() {
+        return SandboxError::V8("execution terminated".to_string());
+    }
+    match tc.exception() {
+        Some(exception) => script_error(tc, exception),
+        None => SandboxError::V8(what.to_string()),
+    }
+}
+
+fn script_error(scope: &mut v8::PinScope<'_, '_>, exception: v8::Local<v8::Value>) -> SandboxError {
+    let message = v8::Exception::create_message(scope, exception);
+    let text = message.get(scope).to_rust_string_lossy(scope);
+    let line = message.get_line_number(scope);
+    let column = line.map(|_| message.get_start_column() + 1);
+    let stack = exception
+        .to_object(scope)
+        .and_then(|object| {
+            let key = v8::String::new(scope, "stack")?;
+            object.get(scope, key.into())
+        })
+        .filter(|stack| stack.is_string())
+        .map(|stack| stack.to_rust_string_lossy(scope));
+    SandboxError::Script {
+        message: text,
+        stack,
+        line,
+        column,
+    }
+}
+
+fn script_origin<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    name: &str,
+    column_offset: i32,
+    is_module: bool,
+) -> Result<v8::ScriptOrigin<'a>, SandboxError> {
+    let name =
+        v8::String::new(scope, name).ok_or_else(|| SandboxError::V8("script name".to_string()))?;
+    Ok(v8::ScriptOrigin::new(
+        scope,
+        name.into(),
+        0,
+        column_offset,
+        false,
+        0,
+        None,
+        false,
+        false,
+        is_module,
+        None,
+    ))
+}
+
+#[cfg(not(feature = "typescript"))]
+fn strip_types(_source: &str, _module: bool, _prefix_len: usize) -> Result<String, SandboxError> {
+    Err(SandboxError::Transpile(
+        "built without the `typescript` feature".to_string(),
+    ))
+}
+
+/// The source V8 compiles for `code`, and the column offset that maps positions back to it.
+struct ScriptSource {
+    source: String,
+    /// Differs from the configured mode when leading imports forced a module wrapper.
+    mode: WrapMode,
+    column_offset: i32,
+}
+
+fn prepare_source(config: &SandboxConfig, code: &str) -> Result<ScriptSource, SandboxError> {
+    let hoisted = match config.wrap_mode {
+        WrapMode::AsyncFunction => split_imports(code),
+        WrapMode::Expression | WrapMode::Module => None,
+    };
+    let (source, mode) = match hoisted {
+        Some((imports, body)) => {
+            let directive = if config.strict {
+                "\"use strict\"; "
+            } else {
+                ""
+            };
+            let source = format!(
+                "{imports}export default await (async function() {{ {directive}{body} }})();"
+            );
+            (source, WrapMode::Module)
+        }
+        None => (
+            wrap_code(code, config.wrap_mode, config.strict),
+            config.wrap_mode,
+        ),
+    };
+    let source = if config.typescript {
+        let prefix_len = match hoisted {
+            Some(_) => 0,
+            None => wrap_prefix(config.wrap_mode, config.strict).len(),
+        };
+        strip_types(&source, mode == WrapMode::Module, prefix_len)?
+    } else {
+        source
+    };
+    // The wrapper shares line 1 with user code; a negative origin offset cancels it out so
+    // error positions and stacks match the submitted source. Transpiled code has no such
+    // correspondence, and hoisted imports already occupy line 1.
+    let column_offset = if config.typescript || hoisted.is_some() {
+        0
+    } else {
+        -(wrap_prefix(config.wrap_mode, config.strict).len() as i32)
+    };
+    Ok(ScriptSource {
+        source,
+        mode,
+        column_offset,
+    })
+}
+
+fn wrap_prefix(mode: WrapMode, strict: bool) -> String {
+    let directive = if strict { "\"use strict\"; " } else { "" };
+    match mode {
+        WrapMode::AsyncFunction => format!("(async function() {{ {directive}"),
+        WrapMode::Expression => directive.to_string(),
+        // Modules are always strict.
+        WrapMode::Module => String::new(),
+    }
+}
+
+fn wrap_code(code: &str, mode: WrapMode, strict: bool) -> String {
+    let prefix = wrap_prefix(mode, strict);
+    match mode {
+        WrapMode::AsyncFunction => format!("{prefix}{code} }})()"),
+        WrapMode::Expression | WrapMode::Module => format!("{prefix}{code}"),
+    }
+}
+
+fn compile_module<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    source: &str,
+    column_offset: i32,
+) -> Result<v8::Local<'a, v8::Module>, SandboxError> {
+    let code = v8::String::new(scope, source)
+        .ok_or_else(|| SandboxError::V8("module source".to_string()))?;
+    let origin = script_origin(scope, "codemode.mjs", column_offset, true)?;
+    let mut source = v8::script_compiler::Source::new(code, Some(&origin));
+    let tc = std::pin::pin!(v8::TryCatch::new(scope));
+    let tc = &mut tc.init();
+    v8::script_compiler::compile_module(tc, &mut source)
+        .ok_or_else(|| caught_error(tc, "module compile"))
+}
+
+fn evaluate_module<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    module: v8::Local<'a, v8::Module>,
+) -> Result<v8::Local<'a, v8::Value>, SandboxError> {
+    let tc = std::pin::pin!(v8::TryCatch::new(scope));
+    let tc = &mut tc.init();
+    if module.instantiate_module(tc, resolve_module) != Some(true) {
+        return Err(caught_error(tc, "module instantiate"));
+    }
+    module
+        .evaluate(tc)
+        .ok_or_else(|| caught_error(tc, "module evaluate"))
+}
+
+fn module_default_export<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    module: v8::Local<'a, v8::Module>,
+) -> Result<v8::Local<'a, v8::Value>, SandboxError> {
+    let namespace = module
+        .get_module_namespace()
+        .to_object(scope)
+        .ok_or_else(|| SandboxError::V8("module namespace".to_string()))?;
+    let key = v8::String::new(scope, "default")
+        .ok_or_else(|| SandboxError::V8("default export key".to_string()))?;
+    Ok(namespace
+        .get(scope, key.into())
+        .unwrap_or_else(|| v8::undefined(scope).into()))
+}
+
+/// Modules registered through [`SandboxConfig::register_module`], compiled on first import and
+/// stored in a context slot.
+struct ModuleRegistry {
+    sources: BTreeMap<String, String>,
+    compiled: RefCell<HashMap<String, v8::Global<v8::Module>>>,
+}
+
+fn resolve_module<'a>(
+    context: v8::Local<'a, v8::Context>,
+    specifier: v8::Local<'a, v8::String>,
+    _import_attributes: v8::Local<'a, v8::FixedArray>,
+    _referrer: v8::Local<'a, v8::Module>,
+) -> Option<v8::Local<'a, v8::Module>> {
+    // SAFETY: V8 calls the resolver with `context` entered.
+    let scope = std::pin::pin!(unsafe { v8::CallbackScope::new(context) });
+    let scope = &mut scope.init();
+    let name = specifier.to_rust_string_lossy(scope);
+    let registry = context.get_slot::<ModuleRegistry>();
+    if let Some(module) = registry
+        .as_ref()
+        .and_then(|registry| registry.compiled.borrow().get(&name).cloned())
+    {
+        return Some(v8::Local::new(scope, module));
+    }
+    let Some(source) = registry
+        .as_ref()
+        .and_then(|registry| registry.sources.get(&name))
+    else {
+        throw_error(scope, &format!("module '{name}' is not registered"));
+        return None;
+    };
+    let module = match compile_module(scope, source, 0) {
+        Ok(module) => module,
+        Err(err) => {
+            throw_error(scope, &format!("module '{name}': {err}"));
+            return None;
+        }
+    };
+    if let Some(registry) = &registry {
+        registry
+            .compiled
+            .borrow_mut()
+            .insert(name, v8::Global::new(scope, module));
+    }
+    Some(module)
+}
+
+fn tool_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let external = match v8::Local::<v8::External>::try_from(args.data()) {
+        Ok(external) => external,
+        Err(_) => return,
+    };
+    let state_ptr = external.value() as *const ToolCallbackState;
+    if state_ptr.is_null() {
+        return;
+    }
+    // SAFETY: The state pointer points to a Box<ToolCallbackState> stored in SandboxState.tool_states.
+    // It remains valid for the entire duration of sandbox execution.
+    let state = unsafe { &*state_ptr };
+    let parsed_args = match arg_to_json(scope, args.get(0)) {
+        Value::Null if state.takes_no_input => json!({}),
+        parsed => parsed,
+    };
+    // SAFETY: state.shared points to AsyncSharedState which is valid as long as SandboxState is alive.
+    let shared = unsafe { &*state.shared };
+    trace!(tool = state.dispatch.tool_name.as_str(), args = %shared.format_value(&parsed_args), "sandbox call_tool");
+
+    if let Err(message) = shared.count_tool_call() {
+        if state.is_async {
+            reject_call(scope, &message, rv);
+        } else {
+            throw_error(scope, &message);
+        }
+        return;
+    }
+
+    if shared.planning.get() {
+        plan_call(scope, state, parsed_args, rv);
+    } else if state.is_streaming {
+        open_stream(scope, state, parsed_args, rv);
+    } else if state.is_async {
+        let resolver = match v8::PromiseResolver::new(scope) {
+            Some(resolver) => resolver,
+            None => {
+                throw_error(scope, "failed to create promise resolver");
+                return;
+            }
+        };
+        let promise = resolver.get_promise(scope);
+        let promise = match &state.js_transformer {
+            Some(transformer) => {
+                let transformer = v8::Local::new(scope, transformer);
+                match promise.then(scope, transformer) {
+                    Some(promise) => promise,
+                    None => return,
+                }
+            }
+            None => promise,
+        };
+        let id = shared.next_id();
+        shared
+            .resolvers
+            .borrow_mut()
+            .insert(id, v8::Global::new(scope, resolver));
+        shared.pending.set(shared.pending.get() + 1);
+
+        let sender = shared.sender.clone();
+        let tool = state.dispatch.tool_name.clone();
+        let call = dispatch_call(shared, state.dispatch.clone(), parsed_args);
+        let cancel = shared.cancel.borrow().clone();
+        state.runtime.spawn(async move {
+            let Some(result) = cancel.run_until_cancelled(call).await else {
+                return;
+            };
+            let completion = Completion {
+                id,
+                tool: Some(tool),
+                result: result.map_err(|err| err.to_string()),
+            };
+            let _ = sender.send(completion);
+        });
+
+        rv.set(promise.into());
+    } else {
+        let cancel = shared.cancel.borrow().clone();
+        let call = dispatch_call(shared, state.dispatch.clone(), parsed_args);
+        // Spawned and awaited over a channel: `block_on` panics when the sandbox itself is driven
+        // from async code.
+        let (done, result) = mpsc::sync_channel(1);
+        state.runtime.spawn(async move {
+            let _ = done.send(cancel.run_until_cancelled(call).await);
+        });
+        let Ok(result) = result.recv() else {
+            throw_error(scope, "tool runtime shut down");
+            return;
+        };
+        let Some(result) = result else {
+            throw_error(scope, "execution cancelled");
+            return;
+        };
+        match result {
+            Ok(value) if !shared.accept_result_size(scope, &value) => {}
+            Ok(value) => {
+                if let Some(value) = json_to_v8(scope, &value) {
+                    if let Some(value) = apply_js_transformer(scope, state, value) {
+                        rv.set(value);
+                    }
+                } else {
+                    throw_error(scope, "failed to serialize tool result");
+                }
+            }
+            Err(err) => {
+                let tool = state.dispatch.tool_name.as_str();
+                match tool_error(scope, shared, tool, &err.to_string()) {
+                    Some(exception) => {
+                        scope.throw_exception(exception);
+                    }
+                    None => throw_error(scope, &err.to_string()),
+                }
+            }
+        }
+    }
+}
+
+// Wraps the call in a span under the execution's OpenTelemetry context when `otel` is enabled.
+fn dispatch_call(
+    shared: &AsyncSharedState,
+    dispatch: ToolDispatch,
+    args: Value,
+) -> impl Future<Output = Result<Value, ToolCallError>> + Send + 'static {
+    #[cfg(feature = "otel")]
+    {
+        crate::otel::traced_call(shared.otel_context.borrow().clone(), dispatch, args)
+    }
+    #[cfg(not(feature = "otel"))]
+    {
+        let _ = shared;
+        async move { dispatch.call(args).await }
+    }
+}
+
+fn plan_call(
+    scope: &mut v8::PinScope,
+    state: &ToolCallbackState,
+    args: Value,
+    mut rv: v8::ReturnValue,
+) {
+    // SAFETY: state.shared points to AsyncSharedState which is valid as long as SandboxState is alive.
+    let shared = unsafe { &*state.shared };
+    shared.plan.borrow_mut().push(PlannedCall {
+        tool: state.dispatch.tool_name.clone(),
+        args: args.clone(),
+    });
+    if state.is_streaming {
+        open_stream(scope, state, args, rv);
+        return;
+    }
+    let Some(value) = json_to_v8(scope, &state.placeholder) else {
+        throw_error(scope, "failed to serialize placeholder");
+        return;
+    };
+    if !state.is_async {
+        rv.set(value);
+        return;
+    }
+    let Some(resolver) = v8::PromiseResolver::new(scope) else {
+        throw_error(scope, "failed to create promise resolver");
+        return;
+    };
+    resolver.resolve(scope, value);
+    rv.set(resolver.get_promise(scope).into());
+}
+
+// Chunks buffered ahead of the script before the producing tool is paused.
+const STREAM_BUFFER: usize = 16;
+
+struct StreamEntry {
+    receiver: Arc<tokio::sync::Mutex<tokio::sync::mpsc::Receiver<Result<Value, String>>>>,
+    runtime: tokio::runtime::Handle,
+}
+
+// Builds `{ [Symbol.asyncIterator], next, return }` objects around a stream id; `next` and
+// `return` call back into Rust.
+const STREAM_FACTORY: &str = r#"(function(next, close) {
+  return function(id) {
+    return {
+      [Symbol.asyncIterator]() { return this; },
+      next() { return next(id); },
+      return() {
+        close(id);
+        return Promise.resolve({ value: undefined, done: true });
+      },
+    };
+  };
+})"#;
+
+fn inject_stream_factory(
+    scope: &mut v8::PinScope<'_, '_>,
+    state: &SandboxState,
+) -> Result<(), SandboxError> {
+    let external = v8::External::new(scope, state.shared_ptr() as *mut c_void);
+    let next_fn = v8::Function::builder(stream_next_callback)
+        .data(external.into())
+        .build(scope)
+        .ok_or_else(|| SandboxError::V8("stream next function".to_string()))?;
+    let close_fn = v8::Function::builder(stream_close_callback)
+        .data(external.into())
+        .build(scope)
+        .ok_or_else(|| SandboxError::V8("stream close function".to_string()))?;
+    let builder = v8::Local::<v8::Function>::try_from(run_script(scope, STREAM_FACTORY)?)
+        .map_err(|_| SandboxError::V8("stream factory".to_string()))?;
+    let receiver = v8::undefined(scope).into();
+    let factory = builder
+        .call(scope, receiver, &[next_fn.into(), close_fn.into()])
+        .and_then(|factory| v8::Local::<v8::Function>::try_from(factory).ok())
+        .ok_or_else(|| SandboxError::V8("stream factory".to_string()))?;
+    *state.shared.stream_factory.borrow_mut() = Some(v8::Global::new(scope, factory));
+    Ok(())
+}
+
+// Built-in globals left in place by `SandboxConfig::harden`; injected globals are always kept.
+const SAFE_GLOBALS: &[&str] = &[
+    "globalThis",
+    "Infinity",
+    "NaN",
+    "undefined",
+    "isFinite",
+    "isNaN",
+    "parseFloat",
+    "parseInt",
+    "decodeURI",
+    "decodeURIComponent",
+    "encodeURI",
+    "encodeURIComponent",
+    "Object",
+    "Boolean",
+    "Symbol",
+    "Error",
+    "AggregateError",
+    "EvalError",
+    "RangeError",
+    "ReferenceError",
+    "SyntaxError",
+    "TypeError",
+    "URIError",
+    "Number",
+    "BigInt",
+    "Math",
+    "Date",
+    "String",
+    "RegExp",
+    "Array",
+    "Int8Array",
+    "Uint8Array",
+    "Uint8ClampedArray",
+    "Int16Array",
+    "Uint16Array",
+    "Int32Array",
+    "Uint32Array",
+    "Float32Array",
+    "Float64Array",
+    "BigInt64Array",
+    "BigUint64Array",
+    "Map",
+    "Set",
+    "WeakMap",
+    "WeakSet",
+    "ArrayBuffer",
+    "DataView",
+    "JSON",
+    "Promise",
+    "Reflect",
+    "Proxy",
+    "Intl",
+    "Iterator",
+];
+
+// Replaces every function constructor (reachable through `fn.constructor`) with a thrower,
+// deletes the banned globals, then freezes the intrinsics scripts could otherwise poison.
+const HARDEN_SCRIPT: &str = r#"(function(banned) {
+  const disabled = function() {
+    throw new EvalError("code generation from strings is disabled");
+  };
+  const functionPrototypes = [
+    function() {},
+    async function() {},
+    function* () {},
+    async function* () {},
+  ].map((fn) => Object.getPrototypeOf(fn));
+  for (const prototype of functionPrototypes) {
+    Object.defineProperty(prototype, "constructor", { value: disabled });
+  }
+  const arrayIterator = Object.getPrototypeOf([][Symbol.iterator]());
+  const intrinsics = [
+    ...functionPrototypes,
+    arrayIterator,
+    Object.getPrototypeOf(arrayIterator),
+    Object, Object.prototype, Array, Array.prototype, String.prototype, Number.prototype,
+    Boolean.prototype, Symbol.prototype, BigInt.prototype, Date.prototype, RegExp.prototype,
+    Map.prototype, Set.prototype, WeakMap.prototype, WeakSet.prototype, Promise,
+    Promise.prototype, Error.prototype, ArrayBuffer.prototype, DataView.prototype,
+    Object.getPrototypeOf(Uint8Array.prototype), JSON, Math, Reflect,
+  ];
+  for (const name of banned) {
+    delete globalThis[name];
+  }
+  for (const intrinsic of intrinsics) {
+    Object.freeze(intrinsic);
+  }
+})"#;
+
+fn global_names(
+    scope: &mut v8::PinScope<'_, '_>,
+    global: v8::Local<v8::Object>,
+) -> Result<Vec<String>, SandboxError> {
+    let names = global
+        .get_own_property_names(scope, Default::default())
+        .ok_or_else(|| SandboxError::V8("global names".to_string()))?;
+    Ok((0..names.length())
+        .filter_map(|index| names.get_index(scope, index))
+        .map(|name| name.to_rust_string_lossy(scope))
+        .collect())
+}
+
+// `builtins` are the globals present before injection; tool namespaces restored from a startup
+// snapshot show up there too and are kept.
+fn harden_realm(
+    scope: &mut v8::PinScope<'_, '_>,
+    builtins: &[String],
+    tools: &[&Tool],
+    interface_generator: &ToolInterfaceGenerator,
+) -> Result<(), SandboxError> {
+    let namespaces = tools
+        .iter()
+        .filter_map(|tool| {
+            let access_path = interface_generator.tool_access_path(tool);
+            access_path.split('.').next().map(str::to_string)
+        })
+        .collect::<BTreeSet<String>>();
+    let mut banned = Vec::new();
+    for name in builtins {
+        if SAFE_GLOBALS.contains(&name.as_str()) || namespaces.contains(name) {
+            continue;
+        }
+        let name = v8::String::new(scope, name)
+            .ok_or_else(|| SandboxError::V8("banned global name".to_string()))?;
+        banned.push(name.into());
+    }
+    let banned = v8::Array::new_with_elements(scope, &banned);
+    let harden = v8::Local::<v8::Function>::try_from(run_script(scope, HARDEN_SCRIPT)?)
+        .map_err(|_| SandboxError::V8("harden script".to_string()))?;
+    let receiver = v8::undefined(scope).into();
+    let tc = std::pin::pin!(v8::TryCatch::new(scope));
+    let tc = &mut tc.init();
+    harden
+        .call(tc, receiver, &[banned.into()])
+        .ok_or_else(|| caught_error(tc, "harden"))?;
+    Ok(())
+}
+
+fn open_stream(
+    scope: &mut v8::PinScope,
+    state: &ToolCallbackState,
+    args: Value,
+    mut rv: v8::ReturnValue,
+) {
+    // SAFETY: state.shared points to AsyncSharedState which is valid as long as SandboxState is alive.
+    let shared = unsafe { &*state.shared };
+    let Some(factory) = shared
+        .stream_factory
+        .borrow()
+        .as_ref()
+        .map(|factory| v8::Local::new(scope, factory))
+    else {
+        throw_error(scope, "stream support not initialized");
+        return;
+    };
+
+    let id = shared.next_id();
+    let (tx, rx) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+    shared.streams.borrow_mut().insert(
+        id,
+        StreamEntry {
+            receiver: Arc::new(tokio::sync::Mutex::new(rx)),
+            runtime: state.runtime.clone(),
+        },
+    );
+    if shared.planning.get() {
+        // Dropping the sender ends the iterator without calling the tool.
+        drop(tx);
+    } else {
+        let dispatch = state.dispatch.clone();
+        let cancel = shared.cancel.borrow().clone();
+        state
+            .runtime
+            .spawn(cancel.run_until_cancelled_owned(async move {
+                match dispatch.open_stream(args).await {
+                    Ok(mut stream) => {
+                        while let Some(chunk) = stream.next().await {
+                            if tx.send(chunk.map_err(|err| err.to_string())).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        let _ = tx.send(Err(err.to_string())).await;
+                    }
+                }
+            }));
+    }
+
+    let receiver = v8::undefined(scope).into();
+    let stream_id = v8::Number::new(scope, id as f64).into();
+    match factory.call(scope, receiver, &[stream_id]) {
+        Some(iterator) => rv.set(iterator),
+        None => throw_error(scope, "failed to create stream iterator"),
+    }
+}
+
+fn stream_next_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<AsyncSharedState> held by SandboxState.
+    let shared = unsafe { &*(external.value() as *const AsyncSharedState) };
+    let stream_id = args.get(0).number_value(scope).unwrap_or(0.0) as u64;
+    let Some(resolver) = v8::PromiseResolver::new(scope) else {
+        throw_error(scope, "failed to create promise resolver");
+        return;
+    };
+    let promise = resolver.get_promise(scope);
+
+    let Some((receiver, runtime)) = shared
+        .streams
+        .borrow()
+        .get(&stream_id)
+        .map(|entry| (entry.receiver.clone(), entry.runtime.clone()))
+    else {
+        if let Some(done) = json_to_v8(scope, &json!({ "done": true })) {
+            resolver.resolve(scope, done);
+        }
+        rv.set(promise.into());
+        return;
+    };
+
+    let id = shared.next_id();
+    shared
+        .resolvers
+        .borrow_mut()
+        .insert(id, v8::Global::new(scope, resolver));
+    shared.pending.set(shared.pending.get() + 1);
+
+    let sender = shared.sender.clone();
+    runtime.spawn(async move {
+        let chunk = receiver.lock().await.recv().await;
+        let result = match chunk {
+            Some(Ok(value)) => Ok(json!({ "value": value, "done": false })),
+            Some(Err(err)) => Err(err),
+            None => Ok(json!({ "done": true })),
+        };
+        let _ = sender.send(Completion {
+            id,
+            tool: None,
+            result,
+        });
+    });
+
+    rv.set(promise.into());
+}
+
+fn stream_close_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<AsyncSharedState> held by SandboxState.
+    let shared = unsafe { &*(external.value() as *const AsyncSharedState) };
+    let stream_id = args.get(0).number_value(scope).unwrap_or(0.0) as u64;
+    // Dropping the receiver stops the producing task at its next send.
+    shared.streams.borrow_mut().remove(&stream_id);
+}
+
+fn reject_call(scope: &mut v8::PinScope, message: &str, mut rv: v8::ReturnValue) {
+    let (Some(resolver), Some(message)) = (
+        v8::PromiseResolver::new(scope),
+        v8::String::new(scope, message),
+    ) else {
+        throw_error(scope, message);
+        return;
+    };
+    let exception = v8::Exception::error(scope, message);
+    resolver.reject(scope, exception);
+    rv.set(resolver.get_promise(scope).into());
+}
+
+fn arg_to_json(scope: &mut v8::PinScope<'_, '_>, value: v8::Local<v8::Value>) -> Value {
+    // A missing argument is passed on as an empty object.
+    if value.is_undefined() {
+        return json!({});
+    }
+    v8_to_json(scope, value).unwrap_or(Value::Null)
+}
+
+fn inject_blob_helpers<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+    state: &SandboxState,
+) -> Result<(), SandboxError> {
+    let Some(offload) = state.dispatch.blob_offload.as_deref() else {
+        return Ok(());
+    };
+    let blobs = ensure_namespace(scope, global, "blobs")?;
+    let external = v8::External::new(scope, offload as *const BlobOffload as *mut c_void);
+    let read_fn = v8::Function::builder(blob_read_callback)
+        .data(external.into())
+        .build(scope)
+        .ok_or_else(|| SandboxError::V8("blobs.read function".to_string()))?;
+    let key = v8::String::new(scope, "read")
+        .ok_or_else(|| SandboxError::V8("blobs.read key".to_string()))?;
+    blobs.set(scope, key.into(), read_fn.into());
+    Ok(())
+}
+
+fn inject_emit<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+    state: &SandboxState,
+) -> Result<(), SandboxError> {
+    let codemode = ensure_namespace(scope, global, "codemode")?;
+    let external = v8::External::new(scope, state.shared_ptr() as *mut c_void);
+    let emit_fn = v8::Function::builder(emit_callback)
+        .data(external.into())
+        .build(scope)
+        .ok_or_else(|| SandboxError::V8("codemode.emit function".to_string()))?;
+    let key = v8::String::new(scope, "emit")
+        .ok_or_else(|| SandboxError::V8("codemode.emit key".to_string()))?;
+    codemode.set(scope, key.into(), emit_fn.into());
+    let handler_fn = v8::Function::builder(on_tool_error_callback)
+        .data(external.into())
+        .build(scope)
+        .ok_or_else(|| SandboxError::V8("codemode.onToolError function".to_string()))?;
+    let key = v8::String::new(scope, "onToolError")
+        .ok_or_else(|| SandboxError::V8("codemode.onToolError key".to_string()))?;
+    codemode.set(scope, key.into(), handler_fn.into());
+    let debug_fn = v8::Function::builder(debug_state_callback)
+        .data(external.into())
+        .build(scope)
+        .ok_or_else(|| SandboxError::V8("codemode.debugState function".to_string()))?;
+    let key = v8::String::new(scope, "debugState")
+        .ok_or_else(|| SandboxError::V8("codemode.debugState key".to_string()))?;
+    codemode.set(scope, key.into(), debug_fn.into());
+    Ok(())
+}
+
+// Each call parses a fresh copy, so scripts cannot tamper with the catalog.
+const DISCOVERY: &str = r#"(function(catalog) {
+  const parse = JSON.parse;
+  const entries = parse(catalog);
+  const find = (name) => entries.findIndex((tool) => tool.name === name || tool.path === name);
+  return {
+    listTools() {
+      return parse(catalog).map(({ name, path, description, tags }) => ({ name, path, description, tags }));
+    },
+    describeTool(name) {
+      const index = find(String(name));
+      return index < 0 ? undefined : parse(catalog)[index];
+    },
+  };
+})"#;
+
+/// Installs `codemode.listTools()` and `codemode.describeTool(name)`, which report each tool's
+/// metadata, schemas and generated TypeScript interface so scripts can pick tools from data.
+fn inject_discovery<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+    tools: &[&Tool],
+    tool_interfaces: &[String],
+    interface_generator: &ToolInterfaceGenerator,
+) -> Result<(), SandboxError> {
+    let catalog = tools
+        .iter()
+        .zip(tool_interfaces)
+        .map(|(tool, interface)| {
+            json!({
+                "name": tool.name,
+                "path": interface_generator.tool_access_path(tool),
+                "description": tool.description,
+                "tags": tool.tags,
+                "isAsync": tool.is_async,
+                "isStreaming": tool.is_streaming,
+                "readOnly": tool.read_only,
+                "deprecated": tool.deprecated,
+                "stability": tool.stability,
+                "inputs": tool.inputs,
+                "outputs": tool.outputs,
+                "interface": interface,
+            })
+        })
+        .collect::<Vec<Value>>();
+    let catalog = serde_json::to_string(&catalog)
+        .map_err(|err| SandboxError::Serialization(err.to_string()))?;
+    let catalog = v8::String::new(scope, &catalog)
+        .ok_or_else(|| SandboxError::V8("tool catalog".to_string()))?;
+    let factory = v8::Local::<v8::Function>::try_from(run_script(scope, DISCOVERY)?)
+        .map_err(|_| SandboxError::V8("tool discovery".to_string()))?;
+    let receiver = v8::undefined(scope).into();
+    let discovery = factory
+        .call(scope, receiver, &[catalog.into()])
+        .and_then(|discovery| discovery.to_object(scope))
+        .ok_or_else(|| SandboxError::V8("tool discovery".to_string()))?;
+    let codemode = ensure_namespace(scope, global, "codemode")?;
+    for name in ["listTools", "describeTool"] {
+        let key = v8::String::new(scope, name)
+            .ok_or_else(|| SandboxError::V8(format!("codemode.{name} key")))?;
+        let function = discovery
+            .get(scope, key.into())
+            .ok_or_else(|| SandboxError::V8(format!("codemode.{name} function")))?;
+        codemode.set(scope, key.into(), function);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "fetch")]
+struct FetchState {
+    shared: *const AsyncSharedState,
+    runtime: tokio::runtime::Handle,
+    client: reqwest::Client,
+    config: Arc<FetchConfig>,
+}
+
+// Normalizes `fetch(url, init)` for the native request and wraps the raw answer in a minimal
+// `Response`.
+#[cfg(feature = "fetch")]
+const FETCH_WRAPPER: &str = r#"(function(request, decode) {
+  class Response {
+    constructor(raw) {
+      this.status = raw.status;
+      this.statusText = raw.statusText;
+      this.ok = raw.status >= 200 && raw.status < 300;
+      this.url = raw.url;
+      this.headers = new Map(Object.entries(raw.headers));
+      this.body = raw.body;
+    }
+    async arrayBuffer() {
+      return this.body.buffer.slice(this.body.byteOffset, this.body.byteOffset + this.body.byteLength);
+    }
+    async bytes() { return this.body; }
+    async text() { return decode(this.body); }
+    async json() { return JSON.parse(decode(this.body)); }
+  }
+  return function fetch(input, init = {}) {
+    const headers = init.headers instanceof Map ? Object.fromEntries(init.headers) : (init.headers ?? {});
+    return request(String(input), String(init.method ?? "GET"), headers, init.body)
+      .then((raw) => new Response(raw));
+  };
+})"#;
+
+#[cfg(feature = "fetch")]
+fn inject_fetch<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+    state: &mut SandboxState,
+    runtime: tokio::runtime::Handle,
+    config: &FetchConfig,
+) -> Result<(), SandboxError> {
+    let fetch_state = Box::new(FetchState {
+        shared: state.shared_ptr(),
+        runtime,
+        client: crate::fetch::client().map_err(SandboxError::V8)?,
+        config: Arc::new(config.clone()),
+    });
+    let external = v8::External::new(scope, &*fetch_state as *const FetchState as *mut c_void);
+    state.fetch = Some(fetch_state);
+    let request = v8::Function::builder(fetch_callback)
+        .data(external.into())
+        .build(scope)
+        .ok_or_else(|| SandboxError::V8("fetch function".to_string()))?;
+    let decode = v8::Function::builder(crate::globals::utf8_decode_callback)
+        .build(scope)
+        .ok_or_else(|| SandboxError::V8("fetch decode function".to_string()))?;
+    let wrapper = v8::Local::<v8::Function>::try_from(run_script(scope, FETCH_WRAPPER)?)
+        .map_err(|_| SandboxError::V8("fetch wrapper".to_string()))?;
+    let receiver = v8::undefined(scope).into();
+    let fetch = wrapper
+        .call(scope, receiver, &[request.into(), decode.into()])
+        .ok_or_else(|| SandboxError::V8("fetch wrapper".to_string()))?;
+    let key =
+        v8::String::new(scope, "fetch").ok_or_else(|| SandboxError::V8("fetch key".to_string()))?;
+    global.set(scope, key.into(), fetch);
+    Ok(())
+}
+
+#[cfg(feature = "fetch")]
+fn fetch_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<FetchState> held by SandboxState.
+    let state = unsafe { &*(external.value() as *const FetchState) };
+    // SAFETY: state.shared points to AsyncSharedState which is valid as long as SandboxState is alive.
+    let shared = unsafe { &*state.shared };
+
+    let url = args.get(0).to_rust_string_lossy(scope);
+    let method = args.get(1).to_rust_string_lossy(scope);
+    let headers = match v8_to_json(scope, args.get(2)) {
+        Ok(Value::Object(headers)) => headers
+            .into_iter()
+            .map(|(name, value)| match value {
+                Value::String(value) => (name, value),
+                other => (name, other.to_string()),
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let body = args.get(3);
+    let body = if body.is_null_or_undefined() {
+        None
+    } else if body.is_string() {
+        Some(body.to_rust_string_lossy(scope).into_bytes())
+    } else {
+        match crate::convert::binary_bytes(body) {
+            Some(bytes) => Some(bytes),
+            None => {
+                reject_call(scope, "fetch body must be a string or binary data", rv);
+                return;
+            }
+        }
+    };
+    trace!(
+        url = url.as_str(),
+        method = method.as_str(),
+        "sandbox fetch"
+    );
+
+    let Some(resolver) = v8::PromiseResolver::new(scope) else {
+        throw_error(scope, "failed to create promise resolver");
+        return;
+    };
+    let id = shared.next_id();
+    shared
+        .resolvers
+        .borrow_mut()
+        .insert(id, v8::Global::new(scope, resolver));
+    shared.pending.set(shared.pending.get() + 1);
+
+    let request = crate::fetch::FetchRequest {
+        url,
+        method,
+        headers,
+        body,
+    };
+    let sender = shared.sender.clone();
+    let cancel = shared.cancel.borrow().clone();
+    let client = state.client.clone();
+    let config = state.config.clone();
+    state.runtime.spawn(async move {
+        let call = crate::fetch::perform(&client, &config, request);
+        let Some(result) = cancel.run_until_cancelled(call).await else {
+            return;
+        };
+        let _ = sender.send(Completion {
+            id,
+            tool: None,
+            result,
+        });
+    });
+    rv.set(resolver.get_promise(scope).into());
+}
+
+// Console levels, passed to `console_callback` as a const parameter.
+const CONSOLE_DEBUG: u8 = 0;
+const CONSOLE_INFO: u8 = 1;
+const CONSOLE_WARN: u8 = 2;
+const CONSOLE_ERROR: u8 = 3;
+
+fn inject_console<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+    state: &SandboxState,
+) -> Result<(), SandboxError> {
+    use v8::MapFnTo;
+
+    let console = ensure_namespace(scope, global, "console")?;
+    let external = v8::External::new(scope, state.shared_ptr() as *mut c_void);
+    let methods: [(&str, v8::FunctionCallback); 5] = [
+        ("debug", console_callback::<CONSOLE_DEBUG>.map_fn_to()),
+        ("log", console_callback::<CONSOLE_INFO>.map_fn_to()),
+        ("info", console_callback::<CONSOLE_INFO>.map_fn_to()),
+        ("warn", console_callback::<CONSOLE_WARN>.map_fn_to()),
+        ("error", console_callback::<CONSOLE_ERROR>.map_fn_to()),
+    ];
+    for (name, callback) in methods {
+        let function = v8::Function::builder_raw(callback)
+            .data(external.into())
+            .build(scope)
+            .ok_or_else(|| SandboxError::V8(format!("console.{name} function")))?;
+        let key = v8::String::new(scope, name)
+            .ok_or_else(|| SandboxError::V8(format!("console.{name} key")))?;
+        console.set(scope, key.into(), function.into());
+    }
+    Ok(())
+}
+
+fn console_callback<const LEVEL: u8>(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<AsyncSharedState> held by SandboxState.
+    let shared = unsafe { &*(external.value() as *const AsyncSharedState) };
+    let message = (0..args.length())
+        .map(|index| {
+            let arg = args.get(index);
+            if arg.is_string() {
+                return arg.to_rust_string_lossy(scope);
+            }
+            match v8_to_json(scope, arg) {
+                Ok(value) => value.to_string(),
+                Err(_) => arg.to_rust_string_lossy(scope),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ");
+    let message = match shared.redactor.borrow().as_deref() {
+        Some(redactor) => match redactor.redact(&Value::String(message)) {
+            Value::String(message) => message,
+            other => other.to_string(),
+        },
+        None => message,
+    };
+    let level = match LEVEL {
+        CONSOLE_DEBUG => "debug",
+        CONSOLE_WARN => "warn",
+        CONSOLE_ERROR => "error",
+        _ => "info",
+    };
+    trace!(level, message = message.as_str(), "sandbox console");
+    shared
+        .logs
+        .borrow_mut()
+        .push(format!("[{level}] {message}"));
+}
+
+/// Defines the read-only, deeply frozen `input` global, or removes it when there is no input so a
+/// reused context does not leak the previous execution's value.
+fn install_input<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+    input: Option<&Value>,
+) -> Result<(), SandboxError> {
+    let key =
+        v8::String::new(scope, "input").ok_or_else(|| SandboxError::V8("input key".to_string()))?;
+    global.delete(scope, key.into());
+    match input {
+        Some(input) => define_frozen(scope, global, "input", input),
+        None => Ok(()),
+    }
+}
+
+fn define_frozen<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+    name: &str,
+    value: &Value,
+) -> Result<(), SandboxError> {
+    let key =
+        v8::String::new(scope, name).ok_or_else(|| SandboxError::V8(format!("{name} key")))?;
+    let value = json_to_v8(scope, value)
+        .ok_or_else(|| SandboxError::Serialization(format!("{name} value")))?;
+    deep_freeze(scope, value);
+    global.define_own_property(scope, key.into(), value, v8::PropertyAttribute::READ_ONLY);
+    Ok(())
+}
+
+fn deep_freeze(scope: &mut v8::PinScope<'_, '_>, value: v8::Local<v8::Value>) {
+    // Typed arrays with elements cannot be frozen.
+    if !value.is_object() || value.is_array_buffer_view() {
+        return;
+    }
+    let Some(object) = value.to_object(scope) else {
+        return;
+    };
+    if let Some(keys) = object.get_own_property_names(scope, Default::default()) {
+        for index in 0..keys.length() {
+            if let Some(child) = keys
+                .get_index(scope, index)
+                .and_then(|key| object.get(scope, key))
+            {
+                deep_freeze(scope, child);
+            }
+        }
+    }
+    object.set_integrity_level(scope, v8::IntegrityLevel::Frozen);
+}
+
+fn inject_timers<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+    state: &SandboxState,
+) -> Result<(), SandboxError> {
+    use v8::MapFnTo;
+
+    let external = v8::External::new(scope, state.shared_ptr() as *mut c_void);
+    let functions: [(&str, v8::FunctionCallback); 4] = [
+        ("setTimeout", set_timeout_callback.map_fn_to()),
+        ("setInterval", set_interval_callback.map_fn_to()),
+        ("clearTimeout", clear_timer_callback.map_fn_to()),
+        ("clearInterval", clear_timer_callback.map_fn_to()),
+    ];
+    for (name, callback) in functions {
+        let function = v8::Function::builder_raw(callback)
+            .data(external.into())
+            .build(scope)
+            .ok_or_else(|| SandboxError::V8(format!("{name} function")))?;
+        let key =
+            v8::String::new(scope, name).ok_or_else(|| SandboxError::V8(format!("{name} key")))?;
+        global.set(scope, key.into(), function.into());
+    }
+    Ok(())
+}
+
+fn set_timeout_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    rv: v8::ReturnValue,
+) {
+    schedule_timer(scope, args, rv, false);
+}
+
+fn set_interval_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    rv: v8::ReturnValue,
+) {
+    schedule_timer(scope, args, rv, true);
+}
+
+fn schedule_timer(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+    repeat: bool,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<AsyncSharedState> held by SandboxState.
+    let shared = unsafe { &*(external.value() as *const AsyncSharedState) };
+    let Ok(callback) = v8::Local::<v8::Function>::try_from(args.get(0)) else {
+        throw_error(scope, "timer callback must be a function");
+        return;
+    };
+    let created = shared.timers_created.get() + 1;
+    let max = shared.max_timers.get();
+    if created > max {
+        throw_error(scope, &format!("timer limit of {max} exceeded"));
+        return;
+    }
+    shared.timers_created.set(created);
+
+    let delay_ms = args
+        .get(1)
+        .number_value(scope)
+        .filter(|delay| delay.is_finite())
+        .unwrap_or(0.0)
+        .max(0.0);
+    let mut delay = Duration::from_secs_f64(delay_ms / 1000.0);
+    if repeat {
+        // A zero interval would otherwise spin the completion loop.
+        delay = delay.max(Duration::from_millis(1));
+    }
+    let extra = (2..args.length())
+        .map(|index| v8::Global::new(scope, args.get(index)))
+        .collect();
+    let id = shared.next_id();
+    shared.timers.borrow_mut().insert(
+        id,
+        TimerEntry {
+            due: Instant::now() + delay,
+            callback: v8::Global::new(scope, callback),
+            args: extra,
+            interval: repeat.then_some(delay),
+        },
+    );
+    rv.set(v8::Number::new(scope, id as f64).into());
+}
+
+fn clear_timer_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<AsyncSharedState> held by SandboxState.
+    let shared = unsafe { &*(external.value() as *const AsyncSharedState) };
+    if let Some(id) = args.get(0).number_value(scope) {
+        shared.timers.borrow_mut().remove(&(id as u64));
+    }
+}
+
+fn emit_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<AsyncSharedState> held by SandboxState.
+    let shared = unsafe { &*(external.value() as *const AsyncSharedState) };
+    let value = arg_to_json(scope, args.get(0));
+    trace!(value = %shared.format_value(&value), "sandbox emit");
+    if let Some(emitter) = shared.emitter.borrow().as_ref() {
+        let _ = emitter.send(ChainEvent::Emitted(value.clone()));
+    }
+    shared.emitted.borrow_mut().push(value);
+}
+
+fn inject_memory<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+    state: &SandboxState,
+) -> Result<(), SandboxError> {
+    use v8::MapFnTo;
+
+    let codemode = ensure_namespace(scope, global, "codemode")?;
+    let memory = ensure_namespace(scope, codemode, "memory")?;
+    let external = v8::External::new(scope, state.shared_ptr() as *mut c_void);
+    let methods: [(&str, v8::FunctionCallback); 3] = [
+        ("get", memory_get_callback.map_fn_to()),
+        ("set", memory_set_callback.map_fn_to()),
+        ("delete", memory_delete_callback.map_fn_to()),
+    ];
+    for (name, callback) in methods {
+        let function = v8::Function::builder_raw(callback)
+            .data(external.into())
+            .build(scope)
+            .ok_or_else(|| SandboxError::V8(format!("codemode.memory.{name} function")))?;
+        let key = v8::String::new(scope, name)
+            .ok_or_else(|| SandboxError::V8(format!("codemode.memory.{name} key")))?;
+        memory.set(scope, key.into(), function.into());
+    }
+    Ok(())
+}
+
+// Missing keys read as `undefined`.
+fn memory_get_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<AsyncSharedState> held by SandboxState.
+    let shared = unsafe { &*(external.value() as *const AsyncSharedState) };
+    let key = args.get(0).to_rust_string_lossy(scope);
+    match shared.memory_store().get(&key) {
+        Ok(Some(value)) => match json_to_v8(scope, &value) {
+            Some(value) => rv.set(value),
+            None => throw_error(scope, "failed to convert memory value"),
+        },
+        Ok(None) => {}
+        Err(err) => throw_error(scope, &err.to_string()),
+    }
+}
+
+fn memory_set_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<AsyncSharedState> held by SandboxState.
+    let shared = unsafe { &*(external.value() as *const AsyncSharedState) };
+    let key = args.get(0).to_rust_string_lossy(scope);
+    let value = match v8_to_json(scope, args.get(1)) {
+        Ok(value) => value,
+        Err(err) => {
+            throw_error(scope, &format!("codemode.memory.set: {err}"));
+            return;
+        }
+    };
+    trace!(key = key.as_str(), value = %shared.format_value(&value), "sandbox memory set");
+    if let Err(err) = shared.memory_store().set(&key, value) {
+        throw_error(scope, &err.to_string());
+    }
+}
+
+fn memory_delete_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<AsyncSharedState> held by SandboxState.
+    let shared = unsafe { &*(external.value() as *const AsyncSharedState) };
+    let key = args.get(0).to_rust_string_lossy(scope);
+    match shared.memory_store().delete(&key) {
+        Ok(deleted) => rv.set(v8::Boolean::new(scope, deleted).into()),
+        Err(err) => throw_error(scope, &err.to_string()),
+    }
+}
+
+fn inject_global_fns<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    global: v8::Local<'a, v8::Object>,
+    global_fns: &BTreeMap<String, Arc<GlobalFn>>,
+    state: &mut SandboxState,
+) -> Result<(), SandboxError> {
+    for (name, function) in global_fns {
+        let mut parts = name.split('.').collect::<Vec<&str>>();
+        let Some(leaf) = parts.pop() else {
+            continue;
+        };
+        let mut target = global;
+        for part in parts {
+            target = ensure_namespace(scope, target, part)?;
+        }
+        // Boxed so the pointer handed to V8 stays put while `global_fns` grows.
+        let function = Box::new(function.clone());
+        let external = v8::External::new(scope, &*function as *const Arc<GlobalFn> as *mut c_void);
+        state.global_fns.push(function);
+        let callback = v8::Function::builder(global_fn_callback)
+            .data(external.into())
+            .build(scope)
+            .ok_or_else(|| SandboxError::V8(format!("{name} function")))?;
+        let key =
+            v8::String::new(scope, leaf).ok_or_else(|| SandboxError::V8(format!("{name} key")))?;
+        target.set(scope, key.into(), callback.into());
+    }
+    Ok(())
+}
+
+fn global_fn_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets a Box<Arc<GlobalFn>> held by SandboxState.global_fns.
+    let function = unsafe { &*(external.value() as *const Arc<GlobalFn>) };
+    let values = (0..args.length())
+        .map(|index| v8_to_json(scope, args.get(index)).unwrap_or(Value::Null))
+        .collect::<Vec<Value>>();
+    match function(values) {
+        Ok(value) => match json_to_v8(scope, &value) {
+            Some(value) => rv.set(value),
+            None => throw_error(scope, "failed to convert host function result"),
+        },
+        Err(message) => throw_error(scope, &message),
+    }
+}
+
+fn debug_state_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<AsyncSharedState> held by SandboxState.
+    let shared = unsafe { &*(external.value() as *const AsyncSharedState) };
+    match v8_to_json(scope, args.get(0)) {
+        Ok(Value::Object(values)) => {
+            let state = shared.format_value(&Value::Object(values.clone()));
+            trace!(state = %state, "sandbox debug state");
+            shared.debug_state.borrow_mut().extend(values);
+        }
+        Ok(_) => throw_error(
+            scope,
+            "codemode.debugState expects an object of named values",
+        ),
+        Err(err) => throw_error(scope, &format!("codemode.debugState: {err}")),
+    }
+}
+
+// Passing anything but a function removes the handler.
+fn on_tool_error_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<AsyncSharedState> held by SandboxState.
+    let shared = unsafe { &*(external.value() as *const AsyncSharedState) };
+    let handler = v8::Local::<v8::Function>::try_from(args.get(0))
+        .ok()
+        .map(|handler| v8::Global::new(scope, handler));
+    *shared.error_handler.borrow_mut() = handler;
+}
+
+fn blob_read_callback(
+    scope: &mut v8::PinScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let Ok(external) = v8::Local::<v8::External>::try_from(args.data()) else {
+        return;
+    };
+    // SAFETY: The pointer targets the Box<BlobOffload> held by SandboxState for the whole execution.
+    let offload = unsafe { &*(external.value() as *const BlobOffload) };
+    let id = args.get(0).to_rust_string_lossy(scope);
+    let range = arg_to_json(scope, args.get(1));
+    let offset = range.get("offset").and_then(Value::as_u64).unwrap_or(0) as usize;
+    let length = range
+        .get("length")
+        .and_then(Value::as_u64)
+        .map(|length| length as usize);
+
+    match offload.store.read(&id, offset, length) {
+        // Raw bytes, since a chunk boundary can fall inside a UTF-8 sequence.
+        Ok(bytes) => match bytes_to_v8(scope, bytes) {
+            Some(chunk) => rv.set(chunk),
+            None => throw_error(scope, "failed to allocate blob chunk"),
+        },
+        Err(err) => throw_error(scope, &err.to_string()),
+    }
+}
+
+pub(crate) fn ensure_namespace<'a>(
+    scope: &mut v8::PinScope<'a, '_>,
+    parent: v8::Local<'a, v8::Object>,
+    name: &str,
+) -> Result<v8::Local<'a, v8::Object>, SandboxError> {
+    let key = v8::String::new(scope, name)
+        .ok_or_else(|| SandboxError::V8(format!("namespace key '{name}'")))?;
+    if let Some(existing) = parent.get(scope, key.into())
+        && existing.is_object()
+    {
+        return existing
+            .to_object(scope)
+            .ok_or_else(|| SandboxError::V8("namespace object".to_string()));
+    }
+    let obj = v8::Object::new(scope);
+    parent.set(scope, key.into(), obj.into());
+    Ok(obj)
+}
+
+fn v8_value_to_json(
+    scope: &mut v8::PinScope<'_, '_>,
+    value: v8::Local<v8::Value>,
+) -> Result<Value, SandboxError> {
+    v8_to_json(scope, value).map_err(SandboxError::Serialization)
+}
+
+pub(crate) fn throw_error(scope: &mut v8::PinScope<'_, '_>, message: &str) {
+    if let Some(message) = v8::String::new(scope, message) {
+        let exception = v8::Exception::error(scope, message);
+        scope.throw_exception(exception);
+    }
+}
@@ -0,0 +1,58 @@
+use std::cmp::Reverse;
+
+use crate::tool::Tool;
+
+/// Picks the tools relevant to a user query, so large catalogs can be narrowed per execution
+/// for both prompt size and the set of functions a script can reach. Implementations may rank
+/// by keywords, embeddings or anything else.
+pub trait ToolSelector: Send + Sync {
+    /// Names of at most `limit` tools from `tools`, most relevant first.
+    fn select(&self, query: &str, tools: &[&Tool], limit: usize) -> Vec<String>;
+}
+
+/// Ranks tools by the query words found in their name, tags and description, in that order of
+/// weight. Tools matching no word are never selected.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeywordSelector;
+
+impl ToolSelector for KeywordSelector {
+    fn select(&self, query: &str, tools: &[&Tool], limit: usize) -> Vec<String> {
+        let terms = words(query);
+        let mut scored = tools
+            .iter()
+            .map(|tool| (keyword_score(&terms, tool), tool.name.as_str()))
+            .filter(|(score, _)| *score > 0)
+            .collect::<Vec<(usize, &str)>>();
+        scored.sort_by_key(|(score, name)| (Reverse(*score), *name));
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, name)| name.to_string())
+            .collect()
+    }
+}
+
+fn keyword_score(terms: &[String], tool: &Tool) -> usize {
+    let name = words(&tool.name);
+    let tags = tool
+        .tags
+        .iter()
+        .flat_map(|tag| words(tag))
+        .collect::<Vec<String>>();
+    let description = words(&tool.description);
+    terms
+        .iter()
+        .map(|term| {
+            3 * usize::from(name.contains(term))
+                + 2 * usize::from(tags.contains(term))
+                + usize::from(description.contains(term))
+        })
+        .sum()
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.len() > 1)
+        .map(str::to_lowercase)
+        .collect()
+}
@@ -0,0 +1,290 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde_json::{Map, Value, json};
+
+use crate::schema::JsonSchema;
+use crate::tool::{AsyncToolCaller, SyncToolCaller, Tool, ToolCallError, ToolMetadataProvider};
+
+const DEFAULT_SEED: u64 = 0x5eed;
+
+// Guards against self-referencing schemas producing endless nesting.
+const MAX_DEPTH: usize = 8;
+
+const WORDS: &[&str] = &[
+    "alpha", "bravo", "cedar", "delta", "ember", "falcon", "granite", "harbor", "indigo",
+    "juniper", "kestrel", "lumen", "meadow", "nova", "orbit", "pine",
+];
+
+/// Produces plausible values from JSON Schemas: types, `enum`, `const`, `examples`, common
+/// `format`s, numeric bounds, array sizes and `required` are honoured. The same seed always
+/// yields the same sequence of values.
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    state: u64,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+impl Simulator {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn generate(&mut self, schema: &JsonSchema) -> Value {
+        self.generate_at(schema, 0)
+    }
+
+    fn generate_at(&mut self, schema: &JsonSchema, depth: usize) -> Value {
+        if depth > MAX_DEPTH {
+            return Value::Null;
+        }
+        if let Some(value) = schema.get("const") {
+            return value.clone();
+        }
+        if let Some(choice) = self.pick(schema.get("examples")) {
+            return choice;
+        }
+        if let Some(choice) = self.pick(schema.get("enum")) {
+            return choice;
+        }
+        let variants = schema.get("anyOf").or_else(|| schema.get("oneOf"));
+        if let Some(Value::Array(variants)) = variants
+            && !variants.is_empty()
+        {
+            let index = self.below(variants.len() as u64) as usize;
+            return self.generate_at(&variants[index], depth + 1);
+        }
+        if let Some(Value::Array(parts)) = schema.get("allOf") {
+            let mut merged = Map::new();
+            for part in parts {
+                if let Value::Object(fields) = self.generate_at(part, depth + 1) {
+                    merged.extend(fields);
+                }
+            }
+            return Value::Object(merged);
+        }
+
+        match schema_type(schema) {
+            Some("string") => self.string(schema),
+            Some("integer") => self.integer(schema),
+            Some("number") => self.number(schema),
+            Some("boolean") => Value::Bool(self.next() & 1 == 1),
+            Some("array") => self.array(schema, depth),
+            Some("object") => self.object(schema, depth),
+            _ => Value::Null,
+        }
+    }
+
+    fn string(&mut self, schema: &JsonSchema) -> Value {
+        let text = match schema.get("format").and_then(Value::as_str) {
+            Some("date-time") => format!(
+                "2024-{:02}-{:02}T{:02}:{:02}:00Z",
+                1 + self.below(12),
+                1 + self.below(28),
+                self.below(24),
+                self.below(60)
+            ),
+            Some("date") => format!("2024-{:02}-{:02}", 1 + self.below(12), 1 + self.below(28)),
+            Some("email") => format!("{}@example.com", self.word()),
+            Some("uri" | "url") => format!("https://example.com/{}", self.word()),
+            Some("uuid") => {
+                let (high, low) = (self.next(), self.next());
+                format!(
+                    "{:08x}-{:04x}-4{:03x}-a{:03x}-{:012x}",
+                    high >> 32,
+                    (high >> 16) & 0xffff,
+                    high & 0xfff,
+                    low >> 52,
+                    low & 0xffff_ffff_ffff
+                )
+            }
+            Some("ipv4") => format!(
+                "10.{}.{}.{}",
+                self.below(256),
+                self.below(256),
+                1 + self.below(254)
+            ),
+            _ => format!("{}-{}", self.word(), self.word()),
+        };
+        let min = schema.get("minLength").and_then(Value::as_u64).unwrap_or(0) as usize;
+        let max = schema
+            .get("maxLength")
+            .and_then(Value::as_u64)
+            .map(|max| max as usize);
+        let mut text = text;
+        while text.chars().count() < min {
+            text.push('x');
+        }
+        if let Some(max) = max {
+            text = text.chars().take(max).collect();
+        }
+        Value::String(text)
+    }
+
+    fn integer(&mut self, schema: &JsonSchema) -> Value {
+        let (low, high) = bounds(schema, 0.0, 100.0);
+        let (low, high) = (low.ceil() as i64, high.floor() as i64);
+        if high < low {
+            return json!(low);
+        }
+        let span = (high - low) as u64 + 1;
+        json!(low + self.below(span) as i64)
+    }
+
+    fn number(&mut self, schema: &JsonSchema) -> Value {
+        let (low, high) = bounds(schema, 0.0, 100.0);
+        let unit = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+        let value = low + (high - low).max(0.0) * unit;
+        json!((value * 100.0).round() / 100.0)
+    }
+
+    fn array(&mut self, schema: &JsonSchema, depth: usize) -> Value {
+        let min = schema.get("minItems").and_then(Value::as_u64).unwrap_or(1);
+        let max = schema
+            .get("maxItems")
+            .and_then(Value::as_u64)
+            .unwrap_or(min.max(3))
+            .max(min);
+        let count = min + self.below(max - min + 1);
+        let items = schema.get("items").cloned().unwrap_or(Value::Null);
+        Value::Array(
+            (0..count)
+                .map(|_| self.generate_at(&items, depth + 1))
+                .collect(),
+        )
+    }
+
+    fn object(&mut self, schema: &JsonSchema, depth: usize) -> Value {
+        let required = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<&str>>()
+            })
+            .unwrap_or_default();
+        let mut fields = Map::new();
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (name, property) in properties {
+                // Optional properties show up two times out of three.
+                if !required.contains(&name.as_str()) && self.below(3) == 0 {
+                    continue;
+                }
+                fields.insert(name.clone(), self.generate_at(property, depth + 1));
+            }
+        }
+        Value::Object(fields)
+    }
+
+    fn pick(&mut self, choices: Option<&Value>) -> Option<Value> {
+        let choices = choices?.as_array().filter(|choices| !choices.is_empty())?;
+        let index = self.below(choices.len() as u64) as usize;
+        Some(choices[index].clone())
+    }
+
+    fn word(&mut self) -> &'static str {
+        WORDS[self.below(WORDS.len() as u64) as usize]
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        if bound == 0 { 0 } else { self.next() % bound }
+    }
+
+    // SplitMix64: tiny, seedable and good enough for fake data.
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+fn schema_type(schema: &JsonSchema) -> Option<&str> {
+    match schema.get("type") {
+        Some(Value::String(kind)) => Some(kind.as_str()),
+        Some(Value::Array(kinds)) => kinds
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|kind| *kind != "null")
+            .or(Some("null")),
+        _ if schema.get("properties").is_some() => Some("object"),
+        _ => None,
+    }
+}
+
+fn bounds(schema: &JsonSchema, low: f64, high: f64) -> (f64, f64) {
+    let get = |key: &str| schema.get(key).and_then(Value::as_f64);
+    let min = get("minimum").or_else(|| get("exclusiveMinimum").map(|min| min + 1.0));
+    let max = get("maximum").or_else(|| get("exclusiveMaximum").map(|max| max - 1.0));
+    match (min, max) {
+        (Some(min), Some(max)) => (min, max),
+        (Some(min), None) => (min, min + (high - low)),
+        (None, Some(max)) => (max - (high - low), max),
+        (None, None) => (low, high),
+    }
+}
+
+/// Answers every call to its tools with values simulated from their `outputs` schemas. Register
+/// it like any other source to exercise scripts without real backends.
+#[derive(Clone)]
+pub struct MockToolCaller {
+    tools: Arc<Vec<Tool>>,
+    simulator: Arc<Mutex<Simulator>>,
+}
+
+impl MockToolCaller {
+    pub fn new(tools: Vec<Tool>) -> Self {
+        Self {
+            tools: Arc::new(tools),
+            simulator: Arc::new(Mutex::new(Simulator::default())),
+        }
+    }
+
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            simulator: Arc::new(Mutex::new(Simulator::new(seed))),
+            ..self
+        }
+    }
+
+    fn simulate(&self, name: &str) -> Result<Value, ToolCallError> {
+        let tool = self
+            .tools
+            .iter()
+            .find(|tool| tool.name == name)
+            .ok_or_else(|| ToolCallError::Message(format!("unknown mock tool '{name}'")))?;
+        let mut simulator = self
+            .simulator
+            .lock()
+            .map_err(|_| ToolCallError::Message("mock simulator poisoned".to_string()))?;
+        Ok(simulator.generate(&tool.outputs))
+    }
+}
+
+#[async_trait]
+impl ToolMetadataProvider for MockToolCaller {
+    async fn list_tools(&self) -> Result<Vec<Tool>, ToolCallError> {
+        Ok(self.tools.as_ref().clone())
+    }
+}
+
+#[async_trait]
+impl AsyncToolCaller for MockToolCaller {
+    async fn call_tool_async(&self, name: &str, _args: Value) -> Result<Value, ToolCallError> {
+        self.simulate(name)
+    }
+}
+
+impl SyncToolCaller for MockToolCaller {
+    fn call_tool_sync(&self, name: &str, _args: Value) -> Result<Value, ToolCallError> {
+        self.simulate(name)
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ts_interface::content_hash;
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+const INTERFACES_HEADER: &str = "// Auto-generated TypeScript interfaces for UTCP tools\n";
+
+/// A frozen copy of the generated interfaces, keyed by tool name.
+///
+/// Persist it and build prompts from [`InterfaceSnapshot::render`] to keep the prompt prefix
+/// byte-stable while the live registry keeps changing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterfaceSnapshot {
+    pub version: u32,
+    /// Same hash as [`InterfaceReport::hash`](crate::sandbox::InterfaceReport) for this tool set.
+    pub hash: String,
+    pub interfaces: BTreeMap<String, String>,
+}
+
+impl InterfaceSnapshot {
+    pub fn new(interfaces: BTreeMap<String, String>) -> Self {
+        let hash = content_hash(&join_interfaces(&interfaces));
+        Self {
+            version: SNAPSHOT_VERSION,
+            hash,
+            interfaces,
+        }
+    }
+
+    pub fn is_supported_version(&self) -> bool {
+        self.version <= SNAPSHOT_VERSION
+    }
+
+    /// The interface text to place in a prompt.
+    pub fn render(&self) -> String {
+        format!("{INTERFACES_HEADER}{}", join_interfaces(&self.interfaces))
+    }
+
+    /// What changed going from `self` to `current`.
+    pub fn drift(&self, current: &InterfaceSnapshot) -> InterfaceDrift {
+        let mut drift = InterfaceDrift::default();
+        for (name, interface) in &current.interfaces {
+            match self.interfaces.get(name) {
+                None => drift.added.push(name.clone()),
+                Some(pinned) if pinned != interface => drift.changed.push(name.clone()),
+                Some(_) => {}
+            }
+        }
+        drift.removed = self
+            .interfaces
+            .keys()
+            .filter(|name| !current.interfaces.contains_key(*name))
+            .cloned()
+            .collect();
+        drift
+    }
+}
+
+/// Tool names whose interfaces differ between two snapshots.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterfaceDrift {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl InterfaceDrift {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn join_interfaces(interfaces: &BTreeMap<String, String>) -> String {
+    interfaces
+        .values()
+        .map(String::as_str)
+        .collect::<Vec<&str>>()
+        .join("\n\n")
+}
@@ -1,11 +1,17 @@
+use std::pin::Pin;
+use std::sync::Arc;
+
 use async_trait::async_trait;
+use futures::Stream;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use thiserror::Error;
 
 use crate::schema::JsonSchema;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A tool as scripts see it. Build one with [`Tool::new`] and the `with_*` setters, which
+/// keeps callers compiling as fields are added.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Tool {
     pub name: String,
     pub description: String,
@@ -14,6 +20,180 @@ pub struct Tool {
     pub outputs: JsonSchema,
     #[serde(default)]
     pub is_async: bool,
+    /// Calls return an async iterable of chunks; see [`StreamingToolCaller`].
+    #[serde(default)]
+    pub is_streaming: bool,
+    /// The tool has no side effects (MCP `readOnlyHint`), so its results may be cached across
+    /// executions.
+    #[serde(default)]
+    pub read_only: bool,
+    /// The tool may destroy data (MCP `destructiveHint`), so policies can gate it; see
+    /// [`PolicyTarget::Destructive`](crate::policy::PolicyTarget::Destructive).
+    #[serde(default)]
+    pub destructive: bool,
+    /// Rendered as `@deprecated`; calls are handled per `SandboxConfig::deprecated_tools`.
+    #[serde(default)]
+    pub deprecated: bool,
+    #[serde(default)]
+    pub stability: Stability,
+}
+
+/// Maturity of a tool's interface, rendered as a JSDoc tag unless `Stable`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stability {
+    #[default]
+    Stable,
+    Beta,
+    Experimental,
+}
+
+impl Tool {
+    /// A synchronous tool that takes and returns any object.
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            inputs: serde_json::json!({ "type": "object" }),
+            outputs: serde_json::json!({ "type": "object" }),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_tags(mut self, tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_inputs(mut self, inputs: JsonSchema) -> Self {
+        self.inputs = inputs;
+        self
+    }
+
+    pub fn with_outputs(mut self, outputs: JsonSchema) -> Self {
+        self.outputs = outputs;
+        self
+    }
+
+    pub fn with_async(mut self, is_async: bool) -> Self {
+        self.is_async = is_async;
+        self
+    }
+
+    pub fn with_streaming(mut self, is_streaming: bool) -> Self {
+        self.is_streaming = is_streaming;
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn with_destructive(mut self, destructive: bool) -> Self {
+        self.destructive = destructive;
+        self
+    }
+
+    pub fn with_deprecated(mut self, deprecated: bool) -> Self {
+        self.deprecated = deprecated;
+        self
+    }
+
+    pub fn with_stability(mut self, stability: Stability) -> Self {
+        self.stability = stability;
+        self
+    }
+
+    /// The input schema is absent, `{}`, or an object with empty `properties` and no
+    /// `additionalProperties`, so the tool is called without arguments.
+    pub fn takes_no_input(&self) -> bool {
+        match &self.inputs {
+            Value::Null => true,
+            Value::Object(schema) if schema.is_empty() => true,
+            Value::Object(schema) => {
+                let no_properties = schema
+                    .get("properties")
+                    .and_then(Value::as_object)
+                    .is_some_and(|properties| properties.is_empty());
+                let closed = matches!(
+                    schema.get("additionalProperties"),
+                    None | Some(Value::Bool(false))
+                );
+                no_properties && closed
+            }
+            _ => false,
+        }
+    }
+
+    /// A tool whose schemas are generated from Rust types: `In` as it is deserialized from the
+    /// call's arguments and `Out` as it is serialized into the result. Shared types land in
+    /// `$defs`, which interfaces resolve like any other local `$ref`.
+    #[cfg(feature = "schemars")]
+    pub fn from_types<In, Out>(name: impl Into<String>, description: impl Into<String>) -> Self
+    where
+        In: schemars::JsonSchema,
+        Out: schemars::JsonSchema,
+    {
+        use schemars::generate::SchemaSettings;
+
+        Self::new(name, description)
+            .with_inputs(type_schema::<In>(
+                SchemaSettings::default().for_deserialize(),
+            ))
+            .with_outputs(type_schema::<Out>(
+                SchemaSettings::default().for_serialize(),
+            ))
+            .with_async(true)
+    }
+}
+
+#[cfg(feature = "schemars")]
+fn type_schema<T: schemars::JsonSchema>(settings: schemars::generate::SchemaSettings) -> Value {
+    let mut schema = settings.into_generator().into_root_schema_for::<T>();
+    schema.remove("$schema");
+    schema.to_value()
+}
+
+/// Selects tools by tag, name prefix or exact name. A tool passes when it matches any listed
+/// criterion; an empty filter passes every tool.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolFilter {
+    pub tags: Vec<String>,
+    pub prefixes: Vec<String>,
+    pub names: Vec<String>,
+}
+
+impl ToolFilter {
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.names.push(name.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.prefixes.is_empty() && self.names.is_empty()
+    }
+
+    pub fn matches(&self, tool: &Tool) -> bool {
+        self.is_empty()
+            || self.tags.iter().any(|tag| tool.tags.contains(tag))
+            || self
+                .prefixes
+                .iter()
+                .any(|prefix| tool.name.starts_with(prefix.as_str()))
+            || self.names.contains(&tool.name)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -35,3 +215,59 @@ pub trait ToolMetadataProvider: Send + Sync {
 pub trait SyncToolCaller: Send + Sync {
     fn call_tool_sync(&self, name: &str, args: Value) -> Result<Value, ToolCallError>;
 }
+
+pub type ValueStream = Pin<Box<dyn Stream<Item = Result<Value, ToolCallError>> + Send>>;
+
+/// Tools that produce output incrementally. Scripts consume them with `for await`.
+#[async_trait]
+pub trait StreamingToolCaller: Send + Sync {
+    async fn call_tool_stream(&self, name: &str, args: Value)
+    -> Result<ValueStream, ToolCallError>;
+}
+
+/// Hooks run around every tool call, in registration order, before results reach the sandbox.
+/// Typical uses are injecting credentials into arguments, redaction and trimming responses.
+pub trait ToolInterceptor: Send + Sync {
+    fn before(&self, _name: &str, args: Value) -> Value {
+        args
+    }
+
+    fn after(
+        &self,
+        _name: &str,
+        result: Result<Value, ToolCallError>,
+    ) -> Result<Value, ToolCallError> {
+        result
+    }
+}
+
+pub type ResultTransformFn = dyn Fn(Value) -> Value + Send + Sync;
+
+/// Rewrites a failed tool's error message, e.g. mapping backend codes to text a model can act
+/// on, before the script sees it. Receives the tool name and the message.
+pub type ToolErrorFn = dyn Fn(&str, String) -> String + Send + Sync;
+
+/// Plain host callback installed as a script global; see `Sandbox::register_global_fn`. Gets the
+/// call's arguments as JSON; an `Err` is thrown into the script.
+pub type GlobalFn = dyn Fn(Vec<Value>) -> Result<Value, String> + Send + Sync;
+
+/// Maps a failed tool's arguments onto its fallback's input shape.
+pub type ArgAdapterFn = dyn Fn(Value) -> Value + Send + Sync;
+
+/// Rewrites a tool's result before it reaches user code.
+#[derive(Clone)]
+pub enum ResultTransformer {
+    Rust(Arc<ResultTransformFn>),
+    /// Body of a JS `function(result) { ... }` evaluated inside the sandbox.
+    Js(String),
+}
+
+impl ResultTransformer {
+    pub fn rust(transform: impl Fn(Value) -> Value + Send + Sync + 'static) -> Self {
+        Self::Rust(Arc::new(transform))
+    }
+
+    pub fn js(body: impl Into<String>) -> Self {
+        Self::Js(body.into())
+    }
+}
@@ -1,137 +1,959 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
 use std::sync::RwLock;
 
-use serde_json::Value;
-use tracing::debug;
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use thiserror::Error;
+use tracing::{debug, warn};
 
 use crate::schema::JsonSchema;
-use crate::tool::Tool;
+use crate::snapshot::InterfaceSnapshot;
+use crate::tool::{Stability, Tool};
 
+// Entries are keyed by tool name and tagged with a fingerprint of the metadata they were
+// generated from, so a tool refreshed with a changed schema misses instead of going stale.
 #[derive(Default)]
 struct ToolInterfaceCache {
-    entries: RwLock<HashMap<String, String>>,
+    entries: RwLock<HashMap<String, (String, String)>>,
 }
 
 impl ToolInterfaceCache {
-    fn get(&self, tool_name: &str) -> Option<String> {
-        self.entries
-            .read()
-            .ok()
-            .and_then(|guard| guard.get(tool_name).cloned())
+    fn get(&self, tool_name: &str, fingerprint: &str) -> Option<String> {
+        self.entries.read().ok().and_then(|guard| {
+            guard
+                .get(tool_name)
+                .filter(|(cached, _)| cached == fingerprint)
+                .map(|(_, interface)| interface.clone())
+        })
+    }
+
+    fn insert(&self, tool_name: &str, fingerprint: String, interface: String) {
+        if let Ok(mut guard) = self.entries.write() {
+            guard.insert(tool_name.to_string(), (fingerprint, interface));
+        }
     }
 
-    fn insert(&self, tool_name: &str, interface: String) {
+    fn remove(&self, tool_name: &str) {
         if let Ok(mut guard) = self.entries.write() {
-            guard.insert(tool_name.to_string(), interface);
+            guard.remove(tool_name);
         }
     }
+
+    fn clear(&self) {
+        if let Ok(mut guard) = self.entries.write() {
+            guard.clear();
+        }
+    }
+}
+
+/// How dotted tool names map onto JS namespaces.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NamespaceStrategy {
+    /// `org.project.tool` becomes `org.project_tool`.
+    #[default]
+    Flatten,
+    /// `org.project.tool` becomes `org.project.tool`.
+    Nested,
+}
+
+#[derive(Debug, Clone, Default, Builder, Serialize, Deserialize)]
+#[builder(pattern = "owned", default)]
+pub struct InterfaceOptions {
+    #[serde(default)]
+    pub namespace_strategy: NamespaceStrategy,
+    /// One line per declaration, with no blank lines or `Access as` comments.
+    #[serde(default)]
+    pub compact: bool,
+    /// Catalog output (see [`ToolInterfaceGenerator::catalog_interfaces`]) merges each
+    /// namespace's tools into one `declare namespace` block under a single JSDoc header.
+    #[serde(default)]
+    pub merge_namespaces: bool,
 }
 
 #[derive(Default)]
 pub struct ToolInterfaceGenerator {
     cache: ToolInterfaceCache,
+    options: InterfaceOptions,
+    // Access paths reserved by `claim_access_path`, keyed by tool name.
+    claimed: RwLock<HashMap<String, String>>,
 }
 
 impl ToolInterfaceGenerator {
+    pub fn new(options: InterfaceOptions) -> Self {
+        Self {
+            cache: ToolInterfaceCache::default(),
+            options,
+            claimed: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn options(&self) -> &InterfaceOptions {
+        &self.options
+    }
+
     pub fn tool_to_typescript_interface(&self, tool: &Tool) -> String {
         debug!(tool = tool.name.as_str(), "tool interface generate");
-        if let Some(interface) = self.cache.get(&tool.name) {
+        let fingerprint = self.fingerprint(tool);
+        if let Some(interface) = self.cache.get(&tool.name, &fingerprint) {
             return interface;
         }
+        let interface = self.generate(tool);
+        self.cache
+            .insert(&tool.name, fingerprint, interface.clone());
+        interface
+    }
 
-        let (interface_content, access_pattern) = if tool.name.contains('.') {
-            let mut parts = tool.name.split('.');
-            let manual_name = parts.next().unwrap_or("manual");
-            let tool_parts: Vec<&str> = parts.collect();
-            let sanitized_manual = sanitize_identifier(manual_name);
-            let tool_name = tool_parts
-                .into_iter()
-                .map(sanitize_identifier)
-                .collect::<Vec<String>>()
-                .join("_");
-            let access_pattern = format!("{sanitized_manual}.{tool_name}");
+    /// Interfaces for `tools` shrunk to fit roughly `max_tokens` (see [`estimate_tokens`]).
+    /// Descriptions are dropped first, then optional input properties collapse into an index
+    /// signature, largest interfaces first; every step taken is listed in `truncated`.
+    pub fn budgeted_interfaces(&self, tools: &[&Tool], max_tokens: usize) -> BudgetedInterfaces {
+        let mut interfaces = tools
+            .iter()
+            .map(|tool| (tool.name.clone(), self.tool_to_typescript_interface(tool)))
+            .collect::<BTreeMap<String, String>>();
+        let mut total = InterfaceSnapshot::new(interfaces.clone()).render().len();
+        let mut truncated = Vec::new();
+        for truncation in [Truncation::Descriptions, Truncation::OptionalFields] {
+            let mut order = tools.to_vec();
+            order.sort_by_key(|tool| Reverse(interfaces.get(&tool.name).map_or(0, String::len)));
+            for tool in order {
+                if estimate_tokens_for_len(total) <= max_tokens {
+                    break;
+                }
+                let shrunk = self.generate(&truncation.apply(tool));
+                let Some(previous) = interfaces.insert(tool.name.clone(), shrunk.clone()) else {
+                    continue;
+                };
+                if previous != shrunk {
+                    total = total - previous.len() + shrunk.len();
+                    truncated.push(TruncatedInterface {
+                        tool: tool.name.clone(),
+                        truncation,
+                    });
+                }
+            }
+        }
+        let snapshot = InterfaceSnapshot::new(interfaces);
+        let estimated_tokens = estimate_tokens(&snapshot.render());
+        debug!(
+            tools = tools.len(),
+            estimated_tokens,
+            truncated = truncated.len(),
+            "budgeted interfaces"
+        );
+        BudgetedInterfaces {
+            within_budget: estimated_tokens <= max_tokens,
+            snapshot,
+            estimated_tokens,
+            truncated,
+        }
+    }
+
+    fn generate(&self, tool: &Tool) -> String {
+        let (namespace, declarations) = self.declarations(tool);
+        let interface_content = match namespace {
+            Some(namespace) => format!("declare namespace {namespace} {{\n{declarations}\n}}"),
+            None => declarations,
+        };
+        if self.options.compact {
+            return compact_interface(&interface_content, tool);
+        }
+        format!("{interface_content}\n\n{}", self.doc_comment(tool))
+    }
 
-            let input_content = json_schema_to_object_content(&tool.inputs);
-            let output_content = json_schema_to_object_content(&tool.outputs);
-            let output_interface = if tool.is_async {
+    /// The tool's type and function declarations, plus the namespace they belong in. Namespaced
+    /// declarations are indented for the namespace body.
+    fn declarations(&self, tool: &Tool) -> (Option<String>, String) {
+        let (namespaces, tool_name) = self.split_tool_name(tool);
+        let (inputs, mut named) = RefResolver::resolve(&tool.inputs, &tool_name);
+        let (outputs, output_named) = RefResolver::resolve(&tool.outputs, &tool_name);
+        for (name, schema) in output_named {
+            if !named.iter().any(|(existing, _)| *existing == name) {
+                named.push((name, schema));
+            }
+        }
+        let output_wrapper = if tool.is_streaming {
+            Some("AsyncIterable")
+        } else if tool.is_async {
+            Some("Promise")
+        } else {
+            None
+        };
+        // Calls without arguments are passed `{}`, so the parameter is optional unless
+        // something is required. Tools without inputs get no parameter at all.
+        let no_input = tool.takes_no_input();
+        let parameter = if no_input {
+            String::new()
+        } else if extract_required_set(&inputs).is_empty() {
+            format!("input?: {tool_name}Input")
+        } else {
+            format!("input: {tool_name}Input")
+        };
+        if !namespaces.is_empty() {
+            let input_declaration = if no_input {
+                String::new()
+            } else {
                 format!(
-                    "  type {tool_name}Output = Promise<{tool_name}OutputBase>;\n\n  interface {tool_name}OutputBase {{\n{output_content}\n  }}"
+                    "{}\n\n",
+                    namespaced_declaration(&inputs, &format!("{tool_name}Input"))
                 )
+            };
+            let output_interface = if let Some(wrapper) = output_wrapper {
+                let base = namespaced_declaration(&outputs, &format!("{tool_name}OutputBase"));
+                format!("  type {tool_name}Output = {wrapper}<{tool_name}OutputBase>;\n\n{base}")
             } else {
-                format!("  interface {tool_name}Output {{\n{output_content}\n  }}")
+                namespaced_declaration(&outputs, &format!("{tool_name}Output"))
             };
 
-            let interface_content = format!(
+            let named_declarations = named
+                .iter()
+                .map(|(name, schema)| format!("\n\n{}", namespaced_declaration(schema, name)))
+                .collect::<String>();
+
+            let declarations = format!(
                 "\
-namespace {sanitized_manual} {{
-  interface {tool_name}Input {{
-{input_content}
-  }}
+{input_declaration}{output_interface}{named_declarations}
 
-{output_interface}
-}}"
+  function {tool_name}({parameter}): {tool_name}Output;"
             );
 
-            (interface_content, access_pattern)
+            (Some(namespaces.join(".")), declarations)
         } else {
-            let sanitized_tool = sanitize_identifier(&tool.name);
-            let access_pattern = sanitized_tool.clone();
-            let input_type =
-                json_schema_to_typescript(&tool.inputs, &format!("{sanitized_tool}Input"));
-            let output_type_name = if tool.is_async {
+            let sanitized_tool = tool_name;
+            let input_type = if no_input {
+                String::new()
+            } else {
+                format!(
+                    "{}\n\n",
+                    json_schema_to_typescript(&inputs, &format!("{sanitized_tool}Input"))
+                )
+            };
+            let output_type_name = if output_wrapper.is_some() {
                 format!("{sanitized_tool}OutputBase")
             } else {
                 format!("{sanitized_tool}Output")
             };
-            let output_type = json_schema_to_typescript(&tool.outputs, &output_type_name);
-            let output_type = if tool.is_async {
-                format!("{output_type}\n\ntype {sanitized_tool}Output = Promise<{sanitized_tool}OutputBase>;")
+            let output_type = json_schema_to_typescript(&outputs, &output_type_name);
+            let output_type = if let Some(wrapper) = output_wrapper {
+                format!("{output_type}\n\ntype {sanitized_tool}Output = {wrapper}<{sanitized_tool}OutputBase>;")
             } else {
                 output_type
             };
-            (format!("{input_type}\n\n{output_type}"), access_pattern)
-        };
+            let function =
+                format!("declare function {sanitized_tool}({parameter}): {sanitized_tool}Output;");
+            let named_declarations = named
+                .iter()
+                .map(|(name, schema)| format!("{}\n\n", json_schema_to_typescript(schema, name)))
+                .collect::<String>();
+            (
+                None,
+                format!("{input_type}{output_type}\n\n{named_declarations}{function}"),
+            )
+        }
+    }
 
-        let access_comment = if tool.is_async {
-            format!("await {access_pattern}")
+    fn doc_comment(&self, tool: &Tool) -> String {
+        let access_pattern = self.tool_access_path(tool);
+        let args = if tool.takes_no_input() { "" } else { "args" };
+        let access_comment = if tool.is_streaming {
+            format!("for await (const chunk of {access_pattern}({args}))")
+        } else if tool.is_async {
+            format!("await {access_pattern}({args})")
         } else {
-            access_pattern.clone()
+            format!("{access_pattern}({args})")
         };
 
-        let interface_string = format!(
-            "\
-{interface_content}
+        let lifecycle = lifecycle_tags(tool)
+            .iter()
+            .map(|tag| format!("\n * {tag}"))
+            .collect::<String>();
 
+        format!(
+            "\
 /**
  * {description}
  * Tags: {tags}
- * Access as: {access_comment}(args)
+ * Access as: {access_comment}{lifecycle}
  */",
             description = escape_comment(&tool.description),
             tags = escape_comment(&tool.tags.join(", ")),
-            access_comment = access_comment
-        );
+        )
+    }
 
-        self.cache.insert(&tool.name, interface_string.clone());
-        interface_string
+    /// Every interface in `tools` as one declaration file: top-level functions first, then one
+    /// `declare namespace` block per namespace, each sorted by access path.
+    pub fn declaration_bundle(&self, tools: &[&Tool]) -> String {
+        let mut sorted = tools.to_vec();
+        sorted.sort_by_cached_key(|tool| self.tool_access_path(tool));
+
+        let mut top_level = Vec::new();
+        let mut namespaces = BTreeMap::<String, Vec<String>>::new();
+        for tool in sorted {
+            match self.declarations(tool) {
+                (Some(namespace), declarations) => {
+                    let doc = indent(&self.doc_comment(tool));
+                    namespaces
+                        .entry(namespace)
+                        .or_default()
+                        .push(format!("{declarations}\n\n{doc}"));
+                }
+                (None, declarations) => {
+                    top_level.push(format!("{declarations}\n\n{}", self.doc_comment(tool)));
+                }
+            }
+        }
+
+        let mut blocks = top_level;
+        blocks.extend(namespaces.into_iter().map(|(namespace, bodies)| {
+            format!(
+                "declare namespace {namespace} {{\n{}\n}}",
+                bodies.join("\n\n")
+            )
+        }));
+        format!("{DECLARATION_HEADER}{}\n", blocks.join("\n\n"))
     }
 
-    pub fn tool_access_path(&self, tool: &Tool) -> String {
-        if tool.name.contains('.') {
-            let mut parts = tool.name.split('.');
-            let manual_name = parts.next().unwrap_or("manual");
-            let tool_parts: Vec<&str> = parts.collect();
-            let sanitized_manual = sanitize_identifier(manual_name);
-            let tool_name = tool_parts
+    /// Interfaces for a whole catalog, sorted by access path. With
+    /// [`InterfaceOptions::merge_namespaces`] each namespace becomes one block under a single
+    /// JSDoc header and its tools keep only their description; otherwise this is every tool's
+    /// interface in turn.
+    pub fn catalog_interfaces(&self, tools: &[&Tool]) -> String {
+        let mut sorted = tools.to_vec();
+        sorted.sort_by_cached_key(|tool| self.tool_access_path(tool));
+        if !self.options.merge_namespaces {
+            return sorted
                 .into_iter()
-                .map(sanitize_identifier)
+                .map(|tool| self.tool_to_typescript_interface(tool))
                 .collect::<Vec<String>>()
-                .join("_");
-            format!("{sanitized_manual}.{tool_name}")
+                .join("\n\n");
+        }
+
+        let mut blocks = Vec::new();
+        let mut namespaces = BTreeMap::<String, Vec<&Tool>>::new();
+        for tool in sorted {
+            match self.split_tool_name(tool) {
+                (namespace, _) if namespace.is_empty() => {
+                    blocks.push(self.tool_to_typescript_interface(tool))
+                }
+                (namespace, _) => namespaces
+                    .entry(namespace.join("."))
+                    .or_default()
+                    .push(tool),
+            }
+        }
+        for (namespace, members) in namespaces {
+            blocks.push(self.merged_namespace(&namespace, &members));
+        }
+        blocks.join("\n\n")
+    }
+
+    fn merged_namespace(&self, namespace: &str, members: &[&Tool]) -> String {
+        let mut tags = members
+            .iter()
+            .flat_map(|tool| tool.tags.iter().map(String::as_str))
+            .collect::<Vec<&str>>();
+        tags.sort_unstable();
+        tags.dedup();
+        let mut header = format!("{namespace}: {} tools", members.len());
+        if !tags.is_empty() {
+            header.push_str(&format!(". Tags: {}", tags.join(", ")));
+        }
+
+        let bodies = members
+            .iter()
+            .map(|tool| {
+                let (_, declarations) = self.declarations(tool);
+                let mut doc = lifecycle_tags(tool)
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect::<Vec<String>>();
+                if !tool.description.is_empty() {
+                    doc.insert(0, escape_comment(&tool.description));
+                }
+                match declarations.rsplit_once('\n') {
+                    Some((types, function)) if !doc.is_empty() => {
+                        format!("{types}\n  /** {} */\n{function}", doc.join(" "))
+                    }
+                    _ => declarations,
+                }
+            })
+            .collect::<Vec<String>>();
+        let merged = format!(
+            "/** {} */\ndeclare namespace {namespace} {{\n{}\n}}",
+            escape_comment(&header),
+            bodies.join("\n\n")
+        );
+        if self.options.compact {
+            compact_blocks(&merged).join("\n")
         } else {
-            sanitize_identifier(&tool.name)
+            merged
+        }
+    }
+
+    /// Writes [`declaration_bundle`](Self::declaration_bundle) to `path`, typically a `.d.ts`
+    /// file, so editors can type-check chains against the registered tools.
+    pub fn write_declaration_file(
+        &self,
+        tools: &[&Tool],
+        path: impl AsRef<Path>,
+    ) -> std::io::Result<()> {
+        std::fs::write(path, self.declaration_bundle(tools))
+    }
+
+    /// Drops the cached interface so the next call regenerates it from the tool's metadata.
+    pub fn invalidate(&self, tool_name: &str) {
+        self.cache.remove(tool_name);
+    }
+
+    /// Drops every cached interface.
+    pub fn clear(&self) {
+        self.cache.clear();
+    }
+
+    /// Forgets `tool_name`: its cached interface and its claimed access path, which another
+    /// tool may claim afterwards.
+    pub fn release(&self, tool_name: &str) {
+        self.cache.remove(tool_name);
+        if let Ok(mut claimed) = self.claimed.write() {
+            claimed.remove(tool_name);
+        }
+    }
+
+    // The claimed access path is part of the output, so it is part of the fingerprint.
+    fn fingerprint(&self, tool: &Tool) -> String {
+        let metadata = serde_json::to_string(tool).unwrap_or_default();
+        content_hash(&format!("{}\n{metadata}", self.tool_access_path(tool)))
+    }
+
+    pub fn tool_access_path(&self, tool: &Tool) -> String {
+        let (mut namespaces, tool_name) = self.split_tool_name(tool);
+        namespaces.push(tool_name);
+        namespaces.join(".")
+    }
+
+    /// Reserves `tool`'s access path. Names that sanitize to a path another tool already holds
+    /// (`get-data` and `get_data` both become `get_data`) get a numeric suffix (`get_data_2`)
+    /// and a warning; the returned path is the one scripts must use.
+    pub fn claim_access_path(&self, tool: &Tool) -> String {
+        let Ok(mut claimed) = self.claimed.write() else {
+            return self.tool_access_path(tool);
+        };
+        if let Some(path) = claimed.get(&tool.name) {
+            return path.clone();
+        }
+        let (mut namespaces, tool_name) = self.sanitized_parts(tool);
+        namespaces.push(tool_name);
+        let base = namespaces.join(".");
+        let mut path = base.clone();
+        let mut suffix = 1;
+        while claimed.values().any(|taken| *taken == path) {
+            suffix += 1;
+            path = format!("{base}_{suffix}");
+        }
+        if path != base {
+            let holder = claimed
+                .iter()
+                .find(|(_, taken)| **taken == base)
+                .map(|(name, _)| name.clone())
+                .unwrap_or_default();
+            warn!(
+                tool = tool.name.as_str(),
+                collides_with = holder.as_str(),
+                path = path.as_str(),
+                "tool identifier collision, renamed"
+            );
+        }
+        claimed.insert(tool.name.clone(), path.clone());
+        path
+    }
+
+    fn split_tool_name(&self, tool: &Tool) -> (Vec<String>, String) {
+        let claimed = self
+            .claimed
+            .read()
+            .ok()
+            .and_then(|claimed| claimed.get(&tool.name).cloned());
+        match claimed {
+            Some(path) => match path.rsplit_once('.') {
+                Some((namespaces, tool_name)) => (
+                    namespaces.split('.').map(str::to_string).collect(),
+                    tool_name.to_string(),
+                ),
+                None => (Vec::new(), path),
+            },
+            None => self.sanitized_parts(tool),
+        }
+    }
+
+    fn sanitized_parts(&self, tool: &Tool) -> (Vec<String>, String) {
+        if !tool.name.contains('.') {
+            return (Vec::new(), sanitize_identifier(&tool.name));
+        }
+
+        let parts = tool
+            .name
+            .split('.')
+            .map(sanitize_identifier)
+            .collect::<Vec<String>>();
+        match self.options.namespace_strategy {
+            NamespaceStrategy::Flatten => {
+                let (manual, tool_parts) = parts.split_first().expect("dotted name has parts");
+                (vec![manual.clone()], tool_parts.join("_"))
+            }
+            NamespaceStrategy::Nested => {
+                let (tool_name, namespaces) = parts.split_last().expect("dotted name has parts");
+                (namespaces.to_vec(), tool_name.clone())
+            }
+        }
+    }
+}
+
+/// What [`ToolInterfaceGenerator::budgeted_interfaces`] removed from an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Truncation {
+    /// Tool and property descriptions and examples.
+    Descriptions,
+    /// Optional input properties, replaced by `[key: string]: any`. Implies `Descriptions`.
+    OptionalFields,
+}
+
+impl Truncation {
+    fn apply(self, tool: &Tool) -> Tool {
+        let mut tool = tool.clone();
+        tool.description.clear();
+        strip_descriptions(&mut tool.inputs);
+        strip_descriptions(&mut tool.outputs);
+        if self == Truncation::OptionalFields {
+            collapse_optional_properties(&mut tool.inputs);
+        }
+        tool
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TruncatedInterface {
+    pub tool: String,
+    pub truncation: Truncation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BudgetedInterfaces {
+    pub snapshot: InterfaceSnapshot,
+    pub estimated_tokens: usize,
+    /// False when even the fully truncated interfaces exceed the budget.
+    pub within_budget: bool,
+    pub truncated: Vec<TruncatedInterface>,
+}
+
+const DECLARATION_HEADER: &str = "// Auto-generated TypeScript declarations for UTCP tools\n\n";
+
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("  {line}"))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// `@deprecated`, `@beta` and `@experimental` as they apply to `tool`.
+fn lifecycle_tags(tool: &Tool) -> Vec<&'static str> {
+    let mut tags = Vec::new();
+    if tool.deprecated {
+        tags.push("@deprecated");
+    }
+    match tool.stability {
+        Stability::Stable => {}
+        Stability::Beta => tags.push("@beta"),
+        Stability::Experimental => tags.push("@experimental"),
+    }
+    tags
+}
+
+fn compact_blocks(text: &str) -> Vec<String> {
+    text.split("\n\n")
+        .map(|block| {
+            block
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<&str>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+fn compact_interface(interface_content: &str, tool: &Tool) -> String {
+    let mut lines = compact_blocks(interface_content);
+    let mut doc = Vec::new();
+    if !tool.description.is_empty() {
+        doc.push(escape_comment(&tool.description));
+    }
+    if !tool.tags.is_empty() {
+        doc.push(format!("Tags: {}", escape_comment(&tool.tags.join(", "))));
+    }
+    doc.extend(lifecycle_tags(tool).into_iter().map(str::to_string));
+    if !doc.is_empty() {
+        lines.push(format!("/** {} */", doc.join(" ")));
+    }
+    lines.join("\n")
+}
+
+/// Rough token count for prompt text, at four bytes per token.
+pub fn estimate_tokens(text: &str) -> usize {
+    estimate_tokens_for_len(text.len())
+}
+
+fn estimate_tokens_for_len(len: usize) -> usize {
+    len.div_ceil(4)
+}
+
+fn strip_descriptions(schema: &mut JsonSchema) {
+    match schema {
+        Value::Object(map) => {
+            map.remove("description");
+            map.remove("examples");
+            for (key, value) in map.iter_mut() {
+                // Property names live under `properties`; only their schemas are stripped.
+                if key == "properties" {
+                    if let Value::Object(properties) = value {
+                        properties.values_mut().for_each(strip_descriptions);
+                    }
+                } else {
+                    strip_descriptions(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_descriptions),
+        _ => {}
+    }
+}
+
+fn collapse_optional_properties(schema: &mut JsonSchema) {
+    let required = extract_required_set(schema);
+    let Some(Value::Object(properties)) = schema.get_mut("properties") else {
+        return;
+    };
+    let before = properties.len();
+    properties.retain(|name, _| required.contains(name));
+    if properties.len() < before {
+        schema[COLLAPSED] = Value::Bool(true);
+    }
+}
+
+/// Stable 64-bit FNV-1a hash, hex encoded, for fingerprinting generated interface text.
+pub fn content_hash(text: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.as_bytes() {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{hash:016x}")
+}
+
+/// A problem [`verify`] found in generated declarations.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum VerifyError {
+    #[error("unbalanced `{delimiter}` on line {line}")]
+    Unbalanced { delimiter: char, line: usize },
+    #[error("unterminated {kind} starting on line {line}")]
+    Unterminated { kind: &'static str, line: usize },
+    #[error("`{0}` is declared more than once")]
+    DuplicateDeclaration(String),
+    #[error("invalid TypeScript: {0}")]
+    Parse(String),
+}
+
+/// Checks generated declarations (interfaces, catalogs or a declaration bundle) before they
+/// reach a prompt or a `.d.ts` file: brackets balance, and no type, interface or function is
+/// declared twice in the same namespace. With the `typescript` feature the text is also parsed
+/// as a declaration file. Reopened `declare namespace` blocks are fine.
+pub fn verify(declarations: &str) -> Result<(), Vec<VerifyError>> {
+    let code = match blank_comments_and_strings(declarations) {
+        Ok(code) => code,
+        Err(error) => return Err(vec![error]),
+    };
+    let mut errors = unbalanced_delimiters(&code);
+    if errors.is_empty() {
+        errors.extend(duplicate_declarations(&code));
+    }
+    #[cfg(feature = "typescript")]
+    errors.extend(
+        crate::typescript::declaration_errors(declarations)
+            .into_iter()
+            .map(VerifyError::Parse),
+    );
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+// Replaces comments and string literals with spaces, keeping newlines so lines still line up.
+fn blank_comments_and_strings(text: &str) -> Result<String, VerifyError> {
+    let mut code = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while let Some(ch) = chars.next() {
+        let start = line;
+        let (kind, quote) = match (ch, chars.peek()) {
+            ('/', Some('/')) => {
+                while chars.next_if(|next| *next != '\n').is_some() {}
+                continue;
+            }
+            ('/', Some('*')) => ("comment", None),
+            ('"' | '\'' | '`', _) => ("string", Some(ch)),
+            _ => {
+                if ch == '\n' {
+                    line += 1;
+                }
+                code.push(ch);
+                continue;
+            }
+        };
+        code.push(' ');
+        if quote.is_none() {
+            chars.next();
+        }
+        let mut closed = false;
+        while let Some(next) = chars.next() {
+            match (next, quote) {
+                ('\n', _) => {
+                    line += 1;
+                    code.push('\n');
+                    continue;
+                }
+                ('*', None) if chars.next_if_eq(&'/').is_some() => closed = true,
+                ('\\', Some(_)) => {
+                    chars.next();
+                }
+                (next, Some(quote)) if next == quote => closed = true,
+                _ => {}
+            }
+            if closed {
+                break;
+            }
+        }
+        if !closed {
+            return Err(VerifyError::Unterminated { kind, line: start });
+        }
+        code.push(' ');
+    }
+    Ok(code)
+}
+
+fn unbalanced_delimiters(code: &str) -> Vec<VerifyError> {
+    let mut errors = Vec::new();
+    let mut open = Vec::new();
+    for (index, text) in code.lines().enumerate() {
+        let line = index + 1;
+        for ch in text.chars() {
+            match ch {
+                '{' | '(' | '[' => open.push((ch, line)),
+                '}' | ')' | ']' => {
+                    let expected = match ch {
+                        '}' => '{',
+                        ')' => '(',
+                        _ => '[',
+                    };
+                    if open.last().is_some_and(|(last, _)| *last == expected) {
+                        open.pop();
+                    } else {
+                        errors.push(VerifyError::Unbalanced {
+                            delimiter: ch,
+                            line,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    errors.extend(
+        open.into_iter()
+            .map(|(delimiter, line)| VerifyError::Unbalanced { delimiter, line }),
+    );
+    errors
+}
+
+const DECLARATION_KEYWORDS: &[&str] = &[
+    "type",
+    "interface",
+    "function",
+    "const",
+    "let",
+    "var",
+    "class",
+    "enum",
+    "namespace",
+];
+
+// Names declared at the top level or directly in a namespace body, qualified by namespace.
+// Anything inside other braces (interface bodies, object types) is a member, not a declaration.
+// Types and values are separate, so `type X` next to `function X` is fine.
+fn duplicate_declarations(code: &str) -> Vec<VerifyError> {
+    let mut errors = Vec::new();
+    let mut seen = HashSet::new();
+    // `Some(namespace)` for namespace bodies, `None` for every other brace.
+    let mut scopes: Vec<Option<String>> = Vec::new();
+    let mut keyword: Option<&str> = None;
+    let mut namespace: Option<String> = None;
+    let mut rest = code;
+    while let Some(ch) = rest.chars().next() {
+        let word_len = rest
+            .find(|ch: char| !(ch.is_ascii_alphanumeric() || matches!(ch, '_' | '$' | '.')))
+            .unwrap_or(rest.len());
+        if word_len == 0 {
+            rest = &rest[ch.len_utf8()..];
+            if ch.is_whitespace() {
+                continue;
+            }
+            match ch {
+                '{' => scopes.push(namespace.take()),
+                '}' => {
+                    scopes.pop();
+                }
+                _ => {}
+            }
+            keyword = None;
+            continue;
+        }
+        let (word, tail) = rest.split_at(word_len);
+        rest = tail;
+        let prefix = match scopes.last() {
+            None => String::new(),
+            Some(Some(namespace)) => format!("{namespace}."),
+            Some(None) => {
+                keyword = None;
+                continue;
+            }
+        };
+        match keyword.take() {
+            Some("namespace") => namespace = Some(format!("{prefix}{word}")),
+            Some(keyword) => {
+                let qualified = format!("{prefix}{word}");
+                let is_type = matches!(keyword, "type" | "interface");
+                if !seen.insert((is_type, qualified.clone())) {
+                    errors.push(VerifyError::DuplicateDeclaration(qualified));
+                }
+            }
+            None => keyword = DECLARATION_KEYWORDS.contains(&word).then_some(word),
         }
     }
+    errors
+}
+
+/// Marks a schema hoisted into a named declaration; it renders as that name.
+const NAMED_TYPE: &str = "$tsType";
+
+/// Marks an object whose optional properties were collapsed into an index signature.
+const COLLAPSED: &str = "$tsCollapsed";
+
+/// Resolves local `$ref`s such as `#/$defs/Foo` or `#/definitions/Foo` (any JSON pointer into
+/// the same document works); keywords next to a `$ref` override the target's. Targets are
+/// inlined unless they are recursive: those are hoisted into `named` declarations, prefixed
+/// with the tool's name, and referenced by name. Unresolvable references render as `any`.
+struct RefResolver<'a> {
+    root: &'a Value,
+    prefix: &'a str,
+    recursive: Vec<String>,
+    named: Vec<(String, JsonSchema)>,
+}
+
+impl<'a> RefResolver<'a> {
+    fn resolve(schema: &'a JsonSchema, prefix: &'a str) -> (JsonSchema, Vec<(String, JsonSchema)>) {
+        let mut resolver = Self {
+            root: schema,
+            prefix,
+            recursive: Vec::new(),
+            named: Vec::new(),
+        };
+        // The first pass finds the references that lead back into themselves; without any,
+        // its fully inlined result is final.
+        let inlined = resolver.inline(schema, &mut Vec::new());
+        if resolver.recursive.is_empty() {
+            return (inlined, Vec::new());
+        }
+        resolver.named.clear();
+        let resolved = resolver.inline(schema, &mut Vec::new());
+        (resolved, resolver.named)
+    }
+
+    fn inline(&mut self, node: &Value, expanding: &mut Vec<String>) -> Value {
+        match node {
+            Value::Object(map) => {
+                let mut resolved = match map.get("$ref").and_then(Value::as_str) {
+                    Some(reference) => self.target(reference, expanding),
+                    None => Map::new(),
+                };
+                for (key, value) in map {
+                    if key != "$ref" {
+                        resolved.insert(key.clone(), self.inline(value, expanding));
+                    }
+                }
+                Value::Object(resolved)
+            }
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.inline(item, expanding))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    fn target(&mut self, reference: &str, expanding: &mut Vec<String>) -> Map<String, Value> {
+        let Some(target) = reference
+            .strip_prefix('#')
+            .and_then(|pointer| self.root.pointer(pointer))
+        else {
+            return Map::new();
+        };
+        if self.recursive.iter().any(|seen| seen == reference) {
+            let name = self.type_name(reference);
+            if !self.named.iter().any(|(named, _)| *named == name) {
+                // Reserve the name first so the definition's own references stop here.
+                self.named.push((name.clone(), Value::Null));
+                let definition = self.inline(target, &mut Vec::new());
+                if let Some(slot) = self.named.iter_mut().find(|(named, _)| *named == name) {
+                    slot.1 = definition;
+                }
+            }
+            let mut marker = Map::new();
+            marker.insert(NAMED_TYPE.to_string(), Value::String(name));
+            return marker;
+        }
+        if expanding.iter().any(|seen| seen == reference) {
+            self.recursive.push(reference.to_string());
+            return Map::new();
+        }
+        expanding.push(reference.to_string());
+        let target = self.inline(target, expanding);
+        expanding.pop();
+        match target {
+            Value::Object(target) => target,
+            _ => Map::new(),
+        }
+    }
+
+    fn type_name(&self, reference: &str) -> String {
+        let segment = reference.rsplit('/').next().unwrap_or_default();
+        let segment = if segment.is_empty() || segment == "#" {
+            "Root".to_string()
+        } else {
+            sanitize_identifier(segment)
+        };
+        format!("{}{segment}", self.prefix)
+    }
+}
+
+fn named_type(schema: &JsonSchema) -> Option<&str> {
+    schema.get(NAMED_TYPE).and_then(Value::as_str)
 }
 
 fn sanitize_identifier(name: &str) -> String {
@@ -170,6 +992,16 @@ fn extract_required_set(schema: &JsonSchema) -> Vec<String> {
         .unwrap_or_default()
 }
 
+/// `const` (or a single-value `enum`) of a scalar as a TS literal type.
+fn const_literal(schema: &JsonSchema) -> Option<String> {
+    let value = match (schema.get("const"), schema.get("enum")) {
+        (Some(value), _) => value,
+        (None, Some(Value::Array(values))) if values.len() == 1 => &values[0],
+        _ => return None,
+    };
+    Some(format_enum_value(value)).filter(|literal| !literal.is_empty())
+}
+
 fn format_enum_value(val: &Value) -> String {
     match val {
         Value::String(s) => serde_json::to_string(s).unwrap_or_else(|_| "\"\"".to_string()),
@@ -180,6 +1012,35 @@ fn format_enum_value(val: &Value) -> String {
     }
 }
 
+// Combinator schemas cannot be interfaces, so they become type aliases.
+fn namespaced_declaration(schema: &JsonSchema, type_name: &str) -> String {
+    if let Some(name) = named_type(schema) {
+        return format!("  type {type_name} = {name};");
+    }
+    if let Some(literal) = const_literal(schema) {
+        return format!("  type {type_name} = {literal};");
+    }
+    if let Some(variants) = discriminated_variants(schema) {
+        let mut declarations = Vec::new();
+        let mut names = Vec::new();
+        for (suffix, variant) in variants {
+            let name = format!("{type_name}{suffix}");
+            let content = json_schema_to_object_content(&variant);
+            declarations.push(format!("  interface {name} {{\n{content}\n  }}"));
+            names.push(name);
+        }
+        declarations.push(format!("  type {type_name} = {};", names.join(" | ")));
+        return declarations.join("\n\n");
+    }
+    match combined_typescript_type(schema) {
+        Some(ts_type) => format!("  type {type_name} = {ts_type};"),
+        None => {
+            let content = json_schema_to_object_content(schema);
+            format!("  interface {type_name} {{\n{content}\n  }}")
+        }
+    }
+}
+
 fn json_schema_to_object_content(schema: &JsonSchema) -> String {
     if schema.get("type").and_then(Value::as_str) != Some("object") {
         return "    [key: string]: any;".to_string();
@@ -193,18 +1054,17 @@ fn json_schema_to_object_content(schema: &JsonSchema) -> String {
         for (prop_name, prop_schema) in props.iter() {
             let is_required = required_set.iter().any(|req| req == prop_name);
             let optional_marker = if is_required { "" } else { "?" };
-            let description = prop_schema
-                .get("description")
-                .and_then(Value::as_str)
-                .unwrap_or("");
             let ts_type = json_schema_to_typescript_type(prop_schema);
 
-            if !description.is_empty() {
-                lines.push(format!("    /** {} */", escape_comment(description)));
+            if let Some(doc) = property_doc(prop_schema) {
+                lines.push(format!("    /** {doc} */"));
             }
             lines.push(format!("    {prop_name}{optional_marker}: {ts_type};"));
         }
     }
+    if schema.get(COLLAPSED).is_some() && !lines.is_empty() {
+        lines.push("    [key: string]: any;".to_string());
+    }
 
     if lines.is_empty() {
         "    [key: string]: any;".to_string()
@@ -214,6 +1074,26 @@ fn json_schema_to_object_content(schema: &JsonSchema) -> String {
 }
 
 fn json_schema_to_typescript(schema: &JsonSchema, type_name: &str) -> String {
+    if let Some(name) = named_type(schema) {
+        return format!("type {type_name} = {name};");
+    }
+    if let Some(literal) = const_literal(schema) {
+        return format!("type {type_name} = {literal};");
+    }
+    if let Some(variants) = discriminated_variants(schema) {
+        let mut declarations = Vec::new();
+        let mut names = Vec::new();
+        for (suffix, variant) in variants {
+            let name = format!("{type_name}{suffix}");
+            declarations.push(object_schema_to_typescript(&variant, &name));
+            names.push(name);
+        }
+        declarations.push(format!("type {type_name} = {};", names.join(" | ")));
+        return declarations.join("\n\n");
+    }
+    if let Some(ts_type) = combined_typescript_type(schema) {
+        return format!("type {type_name} = {ts_type};");
+    }
     let schema_type = schema.get("type");
     match schema_type.and_then(Value::as_str) {
         Some("object") => object_schema_to_typescript(schema, type_name),
@@ -253,20 +1133,85 @@ fn object_schema_to_typescript(schema: &JsonSchema, type_name: &str) -> String {
             let is_required = required_set.iter().any(|req| req == key);
             let optional = if is_required { "" } else { "?" };
             let prop_type = json_schema_to_typescript_type(prop_schema);
-            let description = prop_schema
-                .get("description")
-                .and_then(Value::as_str)
-                .map(|desc| format!("  /** {} */\n", escape_comment(desc)))
+            let description = property_doc(prop_schema)
+                .map(|doc| format!("  /** {doc} */\n"))
                 .unwrap_or_default();
             format!("{description}  {key}{optional}: {prop_type};")
         })
+        .chain(
+            schema
+                .get(COLLAPSED)
+                .map(|_| "  [key: string]: any;".to_string()),
+        )
         .collect::<Vec<String>>()
         .join("\n");
 
     format!("interface {type_name} {{\n{props}\n}}")
 }
 
+// Validation keywords surfaced as JSDoc tags of the same name, in this order.
+const CONSTRAINT_TAGS: &[&str] = &[
+    "format",
+    "pattern",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "minLength",
+    "maxLength",
+    "minItems",
+    "maxItems",
+    "default",
+];
+
+/// The property's description followed by its constraints, default and examples as JSDoc
+/// tags, so callers see valid argument shapes next to the field.
+fn property_doc(schema: &JsonSchema) -> Option<String> {
+    let mut parts = schema
+        .get("description")
+        .and_then(Value::as_str)
+        .filter(|description| !description.is_empty())
+        .map(|description| vec![escape_comment(description)])
+        .unwrap_or_default();
+    for key in CONSTRAINT_TAGS {
+        match schema.get(*key) {
+            // `format` and `pattern` read better unquoted.
+            Some(Value::String(text)) if matches!(*key, "format" | "pattern") => {
+                parts.push(format!("@{key} {}", escape_comment(text)));
+            }
+            Some(value) => parts.push(format!("@{key} {}", escape_comment(&value.to_string()))),
+            None => {}
+        }
+    }
+    if let Some(Value::Array(examples)) = schema.get("examples") {
+        for example in examples {
+            parts.push(format!("@example {}", escape_comment(&example.to_string())));
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join(" "))
+}
+
+/// Draft 2020-12 `prefixItems` as a TS tuple; `items` then types any remaining elements.
+fn tuple_typescript_type(schema: &JsonSchema) -> Option<String> {
+    let prefix = schema.get("prefixItems")?.as_array()?;
+    let mut elements = prefix
+        .iter()
+        .map(json_schema_to_typescript_type)
+        .collect::<Vec<String>>();
+    match schema.get("items") {
+        Some(Value::Bool(false)) => {}
+        Some(rest @ Value::Object(_)) => {
+            elements.push(format!("...({})[]", json_schema_to_typescript_type(rest)))
+        }
+        _ => elements.push("...any[]".to_string()),
+    }
+    Some(format!("[{}]", elements.join(", ")))
+}
+
 fn array_schema_to_typescript(schema: &JsonSchema, type_name: &str) -> String {
+    if let Some(tuple) = tuple_typescript_type(schema) {
+        return format!("type {type_name} = {tuple};");
+    }
     let items = schema.get("items");
     if items.is_none() {
         return format!("type {type_name} = any[];");
@@ -286,6 +1231,9 @@ fn array_schema_to_typescript(schema: &JsonSchema, type_name: &str) -> String {
 }
 
 fn primitive_schema_to_typescript(schema: &JsonSchema, type_name: &str, base_type: &str) -> String {
+    if let Some(literal) = const_literal(schema) {
+        return format!("type {type_name} = {literal};");
+    }
     if let Some(Value::Array(values)) = schema.get("enum") {
         let union = values
             .iter()
@@ -300,6 +1248,176 @@ fn primitive_schema_to_typescript(schema: &JsonSchema, type_name: &str, base_typ
 }
 
 fn json_schema_to_typescript_type(schema: &JsonSchema) -> String {
+    combined_typescript_type(schema).unwrap_or_else(|| base_typescript_type(schema))
+}
+
+/// `schema` with its local `$ref`s inlined; recursive targets render as `prefix`-named types.
+pub(crate) fn resolve_refs(schema: &JsonSchema, prefix: &str) -> JsonSchema {
+    RefResolver::resolve(schema, prefix).0
+}
+
+/// The TypeScript type and JSDoc text generated for a property schema.
+pub(crate) fn describe_property(schema: &JsonSchema) -> (String, Option<String>) {
+    (json_schema_to_typescript_type(schema), property_doc(schema))
+}
+
+/// `anyOf`/`oneOf` become unions and `allOf` an intersection, joined with the schema's own
+/// `type` when it has one. Members that say nothing about the type (e.g. only `required`) are
+/// skipped; `None` means the schema has no informative combinator.
+fn combined_typescript_type(schema: &JsonSchema) -> Option<String> {
+    if let Some(variants) = discriminated_variants(schema) {
+        return Some(
+            variants
+                .iter()
+                .map(|(_, variant)| base_typescript_type(variant))
+                .collect::<Vec<String>>()
+                .join(" | "),
+        );
+    }
+    let mut parts = Vec::new();
+    for (key, separator) in [("anyOf", " | "), ("oneOf", " | "), ("allOf", " & ")] {
+        let Some(members) = schema.get(key).and_then(Value::as_array) else {
+            continue;
+        };
+        let mut types = Vec::new();
+        for member in members {
+            let ts_type = json_schema_to_typescript_type(member);
+            if ts_type != "any" && !types.contains(&ts_type) {
+                types.push(ts_type);
+            }
+        }
+        if types.is_empty() {
+            continue;
+        }
+        if separator == " & " {
+            types = types.into_iter().map(parenthesize_union).collect();
+        }
+        parts.push(types.join(separator));
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    let base = base_typescript_type(schema);
+    if base != "any" {
+        parts.insert(0, base);
+    }
+    if parts.len() == 1 {
+        return parts.pop();
+    }
+    Some(
+        parts
+            .into_iter()
+            .map(parenthesize_union)
+            .collect::<Vec<String>>()
+            .join(" & "),
+    )
+}
+
+/// Splits a `oneOf`/`anyOf` of objects that share a property with a distinct `const` (or
+/// single-value `enum`) in every variant into one object schema per variant, named after the
+/// discriminator value. Each variant also carries the parent's own properties and requires the
+/// discriminator, so TypeScript can narrow on it.
+fn discriminated_variants(schema: &JsonSchema) -> Option<Vec<(String, JsonSchema)>> {
+    let members = schema
+        .get("oneOf")
+        .or_else(|| schema.get("anyOf"))
+        .and_then(Value::as_array)
+        .filter(|members| members.len() > 1)?;
+    let first = members[0].get("properties").and_then(Value::as_object)?;
+    let (discriminator, values) = first.keys().find_map(|key| {
+        let values = members
+            .iter()
+            .map(|member| discriminator_value(member.get("properties")?.get(key)?))
+            .collect::<Option<Vec<&Value>>>()?;
+        let distinct = values
+            .iter()
+            .enumerate()
+            .all(|(index, value)| !values[..index].contains(value));
+        distinct.then_some((key, values))
+    })?;
+
+    let parent_properties = schema.get("properties").and_then(Value::as_object);
+    let parent_required = extract_required_set(schema);
+    let variants = members
+        .iter()
+        .zip(values)
+        .map(|(member, value)| {
+            let mut properties = parent_properties.cloned().unwrap_or_default();
+            if let Some(own) = member.get("properties").and_then(Value::as_object) {
+                properties.extend(own.clone());
+            }
+            let mut required = parent_required.clone();
+            for name in extract_required_set(member)
+                .into_iter()
+                .chain([discriminator.clone()])
+            {
+                if !required.contains(&name) {
+                    required.push(name);
+                }
+            }
+            let mut variant = Map::new();
+            variant.insert("type".to_string(), Value::String("object".to_string()));
+            if let Some(description) = member.get("description") {
+                variant.insert("description".to_string(), description.clone());
+            }
+            variant.insert("properties".to_string(), Value::Object(properties));
+            variant.insert("required".to_string(), json!(required));
+            (variant_suffix(value), Value::Object(variant))
+        })
+        .collect();
+    Some(variants)
+}
+
+fn discriminator_value(schema: &JsonSchema) -> Option<&Value> {
+    let value = match schema.get("const") {
+        Some(value) => value,
+        None => match schema.get("enum")?.as_array()?.as_slice() {
+            [value] => value,
+            _ => return None,
+        },
+    };
+    matches!(value, Value::String(_) | Value::Number(_) | Value::Bool(_)).then_some(value)
+}
+
+// `"pull_request"` becomes `PullRequest`, so variants read as `eventPullRequest`.
+fn variant_suffix(value: &Value) -> String {
+    let text = match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    let suffix = text
+        .split(|ch: char| !ch.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect::<String>();
+    if suffix.is_empty() {
+        "Variant".to_string()
+    } else {
+        suffix
+    }
+}
+
+fn parenthesize_union(ts_type: String) -> String {
+    if ts_type.contains(" | ") {
+        format!("({ts_type})")
+    } else {
+        ts_type
+    }
+}
+
+fn base_typescript_type(schema: &JsonSchema) -> String {
+    if let Some(name) = named_type(schema) {
+        return name.to_string();
+    }
+    if let Some(literal) = const_literal(schema) {
+        return literal;
+    }
     if let Some(Value::Array(values)) = schema.get("enum") {
         let union = values
             .iter()
@@ -333,6 +1451,9 @@ fn json_schema_to_typescript_type(schema: &JsonSchema) -> String {
             format!("{{ {props} }}")
         }
         Some("array") => {
+            if let Some(tuple) = tuple_typescript_type(schema) {
+                return tuple;
+            }
             let items = schema.get("items");
             let item_type = match items {
                 Some(Value::Array(arr)) => arr
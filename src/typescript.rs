@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use oxc::allocator::Allocator;
+use oxc::codegen::Codegen;
+use oxc::diagnostics::OxcDiagnostic;
+use oxc::parser::Parser;
+use oxc::semantic::SemanticBuilder;
+use oxc::span::SourceType;
+use oxc::transformer::{TransformOptions, Transformer};
+
+use crate::sandbox::SandboxError;
+
+/// Strips TypeScript syntax so V8 can run the source. Runs on already wrapped code, so top-level
+/// `return`/`await` in user code are never seen at the top level here; `prefix_len` is the
+/// length of the wrapper ahead of user code on line 1, left out of reported columns.
+pub(crate) fn strip_types(
+    source: &str,
+    module: bool,
+    prefix_len: usize,
+) -> Result<String, SandboxError> {
+    let allocator = Allocator::default();
+    let source_type = SourceType::ts().with_module(module);
+    let parsed = Parser::new(&allocator, source, source_type).parse();
+    if let Some(error) = parsed.errors.first() {
+        return Err(transpile_error(error, source, prefix_len));
+    }
+    let mut program = parsed.program;
+
+    let scoping = SemanticBuilder::new()
+        .build(&program)
+        .semantic
+        .into_scoping();
+    let options = TransformOptions::default();
+    let transformed = Transformer::new(&allocator, Path::new("codemode.ts"), &options)
+        .build_with_scoping(scoping, &mut program);
+    if let Some(error) = transformed.errors.first() {
+        return Err(transpile_error(error, source, prefix_len));
+    }
+
+    Ok(Codegen::new().build(&program).code)
+}
+
+/// The diagnostic with the 1-based line and column of its first label appended.
+fn transpile_error(error: &OxcDiagnostic, source: &str, prefix_len: usize) -> SandboxError {
+    let Some(offset) = error
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.first())
+        .map(|label| label.offset().min(source.len()))
+    else {
+        return SandboxError::Transpile(error.to_string());
+    };
+    let before = source.get(..offset).unwrap_or_default();
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    let mut column = before[line_start..].chars().count() + 1;
+    if line == 1 {
+        column = column.saturating_sub(prefix_len).max(1);
+    }
+    SandboxError::Transpile(format!("{error} (line {line}, column {column})"))
+}
+
+/// Syntax errors in `source` read as a `.d.ts` declaration file.
+pub(crate) fn declaration_errors(source: &str) -> Vec<String> {
+    let allocator = Allocator::default();
+    let parsed = Parser::new(&allocator, source, SourceType::d_ts()).parse();
+    parsed.errors.iter().map(ToString::to_string).collect()
+}
@@ -0,0 +1,96 @@
+use std::sync::{Arc, OnceLock};
+
+use jsonschema::Validator;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::sandbox::SchemaValidation;
+use crate::schema::JsonSchema;
+use crate::tool::ToolCallError;
+
+/// Problems listed in one error message; the rest are counted.
+const MAX_REPORTED_ERRORS: usize = 5;
+
+/// A tool schema compiled on first use and shared by every dispatch of the tool.
+pub(crate) struct LazySchema {
+    schema: JsonSchema,
+    validator: OnceLock<Option<Validator>>,
+}
+
+impl LazySchema {
+    pub(crate) fn new(schema: JsonSchema) -> Self {
+        Self {
+            schema,
+            validator: OnceLock::new(),
+        }
+    }
+
+    /// Field-level problems with `value` as `/path: message`. A schema that does not compile is
+    /// logged once and accepts everything.
+    fn errors(&self, value: &Value) -> Vec<String> {
+        let validator = self.validator.get_or_init(|| {
+            if !self.schema.is_object() {
+                return None;
+            }
+            jsonschema::validator_for(&self.schema)
+                .inspect_err(|err| warn!(error = %err, "tool schema does not compile"))
+                .ok()
+        });
+        let Some(validator) = validator else {
+            return Vec::new();
+        };
+        validator
+            .iter_errors(value)
+            .map(|err| {
+                let path = err.instance_path.to_string();
+                if path.is_empty() {
+                    err.to_string()
+                } else {
+                    format!("{path}: {err}")
+                }
+            })
+            .collect()
+    }
+}
+
+/// A tool's schema with how mismatches are handled, carried by each injected tool.
+#[derive(Clone)]
+pub(crate) struct SchemaCheck {
+    pub(crate) schema: Arc<LazySchema>,
+    pub(crate) mode: SchemaValidation,
+}
+
+impl SchemaCheck {
+    /// `Some` unless `mode` is `Off`.
+    pub(crate) fn new(schema: &Arc<LazySchema>, mode: SchemaValidation) -> Option<Self> {
+        (mode != SchemaValidation::Off).then(|| Self {
+            schema: schema.clone(),
+            mode,
+        })
+    }
+
+    /// Checks `value`; `problem` leads the message, e.g. `'x' called with invalid arguments`.
+    pub(crate) fn check(&self, problem: &str, value: &Value) -> Result<(), ToolCallError> {
+        let errors = self.schema.errors(value);
+        if errors.is_empty() {
+            return Ok(());
+        }
+        let mut message = format!(
+            "{problem}: {}",
+            errors[..errors.len().min(MAX_REPORTED_ERRORS)].join("; ")
+        );
+        if errors.len() > MAX_REPORTED_ERRORS {
+            message.push_str(&format!(
+                " (and {} more)",
+                errors.len() - MAX_REPORTED_ERRORS
+            ));
+        }
+        match self.mode {
+            SchemaValidation::Reject => Err(ToolCallError::Message(message)),
+            SchemaValidation::Warn | SchemaValidation::Off => {
+                warn!("{message}");
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::tool::{AsyncToolCaller, ToolCallError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireFormat {
+    Json,
+    Cbor,
+    MessagePack,
+}
+
+impl WireFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            WireFormat::Json => "application/json",
+            WireFormat::Cbor => "application/cbor",
+            WireFormat::MessagePack => "application/msgpack",
+        }
+    }
+
+    pub fn encode(self, value: &Value) -> Result<Vec<u8>, ToolCallError> {
+        match self {
+            WireFormat::Json => serde_json::to_vec(value).map_err(encode_error),
+            #[cfg(feature = "cbor")]
+            WireFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(encode_error)?;
+                Ok(bytes)
+            }
+            #[cfg(feature = "msgpack")]
+            WireFormat::MessagePack => rmp_serde::to_vec_named(value).map_err(encode_error),
+            #[allow(unreachable_patterns)]
+            other => Err(unsupported(other)),
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> Result<Value, ToolCallError> {
+        match self {
+            WireFormat::Json => serde_json::from_slice(bytes).map_err(decode_error),
+            #[cfg(feature = "cbor")]
+            WireFormat::Cbor => ciborium::from_reader(bytes).map_err(decode_error),
+            #[cfg(feature = "msgpack")]
+            WireFormat::MessagePack => rmp_serde::from_slice(bytes).map_err(decode_error),
+            #[allow(unreachable_patterns)]
+            other => Err(unsupported(other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RawPayload {
+    pub content_type: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+/// A tool caller that exchanges pre-encoded bytes instead of `serde_json::Value`.
+#[async_trait]
+pub trait RawToolCaller: Send + Sync {
+    fn wire_format(&self) -> WireFormat;
+
+    async fn call_tool_raw(
+        &self,
+        name: &str,
+        args: RawPayload,
+    ) -> Result<RawPayload, ToolCallError>;
+}
+
+/// Adapts a [`RawToolCaller`] to the sandbox, encoding arguments and decoding results once.
+pub(crate) struct RawToolAdapter {
+    caller: Arc<dyn RawToolCaller>,
+}
+
+impl RawToolAdapter {
+    pub(crate) fn new(caller: Arc<dyn RawToolCaller>) -> Self {
+        Self { caller }
+    }
+}
+
+#[async_trait]
+impl AsyncToolCaller for RawToolAdapter {
+    async fn call_tool_async(&self, name: &str, args: Value) -> Result<Value, ToolCallError> {
+        let format = self.caller.wire_format();
+        let payload = RawPayload {
+            content_type: format.content_type(),
+            bytes: format.encode(&args)?,
+        };
+        let result = self.caller.call_tool_raw(name, payload).await?;
+        let result_format = match result.content_type {
+            "application/json" => WireFormat::Json,
+            "application/cbor" => WireFormat::Cbor,
+            "application/msgpack" => WireFormat::MessagePack,
+            _ => format,
+        };
+        result_format.decode(&result.bytes)
+    }
+}
+
+fn encode_error(err: impl std::fmt::Display) -> ToolCallError {
+    ToolCallError::Message(format!("failed to encode tool arguments: {err}"))
+}
+
+fn decode_error(err: impl std::fmt::Display) -> ToolCallError {
+    ToolCallError::Message(format!("failed to decode tool result: {err}"))
+}
+
+fn unsupported(format: WireFormat) -> ToolCallError {
+    ToolCallError::Message(format!(
+        "wire format {} requires enabling its crate feature",
+        format.content_type()
+    ))
+}
@@ -0,0 +1,228 @@
+use serde_json::{Map, Value};
+
+use crate::schema::JsonSchema;
+use crate::tool::Tool;
+use crate::ts_interface::{ToolInterfaceGenerator, resolve_refs};
+
+/// An ES module exporting `inputSchemas`, a Zod validator per tool input keyed by access path.
+/// Register it next to a Zod build with `SandboxConfig::register_module` (as `"zod"`), and
+/// scripts or helper modules can `inputSchemas[path].safeParse(args)` before calling a tool.
+pub fn zod_module(tools: &[&Tool], generator: &ToolInterfaceGenerator) -> String {
+    let mut entries = tools
+        .iter()
+        .map(|tool| {
+            let path = generator.tool_access_path(tool);
+            let schema = resolve_refs(&tool.inputs, &path.replace('.', "_"));
+            (path, schema_to_zod(&schema))
+        })
+        .collect::<Vec<(String, String)>>();
+    entries.sort();
+    let entries = entries
+        .into_iter()
+        .map(|(path, zod)| format!("  {}: {zod},", Value::String(path)))
+        .collect::<Vec<String>>()
+        .join("\n");
+    format!("import {{ z }} from \"zod\";\n\nexport const inputSchemas = {{\n{entries}\n}};\n")
+}
+
+/// The Zod expression validating values of `schema`. Keywords Zod has no counterpart for are
+/// ignored, and recursive references accept anything.
+pub fn schema_to_zod(schema: &JsonSchema) -> String {
+    let Value::Object(map) = schema else {
+        // `true` and absent schemas accept anything; `false` accepts nothing.
+        return match schema {
+            Value::Bool(false) => "z.never()".to_string(),
+            _ => "z.any()".to_string(),
+        };
+    };
+    if let Some(value) = map.get("const") {
+        return format!("z.literal({value})");
+    }
+    if let Some(Value::Array(values)) = map.get("enum") {
+        return enum_to_zod(values);
+    }
+    let mut parts = Vec::new();
+    for key in ["anyOf", "oneOf"] {
+        if let Some(Value::Array(variants)) = map.get(key) {
+            parts.push(union(variants.iter().map(schema_to_zod).collect()));
+        }
+    }
+    if let Some(Value::Array(members)) = map.get("allOf") {
+        parts.extend(members.iter().map(schema_to_zod));
+    }
+    if map.contains_key("type") {
+        parts.insert(0, typed_to_zod(map));
+    }
+    parts
+        .into_iter()
+        .reduce(|left, right| format!("z.intersection({left}, {right})"))
+        .unwrap_or_else(|| "z.any()".to_string())
+}
+
+fn enum_to_zod(values: &[Value]) -> String {
+    if !values.is_empty() && values.iter().all(Value::is_string) {
+        let values = values.iter().map(Value::to_string).collect::<Vec<String>>();
+        return format!("z.enum([{}])", values.join(", "));
+    }
+    union(
+        values
+            .iter()
+            .map(|value| format!("z.literal({value})"))
+            .collect(),
+    )
+}
+
+fn union(mut members: Vec<String>) -> String {
+    match members.len() {
+        0 => "z.never()".to_string(),
+        1 => members.remove(0),
+        _ => format!("z.union([{}])", members.join(", ")),
+    }
+}
+
+fn typed_to_zod(map: &Map<String, Value>) -> String {
+    match map.get("type") {
+        Some(Value::String(schema_type)) => single_type_to_zod(schema_type, map),
+        Some(Value::Array(types)) => union(
+            types
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|schema_type| single_type_to_zod(schema_type, map))
+                .collect(),
+        ),
+        _ => "z.any()".to_string(),
+    }
+}
+
+fn single_type_to_zod(schema_type: &str, map: &Map<String, Value>) -> String {
+    let mut zod = match schema_type {
+        "string" => string_to_zod(map),
+        "integer" => format!("z.number().int(){}", number_checks(map)),
+        "number" => format!("z.number(){}", number_checks(map)),
+        "boolean" => "z.boolean()".to_string(),
+        "null" => "z.null()".to_string(),
+        "array" => array_to_zod(map),
+        "object" => object_to_zod(map),
+        _ => "z.any()".to_string(),
+    };
+    if schema_type == "array" {
+        zod.push_str(&size_checks(map, "minItems", "maxItems"));
+    }
+    zod
+}
+
+fn string_to_zod(map: &Map<String, Value>) -> String {
+    let mut zod = "z.string()".to_string();
+    match map.get("format").and_then(Value::as_str) {
+        Some("email") => zod.push_str(".email()"),
+        Some("uri") | Some("url") => zod.push_str(".url()"),
+        Some("uuid") => zod.push_str(".uuid()"),
+        Some("date-time") => zod.push_str(".datetime()"),
+        _ => {}
+    }
+    zod.push_str(&size_checks(map, "minLength", "maxLength"));
+    if let Some(pattern) = map.get("pattern").and_then(Value::as_str) {
+        zod.push_str(&format!(
+            ".regex(new RegExp({}))",
+            Value::String(pattern.into())
+        ));
+    }
+    zod
+}
+
+fn number_checks(map: &Map<String, Value>) -> String {
+    [
+        ("minimum", "gte"),
+        ("maximum", "lte"),
+        ("exclusiveMinimum", "gt"),
+        ("exclusiveMaximum", "lt"),
+    ]
+    .iter()
+    .filter_map(|(keyword, method)| {
+        let bound = map.get(*keyword).filter(|bound| bound.is_number())?;
+        Some(format!(".{method}({bound})"))
+    })
+    .collect()
+}
+
+fn size_checks(map: &Map<String, Value>, min: &str, max: &str) -> String {
+    let mut checks = String::new();
+    if let Some(min) = map.get(min).and_then(Value::as_u64) {
+        checks.push_str(&format!(".min({min})"));
+    }
+    if let Some(max) = map.get(max).and_then(Value::as_u64) {
+        checks.push_str(&format!(".max({max})"));
+    }
+    checks
+}
+
+fn array_to_zod(map: &Map<String, Value>) -> String {
+    let items = map.get("items");
+    if let Some(Value::Array(prefix)) = map.get("prefixItems") {
+        let elements = prefix.iter().map(schema_to_zod).collect::<Vec<String>>();
+        let tuple = format!("z.tuple([{}])", elements.join(", "));
+        return match items {
+            Some(Value::Bool(false)) => tuple,
+            Some(rest) => format!("{tuple}.rest({})", schema_to_zod(rest)),
+            None => format!("{tuple}.rest(z.any())"),
+        };
+    }
+    let items = items.map(schema_to_zod);
+    format!("z.array({})", items.as_deref().unwrap_or("z.any()"))
+}
+
+fn object_to_zod(map: &Map<String, Value>) -> String {
+    let additional = map.get("additionalProperties");
+    let Some(properties) = map.get("properties").and_then(Value::as_object) else {
+        let values = match additional {
+            Some(schema @ Value::Object(_)) => schema_to_zod(schema),
+            _ => "z.any()".to_string(),
+        };
+        return format!("z.record(z.string(), {values})");
+    };
+    let required = map
+        .get("required")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    let fields = properties
+        .iter()
+        .map(|(name, schema)| {
+            let optional = if required.iter().any(|req| req.as_str() == Some(name)) {
+                ""
+            } else {
+                ".optional()"
+            };
+            format!(
+                "{}: {}{optional}",
+                property_key(name),
+                schema_to_zod(schema)
+            )
+        })
+        .collect::<Vec<String>>();
+    let object = if fields.is_empty() {
+        "z.object({})".to_string()
+    } else {
+        format!("z.object({{ {} }})", fields.join(", "))
+    };
+    match additional {
+        Some(Value::Bool(false)) => format!("{object}.strict()"),
+        Some(schema @ Value::Object(_)) => format!("{object}.catchall({})", schema_to_zod(schema)),
+        _ => object,
+    }
+}
+
+fn property_key(name: &str) -> String {
+    let is_identifier = name
+        .chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_' || first == '$')
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '$');
+    if is_identifier {
+        name.to_string()
+    } else {
+        Value::String(name.to_string()).to_string()
+    }
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "boa")]
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use codemode_rs::prelude::*;
+use codemode_rs::sandbox::SandboxError;
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+
+mod common;
+use common::{Echo, Failing, Slow, tool};
+
+fn boa_client(runtime: &tokio::runtime::Runtime, backend: BoaBackend) -> CodeModeClient {
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    client.register_sync_tool(tool("util.echo"), "echo".to_string(), Arc::new(Echo));
+    client.register_sync_tool(tool("util.down"), "down".to_string(), Arc::new(Failing));
+    client.register_async_tool(
+        tool("util.slow"),
+        "slow".to_string(),
+        Arc::new(Slow(Duration::from_secs(5))),
+    );
+    client.set_backend(Arc::new(backend));
+    client
+}
+
+#[test]
+fn boa_backend_runs_scripts_against_registered_tools() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = boa_client(&runtime, BoaBackend::new(runtime.handle().clone()));
+
+    let echoed = runtime
+        .block_on(client.call_tool_chain(
+            "const first = await util.echo({ n: 1 });
+             const second = await util.echo({ n: first.n + 1 });
+             return [first.n, second.n];",
+        ))
+        .unwrap();
+    assert_eq!(echoed.result, json!([1, 2]));
+
+    let failed = runtime.block_on(client.call_tool_chain("await util.down({});"));
+    assert!(
+        matches!(failed, Err(SandboxError::Script { message, .. }) if message.contains("is down"))
+    );
+}
+
+#[test]
+fn boa_backend_lets_scripts_catch_tool_errors() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = boa_client(&runtime, BoaBackend::new(runtime.handle().clone()));
+
+    let result = runtime
+        .block_on(client.call_tool_chain(
+            "try { await util.down({}); return 'up'; } catch (e) { return e.message; }",
+        ))
+        .unwrap();
+
+    assert!(
+        result.result.as_str().unwrap().contains("is down"),
+        "{}",
+        result.result
+    );
+}
+
+#[test]
+fn boa_backend_times_out_slow_tool_calls() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let backend = BoaBackend::new(runtime.handle().clone()).with_timeout_ms(50);
+    let client = boa_client(&runtime, backend);
+
+    let started = Instant::now();
+    let result = runtime.block_on(client.call_tool_chain("return await util.slow({});"));
+
+    assert!(
+        matches!(result, Err(SandboxError::Timeout(50))),
+        "{result:?}"
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn boa_backend_stops_when_cancelled() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = boa_client(&runtime, BoaBackend::new(runtime.handle().clone()));
+    let cancel = CancellationToken::new();
+    let trigger = cancel.clone();
+    runtime.spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        trigger.cancel();
+    });
+
+    let started = Instant::now();
+    let result =
+        runtime.block_on(client.call_tool_chain_with_cancel("return await util.slow({});", cancel));
+
+    assert!(matches!(result, Err(SandboxError::Cancelled)), "{result:?}");
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn boa_backend_bounds_runaway_loops() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let backend = BoaBackend::new(runtime.handle().clone()).with_loop_iteration_limit(1000);
+    let client = boa_client(&runtime, backend);
+
+    let result = runtime.block_on(client.call_tool_chain("while (true) {}"));
+
+    assert!(
+        matches!(result, Err(SandboxError::BudgetExceeded(1000))),
+        "{result:?}"
+    );
+}
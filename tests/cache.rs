@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use codemode_rs::prelude::*;
+use serde_json::json;
+
+#[test]
+fn in_memory_cache_evicts_least_recently_used() {
+    let cache = InMemoryToolCache::new(2);
+    let ttl = Duration::from_secs(60);
+    let a = CacheKey::new("a", &json!({}));
+    let b = CacheKey::new("b", &json!({}));
+    let c = CacheKey::new("c", &json!({}));
+    cache.put(a.clone(), json!(1), ttl);
+    cache.put(b.clone(), json!(2), ttl);
+    assert_eq!(cache.get(&a), Some(json!(1)));
+    cache.put(c.clone(), json!(3), ttl);
+
+    assert_eq!(cache.get(&a), Some(json!(1)));
+    assert_eq!(cache.get(&b), None);
+    assert_eq!(cache.get(&c), Some(json!(3)));
+}
+
+#[test]
+fn in_memory_cache_expires_entries() {
+    let cache = InMemoryToolCache::new(4);
+    let key = CacheKey::new("weather", &json!({ "city": "Oslo" }));
+    cache.put(key.clone(), json!("rain"), Duration::ZERO);
+    assert_eq!(cache.get(&key), None);
+}
@@ -0,0 +1,649 @@
+#![cfg(feature = "v8")]
+
+use std::sync::Arc;
+
+use codemode_rs::prelude::*;
+use codemode_rs::sandbox::SandboxError;
+use futures::StreamExt;
+use serde_json::{Value, json};
+use tokio_util::sync::CancellationToken;
+
+mod common;
+use common::{Echo, Failing, tool};
+
+#[test]
+fn interfaces_are_ordered_by_tool_name() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    for name in ["zeta", "alpha", "mid"] {
+        client.register_sync_tool(tool(name), name.to_string(), Arc::new(Echo));
+    }
+
+    let names = client
+        .get_tools()
+        .iter()
+        .map(|tool| tool.name.clone())
+        .collect::<Vec<String>>();
+    assert_eq!(names, ["alpha", "mid", "zeta"]);
+
+    let interfaces = client.get_all_tools_typescript_interfaces();
+    let alpha = interfaces.find("alphaInput").unwrap();
+    let zeta = interfaces.find("zetaInput").unwrap();
+    assert!(alpha < zeta);
+    assert_eq!(interfaces, client.get_all_tools_typescript_interfaces());
+}
+
+#[test]
+fn snapshots_detect_drift_from_the_live_registry() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    client.register_sync_tool(tool("alpha"), "alpha".to_string(), Arc::new(Echo));
+    client.register_sync_tool(tool("beta"), "beta".to_string(), Arc::new(Echo));
+
+    let snapshot = client.snapshot_interfaces();
+    assert_eq!(
+        snapshot.render(),
+        client.get_all_tools_typescript_interfaces()
+    );
+    assert!(client.interface_drift(&snapshot).is_empty());
+
+    let mut changed = tool("alpha");
+    changed.description = "alpha tool, revised".to_string();
+    client.register_sync_tool(changed, "alpha".to_string(), Arc::new(Echo));
+    client.register_sync_tool(tool("gamma"), "gamma".to_string(), Arc::new(Echo));
+
+    let drift = client.interface_drift(&snapshot);
+    assert_eq!(drift.changed, ["alpha"]);
+    assert_eq!(drift.added, ["gamma"]);
+    assert!(drift.removed.is_empty());
+    assert_ne!(client.snapshot_interfaces().hash, snapshot.hash);
+}
+
+#[test]
+fn interfaces_can_be_filtered_by_tag_and_prefix() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    let mut flights = tool("flights");
+    flights.tags = vec!["travel".to_string()];
+    client.register_sync_tool(flights, "flights".to_string(), Arc::new(Echo));
+    for name in ["finance.quotes", "finance.fx", "weather"] {
+        client.register_sync_tool(tool(name), name.to_string(), Arc::new(Echo));
+    }
+
+    let travel =
+        client.get_tools_typescript_interfaces_filtered(&ToolFilter::default().tag("travel"));
+    assert!(travel.contains("flightsInput"));
+    assert!(!travel.contains("weatherInput"));
+
+    let finance =
+        client.get_tools_typescript_interfaces_filtered(&ToolFilter::default().prefix("finance."));
+    assert!(finance.contains("function quotes("));
+    assert!(finance.contains("function fx("));
+    assert!(!finance.contains("flightsInput"));
+
+    assert_eq!(
+        client.get_tools_typescript_interfaces_filtered(&ToolFilter::default()),
+        client.get_all_tools_typescript_interfaces()
+    );
+}
+
+#[test]
+fn selectors_narrow_interfaces_to_the_query() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    let mut hotels = tool("travel.book_hotel");
+    hotels.description = "Reserve a hotel room".to_string();
+    client.register_sync_tool(hotels, "book_hotel".to_string(), Arc::new(Echo));
+    for name in ["travel.book_flight", "finance.quotes"] {
+        client.register_sync_tool(tool(name), name.to_string(), Arc::new(Echo));
+    }
+    assert_eq!(client.select_tools("anything").len(), 3);
+
+    client.set_tool_selector(Arc::new(KeywordSelector), 1);
+    let selected = client
+        .select_tools("Book a hotel in Lisbon")
+        .iter()
+        .map(|tool| tool.name.clone())
+        .collect::<Vec<String>>();
+    assert_eq!(selected, ["travel.book_hotel"]);
+
+    let interfaces = client.get_tools_typescript_interfaces_for_query("Book a hotel in Lisbon");
+    assert!(interfaces.contains("function book_hotel("));
+    assert!(!interfaces.contains("function book_flight("));
+    assert!(client.select_tools("weather").is_empty());
+}
+
+#[test]
+fn tools_can_be_unregistered() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    for name in [
+        "github.get_issue",
+        "github.list_issues",
+        "get-data",
+        "get_data",
+    ] {
+        client.register_sync_tool(tool(name), name.to_string(), Arc::new(Echo));
+    }
+    assert!(
+        client
+            .get_all_tools_typescript_interfaces()
+            .contains("function get_data_2(")
+    );
+
+    assert_eq!(
+        client.unregister_prefix("github."),
+        ["github.get_issue", "github.list_issues"]
+    );
+    assert!(client.get_tool("github.get_issue").is_none());
+    assert!(
+        !client
+            .get_all_tools_typescript_interfaces()
+            .contains("get_issueInput")
+    );
+
+    assert_eq!(client.unregister_tool("get-data").unwrap().name, "get-data");
+    assert!(client.unregister_tool("get-data").is_none());
+    client.unregister_tool("get_data");
+    client.register_sync_tool(tool("get_data"), "get_data".to_string(), Arc::new(Echo));
+    assert!(
+        client
+            .get_all_tools_typescript_interfaces()
+            .contains("declare function get_data(")
+    );
+
+    client.clear_tools();
+    assert!(client.get_tools().is_empty());
+}
+
+#[test]
+fn a_shared_client_registers_tools_from_many_threads() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = Arc::new(CodeModeClient::new(config));
+
+    let workers = (0..4)
+        .map(|worker| {
+            let client = client.clone();
+            std::thread::spawn(move || {
+                for index in 0..8 {
+                    let name = format!("worker{worker}.tool{index}");
+                    client.register_sync_tool(tool(&name), name.clone(), Arc::new(Echo));
+                    client.get_all_tools_typescript_interfaces();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    assert_eq!(client.get_tools().len(), 32);
+    assert_eq!(client.unregister_prefix("worker0.").len(), 8);
+    assert_eq!(client.get_tools().len(), 24);
+}
+
+#[test]
+fn tool_subsets_reject_unknown_and_excluded_tools() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    for name in ["github.get_issue", "github.delete_repo"] {
+        client.register_sync_tool(tool(name), name.to_string(), Arc::new(Echo));
+    }
+
+    let unknown = runtime.block_on(
+        client.call_tool_chain_with_tools("return 1;", &["github.get_issue", "slack.post"]),
+    );
+    assert!(matches!(unknown, Err(SandboxError::Tool(message)) if message.contains("slack.post")));
+
+    let excluded = runtime.block_on(client.call_tool_chain_with_tools(
+        "const issue = github.get_issue({ number: 1 });\nreturn github.delete_repo ({});",
+        &["github.get_issue"],
+    ));
+    assert!(
+        matches!(excluded, Err(SandboxError::ToolNotAllowed(name)) if name == "github.delete_repo")
+    );
+}
+
+#[test]
+fn direct_tool_calls_share_the_registry_and_policy() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    for name in ["github.get_issue", "github.delete_repo"] {
+        client.register_sync_tool(tool(name), name.to_string(), Arc::new(Echo));
+    }
+    client.set_tool_policy(Arc::new(RulePolicy::new(PolicyDecision::Allow).rule(
+        PolicyTarget::Name("github.delete_repo".to_string()),
+        PolicyDecision::Deny("read-only session".to_string()),
+    )));
+
+    let issue = runtime
+        .block_on(client.call_tool("github.get_issue", json!({ "number": 1 })))
+        .unwrap();
+    assert_eq!(issue, json!({ "number": 1 }));
+
+    let denied = runtime.block_on(client.call_tool("github.delete_repo", json!({})));
+    assert!(
+        matches!(denied, Err(ToolCallError::Message(message)) if message.contains("read-only session"))
+    );
+
+    let unknown = runtime.block_on(client.call_tool("slack.post", json!({})));
+    assert!(
+        matches!(unknown, Err(ToolCallError::Message(message)) if message.contains("unknown tool"))
+    );
+
+    let malformed = runtime.block_on(client.call_tool("github.get_issue", json!([1])));
+    assert!(malformed.is_err());
+}
+
+struct Tenant(&'static str);
+
+impl ToolInterceptor for Tenant {
+    fn before(&self, _name: &str, mut args: Value) -> Value {
+        args["tenant"] = json!(self.0);
+        args
+    }
+}
+
+#[test]
+fn shared_result_caches_are_keyed_after_interceptors() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let cache: Arc<dyn ToolResultCache> = Arc::new(InMemoryToolCache::new(16));
+    let client = |tenant| {
+        let config = CodeModeClientConfigBuilder::default()
+            .sandbox(SandboxConfig::new(runtime.handle().clone()))
+            .build()
+            .unwrap();
+        let client = CodeModeClient::new(config);
+        let profile = tool("users.profile").with_read_only(true);
+        client.register_sync_tool(profile, "profile".to_string(), Arc::new(Echo));
+        client.add_interceptor(Arc::new(Tenant(tenant)));
+        client.set_result_cache(cache.clone(), std::time::Duration::from_secs(60));
+        client
+    };
+    let (alice, bob) = (client("alice"), client("bob"));
+    let profile = |client: &CodeModeClient| {
+        runtime
+            .block_on(client.call_tool_chain("return users.profile({ id: 1 });"))
+            .unwrap()
+            .result
+    };
+
+    assert_eq!(profile(&alice), json!({ "id": 1, "tenant": "alice" }));
+    assert_eq!(profile(&bob), json!({ "id": 1, "tenant": "bob" }));
+    assert_eq!(profile(&alice), json!({ "id": 1, "tenant": "alice" }));
+}
+
+#[test]
+fn arguments_are_validated_against_the_input_schema() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut sandbox = SandboxConfig::new(runtime.handle().clone());
+    sandbox.input_validation = SchemaValidation::Reject;
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(sandbox)
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    let mut get_issue = tool("github.get_issue");
+    get_issue.inputs = json!({
+        "type": "object",
+        "properties": { "number": { "type": "integer" } },
+        "required": ["number"]
+    });
+    client.register_sync_tool(get_issue, "get_issue".to_string(), Arc::new(Echo));
+
+    let Err(ToolCallError::Message(message)) =
+        runtime.block_on(client.call_tool("github.get_issue", json!({ "number": "7" })))
+    else {
+        panic!("expected a validation error");
+    };
+    assert!(message.contains("called with invalid arguments"));
+    assert!(message.contains("/number"));
+
+    let missing = runtime.block_on(client.call_tool("github.get_issue", json!({})));
+    assert!(matches!(missing, Err(ToolCallError::Message(message)) if message.contains("number")));
+
+    let valid = runtime.block_on(client.call_tool("github.get_issue", json!({ "number": 7 })));
+    assert_eq!(valid.unwrap(), json!({ "number": 7 }));
+}
+
+#[test]
+fn results_are_checked_against_the_output_schema_when_enabled() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut sandbox = SandboxConfig::new(runtime.handle().clone());
+    sandbox.output_validation = SchemaValidation::Reject;
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(sandbox)
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    let mut get_issue = tool("github.get_issue");
+    get_issue.outputs = json!({
+        "type": "object",
+        "properties": { "title": { "type": "string" } },
+        "required": ["title"]
+    });
+    client.register_sync_tool(get_issue, "get_issue".to_string(), Arc::new(Echo));
+
+    let Err(ToolCallError::Message(message)) =
+        runtime.block_on(client.call_tool("github.get_issue", json!({ "title": 7 })))
+    else {
+        panic!("expected an output schema error");
+    };
+    assert!(message.contains("does not match its output schema"));
+    assert!(message.contains("/title"));
+
+    let valid = runtime.block_on(client.call_tool("github.get_issue", json!({ "title": "Bug" })));
+    assert_eq!(valid.unwrap(), json!({ "title": "Bug" }));
+}
+
+#[test]
+fn fallbacks_are_subject_to_their_own_policy() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    client.register_sync_tool(
+        tool("search.primary"),
+        "primary".to_string(),
+        Arc::new(Failing),
+    );
+    client.register_sync_tool(tool("search.backup"), "backup".to_string(), Arc::new(Echo));
+    client
+        .register_fallback("search.primary", "search.backup", |args| args)
+        .unwrap();
+
+    let recovered = runtime.block_on(client.call_tool("search.primary", json!({ "q": "x" })));
+    assert_eq!(recovered.unwrap(), json!({ "q": "x" }));
+
+    client.set_tool_policy(Arc::new(RulePolicy::new(PolicyDecision::Allow).rule(
+        PolicyTarget::Name("search.backup".to_string()),
+        PolicyDecision::Deny("backup disabled".to_string()),
+    )));
+    let denied = runtime.block_on(client.call_tool("search.primary", json!({ "q": "x" })));
+    assert!(
+        matches!(denied, Err(ToolCallError::Message(message)) if message.contains("backup disabled"))
+    );
+}
+
+#[test]
+fn fallbacks_are_validated_against_their_own_schemas() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut sandbox = SandboxConfig::new(runtime.handle().clone());
+    sandbox.input_validation = SchemaValidation::Reject;
+    sandbox.output_validation = SchemaValidation::Reject;
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(sandbox)
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    let mut primary = tool("search.primary");
+    primary.outputs = json!({ "type": "object", "required": ["hits"] });
+    let mut backup = tool("search.backup");
+    backup.inputs = json!({
+        "type": "object",
+        "properties": { "query": { "type": "string" } },
+        "required": ["query"]
+    });
+    backup.outputs = json!({ "type": "object", "required": ["query"] });
+    client.register_sync_tool(primary, "primary".to_string(), Arc::new(Failing));
+    client.register_sync_tool(backup, "backup".to_string(), Arc::new(Echo));
+    client
+        .register_fallback(
+            "search.primary",
+            "search.backup",
+            |args| json!({ "query": args["q"] }),
+        )
+        .unwrap();
+
+    let recovered = runtime.block_on(client.call_tool("search.primary", json!({ "q": "x" })));
+    assert_eq!(recovered.unwrap(), json!({ "query": "x" }));
+
+    let Err(ToolCallError::Message(message)) =
+        runtime.block_on(client.call_tool("search.primary", json!({ "q": 7 })))
+    else {
+        panic!("expected the adapted arguments to be rejected");
+    };
+    assert!(message.contains("'search.backup' called with invalid arguments"));
+}
+
+/// Calls the tool named by the script with no arguments and returns its result.
+struct CallingBackend(tokio::runtime::Handle);
+
+impl ScriptBackend for CallingBackend {
+    fn execute(
+        &self,
+        code: &str,
+        bindings: &ToolBindings<'_>,
+        _cancel: &CancellationToken,
+    ) -> Result<ExecutionResult, SandboxError> {
+        let result = self
+            .0
+            .block_on(bindings.call_tool(code, json!({})))
+            .map_err(|err| SandboxError::Tool(err.to_string()))?;
+        Ok(ExecutionResult {
+            result,
+            ..Default::default()
+        })
+    }
+}
+
+#[test]
+fn custom_backends_dispatch_through_the_policy() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    for name in ["github.get_issue", "github.delete_repo"] {
+        client.register_sync_tool(tool(name), name.to_string(), Arc::new(Echo));
+    }
+    client.set_backend(Arc::new(CallingBackend(runtime.handle().clone())));
+    client.set_tool_policy(Arc::new(RulePolicy::new(PolicyDecision::Allow).rule(
+        PolicyTarget::Name("github.delete_repo".to_string()),
+        PolicyDecision::Deny("read-only session".to_string()),
+    )));
+
+    let allowed = runtime
+        .block_on(client.call_tool_chain("github.get_issue"))
+        .unwrap();
+    assert_eq!(allowed.result, json!({}));
+
+    let denied = runtime.block_on(client.call_tool_chain("github.delete_repo"));
+    assert!(
+        matches!(denied, Err(SandboxError::Tool(message)) if message.contains("read-only session"))
+    );
+}
+
+#[test]
+fn pool_workers_reuse_contexts_without_leaking_globals() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut sandbox = SandboxConfig::new(runtime.handle().clone());
+    sandbox.pool_size = Some(1);
+    sandbox.harden = true;
+    sandbox.preamble = Some("const runs = { count: 0 };".to_string());
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(sandbox)
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    client.register_sync_tool(tool("util.echo"), "echo".to_string(), Arc::new(Echo));
+    let run = |code: &str| {
+        runtime
+            .block_on(client.call_tool_chain(code))
+            .unwrap()
+            .result
+    };
+
+    assert_eq!(run("globalThis.leaked = 1; return ++runs.count;"), json!(1));
+    assert_eq!(
+        run("return [typeof leaked, ++runs.count];"),
+        json!(["undefined", 2])
+    );
+    assert_eq!(
+        run("return util.echo({ ok: true });"),
+        json!({ "ok": true })
+    );
+
+    client.register_sync_tool(tool("util.other"), "other".to_string(), Arc::new(Echo));
+    assert_eq!(run("return ++runs.count;"), json!(1));
+}
+
+#[test]
+fn pool_workers_give_each_job_a_fresh_context_without_harden() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut sandbox = SandboxConfig::new(runtime.handle().clone());
+    sandbox.pool_size = Some(1);
+    sandbox.preamble = Some("const runs = { count: 0 };".to_string());
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(sandbox)
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    let run = |code: &str| {
+        runtime
+            .block_on(client.call_tool_chain(code))
+            .unwrap()
+            .result
+    };
+
+    assert_eq!(
+        run("Array.prototype.tenant = 'a'; return ++runs.count;"),
+        json!(1)
+    );
+    assert_eq!(
+        run("return [typeof [].tenant, ++runs.count];"),
+        json!(["undefined", 1])
+    );
+}
+
+#[test]
+fn pool_workers_survive_failed_executions() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut sandbox = SandboxConfig::new(runtime.handle().clone());
+    sandbox.pool_size = Some(1);
+    sandbox.timeout_ms = 100;
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(sandbox)
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+
+    let timed_out = runtime.block_on(client.call_tool_chain("while (true) {}"));
+    assert!(matches!(timed_out, Err(SandboxError::Timeout(100))));
+    let thrown = runtime.block_on(client.call_tool_chain("throw new Error('boom');"));
+    assert!(thrown.is_err());
+    let result = runtime.block_on(client.call_tool_chain("return 'still here';"));
+    assert_eq!(result.unwrap().result, json!("still here"));
+}
+
+#[test]
+fn streams_run_on_the_pool() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut sandbox = SandboxConfig::new(runtime.handle().clone());
+    sandbox.pool_size = Some(1);
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(sandbox)
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+
+    let events = runtime.block_on(
+        client
+            .call_tool_chain_stream("codemode.emit(1); codemode.emit(2); return 3;")
+            .collect::<Vec<ChainEvent>>(),
+    );
+
+    let [
+        ChainEvent::Emitted(first),
+        ChainEvent::Emitted(second),
+        ChainEvent::Finished(Ok(result)),
+    ] = events.as_slice()
+    else {
+        panic!("unexpected events: {events:?}");
+    };
+    assert_eq!((first, second), (&json!(1), &json!(2)));
+    assert_eq!(result.result, json!(3));
+}
+
+#[test]
+fn streams_run_on_the_custom_backend() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    client.register_sync_tool(
+        tool("github.get_issue"),
+        "get_issue".to_string(),
+        Arc::new(Echo),
+    );
+    client.set_backend(Arc::new(CallingBackend(runtime.handle().clone())));
+
+    let events = runtime.block_on(
+        client
+            .call_tool_chain_stream("github.get_issue")
+            .collect::<Vec<ChainEvent>>(),
+    );
+
+    let [ChainEvent::Finished(Ok(result))] = events.as_slice() else {
+        panic!("unexpected events: {events:?}");
+    };
+    assert_eq!(result.result, json!({}));
+}
+
+#[test]
+fn chains_run_on_a_current_thread_runtime() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    client.register_sync_tool(tool("util.echo"), "echo".to_string(), Arc::new(Echo));
+
+    for round in 0..3 {
+        let result = runtime
+            .block_on(client.call_tool_chain(&format!("return util.echo({{ round: {round} }});")))
+            .unwrap();
+        assert_eq!(result.result, json!({ "round": round }));
+    }
+}
@@ -0,0 +1,47 @@
+//! Fixtures shared by the integration tests; each test crate uses a subset of them.
+#![allow(dead_code)]
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use codemode_rs::prelude::*;
+use serde_json::Value;
+
+pub struct Echo;
+
+impl SyncToolCaller for Echo {
+    fn call_tool_sync(&self, _name: &str, args: Value) -> Result<Value, ToolCallError> {
+        Ok(args)
+    }
+}
+
+pub struct Failing;
+
+impl SyncToolCaller for Failing {
+    fn call_tool_sync(&self, name: &str, _args: Value) -> Result<Value, ToolCallError> {
+        Err(ToolCallError::Message(format!("{name} is down")))
+    }
+}
+
+/// Echoes its arguments after sleeping for the given time.
+pub struct Slow(pub Duration);
+
+#[async_trait]
+impl AsyncToolCaller for Slow {
+    async fn call_tool_async(&self, _name: &str, args: Value) -> Result<Value, ToolCallError> {
+        tokio::time::sleep(self.0).await;
+        Ok(args)
+    }
+}
+
+pub fn tool(name: &str) -> Tool {
+    Tool::new(name, format!("{name} tool"))
+}
+
+pub fn sandbox(
+    runtime: &tokio::runtime::Runtime,
+    configure: impl FnOnce(SandboxConfigBuilder) -> SandboxConfigBuilder,
+) -> Sandbox {
+    let builder = SandboxConfigBuilder::default().runtime_handle(runtime.handle().clone());
+    Sandbox::new(configure(builder).build().unwrap())
+}
@@ -0,0 +1,51 @@
+use codemode_rs::prelude::*;
+use serde_json::{Value, json};
+
+/// Checks every `default` in `schema` against the matching value in `defaults`, descending into
+/// nested object schemas whose default is an object.
+fn assert_schema_defaults(schema: &Value, defaults: &Value, path: &str) {
+    let properties = schema["properties"].as_object().unwrap();
+    for key in defaults.as_object().unwrap().keys() {
+        assert!(
+            properties.contains_key(key),
+            "{path}{key} is serialized but missing from the schema"
+        );
+    }
+    for (key, property) in properties {
+        let value = &defaults[key];
+        if let Some(default) = property.get("default") {
+            assert_eq!(default, value, "schema default for {path}{key}");
+        }
+        if property.get("properties").is_some() && value.is_object() {
+            assert_schema_defaults(property, value, &format!("{path}{key}."));
+        }
+    }
+}
+
+#[test]
+fn sandbox_config_defaults_agree_across_serde_builder_and_schema() {
+    let defaults = serde_json::to_value(SandboxConfig::default()).unwrap();
+
+    let deserialized: SandboxConfig = serde_json::from_value(json!({})).unwrap();
+    assert_eq!(serde_json::to_value(deserialized).unwrap(), defaults);
+
+    let built = SandboxConfigBuilder::default().build().unwrap();
+    assert_eq!(serde_json::to_value(built).unwrap(), defaults);
+
+    let round_tripped: SandboxConfig = serde_json::from_value(defaults.clone()).unwrap();
+    assert_eq!(serde_json::to_value(round_tripped).unwrap(), defaults);
+
+    assert_schema_defaults(&SandboxConfig::schema(), &defaults, "");
+}
+
+#[test]
+fn sandbox_config_debug_lists_every_setting() {
+    let debug = format!("{:?}", SandboxConfig::default());
+    let defaults = serde_json::to_value(SandboxConfig::default()).unwrap();
+    for key in defaults.as_object().unwrap().keys() {
+        assert!(
+            debug.contains(&format!("{key}: ")),
+            "Debug output lacks {key}"
+        );
+    }
+}
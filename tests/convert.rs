@@ -0,0 +1,93 @@
+#![cfg(feature = "v8")]
+
+use std::sync::Arc;
+
+use serde_json::json;
+
+mod common;
+use common::{Echo, sandbox, tool};
+
+#[test]
+fn bigints_become_integers_or_decimal_strings() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config);
+
+    let result = sandbox
+        .execute("return [42n, -(2n ** 63n), 2n ** 63n, 2n ** 64n, -(2n ** 63n) - 1n];")
+        .unwrap();
+
+    assert_eq!(
+        result.result,
+        json!([
+            42,
+            i64::MIN,
+            9_223_372_036_854_775_808u64,
+            "18446744073709551616",
+            "-9223372036854775809"
+        ])
+    );
+}
+
+#[test]
+fn large_integers_round_trip_through_tools_as_bigints() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config);
+    sandbox.register_sync_tool(tool("util.echo"), "echo".to_string(), Arc::new(Echo));
+
+    let result = sandbox
+        .execute(
+            "const back = util.echo({ big: 2n ** 60n, small: 7n });\n\
+             return [typeof back.big, back.big === 2n ** 60n, typeof back.small];",
+        )
+        .unwrap();
+
+    assert_eq!(result.result, json!(["bigint", true, "number"]));
+}
+
+#[test]
+fn dates_become_rfc3339_strings_or_null_when_invalid() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config);
+
+    let result = sandbox
+        .execute("return { at: new Date(Date.UTC(2024, 0, 2, 3, 4, 5)), bad: new Date('nope') };")
+        .unwrap();
+
+    assert_eq!(
+        result.result,
+        json!({ "at": "2024-01-02T03:04:05.000Z", "bad": null })
+    );
+}
+
+#[test]
+fn maps_become_objects_keyed_by_stringified_keys() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config);
+
+    let result = sandbox
+        .execute(
+            "return new Map([\n\
+               ['name', 'ada'],\n\
+               [1, new Map([[true, 2n], [null, new Set([1, 1, 2])]])],\n\
+             ]);",
+        )
+        .unwrap();
+
+    assert_eq!(
+        result.result,
+        json!({ "name": "ada", "1": { "true": 2, "null": [1, 2] } })
+    );
+}
+
+#[test]
+fn sets_become_arrays_in_insertion_order() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config);
+    sandbox.register_sync_tool(tool("util.echo"), "echo".to_string(), Arc::new(Echo));
+
+    let result = sandbox
+        .execute("return util.echo({ tags: new Set(['b', 'a', 'b']), empty: new Set() });")
+        .unwrap();
+
+    assert_eq!(result.result, json!({ "tags": ["b", "a"], "empty": [] }));
+}
@@ -0,0 +1,126 @@
+#![cfg(feature = "deno")]
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use codemode_rs::prelude::*;
+use codemode_rs::sandbox::SandboxError;
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+
+mod common;
+use common::{Echo, Failing, Slow, tool};
+
+fn deno_client(runtime: &tokio::runtime::Runtime, backend: DenoBackend) -> CodeModeClient {
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    client.register_sync_tool(tool("util.echo"), "echo".to_string(), Arc::new(Echo));
+    client.register_sync_tool(tool("util.down"), "down".to_string(), Arc::new(Failing));
+    client.register_async_tool(
+        tool("util.slow"),
+        "slow".to_string(),
+        Arc::new(Slow(Duration::from_secs(5))),
+    );
+    client.set_backend(Arc::new(backend));
+    client
+}
+
+#[test]
+fn deno_backend_runs_scripts_against_registered_tools() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = deno_client(&runtime, DenoBackend::new(runtime.handle().clone()));
+
+    let echoed = runtime
+        .block_on(client.call_tool_chain(
+            "const [first, second] = await Promise.all([
+               util.echo({ n: 1 }),
+               util.echo({ n: 2 }),
+             ]);
+             return [first.n, second.n];",
+        ))
+        .unwrap();
+    assert_eq!(echoed.result, json!([1, 2]));
+
+    let failed = runtime.block_on(client.call_tool_chain("await util.down({});"));
+    assert!(
+        matches!(failed, Err(SandboxError::Script { message, .. }) if message.contains("is down"))
+    );
+}
+
+#[test]
+fn deno_backend_runs_timers() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = deno_client(&runtime, DenoBackend::new(runtime.handle().clone()));
+
+    let result = runtime
+        .block_on(client.call_tool_chain(
+            "const order = [];
+             const cancelled = setTimeout(() => order.push('cancelled'), 5);
+             clearTimeout(cancelled);
+             await new Promise((resolve) => setTimeout(() => { order.push('late'); resolve(); }, 20));
+             order.unshift('early');
+             return order;",
+        ))
+        .unwrap();
+
+    assert_eq!(result.result, json!(["early", "late"]));
+}
+
+#[test]
+fn deno_backend_imports_registered_modules() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let backend = DenoBackend::new(runtime.handle().clone())
+        .with_module("math", "export const double = (n) => n * 2;");
+    let client = deno_client(&runtime, backend);
+
+    let result = runtime
+        .block_on(client.call_tool_chain(
+            "import { double } from 'math';
+             return double((await util.echo({ n: 21 })).n);",
+        ))
+        .unwrap();
+    assert_eq!(result.result, json!(42));
+
+    let unknown = runtime.block_on(client.call_tool_chain("import 'fs';\nreturn 1;"));
+    assert!(
+        matches!(unknown, Err(SandboxError::Script { message, .. }) if message.contains("unknown module")),
+    );
+}
+
+#[test]
+fn deno_backend_terminates_runaway_loops_on_timeout() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let backend = DenoBackend::new(runtime.handle().clone()).with_timeout_ms(50);
+    let client = deno_client(&runtime, backend);
+
+    let started = Instant::now();
+    let result = runtime.block_on(client.call_tool_chain("while (true) {}"));
+
+    assert!(
+        matches!(result, Err(SandboxError::Timeout(50))),
+        "{result:?}"
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn deno_backend_stops_when_cancelled() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = deno_client(&runtime, DenoBackend::new(runtime.handle().clone()));
+    let cancel = CancellationToken::new();
+    let trigger = cancel.clone();
+    runtime.spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        trigger.cancel();
+    });
+
+    let started = Instant::now();
+    let result =
+        runtime.block_on(client.call_tool_chain_with_cancel("return await util.slow({});", cancel));
+
+    assert!(matches!(result, Err(SandboxError::Cancelled)), "{result:?}");
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
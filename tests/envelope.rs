@@ -0,0 +1,80 @@
+use codemode_rs::envelope::{ENVELOPE_VERSION, ExecutionEnvelope};
+use codemode_rs::sandbox::{ExecutionResult, SandboxError, TraceEvent};
+use serde_json::{Value, json};
+
+#[test]
+fn envelope_round_trips_with_defaults() {
+    let parsed: ExecutionEnvelope =
+        serde_json::from_value(json!({ "version": 1, "result": { "ok": true } })).unwrap();
+    assert_eq!(parsed.version, ENVELOPE_VERSION);
+    assert!(parsed.logs.is_empty());
+    assert!(parsed.emitted.is_empty());
+
+    let encoded = serde_json::to_value(&parsed).unwrap();
+    assert_eq!(encoded["result"], json!({ "ok": true }));
+    assert_eq!(ExecutionEnvelope::schema()["required"], json!(["version"]));
+}
+
+#[test]
+fn undefined_results_omit_the_result_field() {
+    let undefined = ExecutionResult {
+        undefined: true,
+        ..Default::default()
+    };
+    let encoded = serde_json::to_value(undefined.into_envelope()).unwrap();
+    assert!(encoded.get("result").is_none());
+
+    let null = ExecutionResult::default();
+    let encoded = serde_json::to_value(null.into_envelope()).unwrap();
+    assert_eq!(encoded["result"], Value::Null);
+}
+
+#[test]
+fn fallback_substitutions_land_in_the_envelope_trace() {
+    let result = ExecutionResult {
+        trace: vec![TraceEvent::Fallback {
+            tool: "search.primary".to_string(),
+            fallback: "search.backup".to_string(),
+            error: "unavailable".to_string(),
+        }],
+        ..Default::default()
+    };
+    let envelope = result.into_envelope();
+    assert_eq!(
+        envelope.trace,
+        vec![json!({
+            "type": "fallback",
+            "tool": "search.primary",
+            "fallback": "search.backup",
+            "error": "unavailable"
+        })]
+    );
+}
+
+#[test]
+fn results_deserialize_into_typed_values() {
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Summary {
+        open: u32,
+        titles: Vec<String>,
+    }
+
+    let result = ExecutionResult {
+        result: json!({ "open": 2, "titles": ["a", "b"] }),
+        ..Default::default()
+    };
+    let summary: Summary = result.deserialize().unwrap();
+    assert_eq!(summary.titles, ["a", "b"]);
+
+    let wrong = ExecutionResult {
+        result: json!({ "open": "two" }),
+        ..Default::default()
+    };
+    let Err(SandboxError::ResultShape { expected, message }) = wrong.deserialize::<Summary>()
+    else {
+        panic!("expected a shape error");
+    };
+    assert!(expected.ends_with("Summary"));
+    assert!(message.contains("expected u32"));
+    assert!(message.contains(r#"{"open":"two"}"#));
+}
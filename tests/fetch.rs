@@ -0,0 +1,16 @@
+use codemode_rs::prelude::*;
+
+#[test]
+fn fetch_allowlist_matches_hosts_and_ports() {
+    let config = FetchConfig {
+        allowed_hosts: vec!["api.example.com".to_string(), "localhost:8080".to_string()],
+        ..Default::default()
+    };
+
+    assert!(config.allows("api.example.com", 443));
+    assert!(config.allows("API.example.com", 80));
+    assert!(config.allows("localhost", 8080));
+    assert!(!config.allows("localhost", 9090));
+    assert!(!config.allows("example.com", 443));
+    assert!(!FetchConfig::default().allows("api.example.com", 443));
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "macros")]
+
+use codemode_rs::prelude::*;
+use serde_json::json;
+
+/// Adds two numbers.
+///
+/// `b` defaults to zero.
+#[codemode_rs::tool(read_only)]
+async fn add_numbers(a: i64, b: Option<i64>) -> Result<i64, ToolCallError> {
+    Ok(a + b.unwrap_or(0))
+}
+
+#[codemode_rs::tool(name = "shout", destructive)]
+fn shout(text: String) -> Result<String, String> {
+    if text.is_empty() {
+        return Err("nothing to shout".to_string());
+    }
+    Ok(text.to_uppercase())
+}
+
+#[test]
+fn tool_schema_comes_from_signature_and_docs() {
+    let tool = AddNumbersTool::tool();
+    assert_eq!(tool.name, "add_numbers");
+    assert_eq!(
+        tool.description,
+        "Adds two numbers.\n\n`b` defaults to zero."
+    );
+    assert_eq!(
+        tool.inputs,
+        json!({
+            "type": "object",
+            "properties": { "a": { "type": "integer" }, "b": { "type": "integer" } },
+            "required": ["a"]
+        })
+    );
+    assert_eq!(tool.outputs, json!({ "type": "integer" }));
+    assert!(tool.read_only && !tool.destructive);
+
+    let shout = ShoutTool::tool();
+    assert_eq!(shout.name, "shout");
+    assert!(shout.destructive);
+}
+
+#[test]
+fn generated_caller_parses_arguments_and_maps_errors() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let sum = AddNumbersTool
+            .call_tool_async("add_numbers", json!({ "a": 2, "b": 3 }))
+            .await
+            .unwrap();
+        assert_eq!(sum, json!(5));
+
+        let Err(ToolCallError::Message(message)) = AddNumbersTool
+            .call_tool_async("add_numbers", json!({ "a": "two" }))
+            .await
+        else {
+            panic!("expected an argument error");
+        };
+        assert!(message.contains("invalid argument 'a'"));
+
+        let Err(ToolCallError::Message(message)) = ShoutTool
+            .call_tool_async("shout", json!({ "text": "" }))
+            .await
+        else {
+            panic!("expected the function's error");
+        };
+        assert_eq!(message, "nothing to shout");
+
+        let tools = ShoutTool.list_tools().await.unwrap();
+        assert_eq!(tools.len(), 1);
+    });
+}
@@ -0,0 +1,33 @@
+use codemode_rs::markdown::tools_to_markdown;
+use codemode_rs::{Tool, ToolInterfaceGenerator};
+use serde_json::json;
+
+#[test]
+fn renders_parameter_tables_and_example_calls() {
+    let tool = Tool::new("github.get_pull_request", "Fetch a pull request")
+        .with_tags(["github"])
+        .with_inputs(json!({
+            "type": "object",
+            "properties": {
+                "owner": {
+                    "type": "string",
+                    "description": "Repository owner",
+                    "examples": ["rust-lang"]
+                },
+                "state": { "type": "string", "enum": ["open", "closed"] }
+            },
+            "required": ["owner"]
+        }))
+        .with_async(true);
+
+    let markdown = tools_to_markdown(&[&tool], &ToolInterfaceGenerator::default());
+
+    assert!(markdown.starts_with("## `github.get_pull_request`\n\nFetch a pull request"));
+    assert!(markdown.contains("**Tags:** `github`"));
+    assert!(
+        markdown.contains("| `owner` | `string` | yes | Repository owner @example \"rust-lang\" |")
+    );
+    assert!(markdown.contains("| `state` | `\"open\" \\| \"closed\"` | no |  |"));
+    assert!(markdown.contains("const result = await github.get_pull_request({"));
+    assert!(markdown.contains("\"owner\": \"rust-lang\""));
+}
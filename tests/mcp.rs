@@ -0,0 +1,168 @@
+#![cfg(feature = "mcp")]
+
+use std::time::{Duration, Instant};
+
+use codemode_rs::mcp::rmcp::model::{ListToolsResult, PaginatedRequestParams, Tool as McpTool};
+use codemode_rs::mcp::rmcp::service::{RequestContext, RoleServer};
+use codemode_rs::mcp::rmcp::{ErrorData, ServerHandler, ServiceExt};
+use codemode_rs::mcp::{
+    McpToolClient, NEXT_NOTIFICATION_TOOL, NotificationBuffer, NotificationFilter,
+};
+use codemode_rs::prelude::*;
+use serde_json::{Map, Value, json};
+
+struct NoTools;
+
+impl ServerHandler for NoTools {}
+
+/// A server whose only tool is named like the notification tool.
+struct Colliding;
+
+impl ServerHandler for Colliding {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult::with_all_items(vec![McpTool::new(
+            NEXT_NOTIFICATION_TOOL,
+            "server tool",
+            Map::new(),
+        )]))
+    }
+}
+
+async fn connect(
+    buffer: NotificationBuffer,
+    server: impl ServerHandler,
+) -> McpToolClient<NotificationBuffer> {
+    let (server_transport, client_transport) = tokio::io::duplex(4096);
+    tokio::spawn(async move {
+        if let Ok(server) = server.serve(server_transport).await {
+            let _ = server.waiting().await;
+        }
+    });
+    let service = buffer.clone().serve(client_transport).await.unwrap();
+    McpToolClient::new(service).with_notifications(buffer)
+}
+
+#[test]
+fn notifications_are_buffered_up_to_capacity() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let buffer = NotificationBuffer::with_capacity(2);
+    buffer.push("notifications/a", json!({ "n": 1 }));
+    buffer.push("notifications/b", json!({ "n": 2 }));
+    buffer.push("notifications/c", json!({ "n": 3 }));
+    assert_eq!(buffer.len(), 2);
+
+    let any = NotificationFilter::default();
+    let next = runtime.block_on(buffer.next(Duration::from_millis(10), &any));
+    assert_eq!(
+        next,
+        Some(json!({ "method": "notifications/b", "params": { "n": 2 } }))
+    );
+    assert_eq!(buffer.len(), 1);
+}
+
+#[test]
+fn notification_filters_skip_other_methods() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let buffer = NotificationBuffer::default();
+    buffer.push("notifications/progress", json!({ "progress": 1 }));
+    buffer.push("notifications/message", json!({ "data": "hi" }));
+
+    let messages = NotificationFilter::methods(["notifications/message"]);
+    let next = runtime.block_on(buffer.next(Duration::from_millis(10), &messages));
+    assert_eq!(next.unwrap()["method"], json!("notifications/message"));
+    // The skipped notification stays queued for other waiters.
+    assert_eq!(buffer.len(), 1);
+}
+
+#[test]
+fn waiting_for_a_notification_times_out_with_none() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let buffer = NotificationBuffer::default();
+    buffer.push("notifications/progress", Value::Null);
+
+    let started = Instant::now();
+    let messages = NotificationFilter::methods(["notifications/message"]);
+    let next = runtime.block_on(buffer.next(Duration::from_millis(50), &messages));
+    assert_eq!(next, None);
+    assert!(started.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn waiting_wakes_on_a_later_notification() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let buffer = NotificationBuffer::default();
+    let pusher = buffer.clone();
+    let next = runtime.block_on(async move {
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            pusher.push("notifications/message", json!({ "data": "late" }));
+        });
+        buffer
+            .next(Duration::from_secs(5), &NotificationFilter::default())
+            .await
+    });
+    assert_eq!(next.unwrap()["params"], json!({ "data": "late" }));
+}
+
+#[test]
+fn next_notification_tool_applies_its_filter_and_timeout() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let buffer = NotificationBuffer::default();
+    let (client, waited) = runtime.block_on(async {
+        let client = connect(buffer.clone(), NoTools).await;
+        buffer.push("notifications/progress", json!({ "progress": 1 }));
+        let started = Instant::now();
+        let none = client
+            .call_tool_async(
+                NEXT_NOTIFICATION_TOOL,
+                json!({ "filter": "notifications/message", "timeoutMs": 30 }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(none, Value::Null);
+        (client, started.elapsed())
+    });
+    assert!(waited >= Duration::from_millis(30));
+
+    let progress = runtime
+        .block_on(client.call_tool_async(
+            NEXT_NOTIFICATION_TOOL,
+            json!({ "filter": ["notifications/progress"], "timeoutMs": 30 }),
+        ))
+        .unwrap();
+    assert_eq!(progress["params"], json!({ "progress": 1 }));
+}
+
+#[test]
+fn next_notification_tool_is_listed_with_nullable_outputs() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let tools = runtime.block_on(async {
+        let client = connect(NotificationBuffer::default(), NoTools).await;
+        client.list_tools().await.unwrap()
+    });
+
+    let tool = tools
+        .iter()
+        .find(|tool| tool.name == NEXT_NOTIFICATION_TOOL)
+        .unwrap();
+    assert_eq!(tool.outputs["type"], json!(["object", "null"]));
+    assert_eq!(
+        tool.outputs["properties"]["params"]["type"],
+        json!(["object", "null"])
+    );
+}
+
+#[test]
+fn server_tools_named_like_the_notification_tool_are_rejected() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let listed = runtime.block_on(async {
+        let client = connect(NotificationBuffer::default(), Colliding).await;
+        client.list_tools().await
+    });
+
+    assert!(matches!(listed, Err(ToolCallError::Message(message)) if message.contains("collides")));
+}
@@ -0,0 +1,14 @@
+use codemode_rs::prelude::*;
+use serde_json::json;
+
+#[test]
+fn in_memory_store_sets_gets_and_deletes() {
+    let store = InMemoryStore::default();
+    store.set("cursor", json!({ "page": 2 })).unwrap();
+
+    assert_eq!(store.get("cursor").unwrap(), Some(json!({ "page": 2 })));
+    assert_eq!(store.get("missing").unwrap(), None);
+    assert!(store.delete("cursor").unwrap());
+    assert!(!store.delete("cursor").unwrap());
+    assert!(store.is_empty());
+}
@@ -0,0 +1,162 @@
+#![cfg(feature = "v8")]
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use codemode_rs::prelude::*;
+use serde_json::{Value, json};
+
+mod common;
+use common::{Echo, tool};
+
+/// Answers every request with `answer`, or never answers when it is `None`.
+struct Approver {
+    answer: Option<Approval>,
+}
+
+#[async_trait]
+impl ToolApprover for Approver {
+    async fn approve(&self, _tool: &Tool, _args: &Value) -> Approval {
+        match &self.answer {
+            Some(answer) => answer.clone(),
+            None => std::future::pending().await,
+        }
+    }
+}
+
+fn gated_client(runtime: &tokio::runtime::Runtime, answer: Option<Approval>) -> CodeModeClient {
+    let config = CodeModeClientConfigBuilder::default()
+        .sandbox(SandboxConfig::new(runtime.handle().clone()))
+        .build()
+        .unwrap();
+    let client = CodeModeClient::new(config);
+    client.register_sync_tool(tool("files.delete"), "delete".to_string(), Arc::new(Echo));
+    client.set_tool_policy(Arc::new(RulePolicy::new(PolicyDecision::RequireApproval)));
+    client.set_tool_approver(Arc::new(Approver { answer }), Duration::from_millis(50));
+    client
+}
+
+#[test]
+fn first_matching_rule_decides() {
+    let policy = RulePolicy::new(PolicyDecision::Deny("not allowlisted".to_string()))
+        .rule(
+            PolicyTarget::Name("github.delete_repo".to_string()),
+            PolicyDecision::Deny("never".to_string()),
+        )
+        .rule(PolicyTarget::Destructive, PolicyDecision::RequireApproval)
+        .rule(
+            PolicyTarget::Prefix("github.".to_string()),
+            PolicyDecision::Allow,
+        )
+        .rule(
+            PolicyTarget::Tag("search".to_string()),
+            PolicyDecision::Allow,
+        );
+    let args = json!({});
+
+    let mut close_issue = tool("github.close_issue");
+    close_issue.destructive = true;
+    assert_eq!(
+        policy.evaluate(&close_issue, &args),
+        PolicyDecision::RequireApproval
+    );
+    assert_eq!(
+        policy.evaluate(&tool("github.delete_repo"), &args),
+        PolicyDecision::Deny("never".to_string())
+    );
+    assert_eq!(
+        policy.evaluate(&tool("github.get_issue"), &args),
+        PolicyDecision::Allow
+    );
+    assert_eq!(
+        policy.evaluate(&tool("web.lookup").with_tags(["search"]), &args),
+        PolicyDecision::Allow
+    );
+    assert_eq!(
+        policy.evaluate(&tool("slack.post"), &args),
+        PolicyDecision::Deny("not allowlisted".to_string())
+    );
+}
+
+#[test]
+fn rule_policies_load_from_json() {
+    let policy: RulePolicy = serde_json::from_value(json!({
+        "rules": [[{ "tag": "admin" }, { "deny": "admins only" }], ["read_only", "allow"]],
+        "fallback": "require_approval"
+    }))
+    .unwrap();
+
+    let mut status = tool("status");
+    status.read_only = true;
+    assert_eq!(policy.evaluate(&status, &json!({})), PolicyDecision::Allow);
+    assert_eq!(
+        policy.evaluate(&tool("reset").with_tags(["admin"]), &json!({})),
+        PolicyDecision::Deny("admins only".to_string())
+    );
+    assert_eq!(
+        policy.evaluate(&tool("write"), &json!({})),
+        PolicyDecision::RequireApproval
+    );
+}
+
+#[test]
+fn approved_calls_reach_the_tool() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = gated_client(&runtime, Some(Approval::Approved));
+
+    let result = runtime.block_on(client.call_tool("files.delete", json!({ "path": "a" })));
+
+    assert_eq!(result.unwrap(), json!({ "path": "a" }));
+}
+
+#[test]
+fn rejected_calls_fail_with_the_reason() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = gated_client(&runtime, Some(Approval::Rejected("not today".to_string())));
+
+    let err = runtime
+        .block_on(client.call_tool("files.delete", json!({ "path": "a" })))
+        .unwrap_err();
+
+    assert!(
+        err.to_string()
+            .contains("'files.delete' denied by policy: not today"),
+        "{err}"
+    );
+}
+
+#[test]
+fn unanswered_approvals_time_out() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = gated_client(&runtime, None);
+
+    let started = Instant::now();
+    let err = runtime
+        .block_on(client.call_tool("files.delete", json!({ "path": "a" })))
+        .unwrap_err();
+
+    assert!(started.elapsed() >= Duration::from_millis(50));
+    assert!(
+        err.to_string().contains("approval timed out after 50ms"),
+        "{err}"
+    );
+}
+
+#[test]
+fn scripts_see_rejections_as_tool_errors() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let client = gated_client(&runtime, Some(Approval::Rejected("read only".to_string())));
+
+    let result = runtime
+        .block_on(client.call_tool_chain(
+            "try { await files.delete({ path: 'a' }); return 'deleted'; } catch (e) { return e.message; }",
+        ))
+        .unwrap();
+
+    assert!(
+        result.result.as_str().unwrap().contains("read only"),
+        "{}",
+        result.result
+    );
+}
@@ -0,0 +1,26 @@
+use std::collections::BTreeMap;
+
+use codemode_rs::prelude::*;
+
+#[test]
+fn renders_placeholders_and_drops_empty_sections() {
+    let template = PromptTemplate {
+        constraints: vec!["Scripts time out after 30 seconds.".to_string()],
+        variables: BTreeMap::from([("product".to_string(), "Acme".to_string())]),
+        template: format!(
+            "Assistant for {{{{product}}}}.\n\n{}",
+            codemode_rs::prompt::DEFAULT_TEMPLATE
+        ),
+        ..PromptTemplate::default()
+    };
+
+    let prompt = template.render("declare function ping(): void;\n", 1);
+
+    assert!(prompt.starts_with("Assistant for Acme.\n\nYou solve tasks"));
+    assert!(prompt.contains("- `return` the final result"));
+    assert!(prompt.contains("Available tools (1)"));
+    assert!(prompt.contains("```typescript\ndeclare function ping(): void;\n```"));
+    assert!(prompt.contains("## Constraints\n\n- Scripts time out after 30 seconds."));
+    assert!(!prompt.contains("## Examples"));
+    assert!(!prompt.contains("{{"));
+}
@@ -0,0 +1,23 @@
+use codemode_rs::prelude::*;
+use serde_json::json;
+
+#[test]
+fn secret_redactor_scrubs_keys_and_values() {
+    let redactor = SecretRedactor::new()
+        .key("Authorization")
+        .secret("sk-live-123");
+    let value = json!({
+        "headers": { "authorization": "Bearer abc" },
+        "note": "token sk-live-123 was used",
+        "items": ["sk-live-123", 4]
+    });
+
+    assert_eq!(
+        redactor.redact(&value),
+        json!({
+            "headers": { "authorization": "[REDACTED]" },
+            "note": "token [REDACTED] was used",
+            "items": ["[REDACTED]", 4]
+        })
+    );
+}
@@ -0,0 +1,580 @@
+#![cfg(feature = "v8")]
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use codemode_rs::prelude::*;
+use codemode_rs::sandbox::SandboxError;
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+
+mod common;
+use common::{Echo, Failing, sandbox, tool};
+
+#[test]
+fn hardening_keeps_the_console_capability() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| {
+        config.harden(true).capabilities(SandboxCapabilities {
+            console: true,
+            ..SandboxCapabilities::default()
+        })
+    });
+
+    let result = sandbox
+        .execute("console.log('hello', 1); return typeof eval;")
+        .unwrap();
+
+    assert_eq!(result.result, json!("undefined"));
+    assert_eq!(result.logs, vec!["[info] hello 1".to_string()]);
+}
+
+#[test]
+fn hardening_removes_the_builtin_console_without_the_capability() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config.harden(true));
+
+    let result = sandbox.execute("return typeof console;").unwrap();
+
+    assert_eq!(result.result, json!("undefined"));
+    assert!(sandbox.execute("console.log('x');").is_err());
+}
+
+#[test]
+fn synchronous_loops_are_terminated_at_the_timeout() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config.timeout_ms(100));
+
+    let outcome = sandbox.execute("while (true) {}");
+
+    assert!(matches!(outcome, Err(SandboxError::Timeout(100))));
+    let after = sandbox.execute("return 1 + 1;").unwrap();
+    assert_eq!(after.result, json!(2));
+}
+
+#[test]
+fn interrupt_budgets_stop_runaway_loops() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| {
+        config.interrupt_budget(Some(5)).interrupt_tick_us(1000)
+    });
+
+    let outcome = sandbox.execute("while (true) {}");
+
+    assert!(matches!(outcome, Err(SandboxError::BudgetExceeded(ticks)) if ticks > 5));
+    assert_eq!(sandbox.execute("return 'ok';").unwrap().result, json!("ok"));
+}
+
+#[test]
+fn many_concurrent_executions_share_the_monitor() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = Arc::new(sandbox(&runtime, |config| config.timeout_ms(200)));
+
+    let handles = (0..8)
+        .map(|index| {
+            let sandbox = sandbox.clone();
+            std::thread::spawn(move || {
+                if index % 2 == 0 {
+                    sandbox
+                        .execute("while (true) {}")
+                        .map(|result| result.result)
+                } else {
+                    sandbox
+                        .execute(&format!("return {index};"))
+                        .map(|result| result.result)
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for (index, handle) in handles.into_iter().enumerate() {
+        let outcome = handle.join().unwrap();
+        if index % 2 == 0 {
+            assert!(matches!(outcome, Err(SandboxError::Timeout(200))));
+        } else {
+            assert_eq!(outcome.unwrap(), json!(index));
+        }
+    }
+}
+
+#[test]
+fn cancellation_terminates_synchronous_loops() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config.timeout_ms(10_000));
+    let cancel = CancellationToken::new();
+    let canceller = std::thread::spawn({
+        let cancel = cancel.clone();
+        move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            cancel.cancel();
+        }
+    });
+
+    let outcome = sandbox.execute_with_cancel("while (true) {}", &cancel);
+    canceller.join().unwrap();
+
+    assert!(matches!(outcome, Err(SandboxError::Cancelled)));
+    assert_eq!(sandbox.execute("return 3;").unwrap().result, json!(3));
+}
+
+#[test]
+fn executions_can_run_inside_async_code() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config);
+    sandbox.register_sync_tool(tool("util.echo"), "echo".to_string(), Arc::new(Echo));
+
+    let result =
+        runtime.block_on(async { sandbox.execute("return util.echo({ from: 'async' });") });
+
+    assert_eq!(result.unwrap().result, json!({ "from": "async" }));
+}
+
+#[test]
+fn stack_samples_record_where_scripts_spend_time() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| {
+        config.stack_sample_interval_us(Some(500))
+    });
+
+    let result = sandbox
+        .execute(
+            "function spin() { const end = Date.now() + 50; while (Date.now() < end) {} }
+             spin();
+             return 1;",
+        )
+        .unwrap();
+
+    let samples = result.stack_samples.unwrap();
+    assert_eq!(samples.interval_us, 500);
+    assert!(samples.total_ms >= 50);
+    assert!(samples.stacks.keys().any(|stack| stack.contains("spin")));
+    assert!(samples.folded().lines().count() >= 1);
+}
+
+#[test]
+fn blob_chunks_keep_multibyte_characters_intact() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| {
+        config
+            .blob_store(Arc::new(InMemoryBlobStore::default()))
+            .blob_threshold_bytes(Some(16))
+            .capabilities(SandboxCapabilities {
+                encoding: true,
+                ..SandboxCapabilities::default()
+            })
+    });
+    sandbox.register_sync_tool(tool("util.echo"), "echo".to_string(), Arc::new(Echo));
+
+    let result = sandbox
+        .execute(
+            "const { blobId, size } = util.echo({ text: 'héllo wörld ✓ 日本語' });
+             const chunks = [];
+             for (let offset = 0; offset < size; offset += 3) {
+               chunks.push(blobs.read(blobId, { offset, length: 3 }));
+             }
+             const bytes = new Uint8Array(size);
+             let at = 0;
+             for (const chunk of chunks) { bytes.set(chunk, at); at += chunk.length; }
+             return JSON.parse(new TextDecoder().decode(bytes));",
+        )
+        .unwrap();
+
+    assert_eq!(result.result, json!({ "text": "héllo wörld ✓ 日本語" }));
+}
+
+#[test]
+fn deserialized_configs_resolve_the_runtime_when_executing() {
+    let config: SandboxConfig = serde_json::from_value(json!({ "timeout_ms": 1000 })).unwrap();
+    let sandbox = Sandbox::new(config);
+
+    let outcome = sandbox.execute("return 1;");
+    assert!(matches!(outcome, Err(SandboxError::V8(message)) if message.contains("runtime")));
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let _guard = runtime.enter();
+    assert_eq!(sandbox.execute("return 1;").unwrap().result, json!(1));
+}
+
+#[test]
+fn timers_resolve_from_the_completion_loop() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| {
+        config.max_timers(4).capabilities(SandboxCapabilities {
+            timers: true,
+            ..SandboxCapabilities::default()
+        })
+    });
+
+    let result = sandbox
+        .execute(
+            "const order = [];
+             const cancelled = setTimeout(() => order.push('cancelled'), 5);
+             clearTimeout(cancelled);
+             await new Promise((resolve) => {
+               let ticks = 0;
+               const interval = setInterval(() => {
+                 order.push(`tick ${++ticks}`);
+                 if (ticks === 2) { clearInterval(interval); resolve(); }
+               }, 5);
+             });
+             await new Promise((resolve) => setTimeout(resolve, 10));
+             order.push('timeout');
+             return order;",
+        )
+        .unwrap();
+    assert_eq!(result.result, json!(["tick 1", "tick 2", "timeout"]));
+
+    let limited = sandbox.execute("for (let i = 0; i < 5; i++) setTimeout(() => {}, 0);");
+    assert!(
+        matches!(limited, Err(SandboxError::Script { message, .. }) if message.contains("timer limit of 4"))
+    );
+}
+
+#[test]
+fn oversized_results_fail_the_execution() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config.max_result_bytes(Some(64)));
+    sandbox.register_sync_tool(tool("util.echo"), "echo".to_string(), Arc::new(Echo));
+
+    let result = sandbox.execute("return 'x'.repeat(100);");
+    assert!(matches!(
+        result,
+        Err(SandboxError::ResultTooLarge {
+            size: 102,
+            limit: 64
+        })
+    ));
+
+    // A script cannot catch its way past an oversized tool result.
+    let tool_result = sandbox.execute(
+        "try { util.echo({ data: 'y'.repeat(100) }); } catch (err) {}
+         return 'caught';",
+    );
+    assert!(matches!(
+        tool_result,
+        Err(SandboxError::ResultTooLarge { limit: 64, .. })
+    ));
+
+    let small = sandbox.execute("return util.echo({ ok: true });").unwrap();
+    assert_eq!(small.result, json!({ "ok": true }));
+}
+
+#[test]
+fn failed_calls_resolve_to_error_values_with_continue_on_error() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config.continue_on_error(true));
+    sandbox.register_sync_tool(tool("util.echo"), "echo".to_string(), Arc::new(Echo));
+    sandbox.register_sync_tool(tool("util.fail"), "fail".to_string(), Arc::new(Failing));
+
+    let result = sandbox
+        .execute("return [util.echo({ n: 1 }), util.fail({}), util.echo({ n: 2 })];")
+        .unwrap();
+
+    assert_eq!(
+        result.result,
+        json!([
+            { "n": 1 },
+            { "ok": false, "error": { "tool": "util.fail", "message": "fail is down" } },
+            { "n": 2 }
+        ])
+    );
+}
+
+#[test]
+fn tool_errors_pass_through_the_host_and_script_handlers() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config);
+    sandbox.register_sync_tool(tool("util.fail"), "fail".to_string(), Arc::new(Failing));
+    sandbox.set_tool_error_handler(Some(Arc::new(|tool: &str, message: String| {
+        format!("{tool} failed: {message}")
+    })));
+
+    let host = sandbox
+        .execute("try { util.fail({}); } catch (err) { return [err.message, err.tool]; }")
+        .unwrap();
+    let [message, tool] = host.result.as_array().unwrap().as_slice() else {
+        panic!("unexpected result: {}", host.result);
+    };
+    assert!(
+        message
+            .as_str()
+            .unwrap()
+            .contains("util.fail failed: fail is down")
+    );
+    assert_eq!(tool, &json!("util.fail"));
+
+    let script = sandbox
+        .execute(
+            "codemode.onToolError((err) => `wrapped: ${err.message}`);
+             try { util.fail({}); } catch (err) { return err.message; }",
+        )
+        .unwrap();
+    let message = script.result.as_str().unwrap();
+    assert!(message.starts_with("wrapped: "));
+    assert!(message.contains("util.fail failed: fail is down"));
+
+    let replaced = sandbox
+        .execute(
+            "codemode.onToolError(() => ({ retry: true }));
+             try { util.fail({}); } catch (err) { return err; }",
+        )
+        .unwrap();
+    assert_eq!(replaced.result, json!({ "retry": true }));
+}
+
+#[test]
+fn script_errors_point_into_the_submitted_code() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config);
+
+    let first_line = sandbox.execute("throw new Error('first');");
+    let Err(SandboxError::Script {
+        message,
+        line,
+        column,
+        ..
+    }) = first_line
+    else {
+        panic!("expected a script error");
+    };
+    assert!(message.contains("first"));
+    assert_eq!((line, column), (Some(1), Some(1)));
+
+    let later_line = sandbox.execute("const x = 1;\n  throw new Error('later');");
+    assert!(matches!(
+        later_line,
+        Err(SandboxError::Script {
+            line: Some(2),
+            column: Some(3),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn input_is_exposed_as_a_deeply_frozen_global() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config);
+    let mut prepared = sandbox.prepare().unwrap();
+
+    let result = prepared
+        .execute_with_input(
+            "return [input.user.name, Object.isFrozen(input), Object.isFrozen(input.user)];",
+            json!({ "user": { "name": "ada" } }),
+        )
+        .unwrap();
+
+    assert_eq!(result.result, json!(["ada", true, true]));
+}
+
+#[test]
+fn preambles_define_helpers_and_postambles_shape_results() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| {
+        config
+            .preamble(Some("function double(x) { return x * 2; }".to_string()))
+            .postamble(Some("return { value: result };".to_string()))
+    });
+
+    let result = sandbox.execute("return double(21);").unwrap();
+
+    assert_eq!(result.result, json!({ "value": 42 }));
+}
+
+#[test]
+fn scripts_import_registered_modules() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let modules = BTreeMap::from([(
+        "utils".to_string(),
+        "export function add(a, b) { return a + b; }".to_string(),
+    )]);
+    let function = sandbox(&runtime, |config| config.modules(modules.clone()));
+    let module = sandbox(&runtime, |config| {
+        config.modules(modules.clone()).wrap_mode(WrapMode::Module)
+    });
+
+    let from_function = function
+        .execute("import { add } from 'utils';\nreturn add(2, 3);")
+        .unwrap();
+    assert_eq!(from_function.result, json!(5));
+
+    let from_module = module
+        .execute("import { add } from 'utils';\nexport default add(1, 2);")
+        .unwrap();
+    assert_eq!(from_module.result, json!(3));
+
+    assert!(
+        function
+            .execute("import { missing } from 'nope';\nreturn 1;")
+            .is_err()
+    );
+}
+
+#[test]
+fn capabilities_install_only_the_requested_globals() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let bare = sandbox(&runtime, |config| config);
+    let equipped = sandbox(&runtime, |config| {
+        config.capabilities(SandboxCapabilities {
+            crypto: true,
+            encoding: true,
+            platform: true,
+            ..SandboxCapabilities::default()
+        })
+    });
+    let probe =
+        "return [typeof setTimeout, typeof crypto, typeof TextEncoder, typeof structuredClone];";
+
+    let missing = bare.execute(probe).unwrap();
+    assert_eq!(
+        missing.result,
+        json!(["undefined", "undefined", "undefined", "undefined"])
+    );
+
+    let present = equipped.execute(probe).unwrap();
+    assert_eq!(
+        present.result,
+        json!(["undefined", "object", "function", "function"])
+    );
+
+    let used = equipped
+        .execute(
+            "const bytes = new TextEncoder().encode('hé');
+             const copy = structuredClone({ list: [1, 2] });
+             return [
+               new TextDecoder().decode(bytes),
+               btoa('hi'),
+               copy.list.length,
+               crypto.randomUUID().length,
+             ];",
+        )
+        .unwrap();
+    assert_eq!(used.result, json!(["hé", "aGk=", 2, 36]));
+}
+
+#[cfg(feature = "inspector")]
+#[test]
+fn prepared_sandboxes_are_listed_for_devtools() {
+    use std::io::{Read, Write};
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| {
+        config.inspector(Some(InspectorConfig {
+            addr: "127.0.0.1:0".to_string(),
+            wait_for_debugger: false,
+        }))
+    });
+    let mut prepared = sandbox.prepare().unwrap();
+    let url = prepared.inspector_url().unwrap();
+    let host = url
+        .trim_start_matches("ws://")
+        .split('/')
+        .next()
+        .unwrap()
+        .to_string();
+
+    let mut stream = std::net::TcpStream::connect(&host).unwrap();
+    write!(stream, "GET /json/list HTTP/1.1\r\nHost: {host}\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    let targets: Value = serde_json::from_str(body).unwrap();
+    assert_eq!(targets[0]["webSocketDebuggerUrl"], json!(url));
+
+    // Nothing is attached, so executions run as usual.
+    assert_eq!(prepared.execute("return 1 + 2;").unwrap().result, json!(3));
+}
+
+#[cfg(feature = "inspector")]
+#[test]
+fn devtools_endpoint_refuses_foreign_hosts_and_browser_origins() {
+    use std::io::{Read, Write};
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| {
+        config.inspector(Some(InspectorConfig {
+            addr: "127.0.0.1:0".to_string(),
+            wait_for_debugger: false,
+        }))
+    });
+    let prepared = sandbox.prepare().unwrap();
+    let url = prepared.inspector_url().unwrap();
+    let host = url
+        .trim_start_matches("ws://")
+        .split('/')
+        .next()
+        .unwrap()
+        .to_string();
+    let status = |headers: &str| {
+        let mut stream = std::net::TcpStream::connect(&host).unwrap();
+        write!(stream, "GET /json/list HTTP/1.1\r\n{headers}\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response.lines().next().unwrap().to_string()
+    };
+
+    assert!(status(&format!("Host: {host}\r\n")).contains("200"));
+    assert!(status("Host: attacker.example:9229\r\n").contains("403"));
+    assert!(
+        status(&format!(
+            "Host: {host}\r\nOrigin: https://attacker.example\r\n"
+        ))
+        .contains("403")
+    );
+}
+
+#[cfg(feature = "inspector")]
+#[test]
+fn waiting_for_a_debugger_ends_at_the_timeout() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| {
+        config.timeout_ms(100).inspector(Some(InspectorConfig {
+            addr: "127.0.0.1:0".to_string(),
+            wait_for_debugger: true,
+        }))
+    });
+    let mut prepared = sandbox.prepare().unwrap();
+
+    let started = std::time::Instant::now();
+    let result = prepared.execute("return 1;");
+
+    assert!(matches!(result, Err(SandboxError::Timeout(100))));
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+}
+
+#[cfg(feature = "typescript")]
+#[test]
+fn typescript_is_stripped_before_running() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config.typescript(true));
+
+    let result = sandbox
+        .execute(
+            "interface Point { x: number; y: number }\n\
+             const point: Point = { x: 20, y: 22 };\n\
+             return (point.x as number) + point.y;",
+        )
+        .unwrap();
+
+    assert_eq!(result.result, json!(42));
+}
+
+#[cfg(feature = "typescript")]
+#[test]
+fn typescript_syntax_errors_report_their_position() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let sandbox = sandbox(&runtime, |config| config.typescript(true));
+
+    let err = sandbox.execute("const a = 1;\nconst b: = 2;").unwrap_err();
+    assert!(
+        matches!(&err, SandboxError::Transpile(message) if message.contains("(line 2, column ")),
+        "{err}"
+    );
+
+    // Columns on the first line leave out the wrapper the code is placed in.
+    let err = sandbox.execute("const c: = 3;").unwrap_err().to_string();
+    let column = err
+        .rsplit_once("(line 1, column ")
+        .and_then(|(_, rest)| rest.trim_end_matches(')').parse::<usize>().ok());
+    assert!(matches!(column, Some(1..=13)), "{err}");
+}
@@ -0,0 +1,49 @@
+#![cfg(feature = "schemars")]
+
+use codemode_rs::prelude::*;
+use schemars::JsonSchema as DeriveSchema;
+use serde::{Deserialize, Serialize};
+
+#[allow(dead_code)]
+#[derive(Deserialize, DeriveSchema)]
+struct CreateIssue {
+    /// Issue title
+    title: String,
+    labels: Vec<String>,
+    assignee: Option<User>,
+}
+
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize, DeriveSchema)]
+struct User {
+    login: String,
+}
+
+#[allow(dead_code)]
+#[derive(Serialize, DeriveSchema)]
+struct Issue {
+    number: u64,
+    author: User,
+}
+
+#[test]
+fn schemas_and_interfaces_follow_rust_types() {
+    let tool = Tool::from_types::<CreateIssue, Issue>("github.create_issue", "Open an issue");
+
+    assert_eq!(tool.name, "github.create_issue");
+    assert_eq!(
+        tool.inputs["required"],
+        serde_json::json!(["title", "labels"])
+    );
+    assert_eq!(
+        tool.inputs["properties"]["title"]["description"],
+        "Issue title"
+    );
+    assert!(tool.inputs.get("$schema").is_none());
+    assert!(tool.outputs["$defs"].get("User").is_some());
+
+    let output = ToolInterfaceGenerator::default().tool_to_typescript_interface(&tool);
+    assert!(output.contains("title: string"));
+    assert!(output.contains("labels: (string)[]"));
+    assert!(output.contains("login: string"));
+}
@@ -0,0 +1,32 @@
+use codemode_rs::prelude::*;
+use serde_json::json;
+
+#[test]
+fn simulated_values_follow_the_schema() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "id": { "type": "string", "format": "uuid" },
+            "status": { "enum": ["open", "closed"] },
+            "count": { "type": "integer", "minimum": 5, "maximum": 9 },
+            "tags": { "type": "array", "items": { "type": "string" }, "minItems": 2 }
+        },
+        "required": ["id", "status", "count", "tags"]
+    });
+    let value = Simulator::new(7).generate(&schema);
+
+    assert_eq!(value["id"].as_str().unwrap().len(), 36);
+    assert!(["open", "closed"].contains(&value["status"].as_str().unwrap()));
+    let count = value["count"].as_i64().unwrap();
+    assert!((5..=9).contains(&count));
+    assert!(value["tags"].as_array().unwrap().len() >= 2);
+}
+
+#[test]
+fn the_same_seed_yields_the_same_values() {
+    let schema = json!({ "type": "array", "items": { "type": "number" }, "maxItems": 5 });
+    assert_eq!(
+        Simulator::new(42).generate(&schema),
+        Simulator::new(42).generate(&schema)
+    );
+}
@@ -1,4 +1,7 @@
-use codemode_rs::{Tool, ToolInterfaceGenerator};
+use codemode_rs::ts_interface::{
+    InterfaceOptionsBuilder, NamespaceStrategy, Truncation, VerifyError, verify,
+};
+use codemode_rs::{Stability, Tool, ToolInterfaceGenerator};
 use serde_json::json;
 
 #[test]
@@ -24,6 +27,7 @@ fn generates_namespaced_interfaces_with_jsdoc() {
             }
         }),
         is_async: true,
+        ..Default::default()
     };
 
     let generator = ToolInterfaceGenerator::default();
@@ -36,3 +40,470 @@ fn generates_namespaced_interfaces_with_jsdoc() {
     assert!(output.contains("Promise<get_pull_requestOutputBase>"));
     assert!(output.contains("Access as: await github.get_pull_request(args)"));
 }
+
+#[test]
+fn functions_return_the_output_type() {
+    let tool = Tool::new("github.get_pull_request", "Fetch a pull request")
+        .with_inputs(json!({
+            "type": "object",
+            "properties": { "pull_number": { "type": "integer" } },
+            "required": ["pull_number"]
+        }))
+        .with_async(true);
+
+    let output = ToolInterfaceGenerator::default().tool_to_typescript_interface(&tool);
+    assert!(output.contains(
+        "function get_pull_request(input: get_pull_requestInput): get_pull_requestOutput;"
+    ));
+}
+
+#[test]
+fn nested_strategy_keeps_every_namespace_level() {
+    let tool = Tool::new("org.project.list-issues", "List issues");
+
+    let flat = ToolInterfaceGenerator::default();
+    assert_eq!(flat.tool_access_path(&tool), "org.project_list_issues");
+
+    let nested = ToolInterfaceGenerator::new(
+        InterfaceOptionsBuilder::default()
+            .namespace_strategy(NamespaceStrategy::Nested)
+            .build()
+            .unwrap(),
+    );
+    assert_eq!(nested.tool_access_path(&tool), "org.project.list_issues");
+    let output = nested.tool_to_typescript_interface(&tool);
+    assert!(output.contains("declare namespace org.project {"));
+    assert!(output.contains("function list_issues(input?: list_issuesInput): list_issuesOutput;"));
+    assert!(output.contains("interface list_issuesInput"));
+    assert!(output.contains("Access as: org.project.list_issues(args)"));
+}
+
+#[test]
+fn streaming_tools_are_typed_as_async_iterables() {
+    let tool = Tool::new("media.tail_logs", "Follow a log file")
+        .with_outputs(json!({ "type": "object", "properties": { "line": { "type": "string" } } }))
+        .with_streaming(true);
+
+    let output = ToolInterfaceGenerator::default().tool_to_typescript_interface(&tool);
+    assert!(output.contains("AsyncIterable<tail_logsOutputBase>"));
+    assert!(output.contains("Access as: for await (const chunk of media.tail_logs(args))"));
+}
+
+#[test]
+fn combinators_become_unions_and_intersections() {
+    let tool = Tool::new("search.query", "Search")
+        .with_inputs(json!({
+            "type": "object",
+            "properties": {
+                "limit": { "anyOf": [{ "type": "integer" }, { "type": "null" }] },
+                "filter": {
+                    "oneOf": [
+                        { "type": "object", "properties": { "tag": { "type": "string" } }, "required": ["tag"] },
+                        { "type": "object", "properties": { "author": { "type": "string" } }, "required": ["author"] }
+                    ]
+                },
+                "page": {
+                    "allOf": [
+                        { "type": "object", "properties": { "cursor": { "type": "string" } } },
+                        { "type": "object", "properties": { "size": { "type": "integer" } } }
+                    ]
+                }
+            }
+        }))
+        .with_outputs(json!({ "oneOf": [{ "type": "string" }, { "type": "array", "items": { "type": "string" } }] }))
+        .with_async(true);
+
+    let output = ToolInterfaceGenerator::default().tool_to_typescript_interface(&tool);
+    assert!(output.contains("limit?: number | null;"));
+    assert!(output.contains("filter?: { tag: string } | { author: string };"));
+    assert!(output.contains("page?: { cursor?: string } & { size?: number };"));
+    assert!(output.contains("type queryOutputBase = string | (string)[];"));
+}
+
+#[test]
+fn local_refs_are_inlined_and_recursive_ones_named() {
+    let tool = Tool::new("crm.create_contact", "Create a contact")
+        .with_inputs(json!({
+            "type": "object",
+            "properties": {
+                "address": { "$ref": "#/$defs/Address" },
+                "manager": { "$ref": "#/$defs/Person" }
+            },
+            "$defs": {
+                "Address": {
+                    "type": "object",
+                    "properties": { "city": { "type": "string" } },
+                    "required": ["city"]
+                },
+                "Person": {
+                    "type": "object",
+                    "properties": { "manager": { "$ref": "#/$defs/Person" } }
+                }
+            }
+        }))
+        .with_async(true);
+
+    let output = ToolInterfaceGenerator::default().tool_to_typescript_interface(&tool);
+    assert!(output.contains("address?: { city: string };"));
+    assert!(output.contains("manager?: create_contactPerson;"));
+    assert!(output.contains("interface create_contactPerson {"));
+    assert!(output.contains("    manager?: create_contactPerson;"));
+}
+
+#[test]
+fn prefix_items_become_tuples_with_size_docs() {
+    let tool = Tool::new("geo.route", "Plan a route")
+        .with_inputs(json!({
+            "type": "object",
+            "properties": {
+                "origin": {
+                    "type": "array",
+                    "prefixItems": [{ "type": "number" }, { "type": "number" }],
+                    "items": false
+                },
+                "label": {
+                    "type": "array",
+                    "prefixItems": [{ "type": "string" }],
+                    "items": { "type": "number" }
+                },
+                "stops": {
+                    "type": "array",
+                    "description": "Waypoints",
+                    "items": { "type": "string" },
+                    "minItems": 1,
+                    "maxItems": 5
+                }
+            }
+        }))
+        .with_async(true);
+
+    let output = ToolInterfaceGenerator::default().tool_to_typescript_interface(&tool);
+    assert!(output.contains("origin?: [number, number];"));
+    assert!(output.contains("label?: [string, ...(number)[]];"));
+    assert!(output.contains("/** Waypoints @minItems 1 @maxItems 5 */"));
+}
+
+#[test]
+fn constraints_are_documented_as_jsdoc_tags() {
+    let tool = Tool::new("users.invite", "Invite a user")
+        .with_inputs(json!({
+            "type": "object",
+            "properties": {
+                "email": { "type": "string", "description": "Address", "format": "email" },
+                "code": { "type": "string", "pattern": "^[A-Z]{4}$", "minLength": 4, "maxLength": 4 },
+                "seats": { "type": "integer", "minimum": 1, "maximum": 50, "default": 1, "examples": [5] }
+            }
+        }))
+        .with_async(true);
+
+    let output = ToolInterfaceGenerator::default().tool_to_typescript_interface(&tool);
+    assert!(output.contains("/** Address @format email */"));
+    assert!(output.contains("/** @pattern ^[A-Z]{4}$ @minLength 4 @maxLength 4 */"));
+    assert!(output.contains("/** @minimum 1 @maximum 50 @default 1 @example 5 */"));
+}
+
+#[test]
+fn const_discriminators_produce_per_variant_interfaces() {
+    let tool = Tool::new("hooks.deliver", "Deliver an event")
+        .with_inputs(json!({
+            "type": "object",
+            "properties": { "id": { "type": "string" } },
+            "required": ["id"],
+            "oneOf": [
+                {
+                    "properties": {
+                        "kind": { "const": "push" },
+                        "branch": { "type": "string" }
+                    },
+                    "required": ["branch"]
+                },
+                {
+                    "properties": {
+                        "kind": { "enum": ["pull_request"] },
+                        "number": { "type": "integer" }
+                    }
+                }
+            ]
+        }))
+        .with_async(true);
+
+    let output = ToolInterfaceGenerator::default().tool_to_typescript_interface(&tool);
+    assert!(output.contains("interface deliverInputPush {"));
+    assert!(output.contains("interface deliverInputPullRequest {"));
+    assert!(output.contains("type deliverInput = deliverInputPush | deliverInputPullRequest;"));
+    assert!(output.contains("    kind: \"pull_request\";"));
+    assert!(output.contains("    id: string;"));
+    assert!(output.contains("    number?: number;"));
+}
+
+#[test]
+fn const_and_single_enums_are_literal_types() {
+    let tool = Tool::new("version", "Report the API version")
+        .with_inputs(json!({
+            "type": "object",
+            "properties": {
+                "format": { "type": "string", "const": "semver" },
+                "strict": { "enum": [true] }
+            }
+        }))
+        .with_outputs(json!({ "type": "string", "const": "v2" }));
+
+    let output = ToolInterfaceGenerator::default().tool_to_typescript_interface(&tool);
+    assert!(output.contains("format?: \"semver\";"));
+    assert!(output.contains("strict?: true;"));
+    assert!(output.contains("type versionOutput = \"v2\";"));
+}
+
+#[test]
+fn colliding_identifiers_are_disambiguated() {
+    let tool = |name: &str| Tool::new(name, "");
+
+    let generator = ToolInterfaceGenerator::default();
+    assert_eq!(generator.claim_access_path(&tool("get-data")), "get_data");
+    assert_eq!(generator.claim_access_path(&tool("get_data")), "get_data_2");
+    assert_eq!(generator.claim_access_path(&tool("get-data")), "get_data");
+    assert_eq!(generator.tool_access_path(&tool("get_data")), "get_data_2");
+
+    let output = generator.tool_to_typescript_interface(&tool("get_data"));
+    assert!(output.contains("declare function get_data_2(input?: get_data_2Input)"));
+}
+
+#[test]
+fn changed_metadata_regenerates_cached_interface() {
+    let mut tool = Tool::new("lookup", "Look up a record")
+        .with_inputs(json!({ "type": "object", "properties": { "id": { "type": "string" } } }));
+
+    let generator = ToolInterfaceGenerator::default();
+    assert!(
+        generator
+            .tool_to_typescript_interface(&tool)
+            .contains("id?: string;")
+    );
+
+    tool.inputs = json!({ "type": "object", "properties": { "id": { "type": "integer" } } });
+    assert!(
+        generator
+            .tool_to_typescript_interface(&tool)
+            .contains("id?: number;")
+    );
+
+    generator.clear();
+    assert!(
+        generator
+            .tool_to_typescript_interface(&tool)
+            .contains("id?: number;")
+    );
+}
+
+#[test]
+fn budgeted_interfaces_drop_descriptions_then_optional_fields() {
+    let tool = Tool::new("search", "Search the catalog. ".repeat(20))
+        .with_inputs(json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "Free text query" },
+                "limit": { "type": "integer", "description": "Maximum results" },
+                "cursor": { "type": "string", "description": "Pagination cursor" }
+            },
+            "required": ["query"]
+        }))
+        .with_async(true);
+    let generator = ToolInterfaceGenerator::default();
+
+    let roomy = generator.budgeted_interfaces(&[&tool], 10_000);
+    assert!(roomy.within_budget);
+    assert!(roomy.truncated.is_empty());
+
+    let tight = generator.budgeted_interfaces(&[&tool], 1);
+    assert!(!tight.within_budget);
+    assert_eq!(
+        tight
+            .truncated
+            .iter()
+            .map(|t| t.truncation)
+            .collect::<Vec<_>>(),
+        vec![Truncation::Descriptions, Truncation::OptionalFields]
+    );
+    let interface = &tight.snapshot.interfaces["search"];
+    assert!(!interface.contains("Search the catalog"));
+    assert!(interface.contains("query: string;"));
+    assert!(!interface.contains("limit"));
+    assert!(interface.contains("[key: string]: any;"));
+    assert!(tight.estimated_tokens < roomy.estimated_tokens);
+}
+
+#[test]
+fn compact_mode_emits_one_line_per_declaration() {
+    let tool = Tool::new("github.get_issue", "Fetch an issue")
+        .with_tags(["github"])
+        .with_inputs(json!({
+            "type": "object",
+            "properties": {
+                "number": { "type": "integer", "description": "Issue number" }
+            },
+            "required": ["number"]
+        }))
+        .with_outputs(json!({ "type": "object", "properties": { "title": { "type": "string" } } }))
+        .with_async(true);
+    let options = InterfaceOptionsBuilder::default()
+        .compact(true)
+        .build()
+        .unwrap();
+    let generator = ToolInterfaceGenerator::new(options);
+    let output = generator.tool_to_typescript_interface(&tool);
+    let verbose = ToolInterfaceGenerator::default().tool_to_typescript_interface(&tool);
+
+    assert!(!output.contains("\n\n"));
+    assert!(!output.contains("Access as"));
+    assert!(output.contains("interface get_issueInput { /** Issue number */ number: number; }"));
+    assert!(output.contains("function get_issue(input: get_issueInput): get_issueOutput; }"));
+    assert!(output.ends_with("/** Fetch an issue Tags: github */"));
+    assert!(output.len() < verbose.len());
+}
+
+#[test]
+fn declaration_bundle_merges_namespaces() {
+    let tool = |name: &str| Tool::new(name, format!("{name} tool")).with_async(true);
+    let (list, get, ping) = (
+        tool("github.list_issues"),
+        tool("github.get_issue"),
+        tool("ping"),
+    );
+    let generator = ToolInterfaceGenerator::default();
+    let bundle = generator.declaration_bundle(&[&list, &ping, &get]);
+
+    assert_eq!(bundle.matches("declare namespace github {").count(), 1);
+    let ping_at = bundle.find("declare function ping(").unwrap();
+    let get_at = bundle.find("function get_issue(").unwrap();
+    let list_at = bundle.find("function list_issues(").unwrap();
+    assert!(ping_at < get_at && get_at < list_at);
+    assert!(bundle.contains("  /**\n   * github.get_issue tool\n"));
+
+    let path = std::env::temp_dir().join(format!("codemode-{}.d.ts", std::process::id()));
+    generator.write_declaration_file(&[&get], &path).unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(written.contains("declare namespace github {"));
+    assert!(written.ends_with("}\n"));
+}
+
+#[test]
+fn merged_namespaces_share_one_header() {
+    let tool = |name: &str, tag: &str| Tool::new(name, format!("{name} tool")).with_tags([tag]);
+    let (issues, pulls) = (
+        tool("github.issues", "issues"),
+        tool("github.pulls", "pulls"),
+    );
+    let options = InterfaceOptionsBuilder::default()
+        .merge_namespaces(true)
+        .build()
+        .unwrap();
+    let generator = ToolInterfaceGenerator::new(options);
+    let output = generator.catalog_interfaces(&[&pulls, &issues]);
+
+    assert_eq!(output.matches("declare namespace github {").count(), 1);
+    assert!(output.starts_with("/** github: 2 tools. Tags: issues, pulls */\n"));
+    assert!(output.contains("  /** github.issues tool */\n  function issues("));
+    assert!(output.find("function issues(").unwrap() < output.find("function pulls(").unwrap());
+    assert!(!output.contains("Access as"));
+}
+
+#[test]
+fn tools_without_inputs_take_no_parameter() {
+    let tool = |name: &str, inputs| {
+        Tool::new(name, "")
+            .with_inputs(inputs)
+            .with_outputs(
+                json!({ "type": "object", "properties": { "ok": { "type": "boolean" } } }),
+            )
+            .with_async(true)
+    };
+    let generator = ToolInterfaceGenerator::default();
+
+    let health = tool("health", json!({ "type": "object", "properties": {} }));
+    let output = generator.tool_to_typescript_interface(&health);
+    assert!(!output.contains("healthInput"));
+    assert!(output.contains("declare function health(): healthOutput;"));
+    assert!(output.contains("Access as: await health()"));
+
+    let status = tool("ops.status", json!({}));
+    let output = generator.tool_to_typescript_interface(&status);
+    assert!(!output.contains("statusInput"));
+    assert!(output.contains("  function status(): statusOutput;"));
+
+    let open = tool("search", json!({ "type": "object" }));
+    assert!(!open.takes_no_input());
+    assert!(
+        generator
+            .tool_to_typescript_interface(&open)
+            .contains("input?: searchInput")
+    );
+}
+
+#[test]
+fn deprecated_and_experimental_tools_are_tagged() {
+    let tool = Tool::new("legacy_search", "Old search endpoint")
+        .with_deprecated(true)
+        .with_stability(Stability::Experimental);
+
+    let output = ToolInterfaceGenerator::default().tool_to_typescript_interface(&tool);
+    assert!(
+        output
+            .ends_with(" * Access as: legacy_search(args)\n * @deprecated\n * @experimental\n */")
+    );
+
+    let options = InterfaceOptionsBuilder::default()
+        .compact(true)
+        .build()
+        .unwrap();
+    let compact = ToolInterfaceGenerator::new(options).tool_to_typescript_interface(&tool);
+    assert!(compact.ends_with("/** Old search endpoint @deprecated @experimental */"));
+}
+
+#[test]
+fn verify_accepts_generated_declarations() {
+    let tool = |name: &str| {
+        Tool::new(name, "Has a } brace and a \"quote\" in it")
+            .with_inputs(json!({
+                "type": "object",
+                "properties": { "type": { "type": "string", "pattern": "^[{(]" } }
+            }))
+            .with_async(true)
+    };
+    let tools = [
+        tool("github.get_issue"),
+        tool("github.list_issues"),
+        tool("ping"),
+    ];
+    let tools = tools.iter().collect::<Vec<&Tool>>();
+
+    let generator = ToolInterfaceGenerator::default();
+    assert_eq!(verify(&generator.catalog_interfaces(&tools)), Ok(()));
+    assert_eq!(verify(&generator.declaration_bundle(&tools)), Ok(()));
+}
+
+#[test]
+fn verify_reports_broken_declarations() {
+    assert_eq!(
+        verify("declare namespace a {\n  type X = string;\n"),
+        Err(vec![VerifyError::Unbalanced {
+            delimiter: '{',
+            line: 1
+        }])
+    );
+    assert_eq!(
+        verify("/** open comment\ntype X = string;"),
+        Err(vec![VerifyError::Unterminated {
+            kind: "comment",
+            line: 1
+        }])
+    );
+    assert_eq!(
+        verify(
+            "declare namespace a {\n  type X = string;\n}\n\
+             declare namespace a {\n  type X = number;\n  interface Y { type: X; }\n}\n\
+             type X = boolean;"
+        ),
+        Err(vec![VerifyError::DuplicateDeclaration("a.X".to_string())])
+    );
+}
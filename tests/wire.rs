@@ -0,0 +1,74 @@
+use codemode_rs::prelude::*;
+use serde_json::{Value, json};
+
+fn sample() -> Value {
+    json!({
+        "id": 42,
+        "name": "naïve ☕",
+        "ratio": 0.5,
+        "tags": ["a", "b"],
+        "nested": { "ok": true, "missing": null },
+    })
+}
+
+fn assert_decode_error(format: WireFormat, bytes: &[u8]) {
+    let err = format.decode(bytes).unwrap_err();
+    assert!(
+        err.to_string().contains("failed to decode tool result"),
+        "{err}"
+    );
+}
+
+#[test]
+fn json_round_trips_and_reports_decode_errors() {
+    let bytes = WireFormat::Json.encode(&sample()).unwrap();
+    assert_eq!(WireFormat::Json.decode(&bytes).unwrap(), sample());
+    assert_decode_error(WireFormat::Json, b"{ not json");
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_round_trips_and_reports_decode_errors() {
+    let bytes = WireFormat::Cbor.encode(&sample()).unwrap();
+    assert_ne!(bytes, WireFormat::Json.encode(&sample()).unwrap());
+    assert_eq!(WireFormat::Cbor.decode(&bytes).unwrap(), sample());
+    // A map header announcing more entries than follow.
+    assert_decode_error(WireFormat::Cbor, &[0xa2, 0x61, b'a']);
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_round_trips_and_reports_decode_errors() {
+    let bytes = WireFormat::MessagePack.encode(&sample()).unwrap();
+    assert_ne!(bytes, WireFormat::Json.encode(&sample()).unwrap());
+    assert_eq!(WireFormat::MessagePack.decode(&bytes).unwrap(), sample());
+    // A fixmap header announcing two entries with none following.
+    assert_decode_error(WireFormat::MessagePack, &[0x82]);
+}
+
+#[cfg(not(all(feature = "cbor", feature = "msgpack")))]
+#[test]
+fn formats_without_their_feature_are_rejected() {
+    for format in [WireFormat::Cbor, WireFormat::MessagePack] {
+        let supported = match format {
+            WireFormat::Cbor => cfg!(feature = "cbor"),
+            _ => cfg!(feature = "msgpack"),
+        };
+        if supported {
+            continue;
+        }
+        let err = format.encode(&sample()).unwrap_err();
+        assert!(err.to_string().contains("requires enabling"), "{err}");
+        assert!(format.decode(b"").is_err());
+    }
+}
+
+#[test]
+fn content_types_name_each_format() {
+    assert_eq!(WireFormat::Json.content_type(), "application/json");
+    assert_eq!(WireFormat::Cbor.content_type(), "application/cbor");
+    assert_eq!(
+        WireFormat::MessagePack.content_type(),
+        "application/msgpack"
+    );
+}
@@ -0,0 +1,47 @@
+use codemode_rs::zod::{schema_to_zod, zod_module};
+use codemode_rs::{Tool, ToolInterfaceGenerator};
+use serde_json::json;
+
+#[test]
+fn converts_schema_keywords_to_zod() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "email": { "type": "string", "format": "email", "maxLength": 120 },
+            "age": { "type": "integer", "minimum": 0 },
+            "role": { "enum": ["admin", "member"] },
+            "tags": { "type": "array", "items": { "type": "string" }, "minItems": 1 },
+            "x-trace": { "type": ["string", "null"] }
+        },
+        "required": ["email"],
+        "additionalProperties": false
+    });
+
+    assert_eq!(
+        schema_to_zod(&schema),
+        "z.object({ age: z.number().int().gte(0).optional(), email: z.string().email().max(120), \
+         role: z.enum([\"admin\", \"member\"]).optional(), \
+         tags: z.array(z.string()).min(1).optional(), \
+         \"x-trace\": z.union([z.string(), z.null()]).optional() }).strict()"
+    );
+    assert_eq!(schema_to_zod(&json!({ "const": 3 })), "z.literal(3)");
+    assert_eq!(
+        schema_to_zod(&json!({ "anyOf": [{ "type": "string" }, { "type": "number" }] })),
+        "z.union([z.string(), z.number()])"
+    );
+}
+
+#[test]
+fn module_exports_schemas_by_access_path() {
+    let tool = Tool::new("github.get_issue", "")
+        .with_inputs(json!({
+            "type": "object",
+            "properties": { "number": { "type": "integer" } },
+            "required": ["number"]
+        }))
+        .with_async(true);
+
+    let module = zod_module(&[&tool], &ToolInterfaceGenerator::default());
+    assert!(module.starts_with("import { z } from \"zod\";\n"));
+    assert!(module.contains("  \"github.get_issue\": z.object({ number: z.number().int() }),\n"));
+}